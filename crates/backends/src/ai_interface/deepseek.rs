@@ -59,15 +59,23 @@ pub(crate) struct RequestBody {
     pub(crate) response_format: Option<ResponseFormat>,
     pub(crate) stop: Option<Vec<String>>,
     pub(crate) stream: bool,
-    pub(crate) stream_options: Option<()>,
+    pub(crate) stream_options: Option<StreamOptions>,
     pub(crate) temperature: Option<f64>,
     pub(crate) top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) seed: Option<u32>,
     pub(crate) tools: Option<()>,
     pub(crate) tool_choice: Option<String>,
     pub(crate) logprobs: Option<bool>,
     pub(crate) top_logprobs: Option<i32>,
 }
 
+#[derive(Serialize, Debug)]
+pub(crate) struct StreamOptions {
+    /// Send the token usage in a last chunk without choices.
+    pub(crate) include_usage: bool,
+}
+
 #[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 pub(crate) struct ResponseBody {
@@ -146,6 +154,20 @@ pub(crate) struct Usage {
     pub(crate) completion_tokens_details: Option<CompletionTokensDetails>,
 }
 
+impl From<&Usage> for crate::TokenUsage {
+    fn from(usage: &Usage) -> Self {
+        Self {
+            input_tokens: usage.prompt_tokens,
+            output_tokens: usage.completion_tokens,
+            cached_input_tokens: usage.prompt_cache_hit_tokens,
+            reasoning_tokens: usage
+                .completion_tokens_details
+                .as_ref()
+                .map_or(0, |details| details.reasoning_tokens),
+        }
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 pub(crate) struct CompletionTokensDetails {
@@ -161,6 +183,8 @@ pub(crate) struct StreamResponseBody {
     pub(crate) model: String,
     pub(crate) system_fingerprint: String,
     pub(crate) object: String,
+    #[serde(default)]
+    pub(crate) usage: Option<Usage>,
 }
 
 #[allow(dead_code)]
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub(crate) enum MsgRole {
     #[serde(rename = "user")]
     User,
@@ -11,7 +11,7 @@ pub(crate) enum MsgRole {
 }
 
 /// Note: MsgRole.System must be at the first place.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub(crate) struct Message {
     pub(crate) content: String,
     pub(crate) role: MsgRole,
@@ -21,6 +21,16 @@ pub(crate) struct Message {
 pub(crate) struct ResponseFormat {
     #[serde(rename = "type")]
     pub(crate) type_: ResponseFormatObj,
+    /// Required with `ResponseFormatObj::JsonSchema`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) json_schema: Option<JsonSchemaFormat>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct JsonSchemaFormat {
+    pub(crate) name: String,
+    pub(crate) schema: serde_json::Value,
+    pub(crate) strict: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -29,6 +39,8 @@ pub(crate) enum ResponseFormatObj {
     Text,
     #[serde(rename = "json_object")]
     JsonObject,
+    #[serde(rename = "json_schema")]
+    JsonSchema,
 }
 
 /// ResultFormat::Message is recommended.
@@ -58,6 +70,9 @@ pub(crate) struct RequestParameters {
     pub(crate) top_p: Option<f32>,
     pub(crate) top_k: Option<u32>,
     pub(crate) enable_thinking: Option<bool>,
+    /// Most tokens spent on thinking, if it is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) thinking_budget: Option<u32>,
     pub(crate) repetition_penalty: Option<f32>,
     pub(crate) presence_penalty: Option<f32>,
     pub(crate) max_tokens: Option<u32>,
@@ -74,7 +89,6 @@ pub(crate) struct RequestParameters {
 pub(crate) struct RequestBody {
     pub(crate) model: String,
     pub(crate) input: RequestInput,
-    pub(crate) thinking_budget: Option<u32>,
     pub(crate) stream: Option<bool>,
     pub(crate) parameters: Option<RequestParameters>,
 }
@@ -126,6 +140,23 @@ pub(crate) struct Usage {
     pub(crate) prompt_tokens_details: Option<PromptTokensDetails>,
 }
 
+impl From<&Usage> for crate::TokenUsage {
+    fn from(usage: &Usage) -> Self {
+        Self {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            cached_input_tokens: usage
+                .prompt_tokens_details
+                .as_ref()
+                .map_or(0, |details| details.cached_tokens),
+            reasoning_tokens: usage
+                .output_tokens_details
+                .as_ref()
+                .map_or(0, |details| details.reasoning_tokens),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub(crate) struct OutputTokensDetails {
     pub(crate) text_tokens: u32,
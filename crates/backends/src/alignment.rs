@@ -0,0 +1,338 @@
+//! Sentence alignment of a text and its translation, for reading them side by side.
+//!
+//! Sentences are paired by their lengths as in Gale and Church (1993): a translated
+//! sentence is about as long as its source times the ratio of the whole texts, and
+//! most sentences are translated one to one.
+
+use crate::{Language, document, utils::xml};
+
+/// A sentence, or a few, and their translation. Either side is empty for a sentence
+/// left out of the other text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentPair {
+    pub source: String,
+    pub target: String,
+}
+
+/// Sentences taken together in a pair, with how likely such a pair is.
+const MOVES: [(usize, usize, f64); 6] = [
+    (1, 1, 0.89),
+    (1, 0, 0.005),
+    (0, 1, 0.005),
+    (2, 1, 0.045),
+    (1, 2, 0.045),
+    (2, 2, 0.011),
+];
+/// Variance of the translated length per source character.
+const VARIANCE: f64 = 6.8;
+/// Sentences an alignment may stray from the diagonal, which keeps the tables of long
+/// texts small.
+const BAND: usize = 32;
+
+/// Pair the sentences of `source` with those of `translation`. Paragraphs are aligned
+/// one to one when both texts have as many.
+pub fn align(
+    source: &str,
+    translation: &str,
+    source_language: Language,
+    target_language: Language,
+) -> Vec<SegmentPair> {
+    let source_paragraphs = paragraphs(source);
+    let target_paragraphs = paragraphs(translation);
+    if source_paragraphs.len() > 1 && source_paragraphs.len() == target_paragraphs.len() {
+        return source_paragraphs
+            .into_iter()
+            .zip(target_paragraphs)
+            .flat_map(|(source, target)| {
+                align_sentences(
+                    &sentences(source, source_language),
+                    &sentences(target, target_language),
+                )
+            })
+            .collect();
+    }
+    align_sentences(
+        &sentences(source, source_language),
+        &sentences(translation, target_language),
+    )
+}
+
+fn paragraphs(text: &str) -> Vec<&str> {
+    document::split_paragraphs(text)
+        .into_iter()
+        .filter(|paragraph| !paragraph.trim().is_empty())
+        .collect()
+}
+
+fn sentences(text: &str, language: Language) -> Vec<&str> {
+    document::split_sentences(text, language)
+        .into_iter()
+        .filter(|sentence| !sentence.trim().is_empty())
+        .collect()
+}
+
+fn align_sentences(source: &[&str], target: &[&str]) -> Vec<SegmentPair> {
+    let source_lengths = source.iter().map(|s| length(s)).collect::<Vec<_>>();
+    let target_lengths = target.iter().map(|s| length(s)).collect::<Vec<_>>();
+    let source_total = source_lengths.iter().sum::<f64>();
+    let target_total = target_lengths.iter().sum::<f64>();
+    let ratio = match source_total > 0.0 && target_total > 0.0 {
+        true => target_total / source_total,
+        false => 1.0,
+    };
+
+    // The cheapest alignment of the first `i` source and `j` target sentences, and the
+    // move which ends it, for `j` in a band around the diagonal. The band is wide enough
+    // for each sentence of the shorter text to take its share of the longer one.
+    let (n, m) = (source.len(), target.len());
+    let width = BAND.max(m.div_ceil(n.max(1))).max(n.div_ceil(m.max(1)));
+    let band = (0..=n)
+        .map(|i| {
+            let diagonal = match n {
+                0 => 0,
+                _ => (i * m + n / 2) / n,
+            };
+            (diagonal.saturating_sub(width), (diagonal + width).min(m))
+        })
+        .collect::<Vec<_>>();
+    let mut costs = band
+        .iter()
+        .map(|(low, high)| vec![f64::INFINITY; high + 1 - low])
+        .collect::<Vec<_>>();
+    let mut moves = band
+        .iter()
+        .map(|(low, high)| vec![(0, 0); high + 1 - low])
+        .collect::<Vec<_>>();
+    let cost_at = |costs: &[Vec<f64>], i: usize, j: usize| {
+        let (low, high) = band[i];
+        match (low..=high).contains(&j) {
+            true => costs[i][j - low],
+            false => f64::INFINITY,
+        }
+    };
+    costs[0][0] = 0.0;
+    for i in 0..=n {
+        let (low, high) = band[i];
+        for j in low..=high {
+            for (di, dj, prior) in MOVES {
+                if di > i || dj > j || cost_at(&costs, i - di, j - dj).is_infinite() {
+                    continue;
+                }
+                let source_length = source_lengths[i - di..i].iter().sum::<f64>();
+                let target_length = target_lengths[j - dj..j].iter().sum::<f64>();
+                let cost = cost_at(&costs, i - di, j - dj)
+                    + length_cost(source_length, target_length, ratio)
+                    - prior.ln();
+                if cost < costs[i][j - low] {
+                    costs[i][j - low] = cost;
+                    moves[i][j - low] = (di, dj);
+                }
+            }
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (n, m);
+    while i > 0 || j > 0 {
+        let (di, dj) = moves[i][j - band[i].0];
+        pairs.push(SegmentPair {
+            source: source[i - di..i].concat().trim().to_string(),
+            target: target[j - dj..j].concat().trim().to_string(),
+        });
+        i -= di;
+        j -= dj;
+    }
+    pairs.reverse();
+    pairs
+}
+
+/// Characters other than whitespace, which compares spaced and unspaced scripts.
+fn length(text: &str) -> f64 {
+    text.chars().filter(|c| !c.is_whitespace()).count() as f64
+}
+
+/// How unlikely it is for `source_length` characters to be translated into
+/// `target_length`, as a negative log probability.
+fn length_cost(source_length: f64, target_length: f64, ratio: f64) -> f64 {
+    let mean = (source_length + target_length / ratio) / 2.0;
+    if mean == 0.0 {
+        return 0.0;
+    }
+    let delta = (target_length - source_length * ratio) / (mean * VARIANCE).sqrt();
+    let probability = 2.0 * (1.0 - normal_cdf(delta.abs()));
+    -probability.max(f64::MIN_POSITIVE).ln()
+}
+
+/// The standard normal distribution function, after Abramowitz and Stegun 26.2.17.
+fn normal_cdf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.231_641_9 * x.abs());
+    let polynomial = t
+        * (0.319_381_530
+            + t * (-0.356_563_782
+                + t * (1.781_477_937 + t * (-1.821_255_978 + t * 1.330_274_429))));
+    let tail = (-x * x / 2.0).exp() / (2.0 * std::f64::consts::PI).sqrt() * polynomial;
+    match x >= 0.0 {
+        true => 1.0 - tail,
+        false => tail,
+    }
+}
+
+/// A Markdown table with the source on the left and the translation on the right.
+pub fn to_markdown(
+    pairs: &[SegmentPair],
+    source_language: Language,
+    target_language: Language,
+) -> String {
+    let cell = |text: &str| text.replace('|', "\\|").replace('\n', "<br>");
+    let mut markdown = format!(
+        "| {} | {} |\n| --- | --- |\n",
+        source_language, target_language
+    );
+    for pair in pairs {
+        markdown.push_str(&format!(
+            "| {} | {} |\n",
+            cell(&pair.source),
+            cell(&pair.target)
+        ));
+    }
+    markdown
+}
+
+/// An HTML page with the pairs in a table, whose rows are highlighted on hover.
+pub fn to_html(
+    pairs: &[SegmentPair],
+    source_language: Language,
+    target_language: Language,
+) -> String {
+    let mut rows = String::new();
+    for pair in pairs {
+        rows.push_str(&format!(
+            "<tr><td lang=\"{}\">{}</td><td lang=\"{}\">{}</td></tr>\n",
+            source_language.code(),
+            xml::escape_text(&pair.source).replace('\n', "<br>"),
+            target_language.code(),
+            xml::escape_text(&pair.target).replace('\n', "<br>"),
+        ));
+    }
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{} – {}</title>\n<style>\n\
+         table {{ border-collapse: collapse; width: 100%; }}\n\
+         th, td {{ border: 1px solid #ccc; padding: 6px 10px; vertical-align: top; width: 50%; }}\n\
+         tr:hover td {{ background: #fff3c4; }}\n\
+         </style>\n</head>\n<body>\n<table>\n<tr><th>{}</th><th>{}</th></tr>\n{}</table>\n\
+         </body>\n</html>\n",
+        source_language, target_language, source_language, target_language, rows
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(source: &str, target: &str) -> SegmentPair {
+        SegmentPair {
+            source: source.to_string(),
+            target: target.to_string(),
+        }
+    }
+
+    #[test]
+    fn sentences_are_paired_one_to_one() {
+        let pairs = align(
+            "It is raining. Take an umbrella with you!\nSee you tomorrow.",
+            "Es regnet. Nimm einen Regenschirm mit!\nBis morgen.",
+            Language::English,
+            Language::German,
+        );
+        assert_eq!(
+            pairs,
+            vec![
+                pair("It is raining.", "Es regnet."),
+                pair("Take an umbrella with you!", "Nimm einen Regenschirm mit!"),
+                pair("See you tomorrow.", "Bis morgen."),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_sentences_are_paired_together() {
+        let pairs = align(
+            "The meeting was moved to Friday because the manager, who had planned to \
+             attend, fell ill on Monday. Please bring the report.",
+            "会议改到了星期五。因为原本打算参加的经理星期一生病了。请带上报告。",
+            Language::English,
+            Language::Chinese,
+        );
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(
+            pairs[0].target,
+            "会议改到了星期五。因为原本打算参加的经理星期一生病了。"
+        );
+        assert_eq!(pair("Please bring the report.", "请带上报告。"), pairs[1]);
+    }
+
+    #[test]
+    fn paragraphs_anchor_the_alignment() {
+        // Without the paragraphs the short first sentence would be merged.
+        let pairs = align(
+            "Hi.\n\nThe weather has been terrible all week. We stayed at home.",
+            "Hallo.\n\nDas Wetter war die ganze Woche schrecklich, wir blieben zu Hause.",
+            Language::English,
+            Language::German,
+        );
+        assert_eq!(
+            pairs,
+            vec![
+                pair("Hi.", "Hallo."),
+                pair(
+                    "The weather has been terrible all week. We stayed at home.",
+                    "Das Wetter war die ganze Woche schrecklich, wir blieben zu Hause."
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn long_texts_are_aligned_near_the_diagonal() {
+        let source = (0..3000)
+            .map(|i| format!("Sentence {} is here.", "a".repeat(i % 7)))
+            .collect::<Vec<_>>();
+        let target = (0..3002)
+            .map(|i| format!("Satz {} ist da.", "b".repeat(i % 7)))
+            .collect::<Vec<_>>();
+        let pairs = align_sentences(
+            &source.iter().map(String::as_str).collect::<Vec<_>>(),
+            &target.iter().map(String::as_str).collect::<Vec<_>>(),
+        );
+        assert!(pairs.len() >= 3000, "{}", pairs.len());
+        assert_eq!(
+            pairs.iter().map(|pair| pair.source.len()).sum::<usize>(),
+            source.iter().map(|sentence| sentence.len()).sum::<usize>()
+        );
+        assert_eq!(pairs[0], pair(&source[0], &target[0]));
+
+        // A sentence translated into many still reaches the end of the translation.
+        let pairs = align_sentences(&["One."], &["Eins.", "Zwei.", "Drei.", "Vier."]);
+        assert_eq!(
+            pairs
+                .into_iter()
+                .map(|pair| pair.target)
+                .collect::<String>(),
+            "Eins.Zwei.Drei.Vier."
+        );
+    }
+
+    #[test]
+    fn bilingual_exports_escape_the_text() {
+        let pairs = [pair("a | b", "x < y\nz")];
+        assert_eq!(
+            to_markdown(&pairs, Language::English, Language::French),
+            "| English | French |\n| --- | --- |\n| a \\| b | x < y<br>z |\n"
+        );
+        let html = to_html(&pairs, Language::English, Language::French);
+        assert!(
+            html.contains("<tr><td lang=\"en\">a | b</td><td lang=\"fr\">x &lt; y<br>z</td></tr>")
+        );
+    }
+}
@@ -0,0 +1,298 @@
+//! Back-translation of translated sentences into their source language, for spotting
+//! meaning lost in the translation. The back-translation is scored against the source
+//! with chrF, the F-score of their character n-grams.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Error, Result, anyhow};
+
+use crate::{Language, SentenceTranslator, StreamSentenceTranslator, TokenUsage, Translator};
+
+/// Longest character n-grams compared.
+const CHRF_ORDER: usize = 6;
+/// Weight of recall over precision.
+const CHRF_BETA: f64 = 2.0;
+
+/// The back-translation of a translation and how close it is to the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackTranslationCheck {
+    pub back_translation: String,
+    /// chrF of the back-translation against the source, from 0 to 100.
+    pub score: f64,
+}
+
+/// Sentence translator whose translations are translated back by `back_translator`,
+/// which may be another engine or the same one. The check of the last translation is
+/// kept for [`BackTranslating::last_check`].
+pub struct BackTranslating<T, B> {
+    translator: T,
+    back_translator: B,
+    last_check: Mutex<Option<BackTranslationCheck>>,
+}
+impl<T: SentenceTranslator, B: SentenceTranslator> BackTranslating<T, B> {
+    pub fn new(translator: T, back_translator: B) -> Self {
+        Self {
+            translator,
+            back_translator,
+            last_check: Mutex::new(None),
+        }
+    }
+
+    /// The check of the last sentence translated, `None` if translating back failed.
+    pub fn last_check(&self) -> Option<BackTranslationCheck> {
+        self.last_check.lock().unwrap().clone()
+    }
+}
+
+impl<T: SentenceTranslator, B: SentenceTranslator> Translator for BackTranslating<T, B> {
+    fn get_api_key(&self) -> String {
+        self.translator.get_api_key()
+    }
+    fn save_api_key(&self) -> Result<(), Error> {
+        self.translator.save_api_key()
+    }
+    fn get_url(&self) -> String {
+        self.translator.get_url()
+    }
+    /// Usage of both the translation and the back-translation.
+    fn last_token_usage(&self) -> Option<TokenUsage> {
+        match (
+            self.translator.last_token_usage(),
+            self.back_translator.last_token_usage(),
+        ) {
+            (Some(forward), Some(back)) => Some(forward + back),
+            (forward, back) => forward.or(back),
+        }
+    }
+}
+
+impl<T: SentenceTranslator, B: SentenceTranslator> SentenceTranslator for BackTranslating<T, B> {
+    fn translate_sentence(
+        &self,
+        sentence: &str,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<String, Error> {
+        *self.last_check.lock().unwrap() = None;
+        let translation =
+            self.translator
+                .translate_sentence(sentence, source_language, target_language)?;
+        // The translation is still good without its check.
+        let check = check(
+            &self.back_translator,
+            sentence,
+            &translation,
+            source_language,
+            target_language,
+        )
+        .inspect_err(|e| log::warn!("Failed to translate back: {:#}", e))
+        .ok();
+        *self.last_check.lock().unwrap() = check;
+        Ok(translation)
+    }
+}
+
+/// Translate `translation` of `sentence` back with `back_translator` and score it
+/// against `sentence`.
+pub fn check<B: SentenceTranslator + ?Sized>(
+    back_translator: &B,
+    sentence: &str,
+    translation: &str,
+    source_language: Language,
+    target_language: Language,
+) -> Result<BackTranslationCheck, Error> {
+    let back_translation =
+        back_translator.translate_sentence(translation, target_language, source_language)?;
+    Ok(BackTranslationCheck {
+        score: chrf(&back_translation, sentence),
+        back_translation,
+    })
+}
+
+/// A streaming translator used as a [`SentenceTranslator`], answering with the end of
+/// its stream.
+pub struct Streamed<T: ?Sized>(pub Arc<T>);
+
+impl<T: StreamSentenceTranslator + ?Sized> Translator for Streamed<T> {
+    fn get_api_key(&self) -> String {
+        self.0.get_api_key()
+    }
+    fn save_api_key(&self) -> Result<(), Error> {
+        self.0.save_api_key()
+    }
+    fn get_url(&self) -> String {
+        self.0.get_url()
+    }
+    fn last_token_usage(&self) -> Option<TokenUsage> {
+        self.0.last_token_usage()
+    }
+}
+
+impl<T: StreamSentenceTranslator + ?Sized> SentenceTranslator for Streamed<T> {
+    fn translate_sentence(
+        &self,
+        sentence: &str,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<String, Error> {
+        self.0
+            .stream_translate_sentence(sentence, source_language, target_language)?
+            .into_iter()
+            .last()
+            .ok_or_else(|| anyhow!("No translation was received"))
+    }
+}
+
+/// chrF of `hypothesis` against `reference` from 0 to 100, ignoring whitespace and case.
+/// Precision and recall are averaged over the n-gram orders both texts are long enough
+/// for.
+pub fn chrf(hypothesis: &str, reference: &str) -> f64 {
+    let characters = |text: &str| {
+        text.to_lowercase()
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<Vec<_>>()
+    };
+    let (hypothesis, reference) = (characters(hypothesis), characters(reference));
+
+    let (mut precision, mut recall, mut orders) = (0.0, 0.0, 0);
+    for n in 1..=CHRF_ORDER.min(hypothesis.len()).min(reference.len()) {
+        let mut counts = HashMap::<&[char], usize>::new();
+        reference
+            .windows(n)
+            .for_each(|gram| *counts.entry(gram).or_default() += 1);
+        let matches = hypothesis
+            .windows(n)
+            .filter(|gram| match counts.get_mut(gram) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    true
+                }
+                _ => false,
+            })
+            .count() as f64;
+        precision += matches / (hypothesis.len() + 1 - n) as f64;
+        recall += matches / (reference.len() + 1 - n) as f64;
+        orders += 1;
+    }
+    if orders == 0 || precision + recall == 0.0 {
+        return 0.0;
+    }
+    let (precision, recall) = (precision / orders as f64, recall / orders as f64);
+    let beta2 = CHRF_BETA * CHRF_BETA;
+    100.0 * (1.0 + beta2) * precision * recall / (beta2 * precision + recall)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{self, Receiver};
+
+    use super::*;
+
+    /// Reverses the order of the words, dropping the last one if `lossy`.
+    struct Reversing {
+        lossy: bool,
+        usage: u32,
+    }
+    impl Translator for Reversing {
+        fn get_api_key(&self) -> String {
+            String::new()
+        }
+        fn save_api_key(&self) -> Result<(), Error> {
+            Ok(())
+        }
+        fn get_url(&self) -> String {
+            String::new()
+        }
+        fn last_token_usage(&self) -> Option<TokenUsage> {
+            Some(TokenUsage {
+                input_tokens: self.usage,
+                ..Default::default()
+            })
+        }
+    }
+    impl SentenceTranslator for Reversing {
+        fn translate_sentence(
+            &self,
+            sentence: &str,
+            _source_language: Language,
+            _target_language: Language,
+        ) -> Result<String, Error> {
+            let mut words = sentence.split_whitespace().rev().collect::<Vec<_>>();
+            if self.lossy {
+                words.pop();
+            }
+            Ok(words.join(" "))
+        }
+    }
+    impl StreamSentenceTranslator for Reversing {
+        fn stream_translate_sentence(
+            &self,
+            sentence: &str,
+            source_language: Language,
+            target_language: Language,
+        ) -> Result<Receiver<String>, Error> {
+            let (tx, rx) = mpsc::channel();
+            tx.send(String::new())?;
+            tx.send(self.translate_sentence(sentence, source_language, target_language)?)?;
+            Ok(rx)
+        }
+    }
+
+    #[test]
+    fn chrf_scores_character_n_grams() {
+        assert_eq!(chrf("The cat sat.", "the cat  sat."), 100.0);
+        assert_eq!(chrf("abc", "xyz"), 0.0);
+        assert_eq!(chrf("", "xyz"), 0.0);
+        // Precision 1, recall (2/3 + 1/2) / 2 over unigrams and bigrams.
+        assert!((chrf("ab", "abc") - 100.0 * 35.0 / 55.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn lost_meaning_lowers_the_score() {
+        let faithful = BackTranslating::new(
+            Reversing {
+                lossy: false,
+                usage: 3,
+            },
+            Streamed(Arc::new(Reversing {
+                lossy: false,
+                usage: 2,
+            })),
+        );
+        let translation = faithful
+            .translate_sentence(
+                "the train leaves at noon",
+                Language::English,
+                Language::German,
+            )
+            .unwrap();
+        assert_eq!(translation, "noon at leaves train the");
+        assert_eq!(
+            faithful.last_check(),
+            Some(BackTranslationCheck {
+                back_translation: "the train leaves at noon".to_string(),
+                score: 100.0,
+            })
+        );
+        assert_eq!(faithful.last_token_usage().unwrap().input_tokens, 5);
+
+        let lossy = Reversing {
+            lossy: true,
+            usage: 2,
+        };
+        let check = check(
+            &lossy,
+            "the train leaves at noon",
+            &translation,
+            Language::English,
+            Language::German,
+        )
+        .unwrap();
+        assert_eq!(check.back_translation, "the train leaves at");
+        assert!(check.score > 50.0 && check.score < 100.0, "{}", check.score);
+    }
+}
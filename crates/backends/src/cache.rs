@@ -1,5 +1,212 @@
+//! Cache of word explanations, keyed by lemma so that every inflected form of a word
+//! hits the same entry.
+
+use std::path::Path;
+
+use anyhow::{Context, Error, Result, anyhow};
+use rusqlite::{OptionalExtension, params};
+
+use crate::{
+    Language,
+    database::{self, Schema, Store},
+    dict_interface::WordExplanation,
+};
+
+const SCHEMA: Schema = Schema {
+    name: "word cache table",
+    sql: r#"
+CREATE TABLE IF NOT EXISTS word_cache (
+    lemma TEXT NOT NULL,
+    engine TEXT NOT NULL,
+    source_language TEXT NOT NULL,
+    target_language TEXT NOT NULL,
+    explanation TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (lemma, engine, source_language, target_language)
+);
+"#,
+};
+
+/// Key of a cached word explanation.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct WordQuery {
-    word: String,
-    provider: String, // Custom enum
-    meaning: String,  // Custom struct
+    pub lemma: String,
+    /// Name of the engine and the prompt preset if any, e.g. `Qwen/standard@0123abcd`.
+    pub engine: String,
+    pub source_language: Language,
+    pub target_language: Language,
+}
+
+/// Word explanations returned by engines. The cache can be shared between threads.
+pub struct WordCache {
+    store: Store,
+}
+impl WordCache {
+    /// Open the cache in the local database under the config dir.
+    pub fn open_default() -> Result<Self, Error> {
+        Ok(Self {
+            store: Store::open_default(&SCHEMA)?,
+        })
+    }
+
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        Ok(Self {
+            store: Store::open(path, &SCHEMA)?,
+        })
+    }
+
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Ok(Self {
+            store: Store::open_in_memory(&SCHEMA)?,
+        })
+    }
+
+    pub fn get(&self, query: &WordQuery) -> Result<Option<WordExplanation>, Error> {
+        let explanation = self
+            .store
+            .connection()
+            .query_row(
+                "SELECT explanation FROM word_cache WHERE lemma = ?1 AND engine = ?2 \
+                 AND source_language = ?3 AND target_language = ?4",
+                params![
+                    query.lemma.to_lowercase(),
+                    query.engine,
+                    query.source_language.to_string(),
+                    query.target_language.to_string(),
+                ],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .with_context(|| anyhow!("Failed to read cached explanation of {}", query.lemma))?;
+        explanation
+            .map(|explanation| {
+                serde_json::from_str(&explanation).map_err(|e| {
+                    anyhow!(
+                        "Failed to deserialize cached explanation of {}: {}",
+                        query.lemma,
+                        e
+                    )
+                })
+            })
+            .transpose()
+    }
+
+    pub fn insert(&self, query: &WordQuery, explanation: &WordExplanation) -> Result<(), Error> {
+        let explanation = serde_json::to_string(explanation)
+            .map_err(|e| anyhow!("Failed to serialize explanation of {}: {}", query.lemma, e))?;
+        self.store
+            .connection()
+            .execute(
+                "INSERT OR REPLACE INTO word_cache (lemma, engine, source_language, \
+                 target_language, explanation, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    query.lemma.to_lowercase(),
+                    query.engine,
+                    query.source_language.to_string(),
+                    query.target_language.to_string(),
+                    explanation,
+                    database::now(),
+                ],
+            )
+            .with_context(|| anyhow!("Failed to cache explanation of {}", query.lemma))?;
+        Ok(())
+    }
+
+    pub fn clear(&self) -> Result<(), Error> {
+        self.store
+            .connection()
+            .execute("DELETE FROM word_cache", [])
+            .with_context(|| anyhow!("Failed to clear word cache"))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{Translator, WordTranslator, dict_interface::example_arrive_word_explanation};
+
+    /// Returns the explanation of arrive, counting the requests.
+    #[derive(Default)]
+    struct CountingTranslator {
+        requests: AtomicUsize,
+        prompt_key: Option<String>,
+    }
+    impl Translator for CountingTranslator {
+        fn get_api_key(&self) -> String {
+            String::new()
+        }
+        fn save_api_key(&self) -> Result<(), anyhow::Error> {
+            Ok(())
+        }
+        fn get_url(&self) -> String {
+            String::new()
+        }
+    }
+    impl WordTranslator for CountingTranslator {
+        fn translate_word(
+            &self,
+            word: &str,
+            _source_language: Language,
+            _target_language: Language,
+        ) -> Result<WordExplanation, Error> {
+            assert_eq!(word, "arrive");
+            self.requests.fetch_add(1, Ordering::Relaxed);
+            Ok(example_arrive_word_explanation())
+        }
+        fn prompt_key(&self) -> Option<String> {
+            self.prompt_key.clone()
+        }
+    }
+
+    #[test]
+    fn inflected_forms_share_the_lemma_entry() {
+        let cache = WordCache::open_in_memory().unwrap();
+        let translator = CountingTranslator::default();
+        for word in ["arrived", "arriving", "arrive"] {
+            let word_explanation = crate::look_up_word(
+                &translator,
+                Some((&cache, "Test")),
+                word,
+                Language::English,
+                Language::Chinese,
+            )
+            .unwrap();
+            assert_eq!(word_explanation.word, "arrive");
+            let inflected_form = (word != "arrive").then(|| word.to_string());
+            assert_eq!(word_explanation.inflected_form, inflected_form);
+        }
+        assert_eq!(translator.requests.load(Ordering::Relaxed), 1);
+
+        cache.clear().unwrap();
+        crate::look_up_word(
+            &translator,
+            Some((&cache, "Test")),
+            "arrives",
+            Language::English,
+            Language::Chinese,
+        )
+        .unwrap();
+        assert_eq!(translator.requests.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn new_prompt_versions_miss_the_cache() {
+        let cache = WordCache::open_in_memory().unwrap();
+        let mut translator = CountingTranslator::default();
+        for (prompt_key, requests) in [("standard@1", 1), ("standard@1", 1), ("standard@2", 2)] {
+            translator.prompt_key = Some(prompt_key.to_string());
+            crate::look_up_word(
+                &translator,
+                Some((&cache, "Test")),
+                "arrive",
+                Language::English,
+                Language::Chinese,
+            )
+            .unwrap();
+            assert_eq!(translator.requests.load(Ordering::Relaxed), requests);
+        }
+    }
 }
@@ -0,0 +1,278 @@
+//! Types offered for the X11 and Wayland selections.
+
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use anyhow::{Error, Result, anyhow};
+use wl_clipboard_rs::{paste, watch};
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        Event,
+        xfixes::{self, ConnectionExt as _},
+        xproto::{self, ConnectionExt as _},
+    },
+    rust_connection::RustConnection,
+};
+
+use super::{Offer, SelectionKind};
+
+/// How long the owner of a selection may take to list the types it offers.
+const TARGETS_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Connection to the display server, kept by the watcher thread to learn when a
+/// selection changes and which types its owner offers.
+pub(super) enum OfferedTypes {
+    X11(Box<X11Types>),
+    Wayland(WaylandTypes),
+}
+
+impl OfferedTypes {
+    /// Connect to Wayland when it is running, else to X11. Compositors without the
+    /// data-control protocol are reached through XWayland, as by `arboard`.
+    pub(super) fn connect(ignore_list: &[String], primary: bool) -> Result<Self, Error> {
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            match WaylandTypes::connect(ignore_list, primary) {
+                Ok(types) => return Ok(Self::Wayland(types)),
+                Err(e) => log::debug!("Failed to watch the Wayland clipboard: {:#}", e),
+            }
+        }
+        X11Types::connect(ignore_list).map(|types| Self::X11(Box::new(types)))
+    }
+
+    pub(super) fn check(&mut self, kind: SelectionKind) -> Offer {
+        match self {
+            Self::X11(types) => types.check(kind),
+            Self::Wayland(types) => types.check(kind),
+        }
+    }
+}
+
+/// Selections are watched through XFixes, and their TARGETS are only requested after
+/// the owner set them again.
+pub(super) struct X11Types {
+    connection: RustConnection,
+    window: xproto::Window,
+    targets: xproto::Atom,
+    property: xproto::Atom,
+    /// Indexed by [`SelectionKind`].
+    selections: [xproto::Atom; 2],
+    changed: [bool; 2],
+    ignored: Vec<xproto::Atom>,
+}
+
+impl X11Types {
+    fn connect(ignore_list: &[String]) -> Result<Self, Error> {
+        let (connection, screen) = x11rb::connect(None)?;
+        let window = connection.generate_id()?;
+        connection.create_window(
+            0,
+            window,
+            connection.setup().roots[screen].root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            xproto::WindowClass::INPUT_ONLY,
+            x11rb::COPY_FROM_PARENT,
+            &Default::default(),
+        )?;
+        let intern = |name: &str| -> Result<xproto::Atom, Error> {
+            Ok(connection
+                .intern_atom(false, name.as_bytes())?
+                .reply()?
+                .atom)
+        };
+        let selections = [intern("CLIPBOARD")?, xproto::AtomEnum::PRIMARY.into()];
+        let targets = intern("TARGETS")?;
+        let property = intern("NEAT_TRANSLATOR_TARGETS")?;
+        let ignored = ignore_list
+            .iter()
+            .map(|hint| intern(hint))
+            .collect::<Result<_>>()?;
+
+        connection.xfixes_query_version(5, 0)?.reply()?;
+        for selection in selections {
+            connection.xfixes_select_selection_input(
+                window,
+                selection,
+                xfixes::SelectionEventMask::SET_SELECTION_OWNER
+                    | xfixes::SelectionEventMask::SELECTION_WINDOW_DESTROY
+                    | xfixes::SelectionEventMask::SELECTION_CLIENT_CLOSE,
+            )?;
+        }
+        connection.flush()?;
+
+        Ok(Self {
+            connection,
+            window,
+            targets,
+            property,
+            selections,
+            changed: [true; 2],
+            ignored,
+        })
+    }
+
+    fn check(&mut self, kind: SelectionKind) -> Offer {
+        if let Err(e) = self.read_events() {
+            log::debug!("Failed to read X11 selection events: {:#}", e);
+        }
+        if !std::mem::take(&mut self.changed[kind as usize]) {
+            return Offer::Unchanged;
+        }
+        match self.offers_ignored_type(self.selections[kind as usize]) {
+            Ok(false) => Offer::Changed,
+            Ok(true) => Offer::Ignored,
+            Err(e) => {
+                log::debug!("Failed to query clipboard MIME types: {:#}", e);
+                Offer::Ignored
+            }
+        }
+    }
+
+    fn read_events(&mut self) -> Result<(), Error> {
+        while let Some(event) = self.connection.poll_for_event()? {
+            self.note(event);
+        }
+        Ok(())
+    }
+
+    fn note(&mut self, event: Event) {
+        if let Event::XfixesSelectionNotify(event) = event {
+            for (selection, changed) in self.selections.iter().zip(&mut self.changed) {
+                *changed |= event.selection == *selection;
+            }
+        }
+    }
+
+    fn offers_ignored_type(&mut self, selection: xproto::Atom) -> Result<bool, Error> {
+        self.connection.convert_selection(
+            self.window,
+            selection,
+            self.targets,
+            self.property,
+            x11rb::CURRENT_TIME,
+        )?;
+        self.connection.flush()?;
+
+        let deadline = Instant::now() + TARGETS_TIMEOUT;
+        loop {
+            match self.connection.poll_for_event()? {
+                Some(Event::SelectionNotify(event))
+                    if event.selection == selection && event.target == self.targets =>
+                {
+                    if event.property == x11rb::NONE {
+                        // Nobody owns the selection.
+                        return Ok(false);
+                    }
+                    break;
+                }
+                Some(event) => self.note(event),
+                None if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(5)),
+                None => return Err(anyhow!("The selection owner did not list its types")),
+            }
+        }
+
+        let reply = self
+            .connection
+            .get_property(
+                true,
+                self.window,
+                self.property,
+                xproto::AtomEnum::ANY,
+                0,
+                1024,
+            )?
+            .reply()?;
+        Ok(reply
+            .value32()
+            .is_some_and(|mut targets| targets.any(|target| self.ignored.contains(&target))))
+    }
+}
+
+/// Selections are watched through the data-control protocol, whose offers list their
+/// MIME types.
+pub(super) struct WaylandTypes {
+    offers: Receiver<(SelectionKind, Offer)>,
+    /// Indexed by [`SelectionKind`].
+    latest: [Offer; 2],
+    cancel: watch::CancelHandle,
+}
+
+impl WaylandTypes {
+    fn connect(ignore_list: &[String], primary: bool) -> Result<Self, Error> {
+        let (clipboard, selection_count) = match primary {
+            true => (watch::ClipboardType::Both, 2),
+            false => (watch::ClipboardType::Regular, 1),
+        };
+        let mut watcher = watch::Watcher::new(clipboard, paste::Seat::Unspecified)?;
+        let ignore_list = ignore_list.to_vec();
+
+        // The current selections are reported first, without waiting.
+        let mut latest = [Offer::Unchanged; 2];
+        for _ in 0..selection_count {
+            if let Some(event) = watcher.next_event()? {
+                let (kind, offer) = classify_event(event, &ignore_list);
+                latest[kind as usize] = offer;
+            }
+        }
+
+        let cancel = watcher.cancel_handle();
+        let (tx, offers) = mpsc::channel();
+        std::thread::spawn(move || {
+            loop {
+                let event = match watcher.next_event() {
+                    Ok(Some(event)) => event,
+                    Ok(None) => return,
+                    Err(e) => {
+                        log::warn!("Stop watching the Wayland clipboard: {:#}", e);
+                        return;
+                    }
+                };
+                if tx.send(classify_event(event, &ignore_list)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(Self {
+            offers,
+            latest,
+            cancel,
+        })
+    }
+
+    fn check(&mut self, kind: SelectionKind) -> Offer {
+        for (kind, offer) in self.offers.try_iter() {
+            self.latest[kind as usize] = offer;
+        }
+        std::mem::replace(&mut self.latest[kind as usize], Offer::Unchanged)
+    }
+}
+
+impl Drop for WaylandTypes {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+fn classify_event(event: watch::ClipboardEvent, ignore_list: &[String]) -> (SelectionKind, Offer) {
+    let (clipboard, offer) = match event {
+        watch::ClipboardEvent::Changed {
+            clipboard,
+            mime_types,
+            ..
+        } => match ignore_list.iter().any(|hint| mime_types.contains(hint)) {
+            true => (clipboard, Offer::Ignored),
+            false => (clipboard, Offer::Changed),
+        },
+        watch::ClipboardEvent::Cleared { clipboard } => (clipboard, Offer::Changed),
+    };
+    let kind = match clipboard {
+        paste::ClipboardType::Regular => SelectionKind::Clipboard,
+        paste::ClipboardType::Primary => SelectionKind::Primary,
+    };
+    (kind, offer)
+}
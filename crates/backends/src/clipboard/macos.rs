@@ -0,0 +1,43 @@
+//! Types offered on the macOS pasteboard.
+
+use anyhow::{Error, Result};
+use objc2_app_kit::NSPasteboard;
+
+use super::{Offer, SelectionKind};
+
+/// Pasteboard types in the ignore list, checked whenever the change count of the
+/// pasteboard changes.
+pub(super) struct OfferedTypes {
+    ignore_list: Vec<String>,
+    change_count: Option<isize>,
+}
+
+impl OfferedTypes {
+    pub(super) fn connect(ignore_list: &[String], _primary: bool) -> Result<Self, Error> {
+        Ok(Self {
+            ignore_list: ignore_list.to_vec(),
+            change_count: None,
+        })
+    }
+
+    pub(super) fn check(&mut self, _kind: SelectionKind) -> Offer {
+        objc2::rc::autoreleasepool(|_| {
+            let pasteboard = NSPasteboard::generalPasteboard();
+            let change_count = pasteboard.changeCount();
+            if self.change_count == Some(change_count) {
+                return Offer::Unchanged;
+            }
+            self.change_count = Some(change_count);
+
+            let offers_ignored_type = pasteboard.types().is_some_and(|types| {
+                types
+                    .iter()
+                    .any(|offered| self.ignore_list.contains(&offered.to_string()))
+            });
+            match offers_ignored_type {
+                true => Offer::Ignored,
+                false => Offer::Changed,
+            }
+        })
+    }
+}
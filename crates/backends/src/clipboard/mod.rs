@@ -0,0 +1,317 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+    mpsc::{self, Receiver},
+};
+use std::time::{Duration, Instant};
+
+use anyhow::{Error, Result, anyhow};
+
+use crate::storage::ClipboardWatch;
+
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+))]
+mod linux;
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(target_os = "windows")]
+mod windows;
+
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+))]
+use linux::OfferedTypes;
+#[cfg(target_os = "macos")]
+use macos::OfferedTypes;
+#[cfg(target_os = "windows")]
+use windows::OfferedTypes;
+
+/// How often the clipboard is polled.
+const POLL_INTERVAL: Duration = Duration::from_millis(150);
+/// Longer texts are ignored; they are most likely not meant to be translated.
+const MAX_CLIPBOARD_CHARS: usize = 4_000;
+/// Longest text which is still looked up as a word or phrase.
+const MAX_WORD_CHARS: usize = 48;
+const MAX_WORD_TOKENS: usize = 3;
+/// CJK text without spaces is considered a word up to this length.
+const MAX_CJK_WORD_CHARS: usize = 4;
+/// Characters which never appear inside a single word or phrase.
+const SENTENCE_PUNCTUATION: &[char] = &[
+    '.', ',', ';', ':', '!', '?', '"', '(', ')', '\n', '。', '，', '；', '：', '！', '？', '、',
+    '「', '」', '（', '）',
+];
+
+/// Text picked up from the clipboard, classified by the tab it should be sent to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardText {
+    Word(String),
+    Sentence(String),
+}
+
+/// Decide whether `text` should be looked up as a word or translated as a sentence.
+///
+/// Returns `None` for empty or overly long text.
+pub fn classify(text: &str) -> Option<ClipboardText> {
+    let text = text.trim();
+    let char_count = text.chars().count();
+    if char_count == 0 || char_count > MAX_CLIPBOARD_CHARS {
+        return None;
+    }
+
+    let has_sentence_punctuation = text.contains(SENTENCE_PUNCTUATION);
+    let is_cjk = text.chars().any(is_cjk_char);
+
+    let is_word = if is_cjk {
+        !has_sentence_punctuation
+            && !text.contains(char::is_whitespace)
+            && char_count <= MAX_CJK_WORD_CHARS
+    } else {
+        !has_sentence_punctuation
+            && char_count <= MAX_WORD_CHARS
+            && text.split_whitespace().count() <= MAX_WORD_TOKENS
+    };
+
+    Some(if is_word {
+        ClipboardText::Word(text.to_string())
+    } else {
+        ClipboardText::Sentence(text.to_string())
+    })
+}
+
+fn is_cjk_char(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}'   // Hiragana, Katakana
+        | '\u{3400}'..='\u{4dbf}' // CJK Extension A
+        | '\u{4e00}'..='\u{9fff}' // CJK Unified Ideographs
+        | '\u{ac00}'..='\u{d7af}' // Hangul Syllables
+        | '\u{f900}'..='\u{faff}' // CJK Compatibility Ideographs
+    )
+}
+
+/// Read the currently selected text, falling back to the clipboard on platforms
+/// without a PRIMARY selection or when nothing is selected.
+pub fn read_selection() -> Option<String> {
+    let mut clipboard = arboard::Clipboard::new()
+        .inspect_err(|e| log::warn!("Failed to access the system clipboard: {}", e))
+        .ok()?;
+    let selection = if PRIMARY_SUPPORTED {
+        read_text(&mut clipboard, SelectionKind::Primary)
+    } else {
+        None
+    };
+    selection
+        .filter(|text| !text.trim().is_empty())
+        .or_else(|| read_text(&mut clipboard, SelectionKind::Clipboard))
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+/// Put `text` on the clipboard.
+pub fn write_text(text: &str) -> Result<(), Error> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_text(text))
+        .map_err(|e| anyhow!("Failed to copy to the system clipboard: {}", e))
+}
+
+/// Background monitor of the system clipboard.
+///
+/// Changes are debounced and classified before being sent through the receiver
+/// returned by [`ClipboardWatcher::spawn`]. The monitor stops when the watcher is dropped.
+pub struct ClipboardWatcher {
+    running: Arc<AtomicBool>,
+}
+
+impl ClipboardWatcher {
+    pub fn spawn(config: &ClipboardWatch) -> Result<(Self, Receiver<ClipboardText>), Error> {
+        let mut clipboard = arboard::Clipboard::new()
+            .map_err(|e| anyhow!("Failed to access the system clipboard: {}", e))?;
+        let running = Arc::new(AtomicBool::new(true));
+        let (tx, rx) = mpsc::channel::<ClipboardText>();
+
+        let debounce = Duration::from_millis(config.debounce_ms);
+        let primary = config.primary_selection && PRIMARY_SUPPORTED;
+        let kinds: Vec<SelectionKind> = if primary {
+            vec![SelectionKind::Clipboard, SelectionKind::Primary]
+        } else {
+            vec![SelectionKind::Clipboard]
+        };
+        // Content offered by password managers is never read, so the offered types are
+        // checked first. Without them the clipboard is only watched if nothing is ignored.
+        let mut offered_types = match OfferedTypes::connect(&config.ignore_list, primary) {
+            Ok(offered_types) => Some(offered_types),
+            Err(e) if config.ignore_list.is_empty() => {
+                log::debug!("Failed to watch the offered clipboard types: {:#}", e);
+                None
+            }
+            Err(e) => {
+                return Err(e.context(
+                    "Failed to check whether the clipboard holds content of a password manager",
+                ));
+            }
+        };
+
+        std::thread::spawn({
+            let running = running.clone();
+            move || {
+                let mut check = |kind: SelectionKind| match &mut offered_types {
+                    Some(offered_types) => offered_types.check(kind),
+                    None => Offer::Changed,
+                };
+                // Whatever is in the clipboard on start-up is not translated.
+                let mut last_seen: Vec<Option<String>> = kinds
+                    .iter()
+                    .map(|kind| match check(*kind) {
+                        Offer::Ignored => None,
+                        Offer::Unchanged | Offer::Changed => read_text(&mut clipboard, *kind),
+                    })
+                    .collect();
+                let mut pending: Option<(String, Instant)> = None;
+                let mut last_emitted: Option<String> = None;
+
+                while running.load(Ordering::Relaxed) {
+                    for (kind, seen) in kinds.iter().zip(last_seen.iter_mut()) {
+                        let text = match check(*kind) {
+                            Offer::Unchanged => continue,
+                            Offer::Ignored => {
+                                log::debug!(
+                                    "Ignore clipboard content offered by a password manager."
+                                );
+                                *seen = None;
+                                pending = None;
+                                continue;
+                            }
+                            Offer::Changed => read_text(&mut clipboard, *kind),
+                        };
+                        if text.is_none() || text == *seen {
+                            continue;
+                        }
+                        seen.clone_from(&text);
+                        pending = text.map(|text| (text, Instant::now()));
+                    }
+
+                    if let Some((text, since)) = &pending
+                        && since.elapsed() >= debounce
+                    {
+                        if last_emitted.as_ref() != Some(text) {
+                            if let Some(classified) = classify(text) {
+                                log::debug!("Clipboard changed: {:?}", classified);
+                                if tx.send(classified).is_err() {
+                                    log::info!("Clipboard receiver is dropped, stop watching.");
+                                    return;
+                                }
+                            }
+                            last_emitted = Some(text.clone());
+                        }
+                        pending = None;
+                    }
+
+                    std::thread::sleep(POLL_INTERVAL);
+                }
+            }
+        });
+
+        Ok((Self { running }, rx))
+    }
+}
+
+impl Drop for ClipboardWatcher {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SelectionKind {
+    Clipboard,
+    Primary,
+}
+
+/// What happened to a selection since it was last checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Offer {
+    Unchanged,
+    /// It is offered with a type in the ignore list, so it must not be read.
+    Ignored,
+    Changed,
+}
+
+const PRIMARY_SUPPORTED: bool = cfg!(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "ios"
+    ))
+));
+
+fn read_text(clipboard: &mut arboard::Clipboard, kind: SelectionKind) -> Option<String> {
+    match kind {
+        SelectionKind::Clipboard => clipboard.get_text().ok(),
+        #[cfg(all(
+            unix,
+            not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+        ))]
+        SelectionKind::Primary => {
+            use arboard::{GetExtLinux, LinuxClipboardKind};
+            clipboard
+                .get()
+                .clipboard(LinuxClipboardKind::Primary)
+                .text()
+                .ok()
+        }
+        #[cfg(not(all(
+            unix,
+            not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+        )))]
+        SelectionKind::Primary => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_texts_are_words() {
+        assert_eq!(
+            classify("  serendipity\n"),
+            Some(ClipboardText::Word("serendipity".to_string()))
+        );
+        assert_eq!(
+            classify("look up to"),
+            Some(ClipboardText::Word("look up to".to_string()))
+        );
+        assert_eq!(
+            classify("翻译"),
+            Some(ClipboardText::Word("翻译".to_string()))
+        );
+    }
+
+    #[test]
+    fn punctuated_or_long_texts_are_sentences() {
+        for text in [
+            "It works.",
+            "one two three four",
+            "今天天气很好",
+            "你好，世界",
+            "a, b",
+        ] {
+            assert_eq!(
+                classify(text),
+                Some(ClipboardText::Sentence(text.to_string())),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn empty_and_overly_long_texts_are_ignored() {
+        assert_eq!(classify(" \n\t"), None);
+        assert_eq!(classify(&"a".repeat(MAX_CLIPBOARD_CHARS + 1)), None);
+        assert!(classify(&"a ".repeat(MAX_CLIPBOARD_CHARS / 2)).is_some());
+    }
+}
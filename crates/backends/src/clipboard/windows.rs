@@ -0,0 +1,61 @@
+//! Formats offered on the Windows clipboard.
+
+use std::num::NonZeroU32;
+
+use anyhow::{Error, Result, anyhow};
+
+use super::{Offer, SelectionKind};
+
+/// Registered format holding a DWORD, which is 0 when the data must not be kept in the
+/// clipboard history. Only that value marks the data as secret.
+const HISTORY_FORMAT: &str = "CanIncludeInClipboardHistory";
+
+/// Registered formats in the ignore list, checked whenever the clipboard sequence
+/// number changes.
+pub(super) struct OfferedTypes {
+    /// Each format, and whether it only counts when it holds 0.
+    ignored: Vec<(u32, bool)>,
+    sequence: Option<NonZeroU32>,
+}
+
+impl OfferedTypes {
+    pub(super) fn connect(ignore_list: &[String], _primary: bool) -> Result<Self, Error> {
+        let ignored = ignore_list
+            .iter()
+            .map(|name| {
+                clipboard_win::register_format(name)
+                    .map(|format| (format.get(), name == HISTORY_FORMAT))
+                    .ok_or_else(|| anyhow!("Failed to register the clipboard format {}", name))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            ignored,
+            sequence: None,
+        })
+    }
+
+    pub(super) fn check(&mut self, _kind: SelectionKind) -> Offer {
+        let sequence = clipboard_win::seq_num();
+        if sequence.is_some() && sequence == self.sequence {
+            return Offer::Unchanged;
+        }
+        self.sequence = sequence;
+
+        for &(format, by_value) in &self.ignored {
+            if !clipboard_win::is_format_avail(format) {
+                continue;
+            }
+            if !by_value || excluded_from_history(format).unwrap_or(true) {
+                return Offer::Ignored;
+            }
+        }
+        Offer::Changed
+    }
+}
+
+fn excluded_from_history(format: u32) -> Result<bool, Error> {
+    let _clipboard = clipboard_win::Clipboard::new_attempts(10)?;
+    let mut value = Vec::new();
+    clipboard_win::raw::get_vec(format, &mut value)?;
+    Ok(value.get(..4) == Some(&[0; 4][..]))
+}
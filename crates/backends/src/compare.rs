@@ -0,0 +1,245 @@
+//! Fan-out of a sentence to several engines at once, for comparing their output.
+
+use std::{
+    sync::{
+        Arc,
+        mpsc::{self, Receiver},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{Language, StreamSentenceTranslator, TokenUsage};
+
+/// Progress of one engine, identified by its index in the list of translators.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComparisonEvent {
+    /// The output so far.
+    Partial { engine: usize, text: String },
+    Finished {
+        engine: usize,
+        text: String,
+        /// From sending the request to the end of the output.
+        latency: Duration,
+        token_usage: Option<TokenUsage>,
+    },
+    Failed {
+        engine: usize,
+        /// The output received before the failure, empty if there was none.
+        text: String,
+        error: String,
+        latency: Duration,
+        token_usage: Option<TokenUsage>,
+    },
+}
+
+impl ComparisonEvent {
+    pub fn engine(&self) -> usize {
+        match self {
+            Self::Partial { engine, .. }
+            | Self::Finished { engine, .. }
+            | Self::Failed { engine, .. } => *engine,
+        }
+    }
+}
+
+/// Translate `sentence` with every translator in its own thread. The receiver is
+/// closed once all of them have finished or failed.
+pub fn compare_sentence(
+    translators: Vec<Arc<dyn StreamSentenceTranslator + Send + Sync>>,
+    sentence: &str,
+    source_language: Language,
+    target_language: Language,
+) -> Receiver<ComparisonEvent> {
+    let (tx, rx) = mpsc::channel();
+    for (engine, translator) in translators.into_iter().enumerate() {
+        let tx = tx.clone();
+        let sentence = sentence.to_string();
+        thread::spawn(move || {
+            let start = Instant::now();
+            let stream = match translator.stream_translate_sentence_with_reasoning(
+                &sentence,
+                source_language,
+                target_language,
+            ) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    let _ = tx.send(ComparisonEvent::Failed {
+                        engine,
+                        text: String::new(),
+                        error: format!("{:#}", e),
+                        latency: start.elapsed(),
+                        token_usage: None,
+                    });
+                    return;
+                }
+            };
+            let mut text = String::new();
+            for partial in stream.answer {
+                text.clone_from(&partial);
+                if tx
+                    .send(ComparisonEvent::Partial {
+                        engine,
+                        text: partial,
+                    })
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            let latency = start.elapsed();
+            let token_usage = translator.last_token_usage();
+            let _ = tx.send(match stream.outcome.recv() {
+                // A cut off answer is still billed.
+                Ok(Err(e)) => ComparisonEvent::Failed {
+                    engine,
+                    text,
+                    error: format!("{:#}", e),
+                    latency,
+                    token_usage,
+                },
+                // Translators which cannot tell are trusted.
+                Ok(Ok(())) | Err(_) => ComparisonEvent::Finished {
+                    engine,
+                    text,
+                    latency,
+                    token_usage,
+                },
+            });
+        });
+    }
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Error, anyhow};
+
+    use super::*;
+    use crate::{SentenceStream, Translator};
+
+    /// Streams the sentence word by word, or fails if it has no words.
+    struct WordByWord;
+    impl Translator for WordByWord {
+        fn get_api_key(&self) -> String {
+            String::new()
+        }
+        fn save_api_key(&self) -> Result<(), Error> {
+            Ok(())
+        }
+        fn get_url(&self) -> String {
+            String::new()
+        }
+        fn last_token_usage(&self) -> Option<TokenUsage> {
+            Some(TokenUsage {
+                input_tokens: 3,
+                output_tokens: 2,
+                ..Default::default()
+            })
+        }
+    }
+    impl StreamSentenceTranslator for WordByWord {
+        fn stream_translate_sentence(
+            &self,
+            sentence: &str,
+            _source_language: Language,
+            _target_language: Language,
+        ) -> Result<Receiver<String>, Error> {
+            if sentence.is_empty() {
+                return Err(anyhow!("Nothing to translate"));
+            }
+            let (tx, rx) = mpsc::channel();
+            let mut text = String::new();
+            for word in sentence.split_whitespace() {
+                text.push_str(word);
+                tx.send(text.clone())?;
+            }
+            Ok(rx)
+        }
+    }
+
+    /// Streams the first word of the sentence, then is cut off.
+    struct CutOff;
+    impl Translator for CutOff {
+        fn get_api_key(&self) -> String {
+            String::new()
+        }
+        fn save_api_key(&self) -> Result<(), Error> {
+            Ok(())
+        }
+        fn get_url(&self) -> String {
+            String::new()
+        }
+    }
+    impl StreamSentenceTranslator for CutOff {
+        fn stream_translate_sentence(
+            &self,
+            sentence: &str,
+            source_language: Language,
+            target_language: Language,
+        ) -> Result<Receiver<String>, Error> {
+            WordByWord.stream_translate_sentence(sentence, source_language, target_language)
+        }
+        fn stream_translate_sentence_with_reasoning(
+            &self,
+            sentence: &str,
+            _source_language: Language,
+            _target_language: Language,
+        ) -> Result<SentenceStream, Error> {
+            let (answer_tx, answer) = mpsc::channel();
+            let (_, reasoning) = mpsc::channel();
+            let (outcome_tx, outcome) = mpsc::channel();
+            answer_tx.send(sentence.split_whitespace().next().unwrap_or("").to_string())?;
+            outcome_tx.send(Err(anyhow!("Cut off at the most tokens")))?;
+            Ok(SentenceStream {
+                answer,
+                reasoning,
+                outcome,
+            })
+        }
+    }
+
+    #[test]
+    fn every_engine_reports_its_result() {
+        let translators: Vec<Arc<dyn StreamSentenceTranslator + Send + Sync>> =
+            vec![Arc::new(WordByWord), Arc::new(WordByWord)];
+        let events = compare_sentence(translators, "a b", Language::English, Language::Chinese)
+            .into_iter()
+            .collect::<Vec<_>>();
+        for engine in 0..2 {
+            let events = events
+                .iter()
+                .filter(|event| event.engine() == engine)
+                .collect::<Vec<_>>();
+            assert_eq!(events.len(), 3);
+            assert!(matches!(
+                events[2],
+                ComparisonEvent::Finished { text, token_usage: Some(_), .. } if text == "ab"
+            ));
+        }
+
+        let translators: Vec<Arc<dyn StreamSentenceTranslator + Send + Sync>> =
+            vec![Arc::new(WordByWord)];
+        let events = compare_sentence(translators, "", Language::English, Language::Chinese)
+            .into_iter()
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            events[..],
+            [ComparisonEvent::Failed { engine: 0, .. }]
+        ));
+    }
+
+    #[test]
+    fn cut_off_output_fails_with_its_text() {
+        let translators: Vec<Arc<dyn StreamSentenceTranslator + Send + Sync>> =
+            vec![Arc::new(CutOff)];
+        let events = compare_sentence(translators, "a b", Language::English, Language::Chinese)
+            .into_iter()
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            events.last(),
+            Some(ComparisonEvent::Failed { text, error, .. })
+                if text == "a" && error.contains("Cut off")
+        ));
+    }
+}
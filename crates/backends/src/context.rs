@@ -0,0 +1,183 @@
+//! Looking up a word in the sentence it was found in, marking the sense used there.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Language,
+    dict_interface::{Explanation, PartOfSpeechType, WordExplanation},
+    prompt::{self, PromptVariables},
+};
+
+const SENSE_SYSTEM: &str = "请判断以下{source_language}单词在所给句子中使用的是哪一个义项。你只需要输出该义项的编号，不要输出其他内容；若没有符合的义项，输出 0。";
+
+/// Position of a sense in `WordExplanation::part_of_speeches`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SenseIndex {
+    pub part_of_speech: usize,
+    pub explanation: usize,
+}
+
+/// The explanation of a word with every sense, and the sense fitting its sentence.
+#[derive(Debug, Clone)]
+pub struct WordInContext {
+    pub word_explanation: WordExplanation,
+    pub sense: Option<SenseIndex>,
+}
+
+/// Every sense of the explanation, in the order they are shown.
+pub fn senses(
+    word_explanation: &WordExplanation,
+) -> Vec<(SenseIndex, &PartOfSpeechType, &Explanation)> {
+    word_explanation
+        .part_of_speeches
+        .iter()
+        .flatten()
+        .enumerate()
+        .flat_map(|(part_of_speech_index, part_of_speech)| {
+            part_of_speech.explanations.iter().enumerate().map(
+                move |(explanation_index, explanation)| {
+                    (
+                        SenseIndex {
+                            part_of_speech: part_of_speech_index,
+                            explanation: explanation_index,
+                        },
+                        &part_of_speech.part_of_speech,
+                        explanation,
+                    )
+                },
+            )
+        })
+        .collect()
+}
+
+/// The system and the user message asking which numbered sense `word` has in
+/// `sentence`.
+pub fn sense_prompt(
+    word_explanation: &WordExplanation,
+    word: &str,
+    sentence: &str,
+    source_language: Language,
+    target_language: Language,
+) -> (String, String) {
+    let system = prompt::render(
+        SENSE_SYSTEM,
+        &PromptVariables {
+            source_language,
+            target_language,
+            text: word,
+            schema_example: "",
+        },
+    );
+    let mut user = format!("句子：{}\n单词：{}\n义项：", sentence.trim(), word);
+    for (number, (_, part_of_speech, explanation)) in
+        senses(word_explanation).into_iter().enumerate()
+    {
+        user += &format!(
+            "\n{}. [{}] {}（{}）",
+            number + 1,
+            part_of_speech,
+            explanation.definition,
+            explanation.explanation
+        );
+    }
+    (system, user)
+}
+
+/// The sense named by the number in `reply`, if any. Numbers out of range and 0 mean
+/// that no sense fits.
+pub fn sense_from_reply(word_explanation: &WordExplanation, reply: &str) -> Option<SenseIndex> {
+    let number = reply
+        .split(|c: char| !c.is_ascii_digit())
+        .find(|digits| !digits.is_empty())?
+        .parse::<usize>()
+        .ok()?;
+    let senses = senses(word_explanation);
+    number
+        .checked_sub(1)
+        .and_then(|index| senses.get(index))
+        .map(|(sense, _, _)| *sense)
+}
+
+/// The only sense of a word, which needs no asking.
+pub fn only_sense(word_explanation: &WordExplanation) -> Option<SenseIndex> {
+    match senses(word_explanation).as_slice() {
+        [(sense, _, _)] => Some(*sense),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dict_interface::{PartOfSpeech, example_arrive_word_explanation};
+
+    fn explanation(definition: &str) -> Explanation {
+        Explanation {
+            phonetics: None,
+            abbreviation: None,
+            register: None,
+            explanation: definition.to_string(),
+            definition: definition.to_string(),
+            patterns: None,
+            examples: None,
+        }
+    }
+
+    fn bank() -> WordExplanation {
+        let mut word_explanation = example_arrive_word_explanation();
+        word_explanation.word = "bank".to_string();
+        word_explanation.part_of_speeches = Some(vec![
+            PartOfSpeech {
+                part_of_speech: PartOfSpeechType::Noun,
+                explanations: vec![explanation("money"), explanation("river")],
+                idioms: None,
+                phrasal_verbs: None,
+            },
+            PartOfSpeech {
+                part_of_speech: PartOfSpeechType::Verb,
+                explanations: vec![explanation("rely")],
+                idioms: None,
+                phrasal_verbs: None,
+            },
+        ]);
+        word_explanation
+    }
+
+    #[test]
+    fn senses_are_numbered_across_parts_of_speech() {
+        let (_, user) = sense_prompt(
+            &bank(),
+            "bank",
+            " We sat on the bank. ",
+            Language::English,
+            Language::Chinese,
+        );
+        assert_eq!(
+            user,
+            "句子：We sat on the bank.\n单词：bank\n义项：\n1. [noun] money（money）\n2. [noun] river（river）\n3. [verb] rely（rely）"
+        );
+    }
+
+    #[test]
+    fn replies_name_a_sense_or_none() {
+        let bank = bank();
+        assert_eq!(
+            sense_from_reply(&bank, "2"),
+            Some(SenseIndex {
+                part_of_speech: 0,
+                explanation: 1
+            })
+        );
+        assert_eq!(
+            sense_from_reply(&bank, "义项 3。"),
+            Some(SenseIndex {
+                part_of_speech: 1,
+                explanation: 0
+            })
+        );
+        assert_eq!(sense_from_reply(&bank, "0"), None);
+        assert_eq!(sense_from_reply(&bank, "4"), None);
+        assert_eq!(sense_from_reply(&bank, "none"), None);
+        assert_eq!(only_sense(&bank), None);
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    path::Path,
+    sync::{Mutex, MutexGuard},
+};
+
+use anyhow::{Context, Error, Result, anyhow};
+use rusqlite::Connection;
+
+use crate::utils;
+
+/// File name of the local database under the programme config directory.
+const DATABASE_FILE_NAME: &str = "neat-translator.sqlite3";
+
+/// The tables of a store, created when it is opened.
+pub(crate) struct Schema {
+    /// What the tables hold, for errors.
+    pub(crate) name: &'static str,
+    pub(crate) sql: &'static str,
+}
+
+/// The connection of a store to its tables, shared between threads.
+pub(crate) struct Store {
+    connection: Mutex<Connection>,
+}
+impl Store {
+    /// Open the store in the local database under the config dir.
+    pub(crate) fn open_default(schema: &Schema) -> Result<Self, Error> {
+        Self::from_connection(open_default()?, schema)
+    }
+
+    pub(crate) fn open(path: &Path, schema: &Schema) -> Result<Self, Error> {
+        Self::from_connection(open(path)?, schema)
+    }
+
+    pub(crate) fn open_in_memory(schema: &Schema) -> Result<Self, Error> {
+        Self::from_connection(Connection::open_in_memory()?, schema)
+    }
+
+    fn from_connection(connection: Connection, schema: &Schema) -> Result<Self, Error> {
+        connection
+            .execute_batch(schema.sql)
+            .with_context(|| anyhow!("Failed to create {}", schema.name))?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    pub(crate) fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap()
+    }
+}
+
+/// Open the local database of the programme, creating it if necessary.
+///
+/// Every store opens its own connection and creates its own tables.
+fn open_default() -> Result<Connection, Error> {
+    let prog_config_dir = utils::config_path::get_prog_config_dir()?;
+    std::fs::create_dir_all(&prog_config_dir).with_context(|| {
+        anyhow!(
+            "Failed to create programme config directory at {}",
+            prog_config_dir.display()
+        )
+    })?;
+    open(&prog_config_dir.join(DATABASE_FILE_NAME))
+}
+
+fn open(path: &Path) -> Result<Connection, Error> {
+    let connection = Connection::open(path)
+        .with_context(|| anyhow!("Failed to open database at {}", path.display()))?;
+    // Several stores share the file, possibly from different threads.
+    connection.pragma_update(None, "journal_mode", "WAL")?;
+    connection.busy_timeout(std::time::Duration::from_secs(5))?;
+    Ok(connection)
+}
+
+/// Seconds since the Unix epoch, as stored in the database.
+pub(crate) fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default()
+}
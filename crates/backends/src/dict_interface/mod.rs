@@ -1,13 +1,138 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+mod validation;
+
+pub use validation::{parse_word_explanation, word_explanation_schema};
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct WordExplanation {
     pub word: String,
     pub phonetics: Option<Vec<String>>,
     pub part_of_speeches: Option<Vec<PartOfSpeech>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub synonyms: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub antonyms: Option<Vec<String>>,
+    /// Words derived from the same root, e.g. `arrival` for `arrive`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub word_family: Option<Vec<RelatedWord>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etymology: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cefr_level: Option<CefrLevel>,
+    /// From 1 for the most frequent words to 5 for rare ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_band: Option<u8>,
+    /// Plurals, comparatives or conjugated forms.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inflections: Option<Vec<Inflection>>,
+    /// The form typed by the user, when its lemma was looked up instead.
+    #[schemars(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inflected_form: Option<String>,
+    /// The word typed by the user, when the translator explained a differently
+    /// spelt word instead.
+    #[schemars(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub corrected_from: Option<String>,
+    /// The entry as found in an offline dictionary, kept when it cannot be fully
+    /// mapped into the fields above.
+    #[schemars(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_definition: Option<RawDefinition>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct RelatedWord {
+    pub word: String,
+    pub part_of_speech: PartOfSpeechType,
+    pub explanation: String,
+}
+
+/// Level of the Common European Framework of Reference for Languages.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, strum::Display)]
+pub enum CefrLevel {
+    A1,
+    A2,
+    B1,
+    B2,
+    C1,
+    C2,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Inflection {
+    /// What the form is, e.g. `past tense` or `plural`.
+    pub label: String,
+    pub form: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RawDefinition {
+    /// Name of the dictionary the entry comes from.
+    pub dictionary: String,
+    pub format: RawDefinitionFormat,
+    pub content: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawDefinitionFormat {
+    Text,
+    Html,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl RawDefinition {
+    /// The content with HTML markup removed.
+    pub fn to_plain_text(&self) -> String {
+        match self.format {
+            RawDefinitionFormat::Text => self.content.clone(),
+            RawDefinitionFormat::Html => html_to_plain_text(&self.content),
+        }
+    }
+}
+
+/// Strip tags, turning block elements and line breaks into new lines.
+fn html_to_plain_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            rest = &rest[start..];
+            break;
+        };
+        let tag = rest[start + 1..start + end]
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if matches!(
+            tag.as_str(),
+            "br" | "p" | "div" | "li" | "tr" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6"
+        ) && !text.ends_with('\n')
+        {
+            text.push('\n');
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct PartOfSpeech {
     pub part_of_speech: PartOfSpeechType,
     pub explanations: Vec<Explanation>,
@@ -15,7 +140,7 @@ pub struct PartOfSpeech {
     pub phrasal_verbs: Option<Vec<PhrasalVerb>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub enum PartOfSpeechType {
     #[serde(rename = "noun")]
     Noun,
@@ -97,23 +222,26 @@ impl std::fmt::Display for PartOfSpeechType {
 //     Other: "other"
 // }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Explanation {
     pub phonetics: Option<Vec<String>>,
     pub abbreviation: Option<String>,
+    /// Labels such as `formal`, `slang` or `old-fashioned`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub register: Option<Vec<String>>,
     pub explanation: String,
     pub definition: String,
     pub patterns: Option<Vec<String>>,
     pub examples: Option<Vec<Example>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Example {
     pub example: String,
     pub translation: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Idiom {
     pub idiom: String,
     pub explanation: String,
@@ -121,7 +249,7 @@ pub struct Idiom {
     pub example: Option<Vec<Example>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct PhrasalVerb {
     pub phrasal_verb: String,
     pub explanation: String,
@@ -139,6 +267,7 @@ pub fn example_arrive_word_explanation() -> WordExplanation {
             explanations: vec![
                 Explanation {
                     abbreviation: Some("arr.".to_string()),
+                    register: None,
                     phonetics: None,
                     explanation: "到达，抵达".to_string(),
                     definition: "to get to a place, especially at the end of a journey".to_string(),
@@ -157,6 +286,7 @@ pub fn example_arrive_word_explanation() -> WordExplanation {
                 },
                 Explanation {
                     abbreviation: None,
+                    register: None,
                     phonetics: None,
                     explanation: "（东西）送达；寄到".to_string(),
                     definition: "(of things) to be brought to sb".to_string(),
@@ -195,5 +325,79 @@ pub fn example_arrive_word_explanation() -> WordExplanation {
                 }]),
             }]),
         }]),
+        synonyms: Some(vec![
+            "reach".to_string(),
+            "get to".to_string(),
+            "come".to_string(),
+        ]),
+        antonyms: Some(vec!["depart".to_string(), "leave".to_string()]),
+        word_family: Some(vec![RelatedWord {
+            word: "arrival".to_string(),
+            part_of_speech: PartOfSpeechType::Noun,
+            explanation: "到达；到来".to_string(),
+        }]),
+        etymology: Some(
+            "Middle English, from Old French ariver, from Latin ad- \"to\" + ripa \"shore\""
+                .to_string(),
+        ),
+        cefr_level: Some(CefrLevel::A2),
+        frequency_band: Some(2),
+        inflections: Some(vec![
+            Inflection {
+                label: "third person singular".to_string(),
+                form: "arrives".to_string(),
+            },
+            Inflection {
+                label: "past tense".to_string(),
+                form: "arrived".to_string(),
+            },
+            Inflection {
+                label: "present participle".to_string(),
+                form: "arriving".to_string(),
+            },
+        ]),
+        inflected_form: None,
+        corrected_from: None,
+        raw_definition: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explanations_without_optional_fields_deserialize() {
+        let word_explanation: WordExplanation = serde_json::from_str(
+            r#"{
+                "word": "arrive",
+                "phonetics": null,
+                "part_of_speeches": [{
+                    "part_of_speech": "verb",
+                    "explanations": [{
+                        "phonetics": null,
+                        "abbreviation": null,
+                        "explanation": "到达",
+                        "definition": "to get to a place",
+                        "patterns": null,
+                        "examples": null
+                    }],
+                    "idioms": null,
+                    "phrasal_verbs": null
+                }]
+            }"#,
+        )
+        .unwrap();
+        assert!(word_explanation.synonyms.is_none());
+        assert!(word_explanation.cefr_level.is_none());
+        assert!(
+            word_explanation.part_of_speeches.unwrap()[0].explanations[0]
+                .register
+                .is_none()
+        );
+
+        let example = serde_json::to_string(&example_arrive_word_explanation()).unwrap();
+        let round_trip: WordExplanation = serde_json::from_str(&example).unwrap();
+        assert_eq!(round_trip.cefr_level, Some(CefrLevel::A2));
     }
 }
@@ -0,0 +1,339 @@
+//! Schema of the word explanations requested from AI providers, and a lenient parser
+//! for replies which almost match it.
+
+use anyhow::{Error, Result, anyhow};
+use serde_json::{Map, Value};
+
+use super::WordExplanation;
+
+/// Candidate cut points tried when closing a truncated reply, latest first.
+const MAX_TRUNCATION_ATTEMPTS: usize = 64;
+
+/// JSON Schema of a word explanation, for providers supporting structured output.
+pub fn word_explanation_schema() -> Value {
+    schemars::schema_for!(WordExplanation).to_value()
+}
+
+/// Parse the reply of an AI provider, tolerating code fences, trailing commas, a
+/// truncated end, unknown parts of speech and misnamed example fields.
+pub fn parse_word_explanation(reply: &str) -> Result<WordExplanation, Error> {
+    let reply = reply.trim();
+    let reply = reply
+        .strip_prefix("```json")
+        .or_else(|| reply.strip_prefix("```"))
+        .unwrap_or(reply);
+    let reply = reply.trim().trim_end_matches("```").trim();
+    let start = reply
+        .find('{')
+        .ok_or_else(|| anyhow!("No JSON object in AI output: {}", reply))?;
+    let mut error = anyhow!("Invalid JSON in AI output");
+    for candidate in repair_json(&reply[start..]) {
+        let mut value = match serde_json::from_str::<Value>(&candidate) {
+            Ok(value) => value,
+            Err(e) => {
+                error = anyhow!("Invalid JSON in AI output: {}", e);
+                continue;
+            }
+        };
+        normalise_word_explanation(&mut value);
+        match serde_json::from_value(value) {
+            Ok(word_explanation) => return Ok(word_explanation),
+            Err(e) => {
+                error = anyhow!(
+                    "AI output does not match the word explanation schema: {}",
+                    e
+                )
+            }
+        }
+    }
+    Err(error)
+}
+
+/// Documents to try parsing: `text` without trailing commas, or if it is truncated,
+/// closed as it is and then cut back member by member.
+fn repair_json(text: &str) -> Vec<String> {
+    let mut repaired = String::with_capacity(text.len());
+    // Closers of the open arrays and objects.
+    let mut closers = Vec::new();
+    // Lengths of `repaired` where the document may be cut, with the closers then.
+    let mut cut_points = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in text.chars() {
+        if in_string {
+            repaired.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => {
+                closers.push(if c == '{' { '}' } else { ']' });
+                repaired.push(c);
+                cut_points.push((repaired.len(), closers.clone()));
+                continue;
+            }
+            '}' | ']' => {
+                trim_trailing_comma(&mut repaired);
+                if closers.last() == Some(&c) {
+                    closers.pop();
+                }
+                if closers.is_empty() {
+                    repaired.push(c);
+                    break;
+                }
+            }
+            ',' => cut_points.push((repaired.len(), closers.clone())),
+            _ => {}
+        }
+        repaired.push(c);
+    }
+    if closers.is_empty() && !in_string {
+        return vec![repaired];
+    }
+
+    // The reply was cut off, e.g. by the token limit.
+    let mut attempt = repaired.clone();
+    if in_string {
+        if escaped {
+            attempt.pop();
+        }
+        attempt.push('"');
+    }
+    std::iter::once((attempt, closers))
+        .chain(
+            cut_points
+                .into_iter()
+                .rev()
+                .map(|(length, closers)| (repaired[..length].to_string(), closers)),
+        )
+        .take(MAX_TRUNCATION_ATTEMPTS)
+        .map(|(mut attempt, closers)| {
+            trim_trailing_comma(&mut attempt);
+            attempt.extend(closers.iter().rev());
+            attempt
+        })
+        .collect()
+}
+
+fn trim_trailing_comma(text: &mut String) {
+    let trimmed = text.trim_end();
+    if let Some(without_comma) = trimmed.strip_suffix(',') {
+        text.truncate(without_comma.len());
+    }
+}
+
+fn normalise_word_explanation(value: &mut Value) {
+    let Some(word_explanation) = value.as_object_mut() else {
+        return;
+    };
+    for part_of_speech in objects_mut(word_explanation.get_mut("part_of_speeches")) {
+        normalise_part_of_speech_field(part_of_speech);
+        if !part_of_speech.contains_key("explanations") {
+            part_of_speech.insert("explanations".to_string(), Value::Array(Vec::new()));
+        }
+        for explanation in objects_mut(part_of_speech.get_mut("explanations")) {
+            rename_field(explanation, "example", "examples");
+            normalise_texts(explanation, &["explanation", "definition"]);
+            normalise_examples(explanation.get_mut("examples"));
+        }
+        for key in ["idioms", "phrasal_verbs"] {
+            for entry in objects_mut(part_of_speech.get_mut(key)) {
+                rename_field(entry, "examples", "example");
+                normalise_texts(entry, &["explanation", "definition"]);
+                normalise_examples(entry.get_mut("example"));
+            }
+        }
+    }
+    for related_word in objects_mut(word_explanation.get_mut("word_family")) {
+        normalise_part_of_speech_field(related_word);
+        normalise_texts(related_word, &["explanation"]);
+    }
+    // Optional fields which do not fit are dropped rather than failing the whole word.
+    if let Some(level) = word_explanation.get("cefr_level") {
+        match level.as_str().map(str::to_uppercase) {
+            Some(level) if ["A1", "A2", "B1", "B2", "C1", "C2"].contains(&level.as_str()) => {
+                word_explanation.insert("cefr_level".to_string(), Value::String(level));
+            }
+            _ => {
+                word_explanation.remove("cefr_level");
+            }
+        }
+    }
+    if word_explanation
+        .get("frequency_band")
+        .is_some_and(|band| !band.as_u64().is_some_and(|band| (1..=5).contains(&band)))
+    {
+        word_explanation.remove("frequency_band");
+    }
+    for key in ["synonyms", "antonyms"] {
+        if let Some(Value::String(words)) = word_explanation.get(key) {
+            let words = words
+                .split([',', ';', '，', '；'])
+                .map(str::trim)
+                .filter(|word| !word.is_empty())
+                .map(|word| Value::String(word.to_string()))
+                .collect();
+            word_explanation.insert(key.to_string(), Value::Array(words));
+        }
+    }
+    // Only set by this programme, never by the provider.
+    for key in ["inflected_form", "corrected_from", "raw_definition"] {
+        word_explanation.remove(key);
+    }
+}
+
+/// Objects in an array, skipping anything else.
+fn objects_mut(value: Option<&mut Value>) -> impl Iterator<Item = &mut Map<String, Value>> {
+    value
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object_mut)
+}
+
+fn rename_field(object: &mut Map<String, Value>, from: &str, to: &str) {
+    if !object.contains_key(to)
+        && let Some(value) = object.remove(from)
+    {
+        object.insert(to.to_string(), value);
+    }
+}
+
+fn normalise_part_of_speech_field(object: &mut Map<String, Value>) {
+    let name = object
+        .get("part_of_speech")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let name = part_of_speech(name);
+    object.insert(
+        "part_of_speech".to_string(),
+        Value::String(name.to_string()),
+    );
+}
+
+/// Name of a part of speech as serialised by `PartOfSpeechType`.
+fn part_of_speech(name: &str) -> &'static str {
+    match name.trim().trim_end_matches('.').to_lowercase().as_str() {
+        "noun" | "n" => "noun",
+        "verb" | "v" | "vt" | "vi" => "verb",
+        "adj" | "adjective" => "adj.",
+        "adv" | "adverb" => "adv.",
+        "pron" | "pronoun" => "pron.",
+        "prep" | "preposition" => "prep.",
+        "conj" | "conjunction" => "conj.",
+        "interj" | "interjection" => "interj.",
+        _ => "other",
+    }
+}
+
+/// Required texts which are missing or null become empty.
+fn normalise_texts(object: &mut Map<String, Value>, keys: &[&str]) {
+    for &key in keys {
+        if object.get(key).is_none_or(Value::is_null) {
+            object.insert(key.to_string(), Value::String(String::new()));
+        }
+    }
+}
+
+/// Examples given as bare sentences get an empty translation.
+fn normalise_examples(examples: Option<&mut Value>) {
+    for example in examples.and_then(Value::as_array_mut).into_iter().flatten() {
+        if let Value::String(sentence) = example {
+            let mut object = Map::new();
+            object.insert(
+                "example".to_string(),
+                Value::String(std::mem::take(sentence)),
+            );
+            object.insert("translation".to_string(), Value::String(String::new()));
+            *example = Value::Object(object);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dict_interface::{PartOfSpeechType, example_arrive_word_explanation};
+
+    #[test]
+    fn schema_describes_the_fields_of_providers() {
+        let schema = word_explanation_schema();
+        let properties = schema["properties"].as_object().unwrap();
+        assert!(properties.contains_key("part_of_speeches"));
+        assert!(properties.contains_key("synonyms"));
+        assert!(!properties.contains_key("raw_definition"));
+    }
+
+    #[test]
+    fn valid_output_parses_unchanged() {
+        let example = serde_json::to_string_pretty(&example_arrive_word_explanation()).unwrap();
+        let parsed = parse_word_explanation(&format!("```json\n{}\n```", example)).unwrap();
+        assert_eq!(serde_json::to_string_pretty(&parsed).unwrap(), example);
+    }
+
+    #[test]
+    fn near_misses_are_repaired() {
+        let reply = r#"{
+            "word": "arrive",
+            "phonetics": ["/əˈraɪv/",],
+            "part_of_speeches": [{
+                "part_of_speech": "intransitive verb",
+                "explanations": [{
+                    "explanation": "到达",
+                    "definition": "to get to a place",
+                    "example": ["The train arrived."],
+                }],
+                "idioms": [{
+                    "idiom": "sb has arrived",
+                    "explanation": "某人成功了",
+                    "definition": "somebody has become successful",
+                    "examples": [{"example": "He has arrived.", "translation": "他成功了。"}]
+                }]
+            }],
+            "cefr_level": "a2",
+            "synonyms": "reach, get to"
+        }"#;
+        let word_explanation = parse_word_explanation(reply).unwrap();
+        let part_of_speech = &word_explanation.part_of_speeches.unwrap()[0];
+        assert!(matches!(
+            part_of_speech.part_of_speech,
+            PartOfSpeechType::Other
+        ));
+        let examples = part_of_speech.explanations[0].examples.as_ref().unwrap();
+        assert_eq!(examples[0].example, "The train arrived.");
+        let idioms = part_of_speech.idioms.as_ref().unwrap();
+        assert_eq!(idioms[0].example.as_ref().unwrap().len(), 1);
+        assert_eq!(word_explanation.synonyms.unwrap(), ["reach", "get to"]);
+    }
+
+    #[test]
+    fn truncated_output_is_closed() {
+        let reply = r#"{
+            "word": "arrive",
+            "phonetics": ["/əˈraɪv/"],
+            "part_of_speeches": [{
+                "part_of_speech": "verb",
+                "explanations": [{
+                    "explanation": "到达",
+                    "definition": "to get to a place",
+                    "examples": [
+                        {"example": "The train will arrive on time.", "translation": "火车将准时到达。"},
+                        {"example": "By the time I arri"#;
+        let word_explanation = parse_word_explanation(reply).unwrap();
+        let part_of_speech = &word_explanation.part_of_speeches.unwrap()[0];
+        let examples = part_of_speech.explanations[0].examples.as_ref().unwrap();
+        assert_eq!(examples.len(), 1);
+    }
+
+    #[test]
+    fn other_text_fails() {
+        assert!(parse_word_explanation("Sorry, I cannot help with that.").is_err());
+    }
+}
@@ -0,0 +1,611 @@
+//! Translation of texts too long for one request. The text is split into chunks under
+//! a token budget at paragraph or sentence boundaries, each chunk is translated with
+//! the text before it as context, and the translations are put back together in the
+//! order of the source.
+
+use std::{
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, Sender},
+    },
+    thread,
+};
+
+use crate::{Language, StreamSentenceTranslator, TokenUsage, storage};
+
+/// Punctuation ending a sentence without a space after it, as in Chinese and Japanese.
+const FULL_WIDTH_TERMINATORS: [char; 5] = ['。', '！', '？', '．', '｡'];
+/// Punctuation ending a sentence when followed by whitespace.
+const TERMINATORS: [char; 4] = ['.', '!', '?', '…'];
+/// Quotes and brackets closing a sentence, kept with it.
+const CLOSERS: [char; 12] = [
+    '"', '\'', ')', ']', '”', '’', '」', '』', '）', '】', '》', '»',
+];
+
+/// A part of the source translated in one request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    /// Source text, with the whitespace after it.
+    pub text: String,
+    /// Source text just before the chunk, given to the engine as context.
+    pub context: String,
+}
+impl Chunk {
+    /// Whitespace after the text, kept after the translation.
+    pub fn trailing_whitespace(&self) -> &str {
+        &self.text[self.text.trim_end().len()..]
+    }
+}
+
+/// Progress of the translation of a document.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DocumentEvent {
+    /// The translation so far, in the order of the source. A chunk is only included
+    /// once all chunks before it are finished.
+    Progress {
+        text: String,
+        finished_chunks: usize,
+        total_chunks: usize,
+    },
+    /// A chunk is finished. The source of a failed chunk is kept in the translation.
+    ChunkFinished {
+        chunk: usize,
+        token_usage: Option<TokenUsage>,
+        error: Option<String>,
+    },
+}
+
+/// A rough count of tokens: one per CJK character, one per four other characters.
+pub fn estimate_tokens(text: &str) -> usize {
+    let (wide, narrow): (usize, usize) = text.chars().filter(|c| !c.is_whitespace()).fold(
+        (0, 0),
+        |(wide, narrow), c| match is_wide(c) {
+            true => (wide + 1, narrow),
+            false => (wide, narrow + 1),
+        },
+    );
+    wide + narrow.div_ceil(4)
+}
+
+/// Paragraphs separated by blank lines, each with the whitespace after it, so that
+/// joining them gives back `text`.
+pub fn split_paragraphs(text: &str) -> Vec<&str> {
+    let mut paragraphs = Vec::new();
+    let mut start = 0;
+    let mut search = 0;
+    while let Some(position) = text[search..].find('\n') {
+        let line_end = search + position + 1;
+        let after = &text[line_end..];
+        if after
+            .trim_start_matches([' ', '\t', '\r'])
+            .starts_with('\n')
+        {
+            // The paragraph ends after the blank lines.
+            let end = text.len() - after.trim_start().len();
+            paragraphs.push(&text[start..end]);
+            start = end;
+            search = end;
+        } else {
+            search = line_end;
+        }
+    }
+    if start < text.len() {
+        paragraphs.push(&text[start..]);
+    }
+    paragraphs
+}
+
+/// Sentences of `text`, each with the whitespace after it, so that joining them gives
+/// back `text`. Line breaks end sentences, and so do full-width stops without spaces.
+pub fn split_sentences(text: &str, language: Language) -> Vec<&str> {
+    let chars = text.char_indices().collect::<Vec<_>>();
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut index = 0;
+    while index < chars.len() {
+        let (stop, c) = chars[index];
+        index += 1;
+        let full_width = FULL_WIDTH_TERMINATORS.contains(&c);
+        if !full_width && !TERMINATORS.contains(&c) && c != '\n' {
+            continue;
+        }
+        // Repeated stops, as in `?!` or `……`, and closing quotes belong to the sentence.
+        while index < chars.len()
+            && (FULL_WIDTH_TERMINATORS.contains(&chars[index].1)
+                || TERMINATORS.contains(&chars[index].1)
+                || CLOSERS.contains(&chars[index].1))
+        {
+            index += 1;
+        }
+        let end_of_text = index == chars.len();
+        let followed_by_space = !end_of_text && chars[index].1.is_whitespace();
+        if c != '\n' && !full_width && !end_of_text && !followed_by_space {
+            continue;
+        }
+        if c == '.' && !ends_sentence(&text[start..stop], &text[stop..], language) {
+            continue;
+        }
+        while index < chars.len() && chars[index].1.is_whitespace() {
+            index += 1;
+        }
+        let end = chars.get(index).map_or(text.len(), |(offset, _)| *offset);
+        sentences.push(&text[start..end]);
+        start = end;
+    }
+    if start < text.len() {
+        sentences.push(&text[start..]);
+    }
+    sentences
+}
+
+/// Whether a full stop after `before` ends a sentence rather than an abbreviation or
+/// an initial. `after` starts with the stop.
+fn ends_sentence(before: &str, after: &str, language: Language) -> bool {
+    let word = before
+        .rsplit(|c: char| c.is_whitespace() || c == '(')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    let abbreviations: &[&str] = match language {
+        Language::English => &[
+            "mr", "mrs", "ms", "dr", "prof", "st", "vs", "etc", "e.g", "i.e", "no", "fig", "jr",
+            "sr", "inc", "ltd", "co", "approx", "dept",
+        ],
+        Language::German => &["z.b", "usw", "bzw", "nr", "dr", "prof", "str", "ca", "d.h"],
+        Language::French => &["m", "mme", "mlle", "dr", "etc", "p.ex", "cf", "env"],
+        Language::Spanish => &["sr", "sra", "srta", "dr", "dra", "etc", "pág", "ej"],
+        Language::Russian => &["г", "гг", "т.е", "т.д", "т.п", "др", "см", "стр", "им"],
+        _ => &[],
+    };
+    if abbreviations.contains(&word.as_str()) {
+        return false;
+    }
+    // An initial, as in `J. R. R. Tolkien`.
+    if word.chars().count() == 1 && word.chars().all(char::is_alphabetic) {
+        return false;
+    }
+    // A sentence does not go on in lower case.
+    !after
+        .trim_start_matches(|c: char| TERMINATORS.contains(&c) || CLOSERS.contains(&c))
+        .trim_start()
+        .starts_with(char::is_lowercase)
+}
+
+/// Split `text` into chunks of about `config.chunk_tokens` at most. Paragraphs are
+/// kept whole where they fit, longer ones are split into sentences, and sentences
+/// longer than a chunk are cut.
+pub fn chunks(text: &str, language: Language, config: &storage::Documents) -> Vec<Chunk> {
+    let budget = config.chunk_tokens.max(1);
+    let mut pieces = Vec::new();
+    for paragraph in split_paragraphs(text) {
+        if estimate_tokens(paragraph) <= budget {
+            pieces.push(paragraph);
+            continue;
+        }
+        for sentence in split_sentences(paragraph, language) {
+            if estimate_tokens(sentence) <= budget {
+                pieces.push(sentence);
+            } else {
+                pieces.extend(cut(sentence, budget));
+            }
+        }
+    }
+
+    let mut chunks = Vec::new();
+    let (mut start, mut end) = (0, 0);
+    for piece in pieces {
+        if end > start && estimate_tokens(&text[start..end + piece.len()]) > budget {
+            chunks.push(chunk(text, start, end, language, config.context_tokens));
+            start = end;
+        }
+        end += piece.len();
+    }
+    if end > start && !text[start..end].trim().is_empty() {
+        chunks.push(chunk(text, start, end, language, config.context_tokens));
+    }
+    chunks
+}
+
+fn chunk(text: &str, start: usize, end: usize, language: Language, context_tokens: usize) -> Chunk {
+    // The last sentences before the chunk which fit in the context.
+    let before = split_sentences(&text[..start], language);
+    let mut context_start = start;
+    for sentence in before.iter().rev() {
+        if estimate_tokens(&text[context_start - sentence.len()..start]) > context_tokens {
+            break;
+        }
+        context_start -= sentence.len();
+    }
+    Chunk {
+        text: text[start..end].to_string(),
+        context: text[context_start..start].trim().to_string(),
+    }
+}
+
+/// Pieces of `sentence` of at most `budget` tokens, cut after whitespace where possible.
+fn cut(sentence: &str, budget: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = sentence;
+    while estimate_tokens(rest) > budget {
+        let mut end = 0;
+        let mut last_space = None;
+        for (offset, c) in rest.char_indices() {
+            let next = offset + c.len_utf8();
+            if estimate_tokens(&rest[..next]) > budget {
+                break;
+            }
+            end = next;
+            if c.is_whitespace() {
+                last_space = Some(next);
+            }
+        }
+        let end = last_space.filter(|_| end < rest.len()).unwrap_or(end).max(
+            // At least one character, however small the budget.
+            rest.chars().next().map_or(0, char::len_utf8),
+        );
+        pieces.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    if !rest.is_empty() {
+        pieces.push(rest);
+    }
+    pieces
+}
+
+/// Translate `chunks` with a thread per translator, each taking the next chunk not yet
+/// translated. With one translator the chunks are translated in order. The receiver
+/// is closed once every chunk is finished.
+pub fn translate_document(
+    translators: Vec<Arc<dyn StreamSentenceTranslator + Send + Sync>>,
+    chunks: Vec<Chunk>,
+    source_language: Language,
+    target_language: Language,
+) -> Receiver<DocumentEvent> {
+    let (tx, rx) = mpsc::channel();
+    let assembly = Arc::new(Mutex::new(Assembly {
+        outputs: vec![ChunkOutput::Waiting; chunks.len()],
+        next: 0,
+    }));
+    let chunks = Arc::new(chunks);
+    for translator in translators {
+        let tx = tx.clone();
+        let assembly = assembly.clone();
+        let chunks = chunks.clone();
+        thread::spawn(move || {
+            loop {
+                let Some(index) = assembly.lock().unwrap().take_next() else {
+                    return;
+                };
+                let chunk = &chunks[index];
+                let finished = translate_chunk(
+                    translator.as_ref(),
+                    chunk,
+                    source_language,
+                    target_language,
+                    |partial| {
+                        let mut assembly = assembly.lock().unwrap();
+                        assembly.outputs[index] = ChunkOutput::Partial(partial);
+                        tx.send(assembly.progress(&chunks)).is_ok()
+                    },
+                );
+                let Some(finished) = finished else {
+                    // Nobody is waiting for the translation any more.
+                    assembly.lock().unwrap().next = chunks.len();
+                    return;
+                };
+                let error = finished.as_ref().err().map(|e| format!("{:#}", e));
+                let mut assembly = assembly.lock().unwrap();
+                assembly.outputs[index] = match finished {
+                    Ok(text) => ChunkOutput::Finished(text),
+                    Err(_) => ChunkOutput::Failed,
+                };
+                let events = [
+                    DocumentEvent::ChunkFinished {
+                        chunk: index,
+                        token_usage: translator.last_token_usage(),
+                        error,
+                    },
+                    assembly.progress(&chunks),
+                ];
+                if !send_all(&tx, events) {
+                    assembly.next = chunks.len();
+                    return;
+                }
+            }
+        });
+    }
+    rx
+}
+
+/// The translation of a chunk, `None` if `on_partial` gave up. Answers cut off or
+/// ended with an error fail the chunk.
+fn translate_chunk(
+    translator: &(dyn StreamSentenceTranslator + Send + Sync),
+    chunk: &Chunk,
+    source_language: Language,
+    target_language: Language,
+    mut on_partial: impl FnMut(String) -> bool,
+) -> Option<Result<String, anyhow::Error>> {
+    let stream = match translator.stream_translate_in_context(
+        chunk.text.trim(),
+        &chunk.context,
+        source_language,
+        target_language,
+    ) {
+        Ok(stream) => stream,
+        Err(e) => return Some(Err(e)),
+    };
+    let mut text = String::new();
+    for partial in stream.answer {
+        text.clone_from(&partial);
+        if !on_partial(partial) {
+            return None;
+        }
+    }
+    match stream.outcome.recv() {
+        Ok(Err(e)) => Some(Err(e)),
+        // Translators which cannot tell are trusted.
+        Ok(Ok(())) | Err(_) => Some(Ok(text)),
+    }
+}
+
+fn send_all(tx: &Sender<DocumentEvent>, events: impl IntoIterator<Item = DocumentEvent>) -> bool {
+    events.into_iter().all(|event| tx.send(event).is_ok())
+}
+
+#[derive(Debug, Clone)]
+enum ChunkOutput {
+    Waiting,
+    Partial(String),
+    Finished(String),
+    Failed,
+}
+
+struct Assembly {
+    outputs: Vec<ChunkOutput>,
+    /// Index of the next chunk to translate.
+    next: usize,
+}
+impl Assembly {
+    fn take_next(&mut self) -> Option<usize> {
+        let next = (self.next < self.outputs.len()).then_some(self.next);
+        self.next += 1;
+        next
+    }
+
+    fn progress(&self, chunks: &[Chunk]) -> DocumentEvent {
+        let mut text = String::new();
+        for (output, chunk) in self.outputs.iter().zip(chunks) {
+            match output {
+                ChunkOutput::Finished(translation) => {
+                    text += translation.trim_end();
+                    text += chunk.trailing_whitespace();
+                }
+                ChunkOutput::Failed => text += &chunk.text,
+                ChunkOutput::Partial(partial) => {
+                    text += partial;
+                    break;
+                }
+                ChunkOutput::Waiting => break,
+            }
+        }
+        DocumentEvent::Progress {
+            text,
+            finished_chunks: self
+                .outputs
+                .iter()
+                .filter(|output| matches!(output, ChunkOutput::Finished(_) | ChunkOutput::Failed))
+                .count(),
+            total_chunks: self.outputs.len(),
+        }
+    }
+}
+
+fn is_wide(c: char) -> bool {
+    c >= '\u{2e80}'
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Error, anyhow};
+
+    use super::*;
+    use crate::{SentenceStream, Translator};
+
+    fn config(chunk_tokens: usize, context_tokens: usize) -> storage::Documents {
+        storage::Documents {
+            chunk_tokens,
+            context_tokens,
+            parallel_requests: 1,
+        }
+    }
+
+    #[test]
+    fn sentences_keep_their_whitespace() {
+        let text = "Dr. Smith arrived at 5 p.m. today. He said “Hello!” Then he left…\nNew line";
+        assert_eq!(
+            split_sentences(text, Language::English),
+            [
+                "Dr. Smith arrived at 5 p.m. today. ",
+                "He said “Hello!” ",
+                "Then he left…\n",
+                "New line",
+            ]
+        );
+        assert_eq!(
+            split_sentences("他来了。你呢？「好！」我走了", Language::Chinese),
+            ["他来了。", "你呢？", "「好！」", "我走了"]
+        );
+        assert_eq!(
+            split_sentences("J. R. R. Tolkien wrote it. Done.", Language::English),
+            ["J. R. R. Tolkien wrote it. ", "Done."]
+        );
+    }
+
+    #[test]
+    fn paragraphs_are_split_at_blank_lines() {
+        let text = "First line\nsecond line\n\n  \nSecond paragraph\n\nThird";
+        let paragraphs = split_paragraphs(text);
+        assert_eq!(
+            paragraphs,
+            [
+                "First line\nsecond line\n\n  \n",
+                "Second paragraph\n\n",
+                "Third"
+            ]
+        );
+        assert_eq!(paragraphs.concat(), text);
+        assert_eq!(split_paragraphs(""), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn chunks_fit_the_budget_and_cover_the_text() {
+        let paragraph = "One two three four. Five six seven eight. Nine ten eleven twelve.";
+        let text = format!("{}\n\n{}\n\nShort.", paragraph, paragraph);
+        let chunks = chunks(&text, Language::English, &config(12, 6));
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| chunk.text.as_str())
+                .collect::<String>(),
+            text
+        );
+        assert!(chunks.len() > 2);
+        for chunk in &chunks {
+            assert!(estimate_tokens(&chunk.text) <= 12, "{:?}", chunk);
+        }
+        assert_eq!(chunks[0].context, "");
+        assert_eq!(chunks[1].context, "Five six seven eight.");
+
+        let long_word = "字".repeat(25);
+        let cut = super::chunks(&long_word, Language::Chinese, &config(10, 0));
+        assert_eq!(cut.len(), 3);
+        assert_eq!(estimate_tokens("你好 world"), 4);
+    }
+
+    /// Upper-cases the chunk in two steps, failing on chunks containing `fail`.
+    struct Shouting;
+    impl Translator for Shouting {
+        fn get_api_key(&self) -> String {
+            String::new()
+        }
+        fn save_api_key(&self) -> Result<(), Error> {
+            Ok(())
+        }
+        fn get_url(&self) -> String {
+            String::new()
+        }
+        fn last_token_usage(&self) -> Option<TokenUsage> {
+            Some(TokenUsage {
+                input_tokens: 1,
+                ..Default::default()
+            })
+        }
+    }
+    impl StreamSentenceTranslator for Shouting {
+        fn stream_translate_sentence(
+            &self,
+            sentence: &str,
+            _source_language: Language,
+            _target_language: Language,
+        ) -> Result<Receiver<String>, Error> {
+            if sentence.contains("fail") {
+                return Err(anyhow!("Failed"));
+            }
+            let (tx, rx) = mpsc::channel();
+            let half = sentence.len() / 2;
+            tx.send(sentence[..half].to_uppercase())?;
+            tx.send(sentence.to_uppercase())?;
+            Ok(rx)
+        }
+
+        fn stream_translate_in_context(
+            &self,
+            sentence: &str,
+            _context: &str,
+            source_language: Language,
+            target_language: Language,
+        ) -> Result<SentenceStream, Error> {
+            let answer =
+                self.stream_translate_sentence(sentence, source_language, target_language)?;
+            let (_, reasoning) = mpsc::channel();
+            let (outcome_tx, outcome) = mpsc::channel();
+            match sentence.contains("cut") {
+                true => outcome_tx.send(Err(anyhow!("Cut off")))?,
+                false => outcome_tx.send(Ok(()))?,
+            }
+            Ok(SentenceStream {
+                answer,
+                reasoning,
+                outcome,
+            })
+        }
+    }
+
+    #[test]
+    fn translations_are_reassembled_in_order() {
+        let text = "Alpha beta.\n\nGamma fail.\n\nDelta epsilon.";
+        let chunks = chunks(text, Language::English, &config(4, 0));
+        assert_eq!(chunks.len(), 3);
+        for translators in [1, 3] {
+            let events = translate_document(
+                (0..translators)
+                    .map(|_| Arc::new(Shouting) as Arc<dyn StreamSentenceTranslator + Send + Sync>)
+                    .collect(),
+                chunks.clone(),
+                Language::English,
+                Language::Chinese,
+            )
+            .into_iter()
+            .collect::<Vec<_>>();
+            let finished = events
+                .iter()
+                .filter_map(|event| match event {
+                    DocumentEvent::ChunkFinished { chunk, error, .. } => Some((chunk, error)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(finished.len(), 3);
+            assert!(
+                finished
+                    .iter()
+                    .any(|(chunk, error)| **chunk == 1 && error.is_some())
+            );
+            assert_eq!(
+                events.last(),
+                Some(&DocumentEvent::Progress {
+                    text: "ALPHA BETA.\n\nGamma fail.\n\nDELTA EPSILON.".to_string(),
+                    finished_chunks: 3,
+                    total_chunks: 3,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn chunks_cut_off_are_left_untranslated() {
+        let text = "Alpha beta.\n\nGamma cut.\n\nDelta epsilon.";
+        let chunks = chunks(text, Language::English, &config(4, 0));
+        assert_eq!(chunks.len(), 3);
+        let events = translate_document(
+            vec![Arc::new(Shouting)],
+            chunks,
+            Language::English,
+            Language::Chinese,
+        )
+        .into_iter()
+        .collect::<Vec<_>>();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            DocumentEvent::ChunkFinished { chunk: 1, error: Some(error), .. } if error == "Cut off"
+        )));
+        assert_eq!(
+            events.last(),
+            Some(&DocumentEvent::Progress {
+                text: "ALPHA BETA.\n\nGamma cut.\n\nDELTA EPSILON.".to_string(),
+                finished_chunks: 3,
+                total_chunks: 3,
+            })
+        );
+    }
+}
@@ -61,3 +61,18 @@ impl std::fmt::Display for Error {
     }
 }
 impl std::error::Error for Error {}
+
+/// Response of an AI provider with an unsuccessful status, kept whole so that failures
+/// can be told apart, see [`crate::failover::classify`].
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: reqwest::StatusCode,
+    /// Body of the response, which tells e.g. which quota is used up.
+    pub body: String,
+}
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "API request failed: {}", self.status)
+    }
+}
+impl std::error::Error for ApiError {}
@@ -0,0 +1,616 @@
+//! Export looked-up words as flashcards, to Anki packages or delimited text.
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Error, Result, anyhow};
+use rusqlite::{Connection, params};
+use serde_json::json;
+use sha1::{Digest, Sha1};
+
+use crate::{
+    dict_interface::{Example, WordExplanation},
+    notebook::Card,
+};
+
+/// Note type id, fixed so that repeated imports into Anki share the note type.
+const ANKI_MODEL_ID: i64 = 1_718_000_000_000;
+const ANKI_DECK_ID: i64 = 1_718_000_000_001;
+const ANKI_MODEL_NAME: &str = "Neat Translator Word";
+
+const ANKI_FIELDS: [&str; 6] = [
+    "Word",
+    "Phonetics",
+    "PartOfSpeech",
+    "Definition",
+    "Explanation",
+    "Examples",
+];
+
+const ANKI_CSS: &str = ".card { font-family: arial; font-size: 20px; text-align: center; \
+                        color: black; background-color: white; }\n\
+                        .phonetics, .pos { color: #888; font-size: 16px; }\n\
+                        .examples { font-size: 16px; text-align: left; }";
+
+/// Schema of the legacy collection (version 11), which every Anki version imports.
+const ANKI_SCHEMA: &str = r#"
+CREATE TABLE col (
+    id integer primary key, crt integer not null, mod integer not null, scm integer not null,
+    ver integer not null, dty integer not null, usn integer not null, ls integer not null,
+    conf text not null, models text not null, decks text not null, dconf text not null,
+    tags text not null
+);
+CREATE TABLE notes (
+    id integer primary key, guid text not null, mid integer not null, mod integer not null,
+    usn integer not null, tags text not null, flds text not null, sfld integer not null,
+    csum integer not null, flags integer not null, data text not null
+);
+CREATE TABLE cards (
+    id integer primary key, nid integer not null, did integer not null, ord integer not null,
+    mod integer not null, usn integer not null, type integer not null, queue integer not null,
+    due integer not null, ivl integer not null, factor integer not null, reps integer not null,
+    lapses integer not null, left integer not null, odue integer not null, odid integer not null,
+    flags integer not null, data text not null
+);
+CREATE TABLE revlog (
+    id integer primary key, cid integer not null, usn integer not null, ease integer not null,
+    ivl integer not null, lastIvl integer not null, factor integer not null, time integer not null,
+    type integer not null
+);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+CREATE INDEX ix_notes_usn ON notes (usn);
+CREATE INDEX ix_cards_usn ON cards (usn);
+CREATE INDEX ix_revlog_usn ON revlog (usn);
+CREATE INDEX ix_cards_nid ON cards (nid);
+CREATE INDEX ix_cards_sched ON cards (did, queue, due);
+CREATE INDEX ix_revlog_cid ON revlog (cid);
+CREATE INDEX ix_notes_csum ON notes (csum);
+"#;
+
+/// One sense of a word, the unit of every export.
+#[derive(Debug, Clone)]
+pub struct Flashcard {
+    pub word: String,
+    pub phonetics: String,
+    pub part_of_speech: String,
+    pub definition: String,
+    pub explanation: String,
+    pub examples: Vec<Example>,
+}
+impl Flashcard {
+    /// One flashcard for each sense of `word_explanation`.
+    pub fn from_word_explanation(word_explanation: &WordExplanation) -> Vec<Self> {
+        let word_phonetics = word_explanation
+            .phonetics
+            .as_deref()
+            .unwrap_or_default()
+            .join(", ");
+        let mut flashcards = Vec::new();
+        for part_of_speech in word_explanation.part_of_speeches.iter().flatten() {
+            for explanation in &part_of_speech.explanations {
+                flashcards.push(Self {
+                    word: word_explanation.word.clone(),
+                    phonetics: explanation
+                        .phonetics
+                        .as_ref()
+                        .map(|phonetics| phonetics.join(", "))
+                        .unwrap_or_else(|| word_phonetics.clone()),
+                    part_of_speech: part_of_speech.part_of_speech.to_string(),
+                    definition: explanation.definition.clone(),
+                    explanation: explanation.explanation.clone(),
+                    examples: explanation.examples.clone().unwrap_or_default(),
+                });
+            }
+        }
+        flashcards
+    }
+}
+impl From<&Card> for Flashcard {
+    fn from(card: &Card) -> Self {
+        Self {
+            word: card.word.clone(),
+            phonetics: card
+                .sense
+                .phonetics
+                .as_ref()
+                .or(card.phonetics.as_ref())
+                .map(|phonetics| phonetics.join(", "))
+                .unwrap_or_default(),
+            part_of_speech: card.part_of_speech.to_string(),
+            definition: card.sense.definition.clone(),
+            explanation: card.sense.explanation.clone(),
+            examples: card.sense.examples.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// Layout of a delimited text export.
+#[derive(strum::Display, strum::EnumString, Debug, Clone, Copy, PartialEq, Eq)]
+#[strum(ascii_case_insensitive)]
+pub enum TableFormat {
+    /// Comma-separated, with a header row, for spreadsheets.
+    Csv,
+    /// Tab-separated, with a header row, for spreadsheets.
+    Tsv,
+    /// Tab-separated term and definition without header, as Quizlet imports it.
+    Quizlet,
+}
+impl TableFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Tsv | Self::Quizlet => "tsv",
+        }
+    }
+}
+
+/// `flashcards` with one of each note, the first of those exported as the same note.
+/// Flashcards listed newest first keep the latest explanation of a sense.
+pub fn unique_notes(flashcards: Vec<Flashcard>) -> Vec<Flashcard> {
+    let mut guids = HashSet::new();
+    flashcards
+        .into_iter()
+        .filter(|flashcard| guids.insert(note_guid(flashcard)))
+        .collect()
+}
+
+/// Write `flashcards` as delimited text.
+pub fn write_table<W: Write>(
+    flashcards: &[Flashcard],
+    format: TableFormat,
+    writer: W,
+) -> Result<(), Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(match format {
+            TableFormat::Csv => b',',
+            TableFormat::Tsv | TableFormat::Quizlet => b'\t',
+        })
+        .from_writer(writer);
+    match format {
+        TableFormat::Csv | TableFormat::Tsv => {
+            writer.write_record(ANKI_FIELDS)?;
+            for flashcard in flashcards {
+                writer.write_record([
+                    flashcard.word.as_str(),
+                    &flashcard.phonetics,
+                    &flashcard.part_of_speech,
+                    &flashcard.definition,
+                    &flashcard.explanation,
+                    &flashcard
+                        .examples
+                        .iter()
+                        .map(|example| format!("{} {}", example.example, example.translation))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                ])?;
+            }
+        }
+        TableFormat::Quizlet => {
+            for flashcard in flashcards {
+                writer.write_record([
+                    flashcard.word.as_str(),
+                    &format!(
+                        "{} {}; {}",
+                        flashcard.part_of_speech, flashcard.explanation, flashcard.definition
+                    ),
+                ])?;
+            }
+        }
+    }
+    writer
+        .flush()
+        .map_err(|e| anyhow!("Failed to write table: {}", e))
+}
+
+/// Write `flashcards` into an Anki package at `path`, in a deck named `deck_name`.
+///
+/// Each flashcard becomes a note with a word → meaning and a definition → word card.
+pub fn write_apkg(flashcards: &[Flashcard], deck_name: &str, path: &Path) -> Result<(), Error> {
+    // The collection is built in a file next to the package, as SQLite needs a file.
+    let collection_path = path.with_extension("anki2.tmp");
+    let _ = std::fs::remove_file(&collection_path);
+    let result = write_anki_collection(flashcards, deck_name, &collection_path)
+        .and_then(|()| zip_apkg(&collection_path, path));
+    let _ = std::fs::remove_file(&collection_path);
+    result.with_context(|| anyhow!("Failed to export Anki package to {}", path.display()))
+}
+
+fn write_anki_collection(
+    flashcards: &[Flashcard],
+    deck_name: &str,
+    collection_path: &Path,
+) -> Result<(), Error> {
+    let now_millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or_default();
+    let now = now_millis / 1000;
+
+    let mut connection = Connection::open(collection_path)?;
+    connection.execute_batch(ANKI_SCHEMA)?;
+    let transaction = connection.transaction()?;
+    transaction.execute(
+        "INSERT INTO col VALUES (1, ?1, ?2, ?3, 11, 0, 0, 0, ?4, ?5, ?6, ?7, '{}')",
+        params![
+            now,
+            now_millis,
+            now_millis,
+            anki_conf().to_string(),
+            anki_models(now).to_string(),
+            anki_decks(deck_name, now).to_string(),
+            anki_deck_conf(now).to_string(),
+        ],
+    )?;
+
+    for (index, flashcard) in flashcards.iter().enumerate() {
+        // Ids are creation times in milliseconds, which must be unique.
+        let note_id = now_millis + index as i64;
+        let fields = [
+            html_escape(&flashcard.word),
+            html_escape(&flashcard.phonetics),
+            html_escape(&flashcard.part_of_speech),
+            html_escape(&flashcard.definition),
+            html_escape(&flashcard.explanation),
+            flashcard
+                .examples
+                .iter()
+                .map(|example| {
+                    format!(
+                        "{}<br>{}",
+                        html_escape(&example.example),
+                        html_escape(&example.translation)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("<br><br>"),
+        ];
+        transaction.execute(
+            "INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, '', ?5, ?6, ?7, 0, '')",
+            params![
+                note_id,
+                note_guid(flashcard),
+                ANKI_MODEL_ID,
+                now,
+                fields.join("\x1f"),
+                fields[0],
+                field_checksum(&fields[0]),
+            ],
+        )?;
+        for ord in 0..2 {
+            transaction.execute(
+                "INSERT INTO cards VALUES (?1, ?2, ?3, ?4, ?5, -1, 0, 0, ?6, 0, 0, 0, 0, 0, 0, 0, 0, '')",
+                params![
+                    note_id * 2 + ord,
+                    note_id,
+                    ANKI_DECK_ID,
+                    ord,
+                    now,
+                    index as i64 + 1
+                ],
+            )?;
+        }
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+fn zip_apkg(collection_path: &Path, path: &Path) -> Result<(), Error> {
+    let mut collection = Vec::new();
+    File::open(collection_path)?.read_to_end(&mut collection)?;
+
+    let mut zip = zip::ZipWriter::new(File::create(path)?);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    zip.start_file("collection.anki2", options)?;
+    zip.write_all(&collection)?;
+    // No media files are exported.
+    zip.start_file("media", options)?;
+    zip.write_all(b"{}")?;
+    zip.finish()?;
+    Ok(())
+}
+
+fn anki_conf() -> serde_json::Value {
+    json!({
+        "activeDecks": [1],
+        "curDeck": 1,
+        "newSpread": 0,
+        "collapseTime": 1200,
+        "timeLim": 0,
+        "estTimes": true,
+        "dueCounts": true,
+        "curModel": null,
+        "nextPos": 1,
+        "sortType": "noteFld",
+        "sortBackwards": false,
+        "addToCur": true
+    })
+}
+
+fn anki_models(now: i64) -> serde_json::Value {
+    let front_word = "{{Word}}<div class=\"phonetics\">{{Phonetics}}</div>\
+                      <div class=\"pos\">{{PartOfSpeech}}</div>";
+    let back_meaning = "{{Explanation}}<br>{{Definition}}\
+                        <div class=\"examples\">{{Examples}}</div>";
+    json!({
+        ANKI_MODEL_ID.to_string(): {
+            "id": ANKI_MODEL_ID,
+            "name": ANKI_MODEL_NAME,
+            "type": 0,
+            "mod": now,
+            "usn": -1,
+            "sortf": 0,
+            "did": ANKI_DECK_ID,
+            "tmpls": [
+                {
+                    "name": "Word → Meaning",
+                    "ord": 0,
+                    "qfmt": front_word,
+                    "afmt": format!("{{{{FrontSide}}}}<hr id=answer>{}", back_meaning),
+                    "did": null,
+                    "bqfmt": "",
+                    "bafmt": ""
+                },
+                {
+                    "name": "Definition → Word",
+                    "ord": 1,
+                    "qfmt": "{{Definition}}<div class=\"pos\">{{PartOfSpeech}}</div>",
+                    "afmt": format!("{{{{FrontSide}}}}<hr id=answer>{}<br>{{{{Explanation}}}}", front_word),
+                    "did": null,
+                    "bqfmt": "",
+                    "bafmt": ""
+                }
+            ],
+            "flds": ANKI_FIELDS.iter().enumerate().map(|(ord, name)| json!({
+                "name": name,
+                "ord": ord,
+                "sticky": false,
+                "rtl": false,
+                "font": "Arial",
+                "size": 20,
+                "media": []
+            })).collect::<Vec<_>>(),
+            "css": ANKI_CSS,
+            "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\begin{document}\n",
+            "latexPost": "\\end{document}",
+            "tags": [],
+            "vers": [],
+            // Each card needs its question field: Word and Definition.
+            "req": [[0, "all", [0]], [1, "all", [3]]]
+        }
+    })
+}
+
+fn anki_decks(deck_name: &str, now: i64) -> serde_json::Value {
+    let deck = |id: i64, name: &str| {
+        json!({
+            "id": id,
+            "name": name,
+            "mod": now,
+            "usn": -1,
+            "lrnToday": [0, 0],
+            "revToday": [0, 0],
+            "newToday": [0, 0],
+            "timeToday": [0, 0],
+            "collapsed": false,
+            "desc": "",
+            "dyn": 0,
+            "conf": 1,
+            "extendNew": 10,
+            "extendRev": 50
+        })
+    };
+    json!({
+        "1": deck(1, "Default"),
+        ANKI_DECK_ID.to_string(): deck(ANKI_DECK_ID, deck_name)
+    })
+}
+
+fn anki_deck_conf(now: i64) -> serde_json::Value {
+    json!({
+        "1": {
+            "id": 1,
+            "name": "Default",
+            "mod": now,
+            "usn": -1,
+            "maxTaken": 60,
+            "autoplay": true,
+            "timer": 0,
+            "replayq": true,
+            "dyn": false,
+            "new": {
+                "delays": [1, 10],
+                "ints": [1, 4, 7],
+                "initialFactor": 2500,
+                "order": 1,
+                "perDay": 20,
+                "bury": true,
+                "separate": true
+            },
+            "rev": {
+                "perDay": 200,
+                "ease4": 1.3,
+                "fuzz": 0.05,
+                "maxIvl": 36500,
+                "ivlFct": 1,
+                "bury": true,
+                "minSpace": 1
+            },
+            "lapse": {
+                "delays": [10],
+                "mult": 0,
+                "minInt": 1,
+                "leechFails": 8,
+                "leechAction": 0
+            }
+        }
+    })
+}
+
+/// Derived from the word and its definition, so that exporting the same sense again
+/// updates the note instead of duplicating it.
+fn note_guid(flashcard: &Flashcard) -> String {
+    let digest = Sha1::digest(
+        format!(
+            "{}\x1f{}\x1f{}",
+            flashcard.word, flashcard.part_of_speech, flashcard.definition
+        )
+        .as_bytes(),
+    );
+    digest[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// First 8 hex digits of the SHA-1 of the sort field, which Anki uses to find duplicates.
+fn field_checksum(field: &str) -> i64 {
+    let digest = Sha1::digest(field.as_bytes());
+    i64::from(u32::from_be_bytes([
+        digest[0], digest[1], digest[2], digest[3],
+    ]))
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Default file to export to, in the documents directory if there is one.
+pub fn default_export_path(file_stem: &str, extension: &str) -> PathBuf {
+    dirs::document_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_default()
+        .join(format!("{}.{}", file_stem, extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dict_interface::example_arrive_word_explanation;
+
+    #[test]
+    fn flashcards_have_one_sense_each() {
+        let flashcards = Flashcard::from_word_explanation(&example_arrive_word_explanation());
+        assert_eq!(flashcards.len(), 2);
+        assert_eq!(flashcards[1].phonetics, "/əˈraɪv/");
+        assert_eq!(flashcards[1].part_of_speech, "verb");
+        assert_eq!(flashcards[1].explanation, "（东西）送达；寄到");
+        assert_eq!(flashcards[1].examples.len(), 2);
+    }
+
+    #[test]
+    fn senses_looked_up_again_are_exported_once() {
+        let newer = Flashcard::from_word_explanation(&example_arrive_word_explanation());
+        let mut older = newer.clone();
+        older[0].explanation = "到达".to_string();
+        older[1].definition = "to be delivered".to_string();
+        let flashcards = unique_notes(newer.iter().cloned().chain(older).collect());
+        assert_eq!(flashcards.len(), 3);
+        assert_eq!(flashcards[0].explanation, newer[0].explanation);
+        assert_eq!(flashcards[2].definition, "to be delivered");
+    }
+
+    #[test]
+    fn apkg_contains_notes_and_cards() {
+        let flashcards = Flashcard::from_word_explanation(&example_arrive_word_explanation());
+        let dir = tempfile::tempdir().unwrap();
+        let apkg_path = dir.path().join("words.apkg");
+        write_apkg(&flashcards, "Vocabulary", &apkg_path).unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&apkg_path).unwrap()).unwrap();
+        let mut media = String::new();
+        archive
+            .by_name("media")
+            .unwrap()
+            .read_to_string(&mut media)
+            .unwrap();
+        assert_eq!(media, "{}");
+        let collection_path = dir.path().join("collection.anki2");
+        std::io::copy(
+            &mut archive.by_name("collection.anki2").unwrap(),
+            &mut File::create(&collection_path).unwrap(),
+        )
+        .unwrap();
+
+        let connection = Connection::open(&collection_path).unwrap();
+        let mut statement = connection
+            .prepare("SELECT flds, sfld, csum, mid FROM notes ORDER BY id")
+            .unwrap();
+        let notes: Vec<(String, String, i64, i64)> = statement
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(notes.len(), 2);
+        let fields: Vec<&str> = notes[0].0.split('\x1f').collect();
+        assert_eq!(fields.len(), ANKI_FIELDS.len());
+        assert_eq!(fields[0], "arrive");
+        assert_eq!(fields[2], "verb");
+        assert_eq!(
+            fields[3],
+            "to get to a place, especially at the end of a journey"
+        );
+        assert_eq!(fields[4], "到达，抵达");
+        assert!(fields[5].starts_with("The train will arrive on time.<br>火车将准时到达。"));
+        assert_eq!(notes[0].1, "arrive");
+        assert_eq!(notes[0].2, field_checksum("arrive"));
+        assert_eq!(notes[0].3, ANKI_MODEL_ID);
+
+        let cards: i64 = connection
+            .query_row(
+                "SELECT COUNT(*) FROM cards WHERE did = ?1",
+                [ANKI_DECK_ID],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(cards, 4);
+
+        let (models, decks): (String, String) = connection
+            .query_row("SELECT models, decks FROM col", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        let models: serde_json::Value = serde_json::from_str(&models).unwrap();
+        let model = &models[ANKI_MODEL_ID.to_string()];
+        assert_eq!(model["flds"].as_array().unwrap().len(), ANKI_FIELDS.len());
+        assert_eq!(model["tmpls"].as_array().unwrap().len(), 2);
+        let decks: serde_json::Value = serde_json::from_str(&decks).unwrap();
+        assert_eq!(decks[ANKI_DECK_ID.to_string()]["name"], "Vocabulary");
+    }
+
+    #[test]
+    fn tables_quote_fields() {
+        let flashcards = Flashcard::from_word_explanation(&example_arrive_word_explanation());
+
+        let mut csv = Vec::new();
+        write_table(&flashcards, TableFormat::Csv, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "Word,Phonetics,PartOfSpeech,Definition,Explanation,Examples"
+        );
+        assert!(
+            lines
+                .next()
+                .unwrap()
+                .starts_with("arrive,/əˈraɪv/,verb,\"to get to a place, especially")
+        );
+
+        let mut quizlet = Vec::new();
+        write_table(&flashcards, TableFormat::Quizlet, &mut quizlet).unwrap();
+        assert_eq!(
+            String::from_utf8(quizlet).unwrap().lines().next().unwrap(),
+            "arrive\tverb 到达，抵达; to get to a place, especially at the end of a journey"
+        );
+    }
+}
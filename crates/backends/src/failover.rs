@@ -0,0 +1,559 @@
+//! Fallback chains of engines. A request which fails for reasons of the provider rather
+//! than of the request, e.g. a server error or a used-up quota, is sent to the next
+//! engine of the chain. Failed engines are remembered in the local database, and tried
+//! after the others until their cooldown is over.
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex, mpsc::Receiver},
+    time::Duration,
+};
+
+use anyhow::{Context, Error, Result, anyhow};
+use rusqlite::{OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Language, SentenceStream, StreamSentenceTranslator, TokenUsage, Translator,
+    database::{self, Schema, Store},
+    error::ApiError,
+    storage,
+};
+
+const SCHEMA: Schema = Schema {
+    name: "engine health table",
+    sql: r#"
+CREATE TABLE IF NOT EXISTS engine_health (
+    engine TEXT PRIMARY KEY,
+    failure TEXT NOT NULL,
+    failed_at INTEGER NOT NULL
+);
+"#,
+};
+
+/// Why a request failed, for failures of the provider.
+#[derive(
+    strum::Display, strum::EnumString, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq,
+)]
+#[strum(ascii_case_insensitive)]
+pub enum FailureKind {
+    /// The provider could not be reached or did not answer in time.
+    Network,
+    /// The provider answered with a 5xx status.
+    Server,
+    /// Rate limits, used-up quotas and unpaid balances.
+    Quota,
+    /// The API key was rejected.
+    Auth,
+}
+
+/// The kind of failure of `error`, `None` for failures of the request itself.
+pub fn classify(error: &Error) -> Option<FailureKind> {
+    error.chain().find_map(|cause| {
+        if let Some(api_error) = cause.downcast_ref::<ApiError>() {
+            return classify_response(api_error);
+        }
+        cause
+            .downcast_ref::<reqwest::Error>()
+            .filter(|e| e.is_connect() || e.is_timeout() || e.is_request())
+            .map(|_| FailureKind::Network)
+    })
+}
+
+fn classify_response(error: &ApiError) -> Option<FailureKind> {
+    let body = error.body.to_lowercase();
+    match error.status.as_u16() {
+        402 | 429 => Some(FailureKind::Quota),
+        // DashScope tells used-up quotas and accounts in arrears by their error code.
+        400..=499
+            if body.contains("quota")
+                || body.contains("arrearage")
+                || body.contains("insufficient balance") =>
+        {
+            Some(FailureKind::Quota)
+        }
+        401 | 403 => Some(FailureKind::Auth),
+        500..=599 => Some(FailureKind::Server),
+        _ => None,
+    }
+}
+
+/// The last failure of each engine. The store can be shared between threads.
+pub struct EngineHealth {
+    store: Store,
+}
+impl EngineHealth {
+    /// Open the store in the local database under the config dir.
+    pub fn open_default() -> Result<Self, Error> {
+        Ok(Self {
+            store: Store::open_default(&SCHEMA)?,
+        })
+    }
+
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        Ok(Self {
+            store: Store::open(path, &SCHEMA)?,
+        })
+    }
+
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Ok(Self {
+            store: Store::open_in_memory(&SCHEMA)?,
+        })
+    }
+
+    pub fn record_failure(&self, engine: &str, failure: FailureKind) -> Result<(), Error> {
+        self.record_failure_at(engine, failure, database::now())
+    }
+
+    fn record_failure_at(
+        &self,
+        engine: &str,
+        failure: FailureKind,
+        failed_at: i64,
+    ) -> Result<(), Error> {
+        self.store
+            .connection()
+            .execute(
+                "INSERT OR REPLACE INTO engine_health (engine, failure, failed_at) \
+                 VALUES (?1, ?2, ?3)",
+                params![engine, failure.to_string(), failed_at],
+            )
+            .with_context(|| anyhow!("Failed to record the failure of {}", engine))?;
+        Ok(())
+    }
+
+    /// Forget the failure of `engine`, which answered again.
+    pub fn record_success(&self, engine: &str) -> Result<(), Error> {
+        self.store
+            .connection()
+            .execute(
+                "DELETE FROM engine_health WHERE engine = ?1",
+                params![engine],
+            )
+            .with_context(|| anyhow!("Failed to record the answer of {}", engine))?;
+        Ok(())
+    }
+
+    /// The failure of `engine` if it failed less than `cooldown` ago.
+    pub fn cooling_down(
+        &self,
+        engine: &str,
+        cooldown: Duration,
+    ) -> Result<Option<FailureKind>, Error> {
+        let failure = self
+            .store
+            .connection()
+            .query_row(
+                "SELECT failure, failed_at FROM engine_health WHERE engine = ?1",
+                params![engine],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)),
+            )
+            .optional()
+            .with_context(|| anyhow!("Failed to read the health of {}", engine))?;
+        Ok(failure
+            .filter(|(_, failed_at)| failed_at + cooldown.as_secs() as i64 > database::now())
+            .and_then(|(failure, _)| failure.parse().ok()))
+    }
+}
+
+/// When a chain moves on to its next engine, and for how long failed engines are tried
+/// last.
+#[derive(Clone)]
+pub struct Failover {
+    health: Arc<EngineHealth>,
+    fail_over_on: Vec<FailureKind>,
+    cooldown: Duration,
+}
+impl Failover {
+    pub fn new(health: Arc<EngineHealth>, config: &storage::Fallback) -> Self {
+        Self {
+            health,
+            fail_over_on: config.fail_over_on.clone(),
+            cooldown: Duration::from_secs(config.cooldown_secs),
+        }
+    }
+
+    /// Run `attempt` with the named engines in turn until one succeeds, and return the
+    /// index of that engine with its result. Engines cooling down are tried after the
+    /// others, and errors which are not failed over on are returned at once.
+    pub fn run<T, R>(
+        &self,
+        engines: &[(String, T)],
+        mut attempt: impl FnMut(&str, &T) -> Result<R, Error>,
+    ) -> Result<(usize, R), Error> {
+        let mut order = engines
+            .iter()
+            .enumerate()
+            .map(|(index, (engine, _))| (self.is_cooling_down(engine), index))
+            .collect::<Vec<_>>();
+        order.sort_by_key(|(cooling_down, _)| *cooling_down);
+
+        let mut failures = Vec::new();
+        for (_, index) in order {
+            let (engine, value) = &engines[index];
+            let error = match attempt(engine, value) {
+                Ok(result) => {
+                    if let Err(e) = self.health.record_success(engine) {
+                        log::warn!("{:#}", e);
+                    }
+                    return Ok((index, result));
+                }
+                Err(error) => error,
+            };
+            let Some(failure) = classify(&error).filter(|kind| self.fail_over_on.contains(kind))
+            else {
+                return Err(error);
+            };
+            log::warn!(
+                "{} failed ({}), trying the next engine: {:#}",
+                engine,
+                failure,
+                error
+            );
+            if let Err(e) = self.health.record_failure(engine, failure) {
+                log::warn!("{:#}", e);
+            }
+            failures.push(format!("{}: {}", engine, error));
+        }
+        match failures.is_empty() {
+            true => Err(anyhow!("There is no engine to translate with")),
+            false => Err(anyhow!("Every engine failed. {}", failures.join("; "))),
+        }
+    }
+
+    fn is_cooling_down(&self, engine: &str) -> bool {
+        self.health
+            .cooling_down(engine, self.cooldown)
+            .inspect_err(|e| log::warn!("{:#}", e))
+            .ok()
+            .flatten()
+            .is_some()
+    }
+}
+
+/// The engines of a chain which could be set up, in order. Those which could not, e.g.
+/// for a missing API key or a used-up budget, are left out as if they had failed. Fails
+/// with every reason if none could be set up.
+pub fn available<T>(
+    engines: impl IntoIterator<Item = (String, Result<T, Error>)>,
+) -> Result<Vec<(String, T)>, Error> {
+    let mut available = Vec::new();
+    let mut failures = Vec::new();
+    for (engine, value) in engines {
+        match value {
+            Ok(value) => available.push((engine, value)),
+            Err(e) => {
+                log::warn!("Leave {} out of the fallback chain: {:#}", engine, e);
+                failures.push(format!("{}: {:#}", engine, e));
+            }
+        }
+    }
+    match available.is_empty() {
+        true => Err(anyhow!("No engine can be used. {}", failures.join("; "))),
+        false => Ok(available),
+    }
+}
+
+/// Streams the translation of the first engine of the chain which answers. A failure
+/// after the answer started is not failed over.
+pub struct FallbackSentenceTranslator {
+    engines: Vec<(String, Arc<dyn StreamSentenceTranslator + Send + Sync>)>,
+    failover: Failover,
+    answered: Mutex<Option<usize>>,
+}
+impl FallbackSentenceTranslator {
+    pub fn new(
+        engines: Vec<(String, Arc<dyn StreamSentenceTranslator + Send + Sync>)>,
+        failover: Failover,
+    ) -> Self {
+        Self {
+            engines,
+            failover,
+            answered: Mutex::new(None),
+        }
+    }
+
+    /// Name of the engine which answered the last request.
+    pub fn answered_by(&self) -> Option<String> {
+        let answered = (*self.answered.lock().unwrap())?;
+        Some(self.engines[answered].0.clone())
+    }
+
+    /// The engine which answered the last request, or the first one.
+    fn answering(&self) -> Option<&Arc<dyn StreamSentenceTranslator + Send + Sync>> {
+        let answered = self.answered.lock().unwrap().unwrap_or_default();
+        self.engines.get(answered).map(|(_, translator)| translator)
+    }
+
+    fn request<R>(
+        &self,
+        mut request: impl FnMut(&(dyn StreamSentenceTranslator + Send + Sync)) -> Result<R, Error>,
+    ) -> Result<R, Error> {
+        *self.answered.lock().unwrap() = None;
+        let (answered, result) = self
+            .failover
+            .run(&self.engines, |_, translator| request(translator.as_ref()))?;
+        *self.answered.lock().unwrap() = Some(answered);
+        Ok(result)
+    }
+}
+
+impl Translator for FallbackSentenceTranslator {
+    fn get_api_key(&self) -> String {
+        self.answering()
+            .map(|translator| translator.get_api_key())
+            .unwrap_or_default()
+    }
+    fn save_api_key(&self) -> Result<(), Error> {
+        match self.answering() {
+            Some(translator) => translator.save_api_key(),
+            None => Ok(()),
+        }
+    }
+    fn get_url(&self) -> String {
+        self.answering()
+            .map(|translator| translator.get_url())
+            .unwrap_or_default()
+    }
+    fn last_token_usage(&self) -> Option<TokenUsage> {
+        let answered = (*self.answered.lock().unwrap())?;
+        self.engines[answered].1.last_token_usage()
+    }
+}
+
+impl StreamSentenceTranslator for FallbackSentenceTranslator {
+    fn stream_translate_sentence(
+        &self,
+        sentence: &str,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<Receiver<String>, Error> {
+        self.request(|translator| {
+            translator.stream_translate_sentence(sentence, source_language, target_language)
+        })
+    }
+
+    fn stream_translate_sentence_with_reasoning(
+        &self,
+        sentence: &str,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<SentenceStream, Error> {
+        self.request(|translator| {
+            translator.stream_translate_sentence_with_reasoning(
+                sentence,
+                source_language,
+                target_language,
+            )
+        })
+    }
+
+    fn stream_translate_in_context(
+        &self,
+        sentence: &str,
+        context: &str,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<SentenceStream, Error> {
+        self.request(|translator| {
+            translator.stream_translate_in_context(
+                sentence,
+                context,
+                source_language,
+                target_language,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    };
+
+    use reqwest::StatusCode;
+
+    use super::*;
+
+    /// Answers with its name, or fails with `status`.
+    struct Engine {
+        name: &'static str,
+        status: Option<StatusCode>,
+        requests: AtomicUsize,
+    }
+    impl Engine {
+        fn new(name: &'static str, status: Option<StatusCode>) -> Arc<Self> {
+            Arc::new(Self {
+                name,
+                status,
+                requests: AtomicUsize::new(0),
+            })
+        }
+    }
+    impl Translator for Engine {
+        fn get_api_key(&self) -> String {
+            String::new()
+        }
+        fn save_api_key(&self) -> Result<(), Error> {
+            Ok(())
+        }
+        fn get_url(&self) -> String {
+            String::new()
+        }
+    }
+    impl StreamSentenceTranslator for Engine {
+        fn stream_translate_sentence(
+            &self,
+            _sentence: &str,
+            _source_language: Language,
+            _target_language: Language,
+        ) -> Result<Receiver<String>, Error> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            if let Some(status) = self.status {
+                return Err(ApiError {
+                    status,
+                    body: String::new(),
+                }
+                .into());
+            }
+            let (tx, rx) = mpsc::channel();
+            tx.send(self.name.to_string())?;
+            Ok(rx)
+        }
+    }
+
+    fn chain(engines: &[&Arc<Engine>], health: &Arc<EngineHealth>) -> FallbackSentenceTranslator {
+        FallbackSentenceTranslator::new(
+            engines
+                .iter()
+                .map(|engine| {
+                    (
+                        engine.name.to_string(),
+                        Arc::clone(*engine) as Arc<dyn StreamSentenceTranslator + Send + Sync>,
+                    )
+                })
+                .collect(),
+            Failover::new(health.clone(), &storage::Fallback::default()),
+        )
+    }
+
+    fn translate(translator: &FallbackSentenceTranslator) -> Result<String, Error> {
+        Ok(translator
+            .stream_translate_sentence("Hello", Language::English, Language::German)?
+            .recv()?)
+    }
+
+    #[test]
+    fn failures_are_classified() {
+        let response = |status: u16, body: &str| -> Error {
+            ApiError {
+                status: StatusCode::from_u16(status).unwrap(),
+                body: body.to_string(),
+            }
+            .into()
+        };
+        assert_eq!(classify(&response(503, "")), Some(FailureKind::Server));
+        assert_eq!(classify(&response(429, "")), Some(FailureKind::Quota));
+        assert_eq!(
+            classify(&response(400, r#"{"code":"Arrearage"}"#)),
+            Some(FailureKind::Quota)
+        );
+        assert_eq!(classify(&response(401, "")), Some(FailureKind::Auth));
+        assert_eq!(classify(&response(400, "Invalid model")), None);
+        assert_eq!(
+            classify(&response(502, "").context("Failed to translate")),
+            Some(FailureKind::Server)
+        );
+        assert_eq!(classify(&anyhow!("Unsupported language")), None);
+    }
+
+    #[test]
+    fn failed_engines_are_skipped_until_their_cooldown_is_over() {
+        let health = Arc::new(EngineHealth::open_in_memory().unwrap());
+        let qwen = Engine::new("Qwen", Some(StatusCode::SERVICE_UNAVAILABLE));
+        let deepseek = Engine::new("DeepSeek", None);
+        let translator = chain(&[&qwen, &deepseek], &health);
+
+        assert_eq!(translate(&translator).unwrap(), "DeepSeek");
+        assert_eq!(translator.answered_by().as_deref(), Some("DeepSeek"));
+        assert_eq!(
+            health
+                .cooling_down("Qwen", Duration::from_secs(300))
+                .unwrap(),
+            Some(FailureKind::Server)
+        );
+
+        // Qwen is not asked again while it cools down.
+        assert_eq!(translate(&translator).unwrap(), "DeepSeek");
+        assert_eq!(qwen.requests.load(Ordering::SeqCst), 1);
+
+        health
+            .record_failure_at("Qwen", FailureKind::Server, database::now() - 301)
+            .unwrap();
+        assert_eq!(
+            health
+                .cooling_down("Qwen", Duration::from_secs(300))
+                .unwrap(),
+            None
+        );
+        assert!(translate(&translator).is_ok());
+        assert_eq!(qwen.requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn engines_which_cannot_be_set_up_are_skipped() {
+        let health = Arc::new(EngineHealth::open_in_memory().unwrap());
+        let deepseek = Engine::new("DeepSeek", None);
+        let engines = available([
+            (
+                "Qwen".to_string(),
+                Err(anyhow!("The budget of Qwen is used up")),
+            ),
+            ("DeepSeek".to_string(), Ok(deepseek.clone())),
+        ])
+        .unwrap();
+        let translator = chain(
+            &engines.iter().map(|(_, engine)| engine).collect::<Vec<_>>(),
+            &health,
+        );
+        assert_eq!(translate(&translator).unwrap(), "DeepSeek");
+        assert_eq!(translator.answered_by().as_deref(), Some("DeepSeek"));
+
+        assert_eq!(
+            available([
+                ("Qwen".to_string(), Err::<(), _>(anyhow!("No API key"))),
+                (
+                    "DeepSeek".to_string(),
+                    Err(anyhow!("The budget is used up"))
+                ),
+            ])
+            .unwrap_err()
+            .to_string(),
+            "No engine can be used. Qwen: No API key; DeepSeek: The budget is used up"
+        );
+    }
+
+    #[test]
+    fn failures_of_the_request_are_not_failed_over() {
+        let health = Arc::new(EngineHealth::open_in_memory().unwrap());
+        let qwen = Engine::new("Qwen", Some(StatusCode::BAD_REQUEST));
+        let deepseek = Engine::new("DeepSeek", None);
+        let translator = chain(&[&qwen, &deepseek], &health);
+        assert_eq!(
+            translate(&translator).unwrap_err().to_string(),
+            "API request failed: 400 Bad Request"
+        );
+        assert_eq!(deepseek.requests.load(Ordering::SeqCst), 0);
+        assert_eq!(translator.answered_by(), None);
+
+        let down = Engine::new("DeepSeek", Some(StatusCode::INTERNAL_SERVER_ERROR));
+        let translator = chain(&[&down], &health);
+        assert_eq!(
+            translate(&translator).unwrap_err().to_string(),
+            "Every engine failed. DeepSeek: API request failed: 500 Internal Server Error"
+        );
+    }
+}
@@ -0,0 +1,830 @@
+//! Translation of HTML and XML keeping the markup. Text nodes are translated with the
+//! inline tags around them behind placeholders, so that a sentence split by `<b>` or
+//! `<a>` is still translated as one, and the `alt` and `title` of HTML elements are
+//! translated on their own. The result is checked for unescaped text and unbalanced
+//! tags before it is used.
+
+use std::{collections::HashMap, ops::Range};
+
+use anyhow::{Error, Result, anyhow};
+
+use super::{Escape, Piece, Segment, Summary, failure, keep, placeholder, render, xliff};
+use crate::{Language, document, utils::xml};
+
+/// Elements of HTML kept inside the text around them.
+const INLINE_ELEMENTS: [&str; 26] = [
+    "a", "abbr", "b", "bdi", "bdo", "br", "cite", "code", "data", "dfn", "em", "font", "i", "img",
+    "kbd", "label", "mark", "q", "s", "samp", "small", "span", "strong", "sub", "sup", "u",
+];
+/// Elements whose content is code, kept whole behind one placeholder. The last four
+/// hold the native codes of XLIFF 1.2.
+const CODE_ELEMENTS: [&str; 8] = ["code", "kbd", "samp", "var", "bpt", "ept", "ph", "it"];
+/// Elements of HTML whose content is never translated.
+const UNTRANSLATED_ELEMENTS: [&str; 6] = ["script", "style", "pre", "svg", "math", "textarea"];
+/// Attributes of HTML elements shown to readers.
+const TRANSLATED_ATTRIBUTES: [&str; 4] = ["alt", "title", "placeholder", "aria-label"];
+/// Elements of HTML without end tags.
+const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+/// Elements of HTML whose end tags may be left out.
+const OPTIONAL_END_ELEMENTS: [&str; 18] = [
+    "html", "head", "body", "p", "li", "dt", "dd", "tr", "td", "th", "thead", "tbody", "tfoot",
+    "colgroup", "option", "optgroup", "rt", "rp",
+];
+/// Named character references decoded for the engine, besides those of XML.
+const HTML_ENTITIES: [(&str, char); 24] = [
+    ("nbsp", '\u{a0}'),
+    ("copy", '©'),
+    ("reg", '®'),
+    ("trade", '™'),
+    ("hellip", '…'),
+    ("mdash", '—'),
+    ("ndash", '–'),
+    ("lsquo", '‘'),
+    ("rsquo", '’'),
+    ("ldquo", '“'),
+    ("rdquo", '”'),
+    ("laquo", '«'),
+    ("raquo", '»'),
+    ("middot", '·'),
+    ("bull", '•'),
+    ("deg", '°'),
+    ("euro", '€'),
+    ("pound", '£'),
+    ("yen", '¥'),
+    ("cent", '¢'),
+    ("sect", '§'),
+    ("para", '¶'),
+    ("times", '×'),
+    ("divide", '÷'),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkupKind {
+    /// Tag names are matched ignoring case, and void elements and left out end tags
+    /// are allowed.
+    Html,
+    Xml,
+}
+
+impl MarkupKind {
+    /// The markup `text` is written in, `None` if it has no tags.
+    pub fn detect(text: &str) -> Option<Self> {
+        let text = text.trim_start_matches('\u{feff}').trim_start();
+        if text.starts_with("<?xml") || text.starts_with("<xliff") {
+            return Some(Self::Xml);
+        }
+        tokens(text, Self::Html)
+            .iter()
+            .any(|token| matches!(token.kind, TokenKind::Open | TokenKind::Empty))
+            .then_some(Self::Html)
+    }
+
+    fn matches(self, name: &str, names: &[&str]) -> bool {
+        match self {
+            Self::Html => names.iter().any(|other| name.eq_ignore_ascii_case(other)),
+            Self::Xml => names.contains(&name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum TokenKind {
+    Text,
+    Open,
+    Close,
+    /// Self-closing tags, as `<x/>`.
+    Empty,
+    /// Comments, CDATA sections, declarations and processing instructions, and the
+    /// content of scripts and styles.
+    Other,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Token<'a> {
+    pub(super) kind: TokenKind,
+    /// Name of a tag, empty for other tokens.
+    pub(super) name: &'a str,
+    pub(super) raw: &'a str,
+    pub(super) start: usize,
+}
+
+impl Token<'_> {
+    pub(super) fn end(&self) -> usize {
+        self.start + self.raw.len()
+    }
+
+    /// The value of the attribute `name` of a tag, still escaped.
+    pub(super) fn attribute(&self, name: &str) -> Option<&str> {
+        if !matches!(self.kind, TokenKind::Open | TokenKind::Empty) {
+            return None;
+        }
+        attributes(self.raw)
+            .into_iter()
+            .find(|(attribute, _)| attribute.eq_ignore_ascii_case(name))
+            .map(|(_, value)| &self.raw[value])
+    }
+}
+
+/// Split `text` into tags and the text between them. Joining the tokens gives back
+/// `text`, and a `<` which starts no tag is left in the text.
+pub(super) fn tokens<'a>(text: &'a str, kind: MarkupKind) -> Vec<Token<'a>> {
+    let mut tokens = Vec::new();
+    let mut text_start = 0;
+    let mut index = 0;
+    let push = |tokens: &mut Vec<Token<'a>>, kind, range: Range<usize>| {
+        if !range.is_empty() {
+            let raw = &text[range.clone()];
+            let name = match kind {
+                TokenKind::Open | TokenKind::Empty => tag_name(&raw[1..]),
+                TokenKind::Close => tag_name(&raw[2..]),
+                _ => "",
+            };
+            tokens.push(Token {
+                kind,
+                name,
+                raw,
+                start: range.start,
+            });
+        }
+    };
+    while let Some(offset) = text[index..].find('<') {
+        let start = index + offset;
+        let Some((token_kind, length)) = markup_length(&text[start..]) else {
+            index = start + 1;
+            continue;
+        };
+        push(&mut tokens, TokenKind::Text, text_start..start);
+        push(&mut tokens, token_kind, start..start + length);
+        index = start + length;
+        text_start = index;
+
+        // The content of scripts and styles is not markup.
+        let name = tokens.last().map_or("", |token| token.name);
+        if kind == MarkupKind::Html
+            && token_kind == TokenKind::Open
+            && kind.matches(name, &["script", "style"])
+        {
+            let closing = format!("</{}", name.to_ascii_lowercase());
+            let end = text[index..]
+                .to_ascii_lowercase()
+                .find(&closing)
+                .map_or(text.len(), |end| index + end);
+            push(&mut tokens, TokenKind::Other, index..end);
+            index = end;
+            text_start = end;
+        }
+    }
+    push(&mut tokens, TokenKind::Text, text_start..text.len());
+    tokens
+}
+
+fn tag_name(rest: &str) -> &str {
+    let end = rest
+        .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+        .unwrap_or(rest.len());
+    &rest[..end]
+}
+
+/// The kind and length of the markup at the start of `rest`, `None` if `rest` starts
+/// with a `<` which is text.
+fn markup_length(rest: &str) -> Option<(TokenKind, usize)> {
+    let after = |end: &str| rest.find(end).map(|index| index + end.len());
+    if rest.starts_with("<!--") {
+        return after("-->").map(|length| (TokenKind::Other, length));
+    }
+    if rest.starts_with("<![CDATA[") {
+        return after("]]>").map(|length| (TokenKind::Other, length));
+    }
+    if rest.starts_with("<!") || rest.starts_with("<?") {
+        return after(">").map(|length| (TokenKind::Other, length));
+    }
+    if let Some(name) = rest.strip_prefix("</") {
+        return match name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            true => after(">").map(|length| (TokenKind::Close, length)),
+            false => None,
+        };
+    }
+    if !rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    let mut quote = None;
+    for (index, c) in rest.char_indices() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '<') if index > 0 => return None,
+            (None, '>') => {
+                let kind = match rest[..index].ends_with('/') {
+                    true => TokenKind::Empty,
+                    false => TokenKind::Open,
+                };
+                return Some((kind, index + 1));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Names of the attributes of the tag `raw` with the ranges of their values in `raw`,
+/// without the quotes.
+fn attributes(raw: &str) -> Vec<(&str, Range<usize>)> {
+    let mut attributes = Vec::new();
+    let bytes = raw.as_bytes();
+    let mut index = 1 + tag_name(&raw[1..]).len();
+    let skip_whitespace = |mut index: usize| {
+        while index < bytes.len() && bytes[index].is_ascii_whitespace() {
+            index += 1;
+        }
+        index
+    };
+    loop {
+        index = skip_whitespace(index);
+        if index >= bytes.len() || matches!(bytes[index], b'/' | b'>') {
+            break;
+        }
+        let name_start = index;
+        while index < bytes.len()
+            && !bytes[index].is_ascii_whitespace()
+            && !matches!(bytes[index], b'=' | b'>' | b'/')
+        {
+            index += 1;
+        }
+        let name = &raw[name_start..index];
+        let after_name = skip_whitespace(index);
+        if bytes.get(after_name) != Some(&b'=') {
+            index = after_name.max(index + usize::from(index == name_start));
+            continue;
+        }
+        index = skip_whitespace(after_name + 1);
+        let value = match bytes.get(index) {
+            Some(&quote @ (b'"' | b'\'')) => {
+                let end = raw[index + 1..]
+                    .find(quote as char)
+                    .map_or(raw.len(), |end| index + 1 + end);
+                let value = index + 1..end;
+                index = end + 1;
+                value
+            }
+            _ => {
+                let start = index;
+                while index < bytes.len()
+                    && !bytes[index].is_ascii_whitespace()
+                    && bytes[index] != b'>'
+                {
+                    index += 1;
+                }
+                start..index
+            }
+        };
+        attributes.push((name, value));
+    }
+    attributes
+}
+
+/// Decode the character references of `text`, `None` for an unknown one.
+fn decode_entity(reference: &str) -> Option<char> {
+    let name = reference.strip_prefix('&')?.strip_suffix(';')?;
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => HTML_ENTITIES
+            .iter()
+            .find(|(entity, _)| *entity == name)
+            .map(|(_, c)| *c),
+    }
+}
+
+/// The character reference at the start of `rest`, as `&amp;` or `&#x4e2d;`.
+fn entity_at(rest: &str) -> Option<&str> {
+    let end = rest[1..]
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '#'))
+        .map(|end| end + 1)?;
+    (end > 1 && rest[end..].starts_with(';')).then(|| &rest[..end + 1])
+}
+
+/// Text with its character references decoded. Unknown references are passed to
+/// `on_unknown`, which returns what to put in their place.
+fn unescape_with(text: &str, mut on_unknown: impl FnMut(&str) -> String) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut index = 0;
+    while let Some(offset) = text[index..].find('&') {
+        let start = index + offset;
+        unescaped.push_str(&text[index..start]);
+        match entity_at(&text[start..]) {
+            Some(reference) => {
+                match decode_entity(reference) {
+                    Some(c) => unescaped.push(c),
+                    None => unescaped.push_str(&on_unknown(reference)),
+                }
+                index = start + reference.len();
+            }
+            None => {
+                unescaped.push('&');
+                index = start + 1;
+            }
+        }
+    }
+    unescaped.push_str(&text[index..]);
+    unescaped
+}
+
+pub(super) fn unescape(text: &str) -> String {
+    unescape_with(text, str::to_string)
+}
+
+/// A translation escaped as element content or as an attribute value. References the
+/// engine wrote are decoded first, so that they are not escaped twice.
+pub(super) fn escape(translation: &str, attribute: bool) -> String {
+    let text = unescape(translation);
+    match attribute {
+        true => xml::escape(&text).replace('\'', "&#39;"),
+        false => xml::escape_text(&text),
+    }
+}
+
+/// The segment of the text and inline tags of `run`. Code elements are kept whole,
+/// other tags and unknown character references are kept behind placeholders.
+pub(super) fn segment(run: &str, kind: MarkupKind) -> Segment {
+    let tokens = tokens(run, kind);
+    let mut text = String::new();
+    let mut protected = Vec::new();
+    let mut index = 0;
+    while index < tokens.len() {
+        let token = tokens[index];
+        let span = match token.kind {
+            TokenKind::Text => {
+                text += &unescape_with(token.raw, |reference| {
+                    protected.push(reference.to_string());
+                    placeholder(protected.len() - 1)
+                });
+                index += 1;
+                continue;
+            }
+            TokenKind::Open if kind.matches(token.name, &CODE_ELEMENTS) => {
+                let end = matching_close(&tokens, index, kind).unwrap_or(index);
+                let span = &run[token.start..tokens[end].end()];
+                index = end;
+                span
+            }
+            _ => token.raw,
+        };
+        text += &placeholder(protected.len());
+        protected.push(span.to_string());
+        index += 1;
+    }
+    Segment {
+        text,
+        protected,
+        escape: Escape::XmlText(kind),
+    }
+}
+
+/// Index of the tag closing the element opened at `open`.
+pub(super) fn matching_close(tokens: &[Token], open: usize, kind: MarkupKind) -> Option<usize> {
+    let name = tokens[open].name;
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate().skip(open) {
+        if !kind.matches(token.name, &[name]) {
+            continue;
+        }
+        match token.kind {
+            TokenKind::Open => depth += 1,
+            TokenKind::Close => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Keep the whitespace around `run` and translate the rest.
+pub(super) fn push_run(pieces: &mut Vec<Piece>, run: &str, kind: MarkupKind) {
+    let trimmed = run.trim();
+    let start = run.len() - run.trim_start().len();
+    keep(pieces, &run[..start]);
+    if !trimmed.is_empty() {
+        let segment = segment(trimmed, kind);
+        match segment.text.chars().any(char::is_alphabetic) {
+            true => pieces.push(Piece::Translate(segment)),
+            false => keep(pieces, trimmed),
+        }
+    }
+    keep(pieces, &run[start + trimmed.len()..]);
+}
+
+/// Keep a tag, translating the attributes read by people.
+fn push_tag(pieces: &mut Vec<Piece>, token: &Token, kind: MarkupKind) {
+    let mut kept = 0;
+    if kind == MarkupKind::Html && matches!(token.kind, TokenKind::Open | TokenKind::Empty) {
+        for (name, value) in attributes(token.raw) {
+            let text = unescape(&token.raw[value.clone()]);
+            if kind.matches(name, &TRANSLATED_ATTRIBUTES) && text.chars().any(char::is_alphabetic) {
+                keep(pieces, &token.raw[kept..value.start]);
+                pieces.push(Piece::Translate(Segment {
+                    text,
+                    protected: Vec::new(),
+                    escape: Escape::XmlAttribute,
+                }));
+                kept = value.end;
+            }
+        }
+    }
+    keep(pieces, &token.raw[kept..]);
+}
+
+fn has_translated_attributes(token: &Token, kind: MarkupKind) -> bool {
+    kind == MarkupKind::Html
+        && attributes(token.raw)
+            .into_iter()
+            .any(|(name, _)| kind.matches(name, &TRANSLATED_ATTRIBUTES))
+}
+
+/// Pieces of HTML or XML. In HTML the text and inline elements between other tags are
+/// translated together, in XML every text node is translated on its own. Inline
+/// elements with translated attributes end the text before them.
+pub(super) fn parse(text: &str, kind: MarkupKind, pieces: &mut Vec<Piece>) {
+    let tokens = tokens(text, kind);
+    let mut run: Option<usize> = None;
+    let mut skipped: Option<usize> = None;
+    for (index, token) in tokens.iter().enumerate() {
+        if let Some(end) = skipped {
+            keep(pieces, token.raw);
+            if index == end {
+                skipped = None;
+            }
+            continue;
+        }
+        let inline = match token.kind {
+            TokenKind::Text => true,
+            TokenKind::Open | TokenKind::Close | TokenKind::Empty => {
+                kind == MarkupKind::Html
+                    && kind.matches(token.name, &INLINE_ELEMENTS)
+                    && !has_translated_attributes(token, kind)
+            }
+            TokenKind::Other => false,
+        };
+        if inline {
+            run.get_or_insert(token.start);
+            continue;
+        }
+        if let Some(start) = run.take() {
+            push_run(pieces, &text[start..token.start], kind);
+        }
+        push_tag(pieces, token, kind);
+        let untranslated = kind.matches(token.name, &UNTRANSLATED_ELEMENTS)
+            || token.attribute("translate") == Some("no");
+        if token.kind == TokenKind::Open && untranslated {
+            skipped = Some(matching_close(&tokens, index, kind).unwrap_or(tokens.len()));
+        }
+    }
+    if let Some(start) = run {
+        push_run(pieces, &text[start..], kind);
+    }
+}
+
+/// The first unbalanced tag of `text`, as `</b>`.
+pub(super) fn unbalanced_tag(text: &str, kind: MarkupKind) -> Option<String> {
+    let mut open: Vec<&str> = Vec::new();
+    for token in tokens(text, kind) {
+        match token.kind {
+            TokenKind::Open
+                if kind == MarkupKind::Html && kind.matches(token.name, &VOID_ELEMENTS) => {}
+            TokenKind::Open => open.push(token.name),
+            TokenKind::Close => {
+                let Some(position) = open
+                    .iter()
+                    .rposition(|name| kind.matches(token.name, &[name]))
+                else {
+                    return Some(format!("</{}>", token.name));
+                };
+                let implied = open[position + 1..].iter().all(|name| {
+                    kind == MarkupKind::Html && kind.matches(name, &OPTIONAL_END_ELEMENTS)
+                });
+                if !implied {
+                    return Some(format!("<{}>", open[open.len() - 1]));
+                }
+                open.truncate(position);
+            }
+            _ => {}
+        }
+    }
+    open.into_iter()
+        .find(|name| !(kind == MarkupKind::Html && kind.matches(name, &OPTIONAL_END_ELEMENTS)))
+        .map(|name| format!("<{}>", name))
+}
+
+/// A `&` of `text` which starts no character reference known here or in `source`.
+fn unescaped_ampersand(text: &str, source: &str) -> Option<String> {
+    let mut index = 0;
+    while let Some(offset) = text[index..].find('&') {
+        let start = index + offset;
+        match entity_at(&text[start..]) {
+            Some(reference) if decode_entity(reference).is_some() || source.contains(reference) => {
+                index = start + reference.len();
+            }
+            _ => {
+                let end = text[start..]
+                    .char_indices()
+                    .nth(12)
+                    .map_or(text.len(), |(end, _)| start + end);
+                return Some(text[start..end].to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Check that `translation` is still well-formed where `source` is: every `<` and `&`
+/// in the text is escaped and the tags are balanced.
+pub fn validate(source: &str, translation: &str, kind: MarkupKind) -> Result<(), Error> {
+    for token in tokens(translation, kind) {
+        let text = match token.kind {
+            TokenKind::Text => token.raw,
+            TokenKind::Open | TokenKind::Empty => &token.raw[1 + token.name.len()..],
+            _ => continue,
+        };
+        if token.kind == TokenKind::Text && token.raw.contains('<') {
+            return Err(anyhow!(
+                "An unescaped \"<\" is left in: {}",
+                token.raw.trim()
+            ));
+        }
+        if let Some(ampersand) = unescaped_ampersand(text, source) {
+            return Err(anyhow!("An unescaped \"&\" is left in: {}", ampersand));
+        }
+    }
+    if unbalanced_tag(source, kind).is_none()
+        && let Some(tag) = unbalanced_tag(translation, kind)
+    {
+        return Err(anyhow!(
+            "The tag {} is not balanced in the translation",
+            tag
+        ));
+    }
+    Ok(())
+}
+
+/// Pieces of pasted markup: XLIFF documents, other XML or HTML.
+fn parse_any(text: &str, target_language: Language) -> Result<(Vec<Piece>, MarkupKind), Error> {
+    let kind =
+        MarkupKind::detect(text).ok_or_else(|| anyhow!("No markup was found in the text"))?;
+    let mut pieces = Vec::new();
+    match kind == MarkupKind::Xml && xliff::is_xliff(text) {
+        true => xliff::parse(text, target_language, &mut pieces),
+        false => parse(text, kind, &mut pieces),
+    }
+    Ok((pieces, kind))
+}
+
+/// Translate HTML or XML into `target_language`, with the text of several segments of about `batch_tokens`
+/// in one request. Each segment of a batch is marked `⟪1⟫`, `⟪2⟫`, ... and segments
+/// missing from the answer are translated on their own. Segments which fail are kept
+/// in the source language. `on_progress` is called with the number of segments
+/// translated and the number of segments.
+pub fn translate(
+    text: &str,
+    target_language: Language,
+    batch_tokens: usize,
+    mut translate: impl FnMut(&str) -> Result<String, Error>,
+    mut on_progress: impl FnMut(usize, usize),
+) -> Result<(String, Summary), Error> {
+    let (pieces, kind) = parse_any(text, target_language)?;
+    let segments = super::unique_segments(&pieces);
+    let mut translations = HashMap::new();
+    let mut failures = Vec::new();
+    let mut done = 0;
+    on_progress(0, segments.len());
+    for batch in batches(&segments, batch_tokens) {
+        let mut answers = match batch {
+            [_] => HashMap::new(),
+            _ => {
+                let request = batch
+                    .iter()
+                    .enumerate()
+                    .map(|(index, segment)| format!("⟪{}⟫ {}\n", index + 1, segment))
+                    .collect::<String>();
+                translate(&request)
+                    .map(|answer| split_batch(&answer))
+                    .unwrap_or_else(|e| {
+                        log::warn!("Failed to translate a batch: {:#}", e);
+                        HashMap::new()
+                    })
+            }
+        };
+        for (index, segment) in batch.iter().enumerate() {
+            let translation = match answers.remove(&(index + 1)) {
+                Some(translation) => Ok(translation),
+                None => {
+                    if batch.len() > 1 {
+                        log::warn!(
+                            "Segment {} is missing from the batch, translate it alone",
+                            index + 1
+                        );
+                    }
+                    translate(segment)
+                }
+            };
+            match translation {
+                Ok(translation) => {
+                    translations.insert(*segment, translation);
+                }
+                Err(e) => failures.push(failure(e)),
+            }
+            done += 1;
+        }
+        on_progress(done, segments.len());
+    }
+    let (translated, summary) = render(&pieces, &translations, failures);
+    validate(text, &translated, kind)?;
+    Ok((translated, summary))
+}
+
+/// Consecutive segments of about `budget` tokens together.
+fn batches<'a>(segments: &'a [&'a str], budget: usize) -> Vec<&'a [&'a str]> {
+    let mut batches = Vec::new();
+    let mut start = 0;
+    let mut tokens = 0;
+    for (index, segment) in segments.iter().enumerate() {
+        let segment_tokens = document::estimate_tokens(segment);
+        if index > start && tokens + segment_tokens > budget {
+            batches.push(&segments[start..index]);
+            start = index;
+            tokens = 0;
+        }
+        tokens += segment_tokens;
+    }
+    if start < segments.len() {
+        batches.push(&segments[start..]);
+    }
+    batches
+}
+
+/// Translations of a batch by their numbers. Markers which are not numbered are
+/// kept in the text.
+fn split_batch(answer: &str) -> HashMap<usize, String> {
+    let mut translations = HashMap::new();
+    let mut current: Option<(usize, usize)> = None;
+    let mut search = 0;
+    while let Some(offset) = answer[search..].find('⟪') {
+        let start = search + offset;
+        let Some((number, length)) = batch_marker(&answer[start..]) else {
+            search = start + '⟪'.len_utf8();
+            continue;
+        };
+        if let Some((number, text_start)) = current {
+            translations.insert(number, answer[text_start..start].trim().to_string());
+        }
+        current = Some((number, start + length));
+        search = start + length;
+    }
+    if let Some((number, text_start)) = current {
+        translations.insert(number, answer[text_start..].trim().to_string());
+    }
+    translations
+}
+
+/// The number and the length of the marker `text` starts with.
+fn batch_marker(text: &str) -> Option<(usize, usize)> {
+    let end = text.find('⟫')?;
+    let number = text['⟪'.len_utf8()..end].trim().parse().ok()?;
+    Some((number, end + '⟫'.len_utf8()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uppercase(text: &str, batch_tokens: usize) -> (String, Summary, usize) {
+        let mut requests = 0;
+        let (translated, summary) = translate(
+            text,
+            Language::French,
+            batch_tokens,
+            |request| {
+                requests += 1;
+                Ok(request.to_uppercase())
+            },
+            |_, _| {},
+        )
+        .unwrap();
+        (translated, summary, requests)
+    }
+
+    #[test]
+    fn html_keeps_its_markup() {
+        let (translated, summary, requests) = uppercase(include_str!("sample.html"), 1000);
+        assert_eq!(translated, include_str!("sample.translated.html"));
+        assert_eq!(summary.untranslated, 0);
+        assert_eq!(requests, 1);
+    }
+
+    #[test]
+    fn segments_missing_from_a_batch_are_translated_alone() {
+        let mut requests = Vec::new();
+        let (translated, _) = translate(
+            "<p>One</p><p>Two &amp; three</p>",
+            Language::French,
+            1000,
+            |request| {
+                requests.push(request.to_string());
+                Ok(match request.contains('⟪') {
+                    true => "⟪1⟫ Un".to_string(),
+                    false => "Deux & trois".to_string(),
+                })
+            },
+            |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(translated, "<p>Un</p><p>Deux &amp; trois</p>");
+        assert_eq!(requests.len(), 2);
+    }
+
+    #[test]
+    fn failed_batches_are_translated_alone() {
+        let (translated, summary) = translate(
+            "<p>One</p><p>Two</p>",
+            Language::French,
+            1000,
+            |request| match request {
+                "Two" => Ok("Deux".to_string()),
+                _ => Err(anyhow!("Connection reset")),
+            },
+            |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(translated, "<p>One</p><p>Deux</p>");
+        assert_eq!(summary.failures, ["Connection reset"]);
+    }
+
+    #[test]
+    fn unnumbered_markers_are_kept_in_the_text() {
+        let answers = split_batch("⟪1⟫ Un ⟪deux⟫\n⟪ 2 ⟫ Trois ⟪\n⟪3⟫ Quatre");
+        assert_eq!(answers[&1], "Un ⟪deux⟫");
+        assert_eq!(answers[&2], "Trois ⟪");
+        assert_eq!(answers[&3], "Quatre");
+    }
+
+    #[test]
+    fn reordered_tags_are_checked() {
+        let source = "<p>Click <b>here</b> to <i>start</i>.</p>";
+        let (translated, summary) = translate(
+            source,
+            Language::French,
+            1000,
+            |_| Ok("⟦2⟧Commencez⟦3⟧ en cliquant ⟦1⟧ici⟦0⟧.".to_string()),
+            |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(translated, source);
+        assert_eq!(summary.untranslated, 1);
+
+        assert!(validate(source, "<p>Click <b>here</p>", MarkupKind::Html).is_err());
+        assert!(validate(source, "<p>Fish & chips</p>", MarkupKind::Html).is_err());
+        assert!(validate(source, "<p>1 < 2</p>", MarkupKind::Html).is_err());
+        assert!(
+            validate(
+                "<ul><li>One<li>Two</ul>",
+                "<ul><li>Un<li>Deux</ul>",
+                MarkupKind::Html
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn xliff_targets_are_filled() {
+        for (source, expected) in [
+            (
+                include_str!("sample.xlf"),
+                include_str!("sample.translated.xlf"),
+            ),
+            (
+                include_str!("sample2.xlf"),
+                include_str!("sample2.translated.xlf"),
+            ),
+        ] {
+            let (translated, summary, _) = uppercase(source, 1);
+            assert_eq!(translated, expected);
+            assert_eq!(summary.untranslated, 0);
+        }
+    }
+}
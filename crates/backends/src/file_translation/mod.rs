@@ -0,0 +1,1089 @@
+//! Translation of whole files, keeping their structure. Only the text of a file is
+//! given to the engine: code, links, markup, timestamps and format specifiers are kept
+//! as they are, either outside of the translated segments or behind placeholders which
+//! are put back into the translation.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Error, Result, anyhow};
+
+use crate::{Language, StreamSentenceTranslator, document, storage};
+
+pub mod markup;
+mod xliff;
+
+use markup::MarkupKind;
+
+/// Formats of the files which can be translated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    PlainText,
+    Markdown,
+    SubRip,
+    WebVtt,
+    /// Gettext catalogues, `.po` and `.pot`.
+    Po,
+    Html,
+    /// XLIFF 1.2 and 2.0 documents, whose targets are filled.
+    Xliff,
+}
+
+impl FileFormat {
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        match extension.as_str() {
+            "txt" | "text" => Ok(Self::PlainText),
+            "md" | "markdown" => Ok(Self::Markdown),
+            "srt" => Ok(Self::SubRip),
+            "vtt" => Ok(Self::WebVtt),
+            "po" | "pot" => Ok(Self::Po),
+            "html" | "htm" | "xhtml" => Ok(Self::Html),
+            "xlf" | "xliff" => Ok(Self::Xliff),
+            _ => Err(anyhow!("Unsupported file type: {}", path.display())),
+        }
+    }
+
+    fn markup(self) -> Option<MarkupKind> {
+        match self {
+            Self::Html => Some(MarkupKind::Html),
+            Self::Xliff => Some(MarkupKind::Xml),
+            _ => None,
+        }
+    }
+}
+
+/// Counts of a translated file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    /// Different segments given to the engine.
+    pub segments: usize,
+    /// Segments left in the source language because the translation lost a
+    /// protected span.
+    pub untranslated: usize,
+    /// Why different segments failed to be translated. They are left in the source
+    /// language too.
+    pub failures: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Keep(String),
+    Translate(Segment),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Segment {
+    /// The text given to the engine, with placeholders for the protected spans.
+    text: String,
+    /// Spans replaced by `⟦0⟧`, `⟦1⟧`, ... in `text`.
+    protected: Vec<String>,
+    escape: Escape,
+}
+
+/// How translations are written into the file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Escape {
+    None,
+    /// As the content of a PO string.
+    Po,
+    /// As element content, keeping the tags balanced.
+    XmlText(MarkupKind),
+    XmlAttribute,
+}
+
+impl Segment {
+    fn new(source: &str, format: FileFormat) -> Self {
+        let (text, protected) = protect(source, format);
+        Self {
+            text,
+            protected,
+            escape: match format {
+                FileFormat::Po => Escape::Po,
+                _ => Escape::None,
+            },
+        }
+    }
+
+    /// `translation` with the protected spans put back, or the source if a
+    /// placeholder is missing or the tags are no longer balanced.
+    fn render(&self, translation: &str) -> (String, bool) {
+        let source = self.fill(&self.text).unwrap_or_default();
+        match self.fill(translation) {
+            Some(text) if self.keeps_balance(&source, &text) => (text, true),
+            _ => (source, false),
+        }
+    }
+
+    fn fill(&self, translation: &str) -> Option<String> {
+        match self.escape {
+            Escape::None => restore(translation, &self.protected),
+            Escape::Po => restore(translation, &self.protected).map(|text| escape_po(&text)),
+            Escape::XmlText(_) => restore(&markup::escape(translation, false), &self.protected),
+            Escape::XmlAttribute => restore(&markup::escape(translation, true), &self.protected),
+        }
+    }
+
+    /// Placeholders of paired tags may be reordered as long as the tags still match.
+    fn keeps_balance(&self, source: &str, text: &str) -> bool {
+        match self.escape {
+            Escape::XmlText(kind) => {
+                markup::unbalanced_tag(source, kind).is_some()
+                    || markup::unbalanced_tag(text, kind).is_none()
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Path of the translated copy of `path`, next to it: `notes.md` becomes
+/// `notes.zh.md`. Templates become catalogues, `messages.pot` becomes `messages.zh.po`.
+pub fn translated_path(path: &Path, target_language: Language) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned());
+    let file_name = match extension.as_deref() {
+        Some("pot") => format!("{}.{}.po", stem, target_language.code()),
+        Some(extension) => format!("{}.{}.{}", stem, target_language.code(), extension),
+        None => format!("{}.{}", stem, target_language.code()),
+    };
+    path.with_file_name(file_name)
+}
+
+/// Translate the file at `path` into a copy at `output`, or next to it if `output` is
+/// `None`, each different segment once with `translate`. `on_progress` is called with
+/// the number of segments translated and the number of segments. Returns the path
+/// written.
+pub fn translate_file(
+    path: &Path,
+    output: Option<&Path>,
+    source_language: Language,
+    target_language: Language,
+    translate: impl FnMut(&str) -> Result<String, Error>,
+    on_progress: impl FnMut(usize, usize),
+) -> Result<(PathBuf, Summary), Error> {
+    let format = FileFormat::from_path(path)?;
+    let text =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let (translated, summary) = translate_text(
+        &text,
+        format,
+        source_language,
+        target_language,
+        translate,
+        on_progress,
+    )?;
+    let output = output
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| translated_path(path, target_language));
+    fs::write(&output, translated)
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+    Ok((output, summary))
+}
+
+/// The translation of `segment` by `translator`. Answers which ended with an error or
+/// were cut off fail, so that they are not written into the file.
+pub fn translate_segment(
+    translator: &(dyn StreamSentenceTranslator + Send + Sync),
+    segment: &str,
+    source_language: Language,
+    target_language: Language,
+) -> Result<String, Error> {
+    let stream = translator.stream_translate_sentence_with_reasoning(
+        segment,
+        source_language,
+        target_language,
+    )?;
+    let translation = stream
+        .answer
+        .into_iter()
+        .last()
+        .ok_or_else(|| anyhow!("No translation was received for: {}", segment))?;
+    match stream.outcome.recv() {
+        Ok(Err(e)) => Err(e.context(format!("Failed to translate: {}", segment))),
+        // Translators which cannot tell are trusted.
+        Ok(Ok(())) | Err(_) => Ok(translation),
+    }
+}
+
+/// Translate `text` in `format`, each different segment once with `translate`.
+/// `on_progress` is called with the number of segments translated and the number of
+/// segments. Segments which fail are kept in the source language, but the whole text
+/// fails if the markup of the translation is broken.
+pub fn translate_text(
+    text: &str,
+    format: FileFormat,
+    source_language: Language,
+    target_language: Language,
+    mut translate: impl FnMut(&str) -> Result<String, Error>,
+    mut on_progress: impl FnMut(usize, usize),
+) -> Result<(String, Summary), Error> {
+    let pieces = parse(text, format, source_language, target_language);
+    let segments = unique_segments(&pieces);
+    let mut translations = HashMap::new();
+    let mut failures = Vec::new();
+    on_progress(0, segments.len());
+    for (index, segment) in segments.iter().enumerate() {
+        match translate(segment) {
+            Ok(translation) => {
+                translations.insert(*segment, translation);
+            }
+            Err(e) => failures.push(failure(e)),
+        }
+        on_progress(index + 1, segments.len());
+    }
+    let (translated, summary) = render(&pieces, &translations, failures);
+    if let Some(kind) = format.markup() {
+        markup::validate(text, &translated, kind)?;
+    }
+    Ok((translated, summary))
+}
+
+/// Texts of the segments to translate, each once.
+fn unique_segments(pieces: &[Piece]) -> Vec<&str> {
+    let mut segments: Vec<&str> = Vec::new();
+    for piece in pieces {
+        if let Piece::Translate(segment) = piece
+            && !segments.contains(&segment.text.as_str())
+        {
+            segments.push(&segment.text);
+        }
+    }
+    segments
+}
+
+/// Why a segment failed, logged.
+fn failure(error: Error) -> String {
+    let failure = format!("{:#}", error);
+    log::warn!("{}, the source is kept", failure);
+    failure
+}
+
+/// Join `pieces` with the translations of their segments, or their source if they
+/// failed.
+fn render(
+    pieces: &[Piece],
+    translations: &HashMap<&str, String>,
+    failures: Vec<String>,
+) -> (String, Summary) {
+    let mut summary = Summary {
+        segments: unique_segments(pieces).len(),
+        untranslated: 0,
+        failures,
+    };
+    let mut translated = String::new();
+    for piece in pieces {
+        match piece {
+            Piece::Keep(text) => translated.push_str(text),
+            Piece::Translate(segment) => {
+                let Some(translation) = translations.get(segment.text.as_str()) else {
+                    translated.push_str(&segment.render(&segment.text).0);
+                    continue;
+                };
+                let (text, restored) = segment.render(translation);
+                if !restored {
+                    log::warn!(
+                        "The translation of \"{}\" lost a protected span, the source is kept",
+                        segment.text
+                    );
+                    summary.untranslated += 1;
+                }
+                translated.push_str(&text);
+            }
+        }
+    }
+    (translated, summary)
+}
+
+fn parse(
+    text: &str,
+    format: FileFormat,
+    source_language: Language,
+    target_language: Language,
+) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    match format {
+        FileFormat::PlainText => {
+            let mut end = 0;
+            for chunk in document::chunks(text, source_language, &storage::Documents::default()) {
+                push_text(&mut pieces, &chunk.text, format);
+                end += chunk.text.len();
+            }
+            keep(&mut pieces, &text[end.min(text.len())..]);
+        }
+        FileFormat::Markdown => parse_markdown(text, &mut pieces),
+        FileFormat::SubRip | FileFormat::WebVtt => parse_subtitles(text, format, &mut pieces),
+        FileFormat::Po => parse_po(text, &mut pieces),
+        FileFormat::Html => markup::parse(text, MarkupKind::Html, &mut pieces),
+        FileFormat::Xliff => xliff::parse(text, target_language, &mut pieces),
+    }
+    pieces
+}
+
+fn keep(pieces: &mut Vec<Piece>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    match pieces.last_mut() {
+        Some(Piece::Keep(kept)) => kept.push_str(text),
+        _ => pieces.push(Piece::Keep(text.to_string())),
+    }
+}
+
+/// Keep the whitespace around `text` and translate the rest.
+fn push_text(pieces: &mut Vec<Piece>, text: &str, format: FileFormat) {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        keep(pieces, &escape_kept(text, format));
+        return;
+    }
+    let start = text.len() - text.trim_start().len();
+    let end = start + trimmed.len();
+    keep(pieces, &escape_kept(&text[..start], format));
+    let segment = Segment::new(trimmed, format);
+    // Nothing is left to translate around code and numbers.
+    match segment.text.chars().any(char::is_alphabetic) {
+        true => pieces.push(Piece::Translate(segment)),
+        false => keep(pieces, &escape_kept(trimmed, format)),
+    }
+    keep(pieces, &escape_kept(&text[end..], format));
+}
+
+fn escape_kept(text: &str, format: FileFormat) -> String {
+    match format {
+        FileFormat::Po => escape_po(text),
+        _ => text.to_string(),
+    }
+}
+
+/// Lines of `text` with their line breaks and their offsets.
+fn lines(text: &str) -> Vec<(usize, &str)> {
+    let mut offset = 0;
+    text.split_inclusive('\n')
+        .map(|line| {
+            let start = offset;
+            offset += line.len();
+            (start, line)
+        })
+        .collect()
+}
+
+/// Markdown is read line by line. Front matter, code, HTML blocks, reference
+/// definitions and rules are kept, the markers of headings, quotes and lists are kept
+/// before their text, table rows are translated cell by cell and the lines of a
+/// paragraph are translated together.
+fn parse_markdown(text: &str, pieces: &mut Vec<Piece>) {
+    let lines = lines(text);
+    let mut index = 0;
+    if lines.first().map(|(_, line)| line.trim_end()) == Some("---")
+        && let Some(end) = lines[1..]
+            .iter()
+            .position(|(_, line)| matches!(line.trim_end(), "---" | "..."))
+    {
+        index = end + 2;
+        keep(
+            pieces,
+            &text[..lines.get(index).map_or(text.len(), |line| line.0)],
+        );
+    }
+
+    let mut paragraph: Option<usize> = None;
+    let mut fence: Option<String> = None;
+    let mut previous_blank = true;
+    let mut indented_code = false;
+    let flush = |paragraph: &mut Option<usize>, end: usize, pieces: &mut Vec<Piece>| {
+        if let Some(start) = paragraph.take() {
+            push_text(pieces, &text[start..end], FileFormat::Markdown);
+        }
+    };
+    for &(offset, line) in &lines[index..] {
+        let trimmed = line.trim();
+        if let Some(marker) = &fence {
+            keep(pieces, line);
+            if trimmed.starts_with(marker.as_str())
+                && marker
+                    .chars()
+                    .next()
+                    .is_some_and(|first| trimmed.chars().all(|c| c == first))
+            {
+                fence = None;
+            }
+            continue;
+        }
+        let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+        let is_code = (line.starts_with('\t') || indent >= 4)
+            && !trimmed.is_empty()
+            && paragraph.is_none()
+            && (previous_blank || indented_code);
+        indented_code = is_code;
+        if trimmed.is_empty() || is_code {
+            flush(&mut paragraph, offset, pieces);
+            keep(pieces, line);
+            previous_blank = trimmed.is_empty();
+            continue;
+        }
+        previous_blank = false;
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            flush(&mut paragraph, offset, pieces);
+            let first = trimmed.chars().next().unwrap_or('`');
+            fence = Some(trimmed.chars().take_while(|&c| c == first).collect());
+            keep(pieces, line);
+        } else if is_html_block(trimmed) || is_reference_definition(trimmed) || is_rule(trimmed) {
+            flush(&mut paragraph, offset, pieces);
+            keep(pieces, line);
+        } else if trimmed.starts_with('|') {
+            flush(&mut paragraph, offset, pieces);
+            push_table_row(pieces, line);
+        } else if let Some(prefix) = block_prefix(line) {
+            flush(&mut paragraph, offset, pieces);
+            keep(pieces, &line[..prefix]);
+            push_text(pieces, &line[prefix..], FileFormat::Markdown);
+        } else if paragraph.is_none() {
+            paragraph = Some(offset);
+        }
+    }
+    flush(&mut paragraph, text.len(), pieces);
+}
+
+fn is_html_block(line: &str) -> bool {
+    let mut chars = line.chars();
+    chars.next() == Some('<')
+        && chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '/' || c == '!')
+}
+
+fn is_reference_definition(line: &str) -> bool {
+    line.starts_with('[') && line.contains("]:")
+}
+
+/// Thematic breaks and the underlines of setext headings.
+fn is_rule(line: &str) -> bool {
+    let markers = line.chars().filter(|c| !c.is_whitespace());
+    let first = line.chars().next().unwrap_or_default();
+    matches!(first, '-' | '*' | '_' | '=')
+        && markers.clone().all(|c| c == first)
+        && markers.count() >= if first == '=' { 1 } else { 3 }
+}
+
+/// Length of the heading, quote, list and task markers at the start of `line`.
+fn block_prefix(line: &str) -> Option<usize> {
+    let mut rest = line;
+    let mut found = false;
+    loop {
+        let trimmed = rest.trim_start_matches([' ', '\t']);
+        if let Some(after) = trimmed.strip_prefix('>') {
+            rest = after.strip_prefix(' ').unwrap_or(after);
+        } else if let Some(after) = heading_marker(trimmed) {
+            rest = after;
+        } else if let Some(after) = list_marker(trimmed) {
+            rest = ["[ ] ", "[x] ", "[X] "]
+                .iter()
+                .find_map(|task| after.strip_prefix(task))
+                .unwrap_or(after);
+        } else {
+            break;
+        }
+        found = true;
+    }
+    found.then(|| line.len() - rest.len())
+}
+
+fn heading_marker(line: &str) -> Option<&str> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let after = &line[level..];
+    ((1..=6).contains(&level) && (after.starts_with([' ', '\t']) || after.trim().is_empty()))
+        .then(|| after.trim_start_matches([' ', '\t']))
+}
+
+fn list_marker(line: &str) -> Option<&str> {
+    if let Some(after) = line.strip_prefix(['-', '*', '+'])
+        && after.starts_with([' ', '\t'])
+    {
+        return Some(&after[1..]);
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    let after = line[digits..].strip_prefix(['.', ')'])?;
+    ((1..=9).contains(&digits) && after.starts_with([' ', '\t'])).then(|| &after[1..])
+}
+
+/// Translate the cells of a table row, keeping the pipes. Delimiter rows are kept.
+fn push_table_row(pieces: &mut Vec<Piece>, line: &str) {
+    if line
+        .trim()
+        .chars()
+        .all(|c| matches!(c, '|' | '-' | ':' | ' ' | '\t'))
+    {
+        keep(pieces, line);
+        return;
+    }
+    let mut start = 0;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '\\' if !escaped => {
+                escaped = true;
+                continue;
+            }
+            '|' if !escaped => {
+                push_text(pieces, &line[start..index], FileFormat::Markdown);
+                keep(pieces, "|");
+                start = index + 1;
+            }
+            _ => {}
+        }
+        escaped = false;
+    }
+    push_text(pieces, &line[start..], FileFormat::Markdown);
+}
+
+/// Subtitles are blocks separated by blank lines. In a cue the lines up to the timing
+/// line are kept and the text after it is translated. Blocks without a timing line,
+/// the `WEBVTT` header, notes and styles, are kept.
+fn parse_subtitles(text: &str, format: FileFormat, pieces: &mut Vec<Piece>) {
+    let mut cue_text: Option<usize> = None;
+    let mut after_timing = false;
+    for (offset, line) in lines(text) {
+        if line.trim().is_empty() {
+            if let Some(start) = cue_text.take() {
+                push_text(pieces, &text[start..offset], format);
+            }
+            keep(pieces, line);
+            after_timing = false;
+        } else if after_timing {
+            cue_text.get_or_insert(offset);
+        } else {
+            after_timing = line.contains("-->");
+            keep(pieces, line);
+        }
+    }
+    if let Some(start) = cue_text {
+        push_text(pieces, &text[start..], format);
+    }
+}
+
+/// A keyword of a PO entry and its string, joined from all its lines.
+struct PoField {
+    keyword: String,
+    value: String,
+    /// Index of the first and the last line of the field in the entry.
+    lines: (usize, usize),
+}
+
+/// Entries of a PO file are separated by blank lines. Entries with no translation get
+/// `msgstr` from `msgid`, and with plural forms `msgstr[0]` from `msgid` and the others
+/// from `msgid_plural`, as many as the header's `Plural-Forms` asks for. The header,
+/// obsolete entries and entries already translated are kept.
+fn parse_po(text: &str, pieces: &mut Vec<Piece>) {
+    let lines = lines(text);
+    let mut plural_forms: Option<usize> = None;
+    let mut start = 0;
+    while start < lines.len() {
+        if lines[start].1.trim().is_empty() {
+            keep(pieces, lines[start].1);
+            start += 1;
+            continue;
+        }
+        let end = lines[start..]
+            .iter()
+            .position(|(_, line)| line.trim().is_empty())
+            .map_or(lines.len(), |length| start + length);
+        let entry: Vec<&str> = lines[start..end].iter().map(|(_, line)| *line).collect();
+        push_po_entry(pieces, &entry, &mut plural_forms);
+        start = end;
+    }
+}
+
+fn push_po_entry(pieces: &mut Vec<Piece>, entry: &[&str], plural_forms: &mut Option<usize>) {
+    let fields = po_fields(entry);
+    let field = |keyword: &str| fields.iter().find(|field| field.keyword == keyword);
+    let msgstrs: Vec<&PoField> = fields
+        .iter()
+        .filter(|field| field.keyword.starts_with("msgstr"))
+        .collect();
+    let (Some(msgid), Some(first), Some(last)) = (field("msgid"), msgstrs.first(), msgstrs.last())
+    else {
+        entry.iter().for_each(|line| keep(pieces, line));
+        return;
+    };
+    if msgid.value.is_empty() {
+        *plural_forms = first.value.split_once("nplurals=").and_then(|(_, rest)| {
+            let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+            digits.parse().ok()
+        });
+    }
+    if msgid.value.is_empty() || msgstrs.iter().any(|msgstr| !msgstr.value.is_empty()) {
+        entry.iter().for_each(|line| keep(pieces, line));
+        return;
+    }
+
+    entry[..first.lines.0]
+        .iter()
+        .for_each(|line| keep(pieces, line));
+    let line_break = match entry[last.lines.1].ends_with("\r\n") {
+        true => "\r\n",
+        false => "\n",
+    };
+    match field("msgid_plural") {
+        Some(plural) => {
+            let count = msgstrs.len().max(plural_forms.unwrap_or(2));
+            for index in 0..count {
+                keep(pieces, &format!("msgstr[{}] \"", index));
+                let source = if index == 0 {
+                    &msgid.value
+                } else {
+                    &plural.value
+                };
+                push_text(pieces, source, FileFormat::Po);
+                keep(pieces, &format!("\"{}", line_break));
+            }
+        }
+        None => {
+            keep(pieces, "msgstr \"");
+            push_text(pieces, &msgid.value, FileFormat::Po);
+            keep(pieces, &format!("\"{}", line_break));
+        }
+    }
+    entry[last.lines.1 + 1..]
+        .iter()
+        .for_each(|line| keep(pieces, line));
+}
+
+fn po_fields(entry: &[&str]) -> Vec<PoField> {
+    let mut fields: Vec<PoField> = Vec::new();
+    let mut current = false;
+    for (index, line) in entry.iter().enumerate() {
+        let line = line.trim();
+        if line.starts_with('"') {
+            if current && let Some(field) = fields.last_mut() {
+                field.value += &unescape_po(line);
+                field.lines.1 = index;
+            }
+        } else if line.starts_with('#') {
+            current = false;
+        } else if let Some((keyword, string)) = line.split_once(char::is_whitespace) {
+            fields.push(PoField {
+                keyword: keyword.to_string(),
+                value: unescape_po(string.trim()),
+                lines: (index, index),
+            });
+            current = true;
+        }
+    }
+    fields
+}
+
+/// The content of a quoted PO string.
+fn unescape_po(quoted: &str) -> String {
+    let inner = quoted
+        .strip_prefix('"')
+        .and_then(|quoted| quoted.strip_suffix('"'))
+        .unwrap_or(quoted);
+    let mut text = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => text.push('\n'),
+            Some('t') => text.push('\t'),
+            Some('r') => text.push('\r'),
+            Some(c @ ('"' | '\\')) => text.push(c),
+            Some(c) => {
+                text.push('\\');
+                text.push(c);
+            }
+            None => text.push('\\'),
+        }
+    }
+    text
+}
+
+fn escape_po(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn placeholder(index: usize) -> String {
+    format!("⟦{}⟧", index)
+}
+
+/// Instructions to keep the placeholders and the batch markers of `text` as they are,
+/// empty if it has none.
+pub fn prompt_supplement(text: &str) -> String {
+    let mut supplement = String::new();
+    if text.contains('⟦') {
+        supplement += "\n文本中形如 ⟦0⟧ 的占位符代表不可翻译的内容，必须原样保留在译文中相应的位置，不得翻译、修改或删除。";
+    }
+    if text.contains('⟪') {
+        supplement += "\n文本由 ⟪1⟫、⟪2⟫ 等编号标记分为若干段，请逐段翻译，每段译文前原样保留该段的标记，不得合并、拆分或省略段落。";
+    }
+    supplement
+}
+
+/// Replace the spans of `text` which must not be translated with placeholders.
+/// Markdown keeps code spans, link destinations, tags and URLs, subtitles keep tags
+/// and styles, and PO strings keep format specifiers, tags and URLs.
+fn protect(text: &str, format: FileFormat) -> (String, Vec<String>) {
+    let mut result = String::with_capacity(text.len());
+    let mut protected = Vec::new();
+    let mut index = 0;
+    while index < text.len() {
+        let rest = &text[index..];
+        let span = match format {
+            FileFormat::Markdown => markdown_span(text, index),
+            FileFormat::SubRip | FileFormat::WebVtt => {
+                tag_span(rest).or_else(|| enclosed_span(rest, '{', '}'))
+            }
+            FileFormat::Po => format_specifier(rest)
+                .or_else(|| tag_span(rest))
+                .or_else(|| url_span(text, index)),
+            FileFormat::PlainText | FileFormat::Html | FileFormat::Xliff => None,
+        };
+        match span {
+            Some(length) => {
+                result.push_str(&placeholder(protected.len()));
+                protected.push(rest[..length].to_string());
+                index += length;
+            }
+            None => {
+                let c = rest.chars().next().unwrap_or_default();
+                // A backslash escapes the next character in Markdown.
+                let length = match (format, c) {
+                    (FileFormat::Markdown, '\\') => {
+                        1 + rest[1..].chars().next().map_or(0, char::len_utf8)
+                    }
+                    _ => c.len_utf8(),
+                };
+                result.push_str(&rest[..length]);
+                index += length;
+            }
+        }
+    }
+    (result, protected)
+}
+
+/// Put the protected spans back into `translation`, `None` if a placeholder is
+/// missing.
+fn restore(translation: &str, protected: &[String]) -> Option<String> {
+    let mut text = translation.to_string();
+    for (index, span) in protected.iter().enumerate() {
+        let placeholder = placeholder(index);
+        if !text.contains(&placeholder) {
+            return None;
+        }
+        text = text.replacen(&placeholder, span, 1);
+    }
+    Some(text)
+}
+
+/// Length of the span at `index` of Markdown `text` to protect.
+fn markdown_span(text: &str, index: usize) -> Option<usize> {
+    let rest = &text[index..];
+    if rest.starts_with('`') {
+        let ticks = rest.chars().take_while(|&c| c == '`').count();
+        let fence = &rest[..ticks];
+        return rest[ticks..]
+            .match_indices(fence)
+            .find(|(end, _)| !rest[ticks + end + ticks..].starts_with('`'))
+            .map(|(end, _)| ticks + end + ticks);
+    }
+    if let Some(after) = rest.strip_prefix(']') {
+        return match after.chars().next() {
+            Some('(') => enclosed_span(after, '(', ')').map(|length| 1 + length),
+            Some('[') => enclosed_span(after, '[', ']').map(|length| 1 + length),
+            _ => None,
+        };
+    }
+    tag_span(rest).or_else(|| url_span(text, index))
+}
+
+/// Length of `rest` up to the `close` matching its first character `open`.
+fn enclosed_span(rest: &str, open: char, close: char) -> Option<usize> {
+    if !rest.starts_with(open) {
+        return None;
+    }
+    let mut depth = 0;
+    for (index, c) in rest.char_indices() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(index + c.len_utf8());
+            }
+        } else if c == '\n' && open != '(' {
+            return None;
+        }
+    }
+    None
+}
+
+/// Tags and autolinks, as `<i>`, `</b>` and `<https://example.com>`.
+fn tag_span(rest: &str) -> Option<usize> {
+    let after = rest.strip_prefix('<')?;
+    let first = after.chars().next()?;
+    if !(first.is_ascii_alphabetic() || first == '/' || first == '!') {
+        return None;
+    }
+    let end = after.find(['>', '<', '\n'])?;
+    after[end..].starts_with('>').then_some(end + 2)
+}
+
+fn url_span(text: &str, index: usize) -> Option<usize> {
+    let rest = &text[index..];
+    if !(rest.starts_with("http://") || rest.starts_with("https://"))
+        || text[..index]
+            .chars()
+            .next_back()
+            .is_some_and(char::is_alphanumeric)
+    {
+        return None;
+    }
+    let end = rest
+        .find(|c: char| c.is_whitespace() || matches!(c, ')' | '>' | '"' | '<'))
+        .unwrap_or(rest.len());
+    let url = rest[..end].trim_end_matches(['.', ',', ';', ':', '!', '?']);
+    Some(url.len())
+}
+
+/// printf and Python format specifiers, as `%s`, `%1$d`, `%(name)s` and `{name}`.
+fn format_specifier(rest: &str) -> Option<usize> {
+    if rest.starts_with('{') {
+        return enclosed_span(rest, '{', '}').filter(|&length| {
+            rest[1..length - 1]
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | ':' | '!'))
+        });
+    }
+    let after = rest.strip_prefix('%')?;
+    if after.starts_with('%') {
+        return Some(2);
+    }
+    let mut length = 1;
+    if after.starts_with('(') {
+        length += after.find(')')? + 1;
+    }
+    let conversion = rest[length..].find(|c: char| {
+        !(c.is_ascii_digit() || matches!(c, '$' | '-' | '+' | ' ' | '#' | '.' | '*' | 'l' | 'h'))
+    })?;
+    let c = rest[length + conversion..].chars().next()?;
+    "sdifFeEgGxXoucpaA"
+        .contains(c)
+        .then_some(length + conversion + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(text: &str, format: FileFormat) -> String {
+        translate_text(
+            text,
+            format,
+            Language::English,
+            Language::French,
+            |s| Ok(s.to_string()),
+            |_, _| {},
+        )
+        .unwrap()
+        .0
+    }
+
+    fn uppercase(text: &str, format: FileFormat) -> (String, Summary) {
+        translate_text(
+            text,
+            format,
+            Language::English,
+            Language::French,
+            |s| Ok(s.to_uppercase()),
+            |_, _| {},
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn files_round_trip_unchanged() {
+        for (text, format) in [
+            (include_str!("sample.md"), FileFormat::Markdown),
+            (include_str!("sample.srt"), FileFormat::SubRip),
+            (include_str!("sample.vtt"), FileFormat::WebVtt),
+            (include_str!("sample.txt"), FileFormat::PlainText),
+        ] {
+            assert_eq!(identity(text, format), text);
+        }
+    }
+
+    #[test]
+    fn only_text_is_translated() {
+        for (text, expected, format) in [
+            (
+                include_str!("sample.md"),
+                include_str!("sample.translated.md"),
+                FileFormat::Markdown,
+            ),
+            (
+                include_str!("sample.srt"),
+                include_str!("sample.translated.srt"),
+                FileFormat::SubRip,
+            ),
+            (
+                include_str!("sample.vtt"),
+                include_str!("sample.translated.vtt"),
+                FileFormat::WebVtt,
+            ),
+            (
+                include_str!("sample.po"),
+                include_str!("sample.translated.po"),
+                FileFormat::Po,
+            ),
+        ] {
+            let (translated, summary) = uppercase(text, format);
+            assert_eq!(translated, expected);
+            assert_eq!(summary.untranslated, 0);
+        }
+    }
+
+    #[test]
+    fn lost_placeholders_keep_the_source() {
+        let (translated, summary) = translate_text(
+            "Run `cargo test` first.\n",
+            FileFormat::Markdown,
+            Language::English,
+            Language::French,
+            |_| Ok("Exécutez d'abord les tests.".to_string()),
+            |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(translated, "Run `cargo test` first.\n");
+        assert_eq!(summary.untranslated, 1);
+    }
+
+    #[test]
+    fn translated_copies_are_named_after_the_language() {
+        assert_eq!(
+            translated_path(Path::new("docs/notes.md"), Language::Chinese),
+            Path::new("docs/notes.zh.md")
+        );
+        assert_eq!(
+            translated_path(Path::new("messages.pot"), Language::French),
+            Path::new("messages.fr.po")
+        );
+    }
+
+    /// Answers with the segment, cut off after its first word.
+    struct CutOff;
+    impl crate::Translator for CutOff {
+        fn get_api_key(&self) -> String {
+            String::new()
+        }
+        fn save_api_key(&self) -> Result<(), Error> {
+            Ok(())
+        }
+        fn get_url(&self) -> String {
+            String::new()
+        }
+    }
+    impl StreamSentenceTranslator for CutOff {
+        fn stream_translate_sentence(
+            &self,
+            sentence: &str,
+            source_language: Language,
+            target_language: Language,
+        ) -> Result<std::sync::mpsc::Receiver<String>, Error> {
+            Ok(self
+                .stream_translate_sentence_with_reasoning(
+                    sentence,
+                    source_language,
+                    target_language,
+                )?
+                .answer)
+        }
+
+        fn stream_translate_sentence_with_reasoning(
+            &self,
+            sentence: &str,
+            _source_language: Language,
+            _target_language: Language,
+        ) -> Result<crate::SentenceStream, Error> {
+            let (tx, answer) = std::sync::mpsc::channel();
+            let (_, reasoning) = std::sync::mpsc::channel();
+            let (outcome_tx, outcome) = std::sync::mpsc::channel();
+            tx.send(sentence.split(' ').next().unwrap_or_default().to_string())?;
+            outcome_tx.send(Err(anyhow!(
+                "The translation was cut off at the most tokens"
+            )))?;
+            Ok(crate::SentenceStream {
+                answer,
+                reasoning,
+                outcome,
+            })
+        }
+    }
+
+    #[test]
+    fn segments_cut_off_keep_the_source() {
+        let (translated, summary) = translate_text(
+            "Run the tests first.\n",
+            FileFormat::PlainText,
+            Language::English,
+            Language::French,
+            |segment| translate_segment(&CutOff, segment, Language::English, Language::French),
+            |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(translated, "Run the tests first.\n");
+        assert_eq!(
+            summary.failures,
+            ["Failed to translate: Run the tests first.: \
+              The translation was cut off at the most tokens"]
+        );
+    }
+
+    #[test]
+    fn failed_segments_do_not_lose_the_others() {
+        let (translated, summary) = translate_text(
+            "# Title\n\nThe network failed here.\n\nBut not `here`.\n",
+            FileFormat::Markdown,
+            Language::English,
+            Language::French,
+            |segment| match segment.contains("failed") {
+                true => Err(anyhow!("Connection reset")),
+                false => Ok(segment.to_uppercase()),
+            },
+            |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(
+            translated,
+            "# TITLE\n\nThe network failed here.\n\nBUT NOT `here`.\n"
+        );
+        assert_eq!(summary.segments, 3);
+        assert_eq!(summary.untranslated, 0);
+        assert_eq!(summary.failures, ["Connection reset"]);
+    }
+}
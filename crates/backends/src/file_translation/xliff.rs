@@ -0,0 +1,156 @@
+//! XLIFF 1.2 and 2.0 documents. The source of every unit without a translation is
+//! translated into its target, which is added after the source when it is missing.
+//! Inline codes of the source are kept behind placeholders.
+
+use super::{
+    Piece, keep,
+    markup::{self, MarkupKind, Token, TokenKind},
+};
+use crate::Language;
+
+/// Elements holding earlier or suggested translations, left as they are.
+const SUGGESTIONS: [&str; 2] = ["alt-trans", "mtc:match"];
+
+pub(super) fn is_xliff(text: &str) -> bool {
+    markup::tokens(text, MarkupKind::Xml)
+        .iter()
+        .any(|token| token.kind == TokenKind::Open && token.name == "xliff")
+}
+
+pub(super) fn parse(text: &str, target_language: Language, pieces: &mut Vec<Piece>) {
+    let tokens = markup::tokens(text, MarkupKind::Xml);
+    let mut index = 0;
+    while index < tokens.len() {
+        let token = tokens[index];
+        if token.kind != TokenKind::Open {
+            keep(pieces, token.raw);
+            index += 1;
+            continue;
+        }
+        let untranslated = SUGGESTIONS.contains(&token.name)
+            || (matches!(token.name, "group" | "unit" | "trans-unit")
+                && token.attribute("translate") == Some("no"));
+        if untranslated {
+            let end =
+                markup::matching_close(&tokens, index, MarkupKind::Xml).unwrap_or(tokens.len() - 1);
+            tokens[index..=end]
+                .iter()
+                .for_each(|token| keep(pieces, token.raw));
+            index = end + 1;
+            continue;
+        }
+        if token.name == "source"
+            && let Some(next) = push_unit(pieces, text, &tokens, index)
+        {
+            index = next;
+            continue;
+        }
+        let language = target_language.code();
+        match token.name {
+            "xliff"
+                if token
+                    .attribute("version")
+                    .is_some_and(|v| v.starts_with('2'))
+                    && token.attribute("trgLang").is_none() =>
+            {
+                keep(pieces, &with_attribute(token.raw, "trgLang", language));
+            }
+            "file"
+                if token.attribute("source-language").is_some()
+                    && token.attribute("target-language").is_none() =>
+            {
+                keep(
+                    pieces,
+                    &with_attribute(token.raw, "target-language", language),
+                );
+            }
+            _ => keep(pieces, token.raw),
+        }
+        index += 1;
+    }
+}
+
+/// Translate the source opened at `source` into its target. Returns the index of the
+/// token after the target, or `None` if the unit is already translated.
+fn push_unit(
+    pieces: &mut Vec<Piece>,
+    text: &str,
+    tokens: &[Token],
+    source: usize,
+) -> Option<usize> {
+    let source_end = markup::matching_close(tokens, source, MarkupKind::Xml)?;
+    let content = &text[tokens[source].end()..tokens[source_end].start];
+
+    // The target follows the source, possibly after notes and comments.
+    let mut target = None;
+    let mut next = source_end + 1;
+    while let Some(token) = tokens.get(next) {
+        match token.kind {
+            TokenKind::Open | TokenKind::Empty if token.name == "target" => {
+                target = Some(next);
+                break;
+            }
+            TokenKind::Open if token.name == "source" => break,
+            TokenKind::Open => {
+                next = markup::matching_close(tokens, next, MarkupKind::Xml)? + 1;
+            }
+            TokenKind::Close => break,
+            _ => next += 1,
+        }
+    }
+
+    match target.map(|target| (target, tokens[target])) {
+        Some((target, token)) if token.kind == TokenKind::Empty => {
+            keep_tokens(pieces, &tokens[source..target]);
+            let open = token
+                .raw
+                .trim_end_matches('>')
+                .trim_end_matches('/')
+                .trim_end();
+            keep(pieces, &format!("{}>", open));
+            markup::push_run(pieces, content, MarkupKind::Xml);
+            keep(pieces, "</target>");
+            Some(target + 1)
+        }
+        Some((target, token)) => {
+            let target_end = markup::matching_close(tokens, target, MarkupKind::Xml)?;
+            let translated = &text[token.end()..tokens[target_end].start];
+            if !translated.trim().is_empty() {
+                return None;
+            }
+            keep_tokens(pieces, &tokens[source..=target]);
+            markup::push_run(pieces, content, MarkupKind::Xml);
+            keep(pieces, tokens[target_end].raw);
+            Some(target_end + 1)
+        }
+        None => {
+            keep_tokens(pieces, &tokens[source..=source_end]);
+            // Indent the new target as the source.
+            let indent = source
+                .checked_sub(1)
+                .map(|before| tokens[before])
+                .filter(|before| before.kind == TokenKind::Text && before.raw.contains('\n'))
+                .and_then(|before| before.raw.rsplit_once('\n'))
+                .map(|(_, indent)| format!("\n{}", indent))
+                .unwrap_or_default();
+            keep(pieces, &format!("{}<target>", indent));
+            markup::push_run(pieces, content, MarkupKind::Xml);
+            keep(pieces, "</target>");
+            Some(source_end + 1)
+        }
+    }
+}
+
+fn keep_tokens(pieces: &mut Vec<Piece>, tokens: &[Token]) {
+    tokens.iter().for_each(|token| keep(pieces, token.raw));
+}
+
+/// The start tag `raw` with the attribute `name` added.
+fn with_attribute(raw: &str, name: &str, value: &str) -> String {
+    format!(
+        "{} {}=\"{}\">",
+        raw.trim_end_matches('>').trim_end(),
+        name,
+        value
+    )
+}
@@ -0,0 +1,371 @@
+//! Glossaries of required translations of terms, by language pair. Terms found in a
+//! sentence are given to the AI engines and checked in the translation.
+
+use std::{
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Error, Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::{Language, utils::xml};
+
+const CSV_HEADER: [&str; 4] = [
+    "source_language",
+    "target_language",
+    "source_term",
+    "target_term",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GlossaryEntry {
+    pub source_language: Language,
+    pub target_language: Language,
+    pub source_term: String,
+    /// The only translation of `source_term` allowed.
+    pub target_term: String,
+}
+
+/// Entries of the language pair whose source term occurs in `text`.
+pub fn relevant_entries<'a>(
+    entries: &'a [GlossaryEntry],
+    text: &str,
+    source_language: Language,
+    target_language: Language,
+) -> Vec<&'a GlossaryEntry> {
+    let text = text.to_lowercase();
+    entries
+        .iter()
+        .filter(|entry| {
+            entry.source_language == source_language
+                && entry.target_language == target_language
+                && contains_term(&text, &entry.source_term.to_lowercase())
+        })
+        .collect()
+}
+
+/// Instructions appended to the system prompt, empty without entries.
+pub fn prompt_supplement(entries: &[&GlossaryEntry]) -> String {
+    if entries.is_empty() {
+        return String::new();
+    }
+    let mut supplement = "\n请严格使用以下术语译法：".to_string();
+    for entry in entries {
+        supplement += &format!("\n{} → {}", entry.source_term, entry.target_term);
+    }
+    supplement
+}
+
+/// Entries whose target term is missing from `translation`.
+pub fn violations<'a>(entries: &[&'a GlossaryEntry], translation: &str) -> Vec<&'a GlossaryEntry> {
+    let translation = translation.to_lowercase();
+    entries
+        .iter()
+        .filter(|entry| !translation.contains(&entry.target_term.to_lowercase()))
+        .copied()
+        .collect()
+}
+
+/// Add `imported` to `entries`, replacing entries of the same source term and
+/// language pair. Returns the number of entries added or replaced.
+pub fn merge(entries: &mut Vec<GlossaryEntry>, imported: Vec<GlossaryEntry>) -> usize {
+    let mut changed = 0;
+    for entry in imported {
+        match entries.iter_mut().find(|existing| {
+            existing.source_language == entry.source_language
+                && existing.target_language == entry.target_language
+                && existing
+                    .source_term
+                    .eq_ignore_ascii_case(&entry.source_term)
+        }) {
+            Some(existing) if *existing == entry => continue,
+            Some(existing) => *existing = entry,
+            None => entries.push(entry),
+        }
+        changed += 1;
+    }
+    changed
+}
+
+/// Read a `.tbx` or `.csv` file.
+pub fn import(path: &Path) -> Result<Vec<GlossaryEntry>, Error> {
+    let text = fs::read_to_string(path)
+        .with_context(|| anyhow!("Failed to read glossary {}", path.display()))?;
+    if is_tbx(path) {
+        read_tbx(&text)
+    } else {
+        read_csv(text.as_bytes())
+    }
+    .with_context(|| anyhow!("Failed to import glossary {}", path.display()))
+}
+
+/// Write a `.tbx` or `.csv` file.
+pub fn export(entries: &[GlossaryEntry], path: &Path) -> Result<(), Error> {
+    let mut file = fs::File::create(path)
+        .with_context(|| anyhow!("Failed to create glossary {}", path.display()))?;
+    if is_tbx(path) {
+        file.write_all(write_tbx(entries).as_bytes())?;
+        Ok(())
+    } else {
+        write_csv(entries, true, file)
+    }
+}
+
+fn is_tbx(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("tbx"))
+}
+
+/// Read rows of source language, target language, source term and target term. A
+/// header row is skipped.
+pub fn read_csv<R: Read>(reader: R) -> Result<Vec<GlossaryEntry>, Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let mut entries = Vec::new();
+    for (line, record) in reader.records().enumerate() {
+        let record = record?;
+        if record.iter().all(str::is_empty) || record.get(0) == Some(CSV_HEADER[0]) {
+            continue;
+        }
+        let [source_language, target_language, source_term, target_term] =
+            [0, 1, 2, 3].map(|index| record.get(index).unwrap_or_default());
+        if source_term.is_empty() || target_term.is_empty() {
+            return Err(anyhow!("Missing term on line {}", line + 1));
+        }
+        entries.push(GlossaryEntry {
+            source_language: Language::from_name_or_code(source_language)
+                .with_context(|| anyhow!("Invalid entry on line {}", line + 1))?,
+            target_language: Language::from_name_or_code(target_language)
+                .with_context(|| anyhow!("Invalid entry on line {}", line + 1))?,
+            source_term: source_term.to_string(),
+            target_term: target_term.to_string(),
+        });
+    }
+    Ok(entries)
+}
+
+pub fn write_csv<W: Write>(
+    entries: &[GlossaryEntry],
+    header: bool,
+    writer: W,
+) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+    if header {
+        writer.write_record(CSV_HEADER)?;
+    }
+    for entry in entries {
+        writer.write_record([
+            entry.source_language.to_string().as_str(),
+            &entry.target_language.to_string(),
+            &entry.source_term,
+            &entry.target_term,
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read the concept entries of a TBX file, in the format of TBX 2 (`termEntry`,
+/// `langSet`) or TBX 3 (`conceptEntry`, `langSec`). Every pair of languages of a
+/// concept becomes an entry, using the first term of each language.
+pub fn read_tbx(text: &str) -> Result<Vec<GlossaryEntry>, Error> {
+    let document = roxmltree::Document::parse(text)?;
+    let mut entries = Vec::new();
+    for concept in document
+        .descendants()
+        .filter(|node| matches!(node.tag_name().name(), "termEntry" | "conceptEntry"))
+    {
+        let terms = concept
+            .children()
+            .filter(|node| matches!(node.tag_name().name(), "langSet" | "langSec"))
+            .filter_map(|language_set| {
+                let language =
+                    Language::from_name_or_code(language_set.attribute((xml::NAMESPACE, "lang"))?)
+                        .ok()?;
+                let term = language_set
+                    .descendants()
+                    .find(|node| node.tag_name().name() == "term")?
+                    .text()?
+                    .trim();
+                (!term.is_empty()).then(|| (language, term.to_string()))
+            })
+            .collect::<Vec<_>>();
+        for (source_language, source_term) in &terms {
+            for (target_language, target_term) in &terms {
+                if source_language != target_language {
+                    entries.push(GlossaryEntry {
+                        source_language: *source_language,
+                        target_language: *target_language,
+                        source_term: source_term.clone(),
+                        target_term: target_term.clone(),
+                    });
+                }
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Write a TBX 2 file with one concept entry per glossary entry.
+pub fn write_tbx(entries: &[GlossaryEntry]) -> String {
+    let mut tbx = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <martif type=\"TBX\" xml:lang=\"en\">\n\
+         \x20 <martifHeader><fileDesc><sourceDesc><p>Neat Translator glossary</p></sourceDesc></fileDesc></martifHeader>\n\
+         \x20 <text>\n    <body>\n",
+    );
+    for (index, entry) in entries.iter().enumerate() {
+        tbx += &format!("      <termEntry id=\"t{}\">\n", index + 1);
+        for (language, term) in [
+            (entry.source_language, &entry.source_term),
+            (entry.target_language, &entry.target_term),
+        ] {
+            tbx += &format!(
+                "        <langSet xml:lang=\"{}\"><tig><term>{}</term></tig></langSet>\n",
+                language.code(),
+                xml::escape(term)
+            );
+        }
+        tbx += "      </termEntry>\n";
+    }
+    tbx += "    </body>\n  </text>\n</martif>\n";
+    tbx
+}
+
+/// Whether `term` occurs in `text` as whole words. Both are lower case.
+fn contains_term(text: &str, term: &str) -> bool {
+    if term.is_empty() {
+        return false;
+    }
+    // A term starting or ending with a letter must not continue a word.
+    let joins = |neighbour: Option<char>, edge: Option<char>| {
+        neighbour.is_some_and(is_word_char) && edge.is_some_and(is_word_char)
+    };
+    text.match_indices(term).any(|(start, _)| {
+        !joins(text[..start].chars().next_back(), term.chars().next())
+            && !joins(
+                text[start + term.len()..].chars().next(),
+                term.chars().next_back(),
+            )
+    })
+}
+
+/// Letters and digits of scripts written with spaces between words, unlike CJK.
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() && c < '\u{2e80}'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(source_term: &str, target_term: &str) -> GlossaryEntry {
+        GlossaryEntry {
+            source_language: Language::English,
+            target_language: Language::Chinese,
+            source_term: source_term.to_string(),
+            target_term: target_term.to_string(),
+        }
+    }
+
+    #[test]
+    fn terms_match_whole_words_of_the_language_pair() {
+        let entries = [
+            entry("Kernel", "内核"),
+            entry("core dump", "核心转储"),
+            entry("ram", "内存"),
+            GlossaryEntry {
+                target_language: Language::French,
+                ..entry("kernel", "noyau")
+            },
+        ];
+        let relevant = relevant_entries(
+            &entries,
+            "The kernel wrote a core dump, framing the error.",
+            Language::English,
+            Language::Chinese,
+        );
+        assert_eq!(relevant, [&entries[0], &entries[1]]);
+
+        let supplement = prompt_supplement(&relevant);
+        assert!(supplement.ends_with("Kernel → 内核\ncore dump → 核心转储"));
+        assert_eq!(
+            violations(&relevant, "内核写入了一个核心文件。"),
+            [&entries[1]]
+        );
+        // Chinese is written without spaces.
+        let chinese = [GlossaryEntry {
+            source_language: Language::Chinese,
+            target_language: Language::English,
+            ..entry("内核", "kernel")
+        }];
+        assert_eq!(
+            relevant_entries(
+                &chinese,
+                "这是内核的错误",
+                Language::Chinese,
+                Language::English
+            ),
+            [&chinese[0]]
+        );
+    }
+
+    #[test]
+    fn csv_round_trips() {
+        let entries = vec![entry("bank, river", "河岸"), entry("\"quoted\"", "引用")];
+        let mut csv = Vec::new();
+        write_csv(&entries, true, &mut csv).unwrap();
+        assert_eq!(read_csv(csv.as_slice()).unwrap(), entries);
+        assert_eq!(
+            read_csv("en, zh-CN, kernel, 内核\n\n".as_bytes()).unwrap(),
+            [entry("kernel", "内核")]
+        );
+        assert!(read_csv("English,Chinese,kernel\n".as_bytes()).is_err());
+        assert!(read_csv("Klingon,Chinese,kernel,内核\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn tbx_round_trips_in_both_directions() {
+        let entries = [entry("R&D", "研发")];
+        let imported = read_tbx(&write_tbx(&entries)).unwrap();
+        assert_eq!(
+            imported,
+            [
+                entry("R&D", "研发"),
+                GlossaryEntry {
+                    source_language: Language::Chinese,
+                    target_language: Language::English,
+                    source_term: "研发".to_string(),
+                    target_term: "R&D".to_string(),
+                },
+            ]
+        );
+
+        let tbx3 = r#"<tbx xmlns="urn:iso:std:iso:30042:ed-2"><text><body>
+            <conceptEntry id="c1">
+                <langSec xml:lang="en-GB"><termSec><term>kernel</term></termSec></langSec>
+                <langSec xml:lang="zh-Hans"><termSec><term>内核</term></termSec></langSec>
+            </conceptEntry>
+        </body></text></tbx>"#;
+        assert_eq!(read_tbx(tbx3).unwrap()[0], entry("kernel", "内核"));
+    }
+
+    #[test]
+    fn merging_replaces_the_same_term() {
+        let mut entries = vec![entry("kernel", "核心")];
+        let changed = merge(
+            &mut entries,
+            vec![
+                entry("Kernel", "内核"),
+                entry("shell", "外壳"),
+                entry("shell", "外壳"),
+            ],
+        );
+        assert_eq!(changed, 2);
+        assert_eq!(entries, [entry("Kernel", "内核"), entry("shell", "外壳")]);
+    }
+}
@@ -0,0 +1,475 @@
+//! Translation history, kept in the local database with a full-text index.
+
+use std::path::Path;
+
+use anyhow::{Context, Error, Result, anyhow};
+use rusqlite::{OptionalExtension, Row, params, types::Value};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Language, TokenUsage,
+    database::{self, Schema, Store},
+    dict_interface::WordExplanation,
+    storage,
+};
+
+/// Default number of entries returned by a search.
+const DEFAULT_SEARCH_LIMIT: usize = 200;
+
+/// The trigram tokenizer cannot match shorter queries, which are looked up with `LIKE`.
+const MIN_FULL_TEXT_QUERY_CHARS: usize = 3;
+
+const SCHEMA: Schema = Schema {
+    name: "history tables",
+    sql: r#"
+CREATE TABLE IF NOT EXISTS history (
+    id INTEGER PRIMARY KEY,
+    mode TEXT NOT NULL,
+    input TEXT NOT NULL,
+    output TEXT NOT NULL,
+    output_text TEXT NOT NULL,
+    engine TEXT NOT NULL,
+    source_language TEXT NOT NULL,
+    target_language TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    input_tokens INTEGER,
+    output_tokens INTEGER
+);
+CREATE INDEX IF NOT EXISTS history_created_at ON history (created_at);
+
+-- The trigram tokenizer also matches inside CJK text, which has no word boundaries.
+CREATE VIRTUAL TABLE IF NOT EXISTS history_fts USING fts5 (
+    input, output_text, content = 'history', content_rowid = 'id', tokenize = 'trigram'
+);
+CREATE TRIGGER IF NOT EXISTS history_after_insert AFTER INSERT ON history BEGIN
+    INSERT INTO history_fts (rowid, input, output_text)
+    VALUES (new.id, new.input, new.output_text);
+END;
+CREATE TRIGGER IF NOT EXISTS history_after_delete AFTER DELETE ON history BEGIN
+    INSERT INTO history_fts (history_fts, rowid, input, output_text)
+    VALUES ('delete', old.id, old.input, old.output_text);
+END;
+"#,
+};
+
+#[derive(
+    strum::Display, strum::EnumString, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq,
+)]
+pub enum TranslationMode {
+    Word,
+    Sentence,
+}
+
+/// What the engine returned, stored so that an entry can be shown again without
+/// another API call.
+#[derive(Serialize, Deserialize, Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum TranslationOutput {
+    Word(WordExplanation),
+    Sentence(String),
+}
+impl TranslationOutput {
+    pub fn mode(&self) -> TranslationMode {
+        match self {
+            Self::Word(_) => TranslationMode::Word,
+            Self::Sentence(_) => TranslationMode::Sentence,
+        }
+    }
+
+    /// Plain text of the output, which is indexed for search and shown in lists.
+    pub fn text(&self) -> String {
+        match self {
+            Self::Sentence(sentence) => sentence.clone(),
+            Self::Word(word_explanation) => {
+                let mut texts = vec![word_explanation.word.clone()];
+                for part_of_speech in word_explanation.part_of_speeches.iter().flatten() {
+                    for explanation in &part_of_speech.explanations {
+                        texts.push(explanation.explanation.clone());
+                        texts.push(explanation.definition.clone());
+                    }
+                }
+                texts.join("\n")
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub input: String,
+    pub output: TranslationOutput,
+    /// Name of the engine, e.g. `Qwen`.
+    pub engine: String,
+    pub source_language: Language,
+    pub target_language: Language,
+    /// Seconds since the Unix epoch.
+    pub created_at: i64,
+    pub token_usage: Option<TokenUsage>,
+}
+
+/// Filters of `HistoryStore::search`. Unset filters match every entry.
+#[derive(Debug, Clone)]
+pub struct HistoryQuery {
+    /// Matched against the input and the output.
+    pub text: Option<String>,
+    pub mode: Option<TranslationMode>,
+    pub source_language: Option<Language>,
+    pub target_language: Option<Language>,
+    /// Seconds since the Unix epoch, inclusive.
+    pub since: Option<i64>,
+    /// Seconds since the Unix epoch, exclusive.
+    pub until: Option<i64>,
+    pub limit: usize,
+}
+impl Default for HistoryQuery {
+    fn default() -> Self {
+        Self {
+            text: None,
+            mode: None,
+            source_language: None,
+            target_language: None,
+            since: None,
+            until: None,
+            limit: DEFAULT_SEARCH_LIMIT,
+        }
+    }
+}
+
+/// Translation history. The store can be shared between threads.
+pub struct HistoryStore {
+    store: Store,
+}
+impl HistoryStore {
+    /// Open the history in the local database under the config dir.
+    pub fn open_default() -> Result<Self, Error> {
+        Ok(Self {
+            store: Store::open_default(&SCHEMA)?,
+        })
+    }
+
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        Ok(Self {
+            store: Store::open(path, &SCHEMA)?,
+        })
+    }
+
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Ok(Self {
+            store: Store::open_in_memory(&SCHEMA)?,
+        })
+    }
+
+    /// Record a finished translation and return the id of the new entry.
+    pub fn record(
+        &self,
+        input: &str,
+        output: &TranslationOutput,
+        engine: &str,
+        (source_language, target_language): (Language, Language),
+        token_usage: Option<TokenUsage>,
+    ) -> Result<i64, Error> {
+        let output_json = serde_json::to_string(output)
+            .map_err(|e| anyhow!("Failed to serialize translation output: {}", e))?;
+        let connection = self.store.connection();
+        connection
+            .execute(
+                "INSERT INTO history (mode, input, output, output_text, engine, source_language, \
+                 target_language, created_at, input_tokens, output_tokens) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    output.mode().to_string(),
+                    input,
+                    output_json,
+                    output.text(),
+                    engine,
+                    source_language.to_string(),
+                    target_language.to_string(),
+                    database::now(),
+                    token_usage.map(|usage| usage.input_tokens),
+                    token_usage.map(|usage| usage.output_tokens),
+                ],
+            )
+            .with_context(|| anyhow!("Failed to record history of {}", input))?;
+        Ok(connection.last_insert_rowid())
+    }
+
+    pub fn get(&self, id: i64) -> Result<Option<HistoryEntry>, Error> {
+        let connection = self.store.connection();
+        connection
+            .query_row(
+                "SELECT id, input, output, engine, source_language, target_language, created_at, \
+                 input_tokens, output_tokens FROM history WHERE id = ?1",
+                [id],
+                |row| Ok(entry_from_row(row)),
+            )
+            .optional()
+            .with_context(|| anyhow!("Failed to read history entry {}", id))?
+            .transpose()
+    }
+
+    /// Entries matching `query`, newest first.
+    pub fn search(&self, query: &HistoryQuery) -> Result<Vec<HistoryEntry>, Error> {
+        let mut conditions: Vec<&str> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        if let Some(text) = query
+            .text
+            .as_deref()
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+        {
+            if text.chars().count() >= MIN_FULL_TEXT_QUERY_CHARS {
+                conditions.push("id IN (SELECT rowid FROM history_fts WHERE history_fts MATCH ?)");
+                // Quoted as a single phrase, so that FTS syntax in the text is not interpreted.
+                values.push(Value::Text(format!("\"{}\"", text.replace('"', "\"\""))));
+            } else {
+                conditions.push("(input LIKE ? ESCAPE '\\' OR output_text LIKE ? ESCAPE '\\')");
+                let pattern = format!(
+                    "%{}%",
+                    text.replace('\\', "\\\\")
+                        .replace('%', "\\%")
+                        .replace('_', "\\_")
+                );
+                values.push(Value::Text(pattern.clone()));
+                values.push(Value::Text(pattern));
+            }
+        }
+        if let Some(mode) = query.mode {
+            conditions.push("mode = ?");
+            values.push(Value::Text(mode.to_string()));
+        }
+        if let Some(language) = query.source_language {
+            conditions.push("source_language = ?");
+            values.push(Value::Text(language.to_string()));
+        }
+        if let Some(language) = query.target_language {
+            conditions.push("target_language = ?");
+            values.push(Value::Text(language.to_string()));
+        }
+        if let Some(since) = query.since {
+            conditions.push("created_at >= ?");
+            values.push(Value::Integer(since));
+        }
+        if let Some(until) = query.until {
+            conditions.push("created_at < ?");
+            values.push(Value::Integer(until));
+        }
+
+        let mut sql = String::from(
+            "SELECT id, input, output, engine, source_language, target_language, created_at, \
+             input_tokens, output_tokens FROM history",
+        );
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(" ORDER BY created_at DESC, id DESC LIMIT ?");
+        values.push(Value::Integer(
+            i64::try_from(query.limit).unwrap_or(i64::MAX),
+        ));
+
+        let connection = self.store.connection();
+        let mut statement = connection.prepare(&sql)?;
+        let rows = statement
+            .query_map(rusqlite::params_from_iter(values), |row| {
+                Ok(entry_from_row(row))
+            })
+            .with_context(|| anyhow!("Failed to search history"))?;
+        let mut entries = Vec::new();
+        for row in rows {
+            entries.push(row??);
+        }
+        Ok(entries)
+    }
+
+    pub fn delete(&self, id: i64) -> Result<(), Error> {
+        self.store
+            .connection()
+            .execute("DELETE FROM history WHERE id = ?1", [id])
+            .with_context(|| anyhow!("Failed to delete history entry {}", id))?;
+        Ok(())
+    }
+
+    pub fn clear(&self) -> Result<(), Error> {
+        self.store
+            .connection()
+            .execute("DELETE FROM history", [])
+            .with_context(|| anyhow!("Failed to clear history"))?;
+        Ok(())
+    }
+
+    /// Remove the entries which are too old or beyond the maximum count, and return
+    /// how many were removed.
+    pub fn apply_retention(&self, config: &storage::History) -> Result<usize, Error> {
+        let connection = self.store.connection();
+        let mut removed = 0;
+        if let Some(max_age_days) = config.max_age_days {
+            let oldest = database::now() - i64::from(max_age_days) * 24 * 60 * 60;
+            removed += connection
+                .execute("DELETE FROM history WHERE created_at < ?1", [oldest])
+                .with_context(|| anyhow!("Failed to remove old history"))?;
+        }
+        if let Some(max_entries) = config.max_entries {
+            removed += connection
+                .execute(
+                    "DELETE FROM history WHERE id NOT IN \
+                     (SELECT id FROM history ORDER BY created_at DESC, id DESC LIMIT ?1)",
+                    [max_entries as i64],
+                )
+                .with_context(|| anyhow!("Failed to trim history"))?;
+        }
+        Ok(removed)
+    }
+}
+
+/// Columns are expected in the order of the `SELECT` statements above.
+fn entry_from_row(row: &Row) -> Result<HistoryEntry, Error> {
+    let output: String = row.get(2)?;
+    let source_language: String = row.get(4)?;
+    let target_language: String = row.get(5)?;
+    let input_tokens: Option<u32> = row.get(7)?;
+    let output_tokens: Option<u32> = row.get(8)?;
+    Ok(HistoryEntry {
+        id: row.get(0)?,
+        input: row.get(1)?,
+        output: serde_json::from_str(&output)
+            .map_err(|e| anyhow!("Failed to deserialize history output: {}", e))?,
+        engine: row.get(3)?,
+        source_language: source_language
+            .parse()
+            .map_err(|_| anyhow!("Unknown language in history: {}", source_language))?,
+        target_language: target_language
+            .parse()
+            .map_err(|_| anyhow!("Unknown language in history: {}", target_language))?,
+        created_at: row.get(6)?,
+        token_usage: input_tokens
+            .zip(output_tokens)
+            .map(|(input_tokens, output_tokens)| TokenUsage {
+                input_tokens,
+                output_tokens,
+                ..Default::default()
+            }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store_with_entries() -> HistoryStore {
+        let store = HistoryStore::open_in_memory().unwrap();
+        store
+            .record(
+                "arrive",
+                &TranslationOutput::Word(crate::dict_interface::example_arrive_word_explanation()),
+                "Qwen",
+                (Language::English, Language::Chinese),
+                Some(TokenUsage {
+                    input_tokens: 120,
+                    output_tokens: 80,
+                    ..Default::default()
+                }),
+            )
+            .unwrap();
+        store
+            .record(
+                "The train will arrive on time.",
+                &TranslationOutput::Sentence("火车将准时到达。".to_string()),
+                "DeepSeek",
+                (Language::English, Language::Chinese),
+                None,
+            )
+            .unwrap();
+        store
+    }
+
+    #[test]
+    fn search_matches_input_and_output() {
+        let store = store_with_entries();
+
+        let found = store
+            .search(&HistoryQuery {
+                text: Some("arrive".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(found.len(), 2);
+
+        // Shorter than a trigram.
+        let found = store
+            .search(&HistoryQuery {
+                text: Some("准时".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].input, "The train will arrive on time.");
+
+        let found = store
+            .search(&HistoryQuery {
+                text: Some("到达，抵达".to_string()),
+                mode: Some(TranslationMode::Word),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].token_usage.unwrap().output_tokens, 80);
+        assert!(matches!(found[0].output, TranslationOutput::Word(_)));
+    }
+
+    #[test]
+    fn search_filters_by_mode_and_language() {
+        let store = store_with_entries();
+
+        let found = store
+            .search(&HistoryQuery {
+                mode: Some(TranslationMode::Sentence),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].engine, "DeepSeek");
+
+        let found = store
+            .search(&HistoryQuery {
+                source_language: Some(Language::German),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn deleted_entries_are_not_found() {
+        let store = store_with_entries();
+        let id = store.search(&HistoryQuery::default()).unwrap()[0].id;
+
+        store.delete(id).unwrap();
+        assert!(store.get(id).unwrap().is_none());
+        let found = store
+            .search(&HistoryQuery {
+                text: Some("火车将".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn retention_keeps_latest_entries() {
+        let store = store_with_entries();
+
+        let removed = store
+            .apply_retention(&storage::History {
+                enabled: true,
+                max_entries: Some(1),
+                max_age_days: Some(1),
+            })
+            .unwrap();
+        assert_eq!(removed, 1);
+        let remaining = store.search(&HistoryQuery::default()).unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].input, "The train will arrive on time.");
+    }
+}
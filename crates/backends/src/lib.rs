@@ -11,15 +11,84 @@ mod tests {
         let result = add(2, 2);
         assert_eq!(result, 4);
     }
+
+    #[test]
+    fn only_answers_which_stopped_are_complete() {
+        assert!(finished(Some("stop")).is_ok());
+        assert!(finished(Some("length")).is_err());
+        assert!(finished(Some("content_filter")).is_err());
+        assert!(finished(None).is_err());
+    }
+
+    #[test]
+    fn respellings_are_told_from_lemmas() {
+        assert!(is_respelling("recieve", "receive", Language::English));
+        assert!(!is_respelling("smiled", "smile", Language::English));
+        assert!(!is_respelling("went", "go", Language::English));
+        // An engine explaining another word did not correct the spelling.
+        assert!(!is_respelling("gleam", "shine", Language::English));
+    }
+
+    #[test]
+    fn engines_are_told_to_keep_placeholders() {
+        let messages = |sentence: &str| {
+            sentence_messages(
+                &Prompt::default_sentence(),
+                &[],
+                &[],
+                "",
+                sentence,
+                Language::English,
+                Language::Chinese,
+            )
+        };
+        let (system_message, user_message) = messages("Press ⟦0⟧ to save ⟦1⟧.");
+        assert!(
+            system_message.contains("⟦0⟧ 的占位符"),
+            "{}",
+            system_message
+        );
+        assert!(user_message.contains("Press ⟦0⟧ to save ⟦1⟧."));
+        assert!(!messages("Press Ctrl+S to save.").0.contains('⟦'));
+        let (system_message, _) = messages("⟪1⟫ Open\n⟪2⟫ Close\n");
+        assert!(
+            system_message.contains("⟪1⟫、⟪2⟫ 等编号标记"),
+            "{}",
+            system_message
+        );
+    }
 }
 
 mod ai_interface;
+pub mod alignment;
+pub mod back_translation;
+pub mod cache;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub mod clipboard;
+pub mod compare;
+pub mod context;
+mod database;
 pub mod dict_interface;
+pub mod document;
 pub mod error;
+pub mod export;
+pub mod failover;
+pub mod file_translation;
+pub mod glossary;
+pub mod history;
+pub mod local_mt;
+pub mod morphology;
+pub mod notebook;
+pub mod offline_dict;
+pub mod prompt;
+pub mod spelling;
 pub mod storage;
+pub mod translation_memory;
+pub mod usage;
 mod utils;
 
 use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::{
     io::{BufRead, BufReader, Read, Write},
@@ -29,6 +98,12 @@ use std::{
 use anyhow::{Context, Error, Result, anyhow};
 use reqwest::StatusCode;
 use reqwest::{blocking::Client, header::HeaderMap};
+use serde::{Deserialize, Serialize};
+
+use error::ApiError;
+use glossary::GlossaryEntry;
+use prompt::{Prompt, PromptVariables};
+use translation_memory::{MemoryHit, MemoryLookup, TmMatch};
 // use openai_api_rs::v1::api::OpenAIClient;
 // use openai_api_rs::v1::chat_completion::{self, ChatCompletionMessage, ChatCompletionRequest};
 // use openai_api_rs::v1::common::GPT4_O;
@@ -133,6 +208,17 @@ pub fn initialise() -> Result<storage::Settings, anyhow::Error> {
                         appearance: Some(storage::Appearance {
                             colour_theme: storage::ColourTheme::Auto,
                         }),
+                        quick_lookup: None,
+                        history: None,
+                        offline_dictionaries: None,
+                        usage: None,
+                        prompts: None,
+                        engine_parameters: None,
+                        glossary: None,
+                        translation_memory: None,
+                        documents: None,
+                        local_models: None,
+                        fallback: None,
                     })?;
 
                     file.write_all(toml_content.as_bytes()).with_context(|| {
@@ -219,7 +305,19 @@ pub fn save_config(config_setting: &storage::Settings) -> Result<(), anyhow::Err
     Ok(())
 }
 
-#[derive(strum::Display)]
+#[derive(
+    strum::Display,
+    strum::EnumString,
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+)]
+#[strum(ascii_case_insensitive)]
 pub enum Language {
     Chinese,
     English,
@@ -231,10 +329,80 @@ pub enum Language {
     Spanish,
     Auto,
 }
+impl Language {
+    /// The ISO 639-1 code, as used by `xml:lang` in TBX and TMX files.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Chinese => "zh",
+            Self::English => "en",
+            Self::French => "fr",
+            Self::German => "de",
+            Self::Russian => "ru",
+            Self::Japanese => "ja",
+            Self::Korean => "ko",
+            Self::Spanish => "es",
+            Self::Auto => "und",
+        }
+    }
+
+    /// A language name such as `English`, or a code such as `en` or `en-US`.
+    pub fn from_name_or_code(text: &str) -> Result<Self, Error> {
+        if let Ok(language) = text.parse::<Self>() {
+            return Ok(language);
+        }
+        let code = text.split(['-', '_']).next().unwrap_or_default();
+        [
+            Self::Chinese,
+            Self::English,
+            Self::French,
+            Self::German,
+            Self::Russian,
+            Self::Japanese,
+            Self::Korean,
+            Self::Spanish,
+        ]
+        .into_iter()
+        .find(|language| language.code().eq_ignore_ascii_case(code))
+        .ok_or_else(|| anyhow!("Unknown language: {}", text))
+    }
+}
+#[derive(
+    strum::Display, strum::EnumString, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq,
+)]
+#[strum(ascii_case_insensitive)]
 pub enum AiProvider {
     DeepSeek,
     Youdao,
     Qwen,
+    /// Marian models run on the CPU, see [`local_mt`].
+    Local,
+}
+
+/// Tokens billed for a single request.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    /// Prompt tokens, including the cached ones.
+    pub input_tokens: u32,
+    /// Completion tokens, including the reasoning ones.
+    pub output_tokens: u32,
+    /// Prompt tokens served from the provider's cache, billed at a lower price.
+    #[serde(default)]
+    pub cached_input_tokens: u32,
+    #[serde(default)]
+    pub reasoning_tokens: u32,
+}
+
+impl std::ops::Add for TokenUsage {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            input_tokens: self.input_tokens + other.input_tokens,
+            output_tokens: self.output_tokens + other.output_tokens,
+            cached_input_tokens: self.cached_input_tokens + other.cached_input_tokens,
+            reasoning_tokens: self.reasoning_tokens + other.reasoning_tokens,
+        }
+    }
 }
 
 /// Trait for all modules
@@ -242,6 +410,10 @@ pub trait Translator {
     fn get_api_key(&self) -> String;
     fn save_api_key(&self) -> Result<(), anyhow::Error>;
     fn get_url(&self) -> String;
+    /// Token usage reported for the last finished request, if the API reports it.
+    fn last_token_usage(&self) -> Option<TokenUsage> {
+        None
+    }
 }
 
 /// Trait for translating single word or phrase.
@@ -252,6 +424,122 @@ pub trait WordTranslator: Translator {
         source_language: Language,
         target_language: Language,
     ) -> Result<dict_interface::WordExplanation, Error>;
+    /// The form of `word` to translate, its lemma unless the translator knows better.
+    fn lemma(&self, word: &str, language: Language) -> String {
+        morphology::lemmatise(word, language)
+    }
+    /// Preset and version of the prompt, which are part of the cache key.
+    fn prompt_key(&self) -> Option<String> {
+        None
+    }
+    /// The sense of `word_explanation` in which `word` is used in `sentence`, if the
+    /// translator can tell.
+    fn pick_sense(
+        &self,
+        _word_explanation: &dict_interface::WordExplanation,
+        _word: &str,
+        _sentence: &str,
+        _source_language: Language,
+        _target_language: Language,
+    ) -> Result<Option<context::SenseIndex>, Error> {
+        Ok(None)
+    }
+}
+
+/// Translate the lemma of `word`, reusing an explanation cached for it, and note the
+/// form typed if it differs from the lemma or the translator corrected its spelling.
+pub fn look_up_word(
+    translator: &dyn WordTranslator,
+    cache: Option<(&cache::WordCache, &str)>,
+    word: &str,
+    source_language: Language,
+    target_language: Language,
+) -> Result<dict_interface::WordExplanation, Error> {
+    let word = word.trim();
+    let lemma = translator.lemma(word, source_language);
+    let query = cache.map(|(cache, engine)| {
+        (
+            cache,
+            cache::WordQuery {
+                lemma: lemma.clone(),
+                engine: match translator.prompt_key() {
+                    Some(prompt_key) => format!("{}/{}", engine, prompt_key),
+                    None => engine.to_string(),
+                },
+                source_language,
+                target_language,
+            },
+        )
+    });
+    let cached = query.as_ref().and_then(|(cache, query)| {
+        cache
+            .get(query)
+            .inspect_err(|e| log::warn!("Failed to read word cache: {:#}", e))
+            .ok()
+            .flatten()
+    });
+    let mut word_explanation = match cached {
+        Some(word_explanation) => word_explanation,
+        None => {
+            let word_explanation =
+                translator.translate_word(&lemma, source_language, target_language)?;
+            if let Some((cache, query)) = &query
+                && let Err(e) = cache.insert(query, &word_explanation)
+            {
+                log::warn!("Failed to cache explanation of {}: {:#}", lemma, e);
+            }
+            word_explanation
+        }
+    };
+    let headword = word_explanation.word.trim().to_lowercase();
+    if headword != word.to_lowercase() {
+        match is_respelling(word, &headword, source_language) {
+            true => word_explanation.corrected_from = Some(word.to_string()),
+            false => word_explanation.inflected_form = Some(word.to_string()),
+        }
+    }
+    Ok(word_explanation)
+}
+
+/// Whether `headword` is `word` spelt differently rather than one of its lemmas, as in
+/// recieve and receive.
+fn is_respelling(word: &str, headword: &str, language: Language) -> bool {
+    let is_lemma = morphology::lemma_candidates(word, language)
+        .iter()
+        .any(|candidate| candidate.to_lowercase() == headword);
+    !is_lemma && strsim::damerau_levenshtein(&word.to_lowercase(), headword) <= 2
+}
+
+/// Look up `word` like `look_up_word`, and mark the sense in which it is used in
+/// `sentence`.
+pub fn look_up_word_in_context(
+    translator: &dyn WordTranslator,
+    cache: Option<(&cache::WordCache, &str)>,
+    word: &str,
+    sentence: &str,
+    source_language: Language,
+    target_language: Language,
+) -> Result<context::WordInContext, Error> {
+    let word_explanation = look_up_word(translator, cache, word, source_language, target_language)?;
+    let sense = match context::only_sense(&word_explanation) {
+        Some(sense) => Some(sense),
+        None => translator
+            .pick_sense(
+                &word_explanation,
+                word.trim(),
+                sentence,
+                source_language,
+                target_language,
+            )
+            // The explanation is still worth showing.
+            .inspect_err(|e| log::warn!("Failed to pick the sense of {}: {:#}", word, e))
+            .ok()
+            .flatten(),
+    };
+    Ok(context::WordInContext {
+        word_explanation,
+        sense,
+    })
 }
 
 /// Trait for translating sentences.
@@ -264,6 +552,17 @@ pub trait SentenceTranslator: Translator {
     ) -> Result<String, Error>;
 }
 
+/// Streamed translation of a sentence and the thinking of a reasoning model. Both
+/// channels carry the whole text received so far and are closed when the response ends.
+pub struct SentenceStream {
+    pub answer: Receiver<String>,
+    /// Stays empty for models which do not think.
+    pub reasoning: Receiver<String>,
+    /// Receives `Ok` once the answer is complete, or why it is not, e.g. cut off at the
+    /// most tokens. Closed without either by translators which cannot tell.
+    pub outcome: Receiver<Result<(), Error>>,
+}
+
 pub trait StreamSentenceTranslator: Translator {
     fn stream_translate_sentence(
         &self,
@@ -271,6 +570,161 @@ pub trait StreamSentenceTranslator: Translator {
         source_language: Language,
         target_language: Language,
     ) -> Result<Receiver<String>, Error>;
+
+    /// Like `stream_translate_sentence`, with the thinking trace kept out of the answer.
+    fn stream_translate_sentence_with_reasoning(
+        &self,
+        sentence: &str,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<SentenceStream, Error> {
+        let answer = self.stream_translate_sentence(sentence, source_language, target_language)?;
+        let (_, reasoning) = mpsc::channel();
+        let (_, outcome) = mpsc::channel();
+        Ok(SentenceStream {
+            answer,
+            reasoning,
+            outcome,
+        })
+    }
+
+    /// Like `stream_translate_sentence_with_reasoning`, with the text before `sentence`
+    /// given to the engine as context which is not translated.
+    fn stream_translate_in_context(
+        &self,
+        sentence: &str,
+        context: &str,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<SentenceStream, Error> {
+        let _ = context;
+        self.stream_translate_sentence_with_reasoning(sentence, source_language, target_language)
+    }
+}
+
+/// The system and the user message for translating `sentence`, requiring the
+/// translations of the glossary terms it contains and giving `references` from the
+/// translation memory and the `context` before it.
+fn sentence_messages(
+    prompt: &Prompt,
+    glossary: &[GlossaryEntry],
+    references: &[TmMatch],
+    context: &str,
+    sentence: &str,
+    source_language: Language,
+    target_language: Language,
+) -> (String, String) {
+    let (mut system_message, user_message) = prompt.render(&PromptVariables {
+        source_language,
+        target_language,
+        text: sentence,
+        schema_example: "",
+    });
+    system_message += &glossary::prompt_supplement(&glossary::relevant_entries(
+        glossary,
+        sentence,
+        source_language,
+        target_language,
+    ));
+    system_message += &translation_memory::prompt_supplement(references);
+    system_message += &file_translation::prompt_supplement(sentence);
+    if !context.is_empty() {
+        system_message += &format!(
+            "\n以下是待翻译文本之前的上文，仅供理解语境，不要翻译：\n{}",
+            context
+        );
+    }
+    (system_message, user_message)
+}
+
+/// The translation of `sentence` in the memory if any. Failing to consult the memory
+/// only loses the references.
+fn consult_memory(
+    memory: Option<&MemoryLookup>,
+    sentence: &str,
+    source_language: Language,
+    target_language: Language,
+) -> MemoryHit {
+    let Some(memory) = memory else {
+        return MemoryHit::Fuzzy(Vec::new());
+    };
+    memory
+        .consult(sentence, source_language, target_language)
+        .unwrap_or_else(|e| {
+            log::warn!("Failed to consult translation memory: {:#}", e);
+            MemoryHit::Fuzzy(Vec::new())
+        })
+}
+
+/// The outcome of an answer which ended for `finish_reason`, `None` if the provider
+/// gave none.
+fn finished(finish_reason: Option<&str>) -> Result<(), Error> {
+    match finish_reason {
+        Some("stop") => Ok(()),
+        Some("length") => Err(anyhow!("The translation was cut off at the most tokens")),
+        Some(reason) => Err(anyhow!("The answer ended early: {}", reason)),
+        None => Err(anyhow!("The answer ended before it was complete")),
+    }
+}
+
+/// A finished stream of a translation found in the memory.
+fn remembered_stream(translation: String) -> SentenceStream {
+    let (tx, answer) = mpsc::channel();
+    let _ = tx.send(translation);
+    let (_, reasoning) = mpsc::channel();
+    let (outcome_tx, outcome) = mpsc::channel();
+    let _ = outcome_tx.send(Ok(()));
+    SentenceStream {
+        answer,
+        reasoning,
+        outcome,
+    }
+}
+
+/// The sentence translator of `engine` with the API key, prompts, glossary and
+/// parameters of the config file, for translating outside of the main window.
+pub fn configured_sentence_translator(
+    engine: AiProvider,
+    settings: &storage::Settings,
+    memory: Option<MemoryLookup>,
+) -> Result<Arc<dyn StreamSentenceTranslator + Send + Sync>, Error> {
+    let accounts = settings.ai_accounts.as_ref();
+    let prompts = settings.prompts.clone().unwrap_or_default();
+    let glossary = settings.glossary.clone().unwrap_or_default();
+    let parameters = settings.engine_parameters.clone().unwrap_or_default();
+    match engine {
+        AiProvider::DeepSeek => {
+            let api_key = accounts
+                .and_then(|accounts| accounts.deepseek.as_ref())
+                .map(|deepseek| deepseek.api_key.clone())
+                .ok_or_else(|| anyhow!("The API key of DeepSeek is not set"))?;
+            Ok(Arc::new(
+                DeepSeekSentenceTranslator::new(api_key)
+                    .with_prompts(&prompts)
+                    .with_glossary(&glossary)
+                    .with_translation_memory(memory)
+                    .with_parameters(&parameters.deepseek),
+            ))
+        }
+        AiProvider::Youdao => Err(anyhow!("Youdao api is not supported yet!!")),
+        AiProvider::Qwen => {
+            let api_key = accounts
+                .and_then(|accounts| accounts.qwen.as_ref())
+                .map(|qwen| qwen.api_key.clone())
+                .ok_or_else(|| anyhow!("The API key of Qwen is not set"))?;
+            Ok(Arc::new(
+                QwenWordSentenceTranslator::new(api_key)
+                    .with_prompts(&prompts)
+                    .with_glossary(&glossary)
+                    .with_translation_memory(memory)
+                    .with_parameters(&parameters.qwen),
+            ))
+        }
+        // Local models have no prompt, so the glossary and the memory are not used.
+        AiProvider::Local => Ok(Arc::new(local_mt::LocalSentenceTranslator::new(
+            &settings.local_models.clone().unwrap_or_default(),
+        )?)),
+    }
 }
 
 pub struct YoudaoDictionaryWordTranslator {
@@ -289,8 +743,12 @@ pub struct DeepSeekSentenceTranslator {
     api_key: String,
     web_address: String,
     supported_languages: Vec<Language>,
-    prompt: String,
-    max_tokens: u32,
+    prompt: Prompt,
+    glossary: Vec<GlossaryEntry>,
+    translation_memory: Option<MemoryLookup>,
+    parameters: storage::ModelParameters,
+    /// Shared with the thread reading a streamed response.
+    last_token_usage: Arc<Mutex<Option<TokenUsage>>>,
 }
 impl DeepSeekSentenceTranslator {
     pub fn new(api_key: String) -> Self {
@@ -303,12 +761,36 @@ impl DeepSeekSentenceTranslator {
                 Language::Russian,
                 Language::German,
             ],
-            prompt: String::from(
-                "请翻译以下句子。你只需要输出翻译结果，不要输出任何与翻译无关的内容。应注意用词应尽可能准确，不应改变原句的内容，同时恰到好处地还原原句的情感和写作风格。",
-            ),
-            max_tokens: 8192,
+            prompt: Prompt::default_sentence(),
+            glossary: Vec::new(),
+            translation_memory: None,
+            parameters: storage::ModelParameters::deepseek_default(),
+            last_token_usage: Arc::new(Mutex::new(None)),
         }
     }
+
+    pub fn with_parameters(mut self, parameters: &storage::ModelParameters) -> Self {
+        self.parameters = parameters.clone();
+        self
+    }
+
+    /// Use the sentence preset chosen in `prompts`.
+    pub fn with_prompts(mut self, prompts: &storage::Prompts) -> Self {
+        self.prompt = prompts.sentence_prompt();
+        self
+    }
+
+    pub fn with_glossary(mut self, glossary: &storage::Glossary) -> Self {
+        self.glossary = glossary.entries.clone();
+        self
+    }
+
+    /// Reuse the translations in `memory` if any, asking the engine only for new
+    /// sentences.
+    pub fn with_translation_memory(mut self, memory: Option<MemoryLookup>) -> Self {
+        self.translation_memory = memory;
+        self
+    }
 }
 impl Translator for DeepSeekSentenceTranslator {
     fn get_url(&self) -> String {
@@ -322,6 +804,9 @@ impl Translator for DeepSeekSentenceTranslator {
         return Err(anyhow!("Not implemented yet!"));
         // TODO!
     }
+    fn last_token_usage(&self) -> Option<TokenUsage> {
+        *self.last_token_usage.lock().unwrap()
+    }
 }
 impl SentenceTranslator for DeepSeekSentenceTranslator {
     fn translate_sentence(
@@ -330,23 +815,40 @@ impl SentenceTranslator for DeepSeekSentenceTranslator {
         source_language: Language,
         target_language: Language,
     ) -> Result<String, Error> {
+        // A failed request uses no tokens, rather than those of the one before.
+        *self.last_token_usage.lock().unwrap() = None;
+        let references = match consult_memory(
+            self.translation_memory.as_ref(),
+            sentence,
+            source_language,
+            target_language,
+        ) {
+            MemoryHit::Exact(translation) => return Ok(translation),
+            MemoryHit::Fuzzy(references) => references,
+        };
+        let (system_message, user_message) = sentence_messages(
+            &self.prompt,
+            &self.glossary,
+            &references,
+            "",
+            sentence,
+            source_language,
+            target_language,
+        );
         let request_body = ai_interface::deepseek::RequestBody {
             messages: vec![
                 ai_interface::deepseek::Message {
                     role: ai_interface::deepseek::MsgRole::System,
-                    content: format!(
-                        "{}。请从{}翻译为{}。",
-                        &self.prompt, source_language, target_language
-                    ),
+                    content: system_message,
                 },
                 ai_interface::deepseek::Message {
                     role: ai_interface::deepseek::MsgRole::User,
-                    content: sentence.to_string(),
+                    content: user_message,
                 },
             ],
-            model: "deepseek-chat".to_string(),
+            model: self.parameters.model.clone(),
             frequency_penalty: None,
-            max_tokens: Some(self.max_tokens),
+            max_tokens: Some(self.parameters.max_tokens),
             presence_penalty: None,
             response_format: Some(ai_interface::deepseek::ResponseFormat {
                 type_: ai_interface::deepseek::ResponseFormatObj::Text,
@@ -354,8 +856,9 @@ impl SentenceTranslator for DeepSeekSentenceTranslator {
             stop: None,
             stream: false,
             stream_options: None,
-            temperature: Some(1.3),
-            top_p: None,
+            temperature: self.parameters.temperature,
+            top_p: self.parameters.top_p,
+            seed: self.parameters.seed,
             tools: None,
             tool_choice: None,
             logprobs: Some(false),
@@ -380,8 +883,16 @@ impl SentenceTranslator for DeepSeekSentenceTranslator {
         let result =
             match serde_json::from_str::<ai_interface::deepseek::ResponseBody>(&response_text) {
                 Ok(response_body) => {
+                    *self.last_token_usage.lock().unwrap() =
+                        Some(TokenUsage::from(&response_body.usage));
                     let mut messages: Vec<String> = Vec::new();
                     for choice in response_body.choices {
+                        if matches!(
+                            choice.finish_reason,
+                            ai_interface::deepseek::FinishReason::Length
+                        ) {
+                            log::warn!("The translation was cut off at the most tokens");
+                        }
                         messages.push(choice.message.content.unwrap_or_default())
                     }
                     let message = messages.join("\n");
@@ -407,32 +918,73 @@ impl StreamSentenceTranslator for DeepSeekSentenceTranslator {
         source_language: Language,
         target_language: Language,
     ) -> Result<Receiver<String>, Error> {
+        Ok(self
+            .stream_translate_sentence_with_reasoning(sentence, source_language, target_language)?
+            .answer)
+    }
+
+    fn stream_translate_sentence_with_reasoning(
+        &self,
+        sentence: &str,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<SentenceStream, Error> {
+        self.stream_translate_in_context(sentence, "", source_language, target_language)
+    }
+
+    /// `deepseek-reasoner` thinks before answering.
+    fn stream_translate_in_context(
+        &self,
+        sentence: &str,
+        context: &str,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<SentenceStream, Error> {
+        *self.last_token_usage.lock().unwrap() = None;
+        let references = match consult_memory(
+            self.translation_memory.as_ref(),
+            sentence,
+            source_language,
+            target_language,
+        ) {
+            MemoryHit::Exact(translation) => return Ok(remembered_stream(translation)),
+            MemoryHit::Fuzzy(references) => references,
+        };
+        let (system_message, user_message) = sentence_messages(
+            &self.prompt,
+            &self.glossary,
+            &references,
+            context,
+            sentence,
+            source_language,
+            target_language,
+        );
         let request_body = ai_interface::deepseek::RequestBody {
             messages: vec![
                 ai_interface::deepseek::Message {
                     role: ai_interface::deepseek::MsgRole::System,
-                    content: format!(
-                        "{}。请从{}翻译为{}。",
-                        &self.prompt, source_language, target_language
-                    ),
+                    content: system_message,
                 },
                 ai_interface::deepseek::Message {
                     role: ai_interface::deepseek::MsgRole::User,
-                    content: sentence.to_string(),
+                    content: user_message,
                 },
             ],
-            model: "deepseek-chat".to_string(),
+            model: self.parameters.model.clone(),
             frequency_penalty: None,
-            max_tokens: Some(self.max_tokens),
+            max_tokens: Some(self.parameters.max_tokens),
             presence_penalty: None,
             response_format: Some(ai_interface::deepseek::ResponseFormat {
                 type_: ai_interface::deepseek::ResponseFormatObj::Text,
             }),
             stop: None,
             stream: true,
-            stream_options: None,
-            temperature: Some(1.3),
-            top_p: None,
+            stream_options: Some(ai_interface::deepseek::StreamOptions {
+                include_usage: true,
+            }),
+            temperature: self.parameters.temperature,
+            top_p: self.parameters.top_p,
+            seed: self.parameters.seed,
             tools: None,
             tool_choice: None,
             logprobs: Some(false),
@@ -453,67 +1005,97 @@ impl StreamSentenceTranslator for DeepSeekSentenceTranslator {
             .send()?;
 
         // Process streaming response
-        println!("HTTP status: {}", response.status());
+        log::debug!("HTTP status: {}", response.status());
         if response.status() != StatusCode::OK {
-            eprintln!("HTTP error: {}", response.text().unwrap());
-            std::process::exit(1);
+            let status = response.status();
+            let body = response.text().unwrap_or_default();
+            log::error!("HTTP error: {}", body);
+            return Err(ApiError { status, body }.into());
         }
         let mut reader = BufReader::new(response);
         let (tx, rx) = mpsc::channel::<String>();
+        let (reasoning_tx, reasoning_rx) = mpsc::channel::<String>();
+        let last_token_usage = self.last_token_usage.clone();
+
+        let (outcome_tx, outcome_rx) = mpsc::channel();
 
         thread::spawn(move || {
             let mut content = String::new();
-            loop {
+            let mut reasoning = String::new();
+            let mut finish_reason = None;
+            let outcome = loop {
                 let mut line = String::new();
-                reader.read_line(&mut line).unwrap_or_else(|e| {
-                    eprintln!("Error reading line: {}", e);
-                    std::process::exit(1);
-                });
-                if line.is_empty() {
-                    continue;
+                match reader.read_line(&mut line) {
+                    Ok(0) => break finished(finish_reason),
+                    Ok(_) => (),
+                    Err(e) => {
+                        log::error!("Error reading line: {}", e);
+                        break Err(anyhow!("Failed to read the answer: {}", e));
+                    }
                 }
                 // println!("Received: `{}` from api", line);
-                if line.starts_with("data:") {
-                    let data = line.trim_start_matches("data:").trim();
-                    if data == "[DONE]" {
-                        return;
-                    } else {
-                        match serde_json::from_str::<ai_interface::deepseek::StreamResponseBody>(
-                            &data,
-                        ) {
-                            Ok(response_body) => {
-                                for choice in response_body.choices {
-                                    content += &choice.delta.content.unwrap_or_default();
-                                }
-                                match tx.send(content.clone()) {
-                                    Ok(_) => (),
-                                    Err(e) => {
-                                        eprintln!("Error sending message: {}", e);
-                                        return;
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                let t = format!(
-                                    "Error parsing JSON response: {}.\nReceived: {}",
-                                    e, data
-                                );
-                                eprintln!("Error parsing JSON: {}", e);
-                                match tx.send(t) {
-                                    Ok(_) => (),
-                                    Err(e) => {
-                                        eprintln!("Error sending error message: {}", e);
-                                        return;
-                                    }
-                                }
+                let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+                    continue;
+                };
+                if data == "[DONE]" {
+                    break finished(finish_reason);
+                }
+                let response_body = match serde_json::from_str::<
+                    ai_interface::deepseek::StreamResponseBody,
+                >(data)
+                {
+                    Ok(response_body) => response_body,
+                    Err(e) => {
+                        let t = format!("Error parsing JSON response: {}.\nReceived: {}", e, data);
+                        eprintln!("Error parsing JSON: {}", e);
+                        if let Err(e) = tx.send(t) {
+                            eprintln!("Error sending error message: {}", e);
+                        }
+                        break Err(anyhow!("Failed to parse the answer: {}", e));
+                    }
+                };
+                if let Some(usage) = response_body.usage {
+                    *last_token_usage.lock().unwrap() = Some(TokenUsage::from(&usage));
+                }
+                let content_len = content.len();
+                let reasoning_len = reasoning.len();
+                for choice in response_body.choices {
+                    if let Some(reason) = choice.finish_reason {
+                        finish_reason = Some(match reason {
+                            ai_interface::deepseek::FinishReason::Stop => "stop",
+                            ai_interface::deepseek::FinishReason::Length => "length",
+                            ai_interface::deepseek::FinishReason::ContentFilter => "content_filter",
+                            ai_interface::deepseek::FinishReason::ToolCalls => "tool_calls",
+                            ai_interface::deepseek::FinishReason::InsufficientSystemResource => {
+                                "insufficient_system_resource"
                             }
-                        };
+                        });
                     }
+                    content += &choice.delta.content.unwrap_or_default();
+                    reasoning += &choice.delta.reasoning_content.unwrap_or_default();
+                }
+                if reasoning.len() != reasoning_len {
+                    // Nobody may be interested in the thinking.
+                    let _ = reasoning_tx.send(reasoning.clone());
                 }
+                if content.len() != content_len
+                    && let Err(e) = tx.send(content.clone())
+                {
+                    eprintln!("Error sending message: {}", e);
+                    break Err(anyhow!("The translation is no longer awaited"));
+                }
+            };
+            if let Err(e) = &outcome {
+                log::warn!("The translation is incomplete: {:#}", e);
             }
+            let _ = outcome_tx.send(outcome);
         });
 
-        return Ok(rx);
+        Ok(SentenceStream {
+            answer: rx,
+            reasoning: reasoning_rx,
+            outcome: outcome_rx,
+        })
     }
 }
 
@@ -521,8 +1103,13 @@ pub struct QwenWordSentenceTranslator {
     api_key: String,
     web_address: String,
     supported_languages: Vec<Language>,
-    prompt: String,
-    max_tokens: u32,
+    word_prompt: Prompt,
+    sentence_prompt: Prompt,
+    glossary: Vec<GlossaryEntry>,
+    translation_memory: Option<MemoryLookup>,
+    parameters: storage::ModelParameters,
+    /// Shared with the thread reading a streamed response.
+    last_token_usage: Arc<Mutex<Option<TokenUsage>>>,
 }
 impl QwenWordSentenceTranslator {
     pub fn new(api_key: String) -> Self {
@@ -539,12 +1126,39 @@ impl QwenWordSentenceTranslator {
                 Language::Korean,
                 Language::Japanese,
             ],
-            prompt: String::from(
-                "请翻译以下句子。你只需要输出翻译结果，不要输出任何与翻译无关的内容。应注意用词应尽可能准确，不应改变原句的内容，同时恰到好处地还原原句的情感和写作风格。",
-            ),
-            max_tokens: 8_192,
+            word_prompt: Prompt::default_word(),
+            sentence_prompt: Prompt::default_sentence(),
+            glossary: Vec::new(),
+            translation_memory: None,
+            parameters: storage::ModelParameters::qwen_default(),
+            last_token_usage: Arc::new(Mutex::new(None)),
         }
     }
+
+    pub fn with_parameters(mut self, parameters: &storage::ModelParameters) -> Self {
+        self.parameters = parameters.clone();
+        self
+    }
+
+    /// Use the word and sentence presets chosen in `prompts`.
+    pub fn with_prompts(mut self, prompts: &storage::Prompts) -> Self {
+        self.word_prompt = prompts.word_prompt();
+        self.sentence_prompt = prompts.sentence_prompt();
+        self
+    }
+
+    /// Require the translations of terms in `glossary` when translating sentences.
+    pub fn with_glossary(mut self, glossary: &storage::Glossary) -> Self {
+        self.glossary = glossary.entries.clone();
+        self
+    }
+
+    /// Reuse the translations in `memory` if any, asking the engine only for new
+    /// sentences.
+    pub fn with_translation_memory(mut self, memory: Option<MemoryLookup>) -> Self {
+        self.translation_memory = memory;
+        self
+    }
 }
 impl Translator for QwenWordSentenceTranslator {
     fn get_url(&self) -> String {
@@ -557,16 +1171,23 @@ impl Translator for QwenWordSentenceTranslator {
     fn save_api_key(&self) -> Result<(), anyhow::Error> {
         return Err(anyhow!("Not implemented yet!"));
     }
+    fn last_token_usage(&self) -> Option<TokenUsage> {
+        *self.last_token_usage.lock().unwrap()
+    }
 }
 impl WordTranslator for QwenWordSentenceTranslator {
+    fn prompt_key(&self) -> Option<String> {
+        Some(self.word_prompt.cache_key())
+    }
     fn translate_word(
         &self,
         word: &str,
         source_language: Language,
         target_language: Language,
     ) -> Result<dict_interface::WordExplanation, Error> {
-        use ai_interface::qwen::{Message, MsgRole, RequestBody, RequestInput, RequestParameters};
+        use ai_interface::qwen::{Message, MsgRole};
         log::debug!("Translate word: {}", word);
+        *self.last_token_usage.lock().unwrap() = None;
         let example_json =
             serde_json::to_string_pretty(&dict_interface::example_arrive_word_explanation())
                 .map_err(|e| {
@@ -576,49 +1197,133 @@ impl WordTranslator for QwenWordSentenceTranslator {
                         e
                     )
                 })?;
-        let content_message = format!(
-            r#"
-                            请你翻译以下{}单词或词组到{}，给出音标、解释、搭配和例句。以json格式输出。
-                            若单词并不存在，你应回复一个最为接近的词语，并给出相应的解释；若没有相似的词语，按照我给定的json格式，只回复 {{word: $word}} 即可
-                            警告：你输出的内容应只包括json，诸如“```json```”等非json格式的内容会影响到结果解析。
-                            注：
-                                1. 可选的词性有：[`noun`, `verb`, `adj.`, `adv.`, `pron.`, `prep.`, `conj.`, `interj.`, `other`]
-                                2. `definition` 字段应由单词相应的语言给出定义
-                            例：
-                                User:
-                                    arrive
-                                Assistant:
-                                    {}
-            "#,
-            source_language, target_language, example_json
+        let (system_message, user_message) = self.word_prompt.render(&PromptVariables {
+            source_language,
+            target_language,
+            text: word,
+            schema_example: &example_json,
+        });
+        let mut messages = vec![
+            Message {
+                role: MsgRole::System,
+                content: system_message,
+            },
+            Message {
+                role: MsgRole::User,
+                content: user_message,
+            },
+        ];
+
+        let (reply, usage) = self.request_word_reply(&messages)?;
+        *self.last_token_usage.lock().unwrap() = Some(usage);
+        let error = match dict_interface::parse_word_explanation(&reply) {
+            Ok(word_explanation) => return Ok(word_explanation),
+            Err(e) => e,
+        };
+
+        // Ask once more, pointing out what was wrong with the reply.
+        log::warn!(
+            "Invalid word explanation from Qwen, asking again: {:#}",
+            error
+        );
+        messages.push(Message {
+            role: MsgRole::Assistant,
+            content: reply,
+        });
+        messages.push(Message {
+            role: MsgRole::User,
+            content: format!(
+                "你的输出无法解析：{}。请按照给定的格式重新输出完整的json，不要输出其他内容。",
+                error
+            ),
+        });
+        let (reply, retry_usage) = self.request_word_reply(&messages)?;
+        *self.last_token_usage.lock().unwrap() = Some(usage + retry_usage);
+        dict_interface::parse_word_explanation(&reply)
+            .context("Failed to deserialize AI output to WordExplanation")
+    }
+
+    fn pick_sense(
+        &self,
+        word_explanation: &dict_interface::WordExplanation,
+        word: &str,
+        sentence: &str,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<Option<context::SenseIndex>, Error> {
+        use ai_interface::qwen::{Message, MsgRole};
+        let (system_message, user_message) = context::sense_prompt(
+            word_explanation,
+            word,
+            sentence,
+            source_language,
+            target_language,
         );
+        let messages = [
+            Message {
+                role: MsgRole::System,
+                content: system_message,
+            },
+            Message {
+                role: MsgRole::User,
+                content: user_message,
+            },
+        ];
+        let (reply, usage) = self.request_reply(&messages, None)?;
+        // Added to the usage of the explanation, if it was not cached.
+        let mut last_token_usage = self.last_token_usage.lock().unwrap();
+        *last_token_usage = Some(last_token_usage.unwrap_or_default() + usage);
+        Ok(context::sense_from_reply(word_explanation, &reply))
+    }
+}
+impl QwenWordSentenceTranslator {
+    /// Send a conversation asking for a word explanation in the schema of
+    /// `WordExplanation`, returning the reply and the tokens it took.
+    fn request_word_reply(
+        &self,
+        messages: &[ai_interface::qwen::Message],
+    ) -> Result<(String, TokenUsage), Error> {
+        use ai_interface::qwen::{JsonSchemaFormat, ResponseFormat, ResponseFormatObj};
+        self.request_reply(
+            messages,
+            Some(ResponseFormat {
+                type_: ResponseFormatObj::JsonSchema,
+                json_schema: Some(JsonSchemaFormat {
+                    name: "word_explanation".to_string(),
+                    schema: dict_interface::word_explanation_schema(),
+                    strict: false,
+                }),
+            }),
+        )
+    }
+
+    /// Send a conversation without streaming, returning the reply and the tokens it
+    /// took.
+    fn request_reply(
+        &self,
+        messages: &[ai_interface::qwen::Message],
+        response_format: Option<ai_interface::qwen::ResponseFormat>,
+    ) -> Result<(String, TokenUsage), Error> {
+        use ai_interface::qwen::{RequestBody, RequestInput, RequestParameters};
         let request_body = RequestBody {
-            model: "qwen3-235b-a22b".to_string(),
+            model: self.parameters.model.clone(),
             input: RequestInput {
-                messages: vec![
-                    Message {
-                        role: MsgRole::System,
-                        content: content_message,
-                    },
-                    Message {
-                        role: MsgRole::User,
-                        content: word.to_string(),
-                    },
-                ],
+                messages: messages.to_vec(),
             },
-            thinking_budget: None,
             stream: Some(false),
             parameters: Some(RequestParameters {
-                temperature: Some(1.0),
-                top_p: None,
+                temperature: self.parameters.temperature.map(|t| t as f32),
+                top_p: self.parameters.top_p.map(|p| p as f32),
                 top_k: Some(50),
+                // Thinking is only available for streamed requests.
                 enable_thinking: Some(false),
+                thinking_budget: None,
                 repetition_penalty: Some(1.0),
                 presence_penalty: Some(0.0),
-                max_tokens: Some(self.max_tokens),
-                seed: None,
+                max_tokens: Some(self.parameters.max_tokens),
+                seed: self.parameters.seed,
                 incremental_output: None,
-                response_format: None,
+                response_format,
                 result_format: None,
                 tools: None,
                 tool_choice: None,
@@ -652,52 +1357,216 @@ impl WordTranslator for QwenWordSentenceTranslator {
 
                 log::info!("Received: {}", response_text);
 
-                let result = match serde_json::from_str::<ai_interface::qwen::ResponseBody>(
-                    &response_text,
-                ) {
-                    Ok(response_body) => match response_body.output.text {
-                        Some(text) => Ok(text),
-                        None => match response_body.output.choices {
-                            Some(choices) => {
-                                let mut messages = Vec::new();
-                                for choice in choices {
-                                    if let Some(content) = choice.message.content {
-                                        messages.push(content);
-                                    }
+                let response_body =
+                    serde_json::from_str::<ai_interface::qwen::ResponseBody>(&response_text)
+                        .map_err(|e| anyhow!("Failed to parse response: {}", e))?;
+                let usage = TokenUsage::from(&response_body.usage);
+                let text = match response_body.output.text {
+                    Some(text) => text,
+                    None => match response_body.output.choices {
+                        Some(choices) => {
+                            let mut messages = Vec::new();
+                            for choice in choices {
+                                if let Some(content) = choice.message.content {
+                                    messages.push(content);
                                 }
-                                Ok(messages.join(""))
                             }
-                            None => Err(anyhow!("No valid output detected!")),
-                        },
+                            messages.join("")
+                        }
+                        None => return Err(anyhow!("No valid output detected!")),
                     },
-                    Err(e) => Err(anyhow!("Failed to parse response: {}", e)),
-                }
-                .and_then(|text| {
-                    let text = text.trim();
-                    let text = if text.starts_with("```json") {
-                        text.trim_start_matches("```json").trim()
-                    } else {
-                        text
-                    };
-                    let text = if text.ends_with("```") {
-                        text.trim_end_matches("```").trim()
-                    } else {
-                        text
-                    };
-
-                    serde_json::from_str::<dict_interface::WordExplanation>(&text).map_err(|e| {
-                        anyhow!("Failed to deserialize AI output to WordExplanation: {}", e)
-                    })
-                });
-
-                result
+                };
+                Ok((text, usage))
             }
             _ => {
                 log::error!("API request failed: {}", response_status);
                 log::info!("Send: {}", serde_json::to_string(&request_body)?);
-                log::info!("Received: {}", response.text()?);
-                Err(anyhow!("API request failed: {}", response_status))
+                let body = response.text()?;
+                log::info!("Received: {}", body);
+                Err(ApiError {
+                    status: response_status,
+                    body,
+                }
+                .into())
             }
         }
     }
 }
+impl StreamSentenceTranslator for QwenWordSentenceTranslator {
+    fn stream_translate_sentence(
+        &self,
+        sentence: &str,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<Receiver<String>, Error> {
+        Ok(self
+            .stream_translate_sentence_with_reasoning(sentence, source_language, target_language)?
+            .answer)
+    }
+
+    fn stream_translate_sentence_with_reasoning(
+        &self,
+        sentence: &str,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<SentenceStream, Error> {
+        self.stream_translate_in_context(sentence, "", source_language, target_language)
+    }
+
+    /// Qwen3 models think if `enable_thinking` is set.
+    fn stream_translate_in_context(
+        &self,
+        sentence: &str,
+        context: &str,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<SentenceStream, Error> {
+        use ai_interface::qwen::{
+            Message, MsgRole, RequestBody, RequestInput, RequestParameters, ResultFormat,
+        };
+        *self.last_token_usage.lock().unwrap() = None;
+        let references = match consult_memory(
+            self.translation_memory.as_ref(),
+            sentence,
+            source_language,
+            target_language,
+        ) {
+            MemoryHit::Exact(translation) => return Ok(remembered_stream(translation)),
+            MemoryHit::Fuzzy(references) => references,
+        };
+        let (system_message, user_message) = sentence_messages(
+            &self.sentence_prompt,
+            &self.glossary,
+            &references,
+            context,
+            sentence,
+            source_language,
+            target_language,
+        );
+        let request_body = RequestBody {
+            model: self.parameters.model.clone(),
+            input: RequestInput {
+                messages: vec![
+                    Message {
+                        role: MsgRole::System,
+                        content: system_message,
+                    },
+                    Message {
+                        role: MsgRole::User,
+                        content: user_message,
+                    },
+                ],
+            },
+            stream: Some(true),
+            parameters: Some(RequestParameters {
+                temperature: self.parameters.temperature.map(|t| t as f32),
+                top_p: self.parameters.top_p.map(|p| p as f32),
+                top_k: None,
+                enable_thinking: Some(self.parameters.enable_thinking),
+                thinking_budget: self
+                    .parameters
+                    .thinking_budget
+                    .filter(|_| self.parameters.enable_thinking),
+                repetition_penalty: None,
+                presence_penalty: None,
+                max_tokens: Some(self.parameters.max_tokens),
+                seed: self.parameters.seed,
+                incremental_output: Some(true),
+                response_format: None,
+                result_format: Some(ResultFormat::Message),
+                tools: None,
+                tool_choice: None,
+                parallel_tool_calls: None,
+            }),
+        };
+
+        let client = Client::new();
+        let response = client
+            .post(&self.web_address)
+            .headers({
+                let mut headers = HeaderMap::new();
+                headers.insert("Content-Type", "application/json".parse().unwrap());
+                headers.insert("X-DashScope-SSE", "enable".parse().unwrap());
+                headers
+            })
+            .bearer_auth(&self.api_key)
+            .json(&request_body)
+            .send()?;
+
+        if response.status() != StatusCode::OK {
+            let status = response.status();
+            let body = response.text().unwrap_or_default();
+            log::error!("HTTP error: {}", body);
+            return Err(ApiError { status, body }.into());
+        }
+        let mut reader = BufReader::new(response);
+        let (tx, rx) = mpsc::channel::<String>();
+        let (reasoning_tx, reasoning_rx) = mpsc::channel::<String>();
+        let last_token_usage = self.last_token_usage.clone();
+
+        let (outcome_tx, outcome_rx) = mpsc::channel();
+
+        // Every event carries the new part of the output and the usage so far.
+        thread::spawn(move || {
+            let mut content = String::new();
+            let mut reasoning = String::new();
+            let outcome = loop {
+                let mut line = String::new();
+                match reader.read_line(&mut line) {
+                    Ok(0) => break finished(None),
+                    Ok(_) => (),
+                    Err(e) => {
+                        log::error!("Error reading line: {}", e);
+                        break Err(anyhow!("Failed to read the answer: {}", e));
+                    }
+                }
+                let Some(data) = line.strip_prefix("data:") else {
+                    continue;
+                };
+                let response_body =
+                    match serde_json::from_str::<ai_interface::qwen::ResponseBody>(data.trim()) {
+                        Ok(response_body) => response_body,
+                        Err(e) => {
+                            log::error!("Error parsing JSON: {}. Received: {}", e, data);
+                            let _ = tx.send(format!(
+                                "Error parsing JSON response: {}.\nReceived: {}",
+                                e, data
+                            ));
+                            break Err(anyhow!("Failed to parse the answer: {}", e));
+                        }
+                    };
+                *last_token_usage.lock().unwrap() = Some(TokenUsage::from(&response_body.usage));
+                let mut finish_reason = None;
+                let content_len = content.len();
+                let reasoning_len = reasoning.len();
+                for choice in response_body.output.choices.unwrap_or_default() {
+                    content += &choice.message.content.unwrap_or_default();
+                    reasoning += &choice.message.reasoning_content.unwrap_or_default();
+                    finish_reason = choice
+                        .finish_reason
+                        .filter(|reason| reason != "null")
+                        .or(finish_reason);
+                }
+                if reasoning.len() != reasoning_len {
+                    let _ = reasoning_tx.send(reasoning.clone());
+                }
+                if content.len() != content_len && tx.send(content.clone()).is_err() {
+                    break Err(anyhow!("The translation is no longer awaited"));
+                }
+                if finish_reason.is_some() {
+                    break finished(finish_reason.as_deref());
+                }
+            };
+            if let Err(e) = &outcome {
+                log::warn!("The translation is incomplete: {:#}", e);
+            }
+            let _ = outcome_tx.send(outcome);
+        });
+
+        Ok(SentenceStream {
+            answer: rx,
+            reasoning: reasoning_rx,
+            outcome: outcome_rx,
+        })
+    }
+}
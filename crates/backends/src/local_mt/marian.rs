@@ -0,0 +1,86 @@
+//! Greedy decoding of Marian models with candle on the CPU.
+
+use std::path::Path;
+
+use anyhow::{Context, Error, Result, anyhow};
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::marian::{Config, MTModel};
+use tokenizers::Tokenizer;
+
+pub(super) struct MarianModel {
+    model: MTModel,
+    config: Config,
+    source_tokenizer: Tokenizer,
+    target_tokenizer: Tokenizer,
+}
+impl MarianModel {
+    pub(super) fn load(directory: &Path) -> Result<Self, Error> {
+        let config = std::fs::read_to_string(directory.join("config.json"))
+            .with_context(|| anyhow!("Failed to read the config of {}", directory.display()))?;
+        let config = serde_json::from_str::<Config>(&config)
+            .with_context(|| anyhow!("Failed to parse the config of {}", directory.display()))?;
+        // The weights are only read, and the file is not expected to change.
+        let weights = unsafe {
+            VarBuilder::from_mmaped_safetensors(
+                &[directory.join("model.safetensors")],
+                DType::F32,
+                &Device::Cpu,
+            )
+        }
+        .with_context(|| anyhow!("Failed to load the weights of {}", directory.display()))?;
+        let model = MTModel::new(&config, weights)
+            .with_context(|| anyhow!("Failed to build the model of {}", directory.display()))?;
+        Ok(Self {
+            model,
+            config,
+            source_tokenizer: tokenizer(directory, "source.tokenizer.json")?,
+            target_tokenizer: tokenizer(directory, "target.tokenizer.json")?,
+        })
+    }
+
+    pub(super) fn translate(&mut self, sentence: &str) -> Result<String, Error> {
+        let device = Device::Cpu;
+        self.model.reset_kv_cache();
+        let mut tokens = self
+            .source_tokenizer
+            .encode(sentence, true)
+            .map_err(|e| anyhow!("Failed to tokenize the sentence: {}", e))?
+            .get_ids()
+            .to_vec();
+        tokens.push(self.config.eos_token_id);
+        let tokens = Tensor::new(tokens.as_slice(), &device)?.unsqueeze(0)?;
+        let encoded = self.model.encoder().forward(&tokens, 0)?;
+
+        let mut output = vec![self.config.decoder_start_token_id];
+        while output.len() < self.config.max_position_embeddings {
+            // After the first step, the cache holds the earlier tokens.
+            let start = match output.len() {
+                1 => 0,
+                length => length - 1,
+            };
+            let input = Tensor::new(&output[start..], &device)?.unsqueeze(0)?;
+            let logits = self.model.decode(&input, &encoded, start)?.squeeze(0)?;
+            let logits = logits.get(logits.dim(0)? - 1)?;
+            let token = logits.argmax(0)?.to_scalar::<u32>()?;
+            if token == self.config.eos_token_id || token == self.config.forced_eos_token_id {
+                break;
+            }
+            output.push(token);
+        }
+        self.target_tokenizer
+            .decode(&output[1..], true)
+            .map_err(|e| anyhow!("Failed to detokenize the translation: {}", e))
+    }
+}
+
+/// The tokenizer `name` of the model, or the one shared by both languages.
+fn tokenizer(directory: &Path, name: &str) -> Result<Tokenizer, Error> {
+    let path = directory.join(name);
+    let path = match path.exists() {
+        true => path,
+        false => directory.join("tokenizer.json"),
+    };
+    Tokenizer::from_file(&path)
+        .map_err(|e| anyhow!("Failed to load the tokenizer {}: {}", path.display(), e))
+}
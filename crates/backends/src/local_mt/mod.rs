@@ -0,0 +1,389 @@
+//! Sentence translation with Marian (OPUS-MT) models run on the CPU, which needs no
+//! network and no API key. Running the models needs the `local-mt` feature; without
+//! it, the models are still found but translating fails.
+//!
+//! A model is a directory with the `config.json` and `model.safetensors` of the
+//! Hugging Face model and its tokenizers, `source.tokenizer.json` and
+//! `target.tokenizer.json`, or a shared `tokenizer.json`.
+
+#[cfg(feature = "local-mt")]
+mod marian;
+#[cfg(not(feature = "local-mt"))]
+mod unavailable;
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, LazyLock, Mutex,
+        mpsc::{self, Receiver},
+    },
+    thread,
+};
+
+use anyhow::{Error, Result, anyhow};
+
+#[cfg(feature = "local-mt")]
+use marian::MarianModel;
+#[cfg(not(feature = "local-mt"))]
+use unavailable::MarianModel;
+
+use crate::{
+    Language, SentenceStream, SentenceTranslator, StreamSentenceTranslator, Translator, document,
+    storage::{self, LocalModel},
+    utils::config_path,
+};
+
+/// Directory of the config dir whose models are found without being listed.
+const MODELS_DIR: &str = "models";
+
+/// Models by directory, loaded on their first use and shared by every translator.
+static LOADED_MODELS: LazyLock<Mutex<HashMap<PathBuf, Arc<Mutex<MarianModel>>>>> =
+    LazyLock::new(Default::default);
+
+/// The listed models and those in the models directory, whose names end with their
+/// language pair as in `opus-mt-en-de`. Listed models come first.
+pub fn available_models(config: &storage::LocalModels) -> Vec<LocalModel> {
+    let mut models = config.models.clone();
+    let directory = match config_path::get_prog_config_dir() {
+        Ok(directory) => directory.join(MODELS_DIR),
+        Err(e) => {
+            log::warn!("Failed to locate the models directory: {:#}", e);
+            return models;
+        }
+    };
+    let Ok(entries) = std::fs::read_dir(&directory) else {
+        return models;
+    };
+    let mut found = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .filter_map(|path| {
+            let (source_language, target_language) = language_pair(&path)?;
+            Some(LocalModel {
+                source_language,
+                target_language,
+                path,
+            })
+        })
+        .collect::<Vec<_>>();
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    models.extend(found);
+    models
+}
+
+/// The language pair at the end of the name of a model directory, as in `opus-mt-en-de`.
+fn language_pair(path: &Path) -> Option<(Language, Language)> {
+    let name = path.file_name()?.to_str()?;
+    let mut codes = name.rsplit(['-', '_']);
+    let target_language = codes.next()?;
+    let source_language = codes.next()?;
+    let language = |code: &str| {
+        (code.len() == 2)
+            .then(|| Language::from_name_or_code(code).ok())
+            .flatten()
+    };
+    Some((language(source_language)?, language(target_language)?))
+}
+
+/// Models entered in the setting window, one per line: a directory, optionally after
+/// its language pair as in `en-de /path/to/model`.
+pub fn models_from_text(text: &str) -> Result<Vec<LocalModel>, Error> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (pair, path) = match line.split_once(char::is_whitespace) {
+                Some((pair, path)) if pair.contains('-') && !pair.contains(['/', '\\']) => {
+                    (Some(pair), PathBuf::from(path.trim()))
+                }
+                _ => (None, PathBuf::from(line)),
+            };
+            let (source_language, target_language) = match pair {
+                Some(pair) => {
+                    let (source, target) = pair.split_once('-').unwrap_or_default();
+                    (
+                        Language::from_name_or_code(source)?,
+                        Language::from_name_or_code(target)?,
+                    )
+                }
+                None => language_pair(&path).ok_or_else(|| {
+                    anyhow!(
+                        "The language pair of {} is unknown, write it before the path as in `en-de {}`",
+                        path.display(),
+                        path.display()
+                    )
+                })?,
+            };
+            Ok(LocalModel {
+                source_language,
+                target_language,
+                path,
+            })
+        })
+        .collect()
+}
+
+/// The listed models as entered in the setting window.
+pub fn models_to_text(models: &[LocalModel]) -> String {
+    models
+        .iter()
+        .map(|model| {
+            format!(
+                "{}-{} {}",
+                model.source_language.code(),
+                model.target_language.code(),
+                model.path.display()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Translates sentence by sentence with the model of the language pair.
+pub struct LocalSentenceTranslator {
+    models: Vec<LocalModel>,
+}
+impl LocalSentenceTranslator {
+    pub fn new(config: &storage::LocalModels) -> Result<Self, Error> {
+        let models = available_models(config);
+        if models.is_empty() {
+            return Err(anyhow!(
+                "No local translation model is installed, see the Local Models settings"
+            ));
+        }
+        Ok(Self { models })
+    }
+
+    pub fn models(&self) -> &[LocalModel] {
+        &self.models
+    }
+
+    /// Whether a model translates from `source_language` into `target_language`.
+    pub fn supports(&self, source_language: Language, target_language: Language) -> bool {
+        self.model(source_language, target_language).is_ok()
+    }
+
+    fn model(
+        &self,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<&LocalModel, Error> {
+        self.models
+            .iter()
+            .find(|model| {
+                model.source_language == source_language && model.target_language == target_language
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "No local model translates from {} into {}",
+                    source_language,
+                    target_language
+                )
+            })
+    }
+
+    /// The model of the language pair, loaded if it is not yet.
+    fn load(
+        &self,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<Arc<Mutex<MarianModel>>, Error> {
+        let path = &self.model(source_language, target_language)?.path;
+        let mut loaded = LOADED_MODELS.lock().unwrap();
+        if let Some(model) = loaded.get(path) {
+            return Ok(model.clone());
+        }
+        log::info!("Load local model {}", path.display());
+        let model = Arc::new(Mutex::new(MarianModel::load(path)?));
+        loaded.insert(path.clone(), model.clone());
+        Ok(model)
+    }
+}
+
+/// Translate `text` a sentence at a time, giving the translation so far to `on_partial`
+/// after each sentence until it fails.
+fn translate_sentences<M>(
+    text: &str,
+    source_language: Language,
+    target_language: Language,
+    mut translate: impl FnMut(&str) -> Result<String, Error>,
+    mut on_partial: impl FnMut(&str) -> Result<(), M>,
+) -> Result<String, Error> {
+    // Scripts without spaces between words have none between sentences either.
+    let unspaced = matches!(target_language, Language::Chinese | Language::Japanese);
+    let mut translation = String::new();
+    for sentence in document::split_sentences(text, source_language) {
+        let trimmed = sentence.trim();
+        let space = &sentence[sentence.trim_end().len()..];
+        translation += &sentence[..sentence.len() - sentence.trim_start().len()];
+        if !trimmed.is_empty() {
+            translation += &translate(trimmed)?;
+        }
+        match unspaced && !space.contains('\n') {
+            true => (),
+            false => translation += space,
+        }
+        if on_partial(&translation).is_err() {
+            break;
+        }
+    }
+    Ok(translation)
+}
+
+impl Translator for LocalSentenceTranslator {
+    fn get_api_key(&self) -> String {
+        String::new()
+    }
+    fn save_api_key(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    fn get_url(&self) -> String {
+        String::new()
+    }
+}
+
+impl SentenceTranslator for LocalSentenceTranslator {
+    fn translate_sentence(
+        &self,
+        sentence: &str,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<String, Error> {
+        let model = self.load(source_language, target_language)?;
+        translate_sentences(
+            sentence,
+            source_language,
+            target_language,
+            |sentence| model.lock().unwrap().translate(sentence),
+            |_| Ok::<(), ()>(()),
+        )
+    }
+}
+
+impl StreamSentenceTranslator for LocalSentenceTranslator {
+    fn stream_translate_sentence(
+        &self,
+        sentence: &str,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<Receiver<String>, Error> {
+        Ok(self
+            .stream_translate_sentence_with_reasoning(sentence, source_language, target_language)?
+            .answer)
+    }
+
+    /// The translation grows by a sentence at a time.
+    fn stream_translate_sentence_with_reasoning(
+        &self,
+        sentence: &str,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<SentenceStream, Error> {
+        let model = self.load(source_language, target_language)?;
+        let text = sentence.to_string();
+        let (tx, answer) = mpsc::channel();
+        let (_, reasoning) = mpsc::channel();
+        let (outcome_tx, outcome) = mpsc::channel();
+        thread::spawn(move || {
+            let result = translate_sentences(
+                &text,
+                source_language,
+                target_language,
+                |sentence| model.lock().unwrap().translate(sentence),
+                |translation| tx.send(translation.to_string()),
+            );
+            match result {
+                Ok(_) => {
+                    let _ = outcome_tx.send(Ok(()));
+                }
+                Err(e) => {
+                    log::error!("Failed to translate with the local model: {:#}", e);
+                    let _ = tx.send(format!("Error: {}", e));
+                    let _ = outcome_tx.send(Err(e));
+                }
+            }
+        });
+        Ok(SentenceStream {
+            answer,
+            reasoning,
+            outcome,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn models_are_read_with_their_language_pairs() {
+        let models =
+            models_from_text("/opt/models/opus-mt-en-de\n\nzh-en /opt/models/chinese english\n")
+                .unwrap();
+        assert_eq!(
+            models,
+            vec![
+                LocalModel {
+                    source_language: Language::English,
+                    target_language: Language::German,
+                    path: PathBuf::from("/opt/models/opus-mt-en-de"),
+                },
+                LocalModel {
+                    source_language: Language::Chinese,
+                    target_language: Language::English,
+                    path: PathBuf::from("/opt/models/chinese english"),
+                },
+            ]
+        );
+        assert_eq!(
+            models_to_text(&models),
+            "en-de /opt/models/opus-mt-en-de\nzh-en /opt/models/chinese english"
+        );
+        assert!(models_from_text("/opt/models/marian").is_err());
+        assert_eq!(
+            language_pair(Path::new("opus-mt-tc-big-fr-en")),
+            Some((Language::French, Language::English))
+        );
+    }
+
+    #[test]
+    fn sentences_are_translated_one_by_one() {
+        let mut partials = Vec::new();
+        let translation = translate_sentences(
+            "Good morning. How are you?\nFine.",
+            Language::English,
+            Language::Chinese,
+            |sentence| {
+                Ok(match sentence {
+                    "Good morning." => "早上好。",
+                    "How are you?" => "你好吗？",
+                    _ => "很好。",
+                }
+                .to_string())
+            },
+            |partial| {
+                partials.push(partial.to_string());
+                Ok::<(), ()>(())
+            },
+        )
+        .unwrap();
+        assert_eq!(translation, "早上好。你好吗？\n很好。");
+        assert_eq!(
+            partials,
+            ["早上好。", "早上好。你好吗？\n", "早上好。你好吗？\n很好。"]
+        );
+
+        let failed = translate_sentences(
+            "One. Two.",
+            Language::English,
+            Language::German,
+            |sentence| match sentence {
+                "One." => Ok("Eins.".to_string()),
+                _ => Err(anyhow!("Out of memory")),
+            },
+            |_| Ok::<(), ()>(()),
+        );
+        assert!(failed.is_err());
+    }
+}
@@ -0,0 +1,18 @@
+//! Stand-in for the models in builds without the `local-mt` feature.
+
+use std::path::Path;
+
+use anyhow::{Error, Result, anyhow};
+
+pub(super) struct MarianModel;
+impl MarianModel {
+    pub(super) fn load(_directory: &Path) -> Result<Self, Error> {
+        Err(anyhow!(
+            "This build cannot run local models, build it with the `local-mt` feature"
+        ))
+    }
+
+    pub(super) fn translate(&mut self, _sentence: &str) -> Result<String, Error> {
+        Err(anyhow!("No local model is loaded"))
+    }
+}
@@ -0,0 +1,145 @@
+//! English suffix rules.
+
+/// Lemmas of a lowercase word by its suffix, most likely first. Comparatives and
+/// adverbs are only guessed when not `confident_only`, as many words merely end alike.
+pub(super) fn lemmas(word: &str, confident_only: bool) -> Vec<String> {
+    let mut lemmas = Vec::new();
+    if word.len() < 4 || !word.bytes().all(|b| b.is_ascii_lowercase()) {
+        return lemmas;
+    }
+
+    if let Some(stem) = word.strip_suffix("ies") {
+        lemmas.push(format!("{stem}y"));
+    } else if word.ends_with("sses")
+        || word.ends_with("ches")
+        || word.ends_with("shes")
+        || word.ends_with("xes")
+    {
+        lemmas.push(word[..word.len() - 2].to_string());
+    } else if let Some(stem) = word.strip_suffix('s')
+        && !["ss", "us", "is"]
+            .iter()
+            .any(|ending| word.ends_with(ending))
+    {
+        lemmas.push(stem.to_string());
+    }
+
+    if let Some(stem) = word.strip_suffix("ied") {
+        lemmas.push(format!("{stem}y"));
+    } else if let Some(stem) = word.strip_suffix("ed")
+        && !word.ends_with("eed")
+        && has_vowel(stem)
+    {
+        lemmas.extend(restore_stem(stem));
+    }
+
+    if let Some(stem) = word.strip_suffix("ing")
+        && stem.len() >= 2
+        && has_vowel(stem)
+    {
+        lemmas.extend(restore_stem(stem));
+    }
+
+    for suffix in ["est", "er"] {
+        let Some(stem) = word.strip_suffix(suffix) else {
+            continue;
+        };
+        if let Some(stem) = stem.strip_suffix('i') {
+            lemmas.push(format!("{stem}y"));
+        } else if is_doubled(stem) {
+            lemmas.push(stem[..stem.len() - 1].to_string());
+        } else if !confident_only && has_vowel(stem) {
+            lemmas.extend(restore_stem(stem));
+        }
+    }
+
+    if !confident_only {
+        if let Some(stem) = word.strip_suffix("ily") {
+            lemmas.push(format!("{stem}y"));
+        } else if let Some(stem) = word.strip_suffix("ly") {
+            lemmas.push(stem.to_string());
+        }
+    }
+    lemmas
+}
+
+/// Stems left by -ed, -ing, -er or -est, with a doubled consonant undone or a silent
+/// e restored, most likely first.
+fn restore_stem(stem: &str) -> Vec<String> {
+    let with_e = format!("{stem}e");
+    if is_doubled(stem) {
+        let undoubled = stem[..stem.len() - 1].to_string();
+        return match stem.as_bytes()[stem.len() - 1] {
+            b's' | b'z' | b'f' => vec![stem.to_string()],
+            b'l' => vec![stem.to_string(), undoubled],
+            _ => vec![undoubled, stem.to_string()],
+        };
+    }
+    if stem.ends_with('y') {
+        vec![stem.to_string()]
+    } else if needs_e(stem.as_bytes()) {
+        vec![with_e, stem.to_string()]
+    } else {
+        vec![stem.to_string(), with_e]
+    }
+}
+
+/// Whether a stem most likely lost a silent e, as in arriv-ed, danc-ing or mak-ing.
+fn needs_e(stem: &[u8]) -> bool {
+    let n = stem.len();
+    if n < 2 {
+        return false;
+    }
+    let (before, previous, last) = (n.checked_sub(3).map(|i| stem[i]), stem[n - 2], stem[n - 1]);
+    let after_consonant = before.is_some_and(is_consonant);
+    match last {
+        b'v' | b'u' | b'c' | b's' | b'z' => true,
+        b'g' => {
+            matches!(previous, b'r' | b'd') || (previous == b'n' && n >= 5 && before == Some(b'a'))
+        }
+        b'l' => is_consonant(previous) && previous != b'r',
+        b'r' => {
+            after_consonant
+                && (matches!(previous, b'a' | b'i' | b'u')
+                    || (previous == b'o' && matches!(before, Some(b'n' | b't' | b'c'))))
+        }
+        b't' if matches!(previous, b'a' | b'u') && after_consonant => true,
+        b'd' if matches!(previous, b'a' | b'i' | b'o' | b'u') && after_consonant => true,
+        b'm' if previous == b'u' && after_consonant => true,
+        b'n' | b'b' if previous == b'i' && after_consonant => true,
+        // A single syllable closed by one consonant, as in hop-ed or lik-ing.
+        _ => {
+            vowel_groups(stem) == 1
+                && is_consonant(last)
+                && !matches!(last, b'w' | b'x' | b'y')
+                && is_vowel(previous)
+                && after_consonant
+        }
+    }
+}
+
+fn is_doubled(stem: &str) -> bool {
+    let stem = stem.as_bytes();
+    stem.len() >= 3
+        && stem[stem.len() - 1] == stem[stem.len() - 2]
+        && is_consonant(stem[stem.len() - 1])
+}
+
+fn is_vowel(b: u8) -> bool {
+    matches!(b, b'a' | b'e' | b'i' | b'o' | b'u')
+}
+
+fn is_consonant(b: u8) -> bool {
+    b.is_ascii_lowercase() && !is_vowel(b)
+}
+
+fn has_vowel(stem: &str) -> bool {
+    stem.bytes().any(|b| is_vowel(b) || b == b'y')
+}
+
+fn vowel_groups(stem: &[u8]) -> usize {
+    stem.iter()
+        .enumerate()
+        .filter(|(i, b)| is_vowel(**b) && (*i == 0 || !is_vowel(stem[i - 1])))
+        .count()
+}
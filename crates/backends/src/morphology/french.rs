@@ -0,0 +1,82 @@
+//! French suffix rules.
+
+/// Verb endings replaced by -er, longest first.
+const VERB_ENDINGS: [&str; 12] = [
+    "aient", "erons", "eront", "ions", "erai", "era", "ons", "ent", "ais", "ait", "ez", "es",
+];
+
+/// Lemmas of a lowercase word by its suffix, most likely first. Only plurals of -al
+/// and -eau nouns and feminines of -eux adjectives are recognised with confidence.
+pub(super) fn lemmas(word: &str, confident_only: bool) -> Vec<String> {
+    let mut lemmas = Vec::new();
+    let length = word.chars().count();
+    if length < 4 {
+        return lemmas;
+    }
+
+    if let Some(stem) = word.strip_suffix("eaux") {
+        lemmas.push(format!("{stem}eau"));
+    } else if let Some(stem) = word.strip_suffix("aux") {
+        lemmas.push(format!("{stem}al"));
+    } else if let Some(stem) = word
+        .strip_suffix("euses")
+        .or_else(|| word.strip_suffix("euse"))
+    {
+        lemmas.push(format!("{stem}eux"));
+    }
+    if confident_only {
+        return lemmas;
+    }
+
+    // Past participles of -er verbs.
+    for ending in ["ées", "és", "ée", "é"] {
+        if let Some(stem) = word.strip_suffix(ending) {
+            lemmas.push(format!("{stem}er"));
+            break;
+        }
+    }
+    // Feminine and plural adjectives.
+    for (ending, masculine) in [
+        ("ives", "if"),
+        ("ive", "if"),
+        ("elles", "el"),
+        ("elle", "el"),
+        ("ennes", "en"),
+        ("enne", "en"),
+    ] {
+        if let Some(stem) = word.strip_suffix(ending) {
+            lemmas.push(format!("{stem}{masculine}"));
+            break;
+        }
+    }
+    // Plurals, then feminines.
+    let singular = word
+        .strip_suffix('s')
+        .or_else(|| word.strip_suffix('x'))
+        .unwrap_or(word);
+    if singular != word {
+        lemmas.push(singular.to_string());
+    }
+    if let Some(masculine) = singular.strip_suffix('e') {
+        lemmas.push(masculine.to_string());
+    }
+    // Conjugated verbs of the -er, -ir and -re groups.
+    for ending in VERB_ENDINGS {
+        if let Some(stem) = word.strip_suffix(ending)
+            && stem.chars().count() >= 2
+        {
+            lemmas.push(format!("{stem}er"));
+            break;
+        }
+    }
+    if let Some(stem) = word.strip_suffix('e') {
+        lemmas.push(format!("{stem}er"));
+    }
+    for ending in ["issons", "issez", "issent", "it", "is", "ie", "i"] {
+        if let Some(stem) = word.strip_suffix(ending) {
+            lemmas.push(format!("{stem}ir"));
+            break;
+        }
+    }
+    lemmas
+}
@@ -0,0 +1,59 @@
+//! German suffix rules.
+
+/// Separable prefixes which come before the ge- of a past participle.
+const SEPARABLE_PREFIXES: [&str; 12] = [
+    "ab", "an", "auf", "aus", "bei", "ein", "mit", "nach", "vor", "weg", "zu", "zurück",
+];
+
+/// Endings of declension and conjugation, longest first.
+const ENDINGS: [&str; 15] = [
+    "test", "ern", "ten", "tet", "en", "er", "es", "em", "st", "et", "te", "e", "n", "s", "t",
+];
+
+/// Lemmas of a lowercase word by its suffix, most likely first. Only weak past
+/// participles are recognised with confidence, since endings are shared by nouns,
+/// adjectives and verbs.
+pub(super) fn lemmas(word: &str, confident_only: bool) -> Vec<String> {
+    let mut lemmas = Vec::new();
+    if let Some(lemma) = weak_participle_infinitive(word) {
+        lemmas.push(lemma);
+    }
+    if confident_only {
+        return lemmas;
+    }
+    for ending in ENDINGS {
+        let Some(stem) = word.strip_suffix(ending) else {
+            continue;
+        };
+        if stem.chars().count() < 3 {
+            continue;
+        }
+        lemmas.push(stem.to_string());
+        lemmas.push(infinitive(stem));
+        lemmas.push(format!("{stem}e"));
+    }
+    lemmas
+}
+
+/// The infinitive of a weak past participle, as in gemacht or eingekauft.
+fn weak_participle_infinitive(word: &str) -> Option<String> {
+    let stem = word.strip_suffix('t')?;
+    let (prefix, stem) = SEPARABLE_PREFIXES
+        .iter()
+        .find_map(|prefix| {
+            word.strip_prefix(prefix)
+                .filter(|rest| rest.starts_with("ge"))
+                .map(|_| (*prefix, &stem[prefix.len()..]))
+        })
+        .unwrap_or(("", stem));
+    let stem = stem.strip_prefix("ge")?;
+    (stem.chars().count() >= 3).then(|| format!("{prefix}{}", infinitive(stem)))
+}
+
+fn infinitive(stem: &str) -> String {
+    if stem.ends_with('e') {
+        format!("{stem}n")
+    } else {
+        format!("{stem}en")
+    }
+}
@@ -0,0 +1,201 @@
+//! Reduction of inflected words to their dictionary form (lemma), from bundled tables
+//! of irregular forms and suffix rules for English, German, French and Russian.
+
+mod english;
+mod french;
+mod german;
+mod russian;
+
+use std::{collections::HashMap, sync::OnceLock};
+
+use crate::{Language, spelling};
+
+/// Irregular forms by lowercase form, each mapped to its lemma.
+type IrregularForms = HashMap<String, &'static str>;
+
+/// The lemma of `word`: its entry in the irregular forms, else the only lemma of the
+/// confident suffix rules which is in the word list, else the word as it is. Listed
+/// words and phrases are not reduced, and neither are words of languages without a
+/// word list, as a guessed lemma may be no word at all.
+pub fn lemmatise(word: &str, language: Language) -> String {
+    let word = word.trim();
+    if word.is_empty() || word.contains(char::is_whitespace) {
+        return word.to_string();
+    }
+    if let Some(lemma) = irregular_forms(language).and_then(|forms| forms.get(&word.to_lowercase()))
+    {
+        return lemma.to_string();
+    }
+    if spelling::is_listed(word, language) {
+        return word.to_string();
+    }
+    let mut listed = rule_lemmas(word, language, true)
+        .into_iter()
+        .filter(|lemma| spelling::is_listed(lemma, language))
+        .collect::<Vec<_>>();
+    listed.dedup();
+    // Several listed lemmas, as past and paste of pasted, cannot be told apart.
+    match listed.as_slice() {
+        [lemma] => lemma.clone(),
+        _ => word.to_string(),
+    }
+}
+
+/// Possible lemmas of `word`, most likely first and the word itself last, for lookups
+/// which can check each of them against a dictionary.
+pub fn lemma_candidates(word: &str, language: Language) -> Vec<String> {
+    let word = word.trim();
+    let mut candidates = Vec::new();
+    if !word.is_empty() && !word.contains(char::is_whitespace) {
+        if let Some(lemma) =
+            irregular_forms(language).and_then(|forms| forms.get(&word.to_lowercase()))
+        {
+            candidates.push(lemma.to_string());
+        }
+        candidates.extend(rule_lemmas(word, language, false));
+    }
+    candidates.push(word.to_string());
+    let mut seen = std::collections::HashSet::new();
+    candidates.retain(|candidate| seen.insert(candidate.clone()));
+    candidates
+}
+
+/// Lemmas from suffix rules, only the confident ones if `confident_only`.
+fn rule_lemmas(word: &str, language: Language, confident_only: bool) -> Vec<String> {
+    let lowercase = word.to_lowercase();
+    match language {
+        // Nouns are capitalised in German, which keep their case and are too irregular
+        // to be reduced with confidence.
+        Language::German if word.starts_with(char::is_uppercase) => {
+            if confident_only {
+                return Vec::new();
+            }
+            german::lemmas(&lowercase, false)
+                .iter()
+                .map(|lemma| capitalise(lemma))
+                .collect()
+        }
+        Language::German => german::lemmas(&lowercase, confident_only),
+        // Elsewhere a capital letter usually marks a name, which is not inflected.
+        _ if word != lowercase => Vec::new(),
+        Language::English => english::lemmas(word, confident_only),
+        Language::French => french::lemmas(word, confident_only),
+        Language::Russian => russian::lemmas(word, confident_only),
+        _ => Vec::new(),
+    }
+}
+
+fn irregular_forms(language: Language) -> Option<&'static IrregularForms> {
+    static ENGLISH: OnceLock<IrregularForms> = OnceLock::new();
+    static GERMAN: OnceLock<IrregularForms> = OnceLock::new();
+    static FRENCH: OnceLock<IrregularForms> = OnceLock::new();
+    static RUSSIAN: OnceLock<IrregularForms> = OnceLock::new();
+    let (forms, table) = match language {
+        Language::English => (&ENGLISH, include_str!("english.txt")),
+        Language::German => (&GERMAN, include_str!("german.txt")),
+        Language::French => (&FRENCH, include_str!("french.txt")),
+        Language::Russian => (&RUSSIAN, include_str!("russian.txt")),
+        _ => return None,
+    };
+    Some(forms.get_or_init(|| parse_irregular_forms(table)))
+}
+
+/// Parse lines of a lemma followed by its forms. Lemmas map to themselves ahead of
+/// being a form of another lemma, and earlier lines win among forms.
+fn parse_irregular_forms(table: &'static str) -> IrregularForms {
+    let entries = table
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let mut forms = IrregularForms::new();
+    for entry in &entries {
+        forms.entry(entry[0].to_lowercase()).or_insert(entry[0]);
+    }
+    for entry in &entries {
+        for form in &entry[1..] {
+            forms.entry(form.to_lowercase()).or_insert(entry[0]);
+        }
+    }
+    forms
+}
+
+fn capitalise(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn english_lemmas() {
+        for (word, lemma) in [
+            ("arrived", "arrive"),
+            ("went", "go"),
+            ("children", "child"),
+            ("studies", "study"),
+            ("stopped", "stop"),
+            ("making", "make"),
+            ("boxes", "box"),
+            ("happier", "happy"),
+            ("decided", "decide"),
+            ("played", "play"),
+            ("news", "news"),
+            ("nothing", "nothing"),
+            ("Paris", "Paris"),
+            ("give up", "give up"),
+        ] {
+            assert_eq!(lemmatise(word, Language::English), lemma, "{}", word);
+        }
+    }
+
+    #[test]
+    fn unverified_english_lemmas_are_not_used() {
+        for (word, lemma) in [
+            ("smiled", "smile"),
+            ("typed", "type"),
+            ("wasted", "waste"),
+            ("tasted", "taste"),
+            ("pasted", "pasted"),
+            // Listed as an adjective.
+            ("bored", "bored"),
+            ("adored", "adore"),
+            ("clothes", "clothes"),
+            ("beloved", "beloved"),
+        ] {
+            assert_eq!(lemmatise(word, Language::English), lemma, "{}", word);
+        }
+    }
+
+    #[test]
+    fn other_language_lemmas() {
+        assert_eq!(lemmatise("ging", Language::German), "gehen");
+        assert_eq!(lemmatise("Kinder", Language::German), "Kind");
+        assert_eq!(lemmatise("sont", Language::French), "être");
+        assert_eq!(lemmatise("люди", Language::Russian), "человек");
+        assert_eq!(lemmatise("gemacht", Language::German), "machen");
+        assert_eq!(lemmatise("chevaux", Language::French), "cheval");
+        assert_eq!(lemmatise("нового", Language::Russian), "новый");
+        assert_eq!(lemmatise("Häuschen", Language::German), "Häuschen");
+        assert_eq!(lemmatise("arrived", Language::Japanese), "arrived");
+    }
+
+    #[test]
+    fn candidates_end_with_the_word() {
+        let candidates = lemma_candidates("larger", Language::English);
+        assert!(candidates.contains(&"large".to_string()));
+        assert_eq!(candidates.last().unwrap(), "larger");
+        assert_eq!(
+            lemma_candidates("parlées", Language::French)
+                .first()
+                .unwrap(),
+            "parler"
+        );
+    }
+}
@@ -0,0 +1,103 @@
+//! Russian suffix rules.
+
+/// Endings of adjectives in oblique cases, longest first, with the nominative ending of
+/// hard and soft stems.
+const ADJECTIVE_ENDINGS: [(&str, &str); 19] = [
+    ("ыми", "ый"),
+    ("ими", "ий"),
+    ("ого", "ый"),
+    ("его", "ий"),
+    ("ому", "ый"),
+    ("ему", "ий"),
+    ("ая", "ый"),
+    ("яя", "ий"),
+    ("ое", "ый"),
+    ("ее", "ий"),
+    ("ые", "ый"),
+    ("ие", "ий"),
+    ("ую", "ый"),
+    ("юю", "ий"),
+    ("ых", "ый"),
+    ("их", "ий"),
+    ("ым", "ый"),
+    ("им", "ий"),
+    ("ой", "ой"),
+];
+
+/// Endings of nouns in oblique cases and the plural, longest first.
+const NOUN_ENDINGS: [&str; 17] = [
+    "ами", "ями", "ах", "ях", "ов", "ев", "ей", "ом", "ем", "ам", "ям", "у", "ю", "ы", "и", "а",
+    "я",
+];
+
+/// Endings of verbs in the present tense, with the infinitive ending replacing them.
+const VERB_ENDINGS: [(&str, &str); 12] = [
+    ("ешь", "ть"),
+    ("ете", "ть"),
+    ("ишь", "ить"),
+    ("ите", "ить"),
+    ("ет", "ть"),
+    ("ем", "ть"),
+    ("ют", "ть"),
+    ("ут", "ть"),
+    ("ит", "ить"),
+    ("им", "ить"),
+    ("ят", "ить"),
+    ("ат", "ать"),
+];
+
+/// Lemmas of a lowercase word by its suffix, most likely first. Only the long oblique
+/// endings of adjectives are recognised with confidence.
+pub(super) fn lemmas(word: &str, confident_only: bool) -> Vec<String> {
+    let mut lemmas = Vec::new();
+    for (ending, nominative) in ADJECTIVE_ENDINGS {
+        let Some(stem) = word.strip_suffix(ending) else {
+            continue;
+        };
+        if stem.chars().count() < 3 || (confident_only && ending.chars().count() < 3) {
+            continue;
+        }
+        // Stems ending in г, к, х, ж, ш, ч or щ take -ий.
+        let nominative =
+            if stem.ends_with(['г', 'к', 'х', 'ж', 'ш', 'ч', 'щ']) && nominative == "ый" {
+                "ий"
+            } else {
+                nominative
+            };
+        lemmas.push(format!("{stem}{nominative}"));
+        break;
+    }
+    if confident_only {
+        return lemmas;
+    }
+
+    // Past tense of verbs.
+    for ending in ["ла", "ло", "ли", "л"] {
+        if let Some(stem) = word.strip_suffix(ending)
+            && stem.chars().count() >= 2
+        {
+            lemmas.push(format!("{stem}ть"));
+            break;
+        }
+    }
+    for (ending, infinitive) in VERB_ENDINGS {
+        if let Some(stem) = word.strip_suffix(ending)
+            && stem.chars().count() >= 2
+        {
+            lemmas.push(format!("{stem}{infinitive}"));
+            break;
+        }
+    }
+    for ending in NOUN_ENDINGS {
+        if let Some(stem) = word.strip_suffix(ending)
+            && stem.chars().count() >= 2
+        {
+            lemmas.push(stem.to_string());
+            lemmas.push(format!("{stem}а"));
+            lemmas.push(format!("{stem}о"));
+            lemmas.push(format!("{stem}ь"));
+            break;
+        }
+    }
+    lemmas
+}
@@ -0,0 +1,526 @@
+//! Vocabulary notebook of starred senses, reviewed with the SM-2 scheduler.
+
+use std::path::Path;
+
+use anyhow::{Context, Error, Result, anyhow};
+use rusqlite::{OptionalExtension, Row, params};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Language,
+    database::{self, Schema, Store},
+    dict_interface::{Explanation, PartOfSpeechType, WordExplanation},
+};
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Cards answered with `Again` are shown once more after this many seconds.
+const RELEARN_DELAY_SECONDS: i64 = 10 * 60;
+
+const INITIAL_EASE_FACTOR: f64 = 2.5;
+const MIN_EASE_FACTOR: f64 = 1.3;
+
+const SCHEMA: Schema = Schema {
+    name: "notebook tables",
+    sql: r#"
+CREATE TABLE IF NOT EXISTS notebook (
+    id INTEGER PRIMARY KEY,
+    word TEXT NOT NULL,
+    phonetics TEXT,
+    part_of_speech TEXT NOT NULL,
+    definition TEXT NOT NULL,
+    sense TEXT NOT NULL,
+    source_language TEXT NOT NULL,
+    target_language TEXT NOT NULL,
+    added_at INTEGER NOT NULL,
+    repetitions INTEGER NOT NULL,
+    interval_days INTEGER NOT NULL,
+    ease_factor REAL NOT NULL,
+    due_at INTEGER NOT NULL,
+    reviewed_at INTEGER,
+    UNIQUE (word, part_of_speech, definition)
+);
+CREATE INDEX IF NOT EXISTS notebook_due_at ON notebook (due_at);
+"#,
+};
+
+/// How well a card was remembered, from the worst to the best.
+#[derive(strum::Display, strum::EnumString, Debug, Clone, Copy, PartialEq, Eq)]
+#[strum(ascii_case_insensitive)]
+pub enum ReviewGrade {
+    Again,
+    Hard,
+    Good,
+    Easy,
+}
+impl ReviewGrade {
+    /// Response quality on the 0-5 scale of SM-2.
+    fn quality(self) -> u8 {
+        match self {
+            Self::Again => 1,
+            Self::Hard => 3,
+            Self::Good => 4,
+            Self::Easy => 5,
+        }
+    }
+}
+
+/// Study state of a card.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Schedule {
+    /// Successful reviews in a row.
+    pub repetitions: u32,
+    pub interval_days: u32,
+    pub ease_factor: f64,
+    /// Seconds since the Unix epoch.
+    pub due_at: i64,
+    pub reviewed_at: Option<i64>,
+}
+impl Schedule {
+    /// A new card, which is due immediately.
+    pub fn new(now: i64) -> Self {
+        Self {
+            repetitions: 0,
+            interval_days: 0,
+            ease_factor: INITIAL_EASE_FACTOR,
+            due_at: now,
+            reviewed_at: None,
+        }
+    }
+
+    /// The schedule after reviewing the card at `now`, following SM-2.
+    pub fn review(&self, grade: ReviewGrade, now: i64) -> Self {
+        let quality = f64::from(grade.quality());
+        let ease_factor = (self.ease_factor
+            + (0.1 - (5.0 - quality) * (0.08 + (5.0 - quality) * 0.02)))
+            .max(MIN_EASE_FACTOR);
+
+        if grade == ReviewGrade::Again {
+            return Self {
+                repetitions: 0,
+                interval_days: 0,
+                ease_factor,
+                due_at: now + RELEARN_DELAY_SECONDS,
+                reviewed_at: Some(now),
+            };
+        }
+
+        let repetitions = self.repetitions + 1;
+        let interval_days = match repetitions {
+            1 => 1,
+            2 => 6,
+            _ => (f64::from(self.interval_days) * ease_factor).round() as u32,
+        };
+        Self {
+            repetitions,
+            interval_days,
+            ease_factor,
+            due_at: now + i64::from(interval_days) * SECONDS_PER_DAY,
+            reviewed_at: Some(now),
+        }
+    }
+}
+
+/// A starred sense of a word.
+#[derive(Debug, Clone)]
+pub struct Card {
+    pub id: i64,
+    pub word: String,
+    pub phonetics: Option<Vec<String>>,
+    pub part_of_speech: PartOfSpeechType,
+    /// The chosen sense, with its examples.
+    pub sense: Explanation,
+    pub source_language: Language,
+    pub target_language: Language,
+    pub added_at: i64,
+    pub schedule: Schedule,
+}
+impl Card {
+    /// The question and answer shown when the card is reviewed in `direction`.
+    pub fn quiz(&self, direction: QuizDirection) -> Quiz {
+        let phonetics = self.phonetics.as_deref().unwrap_or_default().join(", ");
+        let examples = self.sense.examples.as_deref().unwrap_or_default();
+        match direction {
+            QuizDirection::DefinitionToWord => Quiz {
+                prompt: self.sense.definition.clone(),
+                // The word is hidden, so that the example does not give it away.
+                hint: examples
+                    .first()
+                    .map(|example| {
+                        format!(
+                            "{} {}",
+                            self.part_of_speech,
+                            mask_word(&example.example, &self.word)
+                        )
+                    })
+                    .unwrap_or_else(|| self.part_of_speech.to_string()),
+                answer: format!("{} {}\n{}", self.word, phonetics, self.sense.explanation),
+            },
+            QuizDirection::WordToTranslation => Quiz {
+                prompt: self.word.clone(),
+                hint: format!("{} {}", self.part_of_speech, phonetics),
+                answer: std::iter::once(self.sense.explanation.clone())
+                    .chain(
+                        examples
+                            .iter()
+                            .map(|example| format!("{}\n{}", example.example, example.translation)),
+                    )
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            },
+        }
+    }
+}
+
+/// What a review card asks for.
+#[derive(strum::Display, strum::EnumString, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuizDirection {
+    /// Show the definition, recall the word.
+    DefinitionToWord,
+    /// Show the word, recall its translation.
+    WordToTranslation,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quiz {
+    pub prompt: String,
+    pub hint: String,
+    pub answer: String,
+}
+
+/// Replace every occurrence of `word` in `text`, ignoring ASCII case.
+fn mask_word(text: &str, word: &str) -> String {
+    if word.is_empty() {
+        return text.to_string();
+    }
+    let lower_text = text.to_ascii_lowercase();
+    let lower_word = word.to_ascii_lowercase();
+    let mut masked = String::new();
+    let mut rest = 0;
+    for (start, _) in lower_text.match_indices(&lower_word) {
+        if start < rest {
+            continue;
+        }
+        masked.push_str(&text[rest..start]);
+        masked.push_str("____");
+        rest = start + lower_word.len();
+    }
+    masked.push_str(&text[rest..]);
+    masked
+}
+
+/// Starred senses and their study state. The store can be shared between threads.
+pub struct NotebookStore {
+    store: Store,
+}
+impl NotebookStore {
+    /// Open the notebook in the local database under the config dir.
+    pub fn open_default() -> Result<Self, Error> {
+        Ok(Self {
+            store: Store::open_default(&SCHEMA)?,
+        })
+    }
+
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        Ok(Self {
+            store: Store::open(path, &SCHEMA)?,
+        })
+    }
+
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Ok(Self {
+            store: Store::open_in_memory(&SCHEMA)?,
+        })
+    }
+
+    /// Star the `sense`-th explanation of `word_explanation`, counted across all parts
+    /// of speech, and return the id of its card. Starring a sense twice keeps the
+    /// existing card and its study state.
+    pub fn star(
+        &self,
+        word_explanation: &WordExplanation,
+        sense: usize,
+        (source_language, target_language): (Language, Language),
+    ) -> Result<i64, Error> {
+        let (part_of_speech, explanation) =
+            nth_sense(word_explanation, sense).ok_or_else(|| {
+                anyhow!(
+                    "Sense {} of {} does not exist",
+                    sense,
+                    word_explanation.word
+                )
+            })?;
+        let now = database::now();
+        let schedule = Schedule::new(now);
+        let connection = self.store.connection();
+        connection
+            .execute(
+                "INSERT INTO notebook (word, phonetics, part_of_speech, definition, sense, \
+                 source_language, target_language, added_at, repetitions, interval_days, \
+                 ease_factor, due_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12) \
+                 ON CONFLICT (word, part_of_speech, definition) DO NOTHING",
+                params![
+                    word_explanation.word,
+                    word_explanation
+                        .phonetics
+                        .as_ref()
+                        .map(serde_json::to_string)
+                        .transpose()?,
+                    part_of_speech.as_str(),
+                    explanation.definition,
+                    serde_json::to_string(explanation)?,
+                    source_language.to_string(),
+                    target_language.to_string(),
+                    now,
+                    schedule.repetitions,
+                    schedule.interval_days,
+                    schedule.ease_factor,
+                    schedule.due_at,
+                ],
+            )
+            .with_context(|| anyhow!("Failed to star {}", word_explanation.word))?;
+        connection
+            .query_row(
+                "SELECT id FROM notebook \
+                 WHERE word = ?1 AND part_of_speech = ?2 AND definition = ?3",
+                params![
+                    word_explanation.word,
+                    part_of_speech.as_str(),
+                    explanation.definition
+                ],
+                |row| row.get(0),
+            )
+            .with_context(|| anyhow!("Failed to read card of {}", word_explanation.word))
+    }
+
+    pub fn unstar(&self, id: i64) -> Result<(), Error> {
+        self.store
+            .connection()
+            .execute("DELETE FROM notebook WHERE id = ?1", [id])
+            .with_context(|| anyhow!("Failed to remove card {}", id))?;
+        Ok(())
+    }
+
+    /// Ids of the cards of each sense of `word_explanation`, in the order of `star`.
+    pub fn starred_senses(
+        &self,
+        word_explanation: &WordExplanation,
+    ) -> Result<Vec<Option<i64>>, Error> {
+        let connection = self.store.connection();
+        let mut statement = connection.prepare(
+            "SELECT id FROM notebook WHERE word = ?1 AND part_of_speech = ?2 AND definition = ?3",
+        )?;
+        let mut ids = Vec::new();
+        for part_of_speech in word_explanation.part_of_speeches.iter().flatten() {
+            for explanation in &part_of_speech.explanations {
+                ids.push(
+                    statement
+                        .query_row(
+                            params![
+                                word_explanation.word,
+                                part_of_speech.part_of_speech.as_str(),
+                                explanation.definition
+                            ],
+                            |row| row.get(0),
+                        )
+                        .optional()?,
+                );
+            }
+        }
+        Ok(ids)
+    }
+
+    pub fn get(&self, id: i64) -> Result<Option<Card>, Error> {
+        let connection = self.store.connection();
+        connection
+            .query_row(&format!("{} WHERE id = ?1", SELECT_CARD), [id], |row| {
+                Ok(card_from_row(row))
+            })
+            .optional()
+            .with_context(|| anyhow!("Failed to read card {}", id))?
+            .transpose()
+    }
+
+    /// All cards, in the order they were starred.
+    pub fn cards(&self) -> Result<Vec<Card>, Error> {
+        let connection = self.store.connection();
+        let mut statement =
+            connection.prepare(&format!("{} ORDER BY added_at, id", SELECT_CARD))?;
+        let rows = statement
+            .query_map([], |row| Ok(card_from_row(row)))
+            .with_context(|| anyhow!("Failed to read cards"))?;
+        let mut cards = Vec::new();
+        for row in rows {
+            cards.push(row??);
+        }
+        Ok(cards)
+    }
+
+    /// Cards due at `now`, the most overdue first.
+    pub fn due_cards(&self, now: i64, limit: usize) -> Result<Vec<Card>, Error> {
+        let connection = self.store.connection();
+        let mut statement = connection.prepare(&format!(
+            "{} WHERE due_at <= ?1 ORDER BY due_at, id LIMIT ?2",
+            SELECT_CARD
+        ))?;
+        let rows = statement
+            .query_map(params![now, limit as i64], |row| Ok(card_from_row(row)))
+            .with_context(|| anyhow!("Failed to read due cards"))?;
+        let mut cards = Vec::new();
+        for row in rows {
+            cards.push(row??);
+        }
+        Ok(cards)
+    }
+
+    pub fn due_count(&self, now: i64) -> Result<usize, Error> {
+        self.store
+            .connection()
+            .query_row(
+                "SELECT COUNT(*) FROM notebook WHERE due_at <= ?1",
+                [now],
+                |row| row.get::<_, i64>(0),
+            )
+            .map(|count| count as usize)
+            .with_context(|| anyhow!("Failed to count due cards"))
+    }
+
+    /// Record a review of card `id` at `now` and return its new schedule.
+    pub fn review(&self, id: i64, grade: ReviewGrade, now: i64) -> Result<Schedule, Error> {
+        let card = self
+            .get(id)?
+            .ok_or_else(|| anyhow!("Card {} does not exist", id))?;
+        let schedule = card.schedule.review(grade, now);
+        self.store
+            .connection()
+            .execute(
+                "UPDATE notebook SET repetitions = ?1, interval_days = ?2, ease_factor = ?3, \
+                 due_at = ?4, reviewed_at = ?5 WHERE id = ?6",
+                params![
+                    schedule.repetitions,
+                    schedule.interval_days,
+                    schedule.ease_factor,
+                    schedule.due_at,
+                    schedule.reviewed_at,
+                    id
+                ],
+            )
+            .with_context(|| anyhow!("Failed to save review of card {}", id))?;
+        Ok(schedule)
+    }
+}
+
+const SELECT_CARD: &str = "SELECT id, word, phonetics, part_of_speech, sense, source_language, \
+                           target_language, added_at, repetitions, interval_days, ease_factor, \
+                           due_at, reviewed_at FROM notebook";
+
+/// Columns are expected in the order of `SELECT_CARD`.
+fn card_from_row(row: &Row) -> Result<Card, Error> {
+    let phonetics: Option<String> = row.get(2)?;
+    let part_of_speech: String = row.get(3)?;
+    let sense: String = row.get(4)?;
+    let source_language: String = row.get(5)?;
+    let target_language: String = row.get(6)?;
+    Ok(Card {
+        id: row.get(0)?,
+        word: row.get(1)?,
+        phonetics: phonetics
+            .map(|phonetics| serde_json::from_str(&phonetics))
+            .transpose()?,
+        part_of_speech: serde_json::from_value(serde_json::Value::String(part_of_speech))?,
+        sense: serde_json::from_str(&sense)
+            .map_err(|e| anyhow!("Failed to deserialize starred sense: {}", e))?,
+        source_language: source_language
+            .parse()
+            .map_err(|_| anyhow!("Unknown language in notebook: {}", source_language))?,
+        target_language: target_language
+            .parse()
+            .map_err(|_| anyhow!("Unknown language in notebook: {}", target_language))?,
+        added_at: row.get(7)?,
+        schedule: Schedule {
+            repetitions: row.get(8)?,
+            interval_days: row.get(9)?,
+            ease_factor: row.get(10)?,
+            due_at: row.get(11)?,
+            reviewed_at: row.get(12)?,
+        },
+    })
+}
+
+/// The `n`-th explanation of `word_explanation`, counted across all parts of speech.
+pub fn nth_sense(
+    word_explanation: &WordExplanation,
+    n: usize,
+) -> Option<(&PartOfSpeechType, &Explanation)> {
+    word_explanation
+        .part_of_speeches
+        .iter()
+        .flatten()
+        .flat_map(|part_of_speech| {
+            part_of_speech
+                .explanations
+                .iter()
+                .map(move |explanation| (&part_of_speech.part_of_speech, explanation))
+        })
+        .nth(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dict_interface::example_arrive_word_explanation;
+
+    #[test]
+    fn sm2_intervals_grow_with_ease() {
+        let mut schedule = Schedule::new(0);
+        let mut intervals = Vec::new();
+        for _ in 0..4 {
+            schedule = schedule.review(ReviewGrade::Good, schedule.due_at);
+            intervals.push(schedule.interval_days);
+        }
+        assert_eq!(intervals, vec![1, 6, 15, 38]);
+        assert_eq!(schedule.ease_factor, INITIAL_EASE_FACTOR);
+
+        let lapsed = schedule.review(ReviewGrade::Again, schedule.due_at);
+        assert_eq!(lapsed.repetitions, 0);
+        assert_eq!(lapsed.due_at, schedule.due_at + RELEARN_DELAY_SECONDS);
+        assert!(lapsed.ease_factor < schedule.ease_factor);
+    }
+
+    #[test]
+    fn ease_factor_has_a_floor() {
+        let mut schedule = Schedule::new(0);
+        for _ in 0..20 {
+            schedule = schedule.review(ReviewGrade::Again, schedule.due_at);
+        }
+        assert_eq!(schedule.ease_factor, MIN_EASE_FACTOR);
+    }
+
+    #[test]
+    fn starred_senses_are_due_until_reviewed() {
+        let store = NotebookStore::open_in_memory().unwrap();
+        let arrive = example_arrive_word_explanation();
+        let languages = (Language::English, Language::Chinese);
+
+        let id = store.star(&arrive, 1, languages).unwrap();
+        assert_eq!(store.star(&arrive, 1, languages).unwrap(), id);
+        assert_eq!(store.starred_senses(&arrive).unwrap(), vec![None, Some(id)]);
+
+        let now = database::now();
+        let card = &store.due_cards(now, 10).unwrap()[0];
+        assert_eq!(card.sense.explanation, "（东西）送达；寄到");
+        assert_eq!(card.part_of_speech.as_str(), "verb");
+
+        store.review(id, ReviewGrade::Good, now).unwrap();
+        assert_eq!(store.due_count(now).unwrap(), 0);
+        assert_eq!(store.due_count(now + SECONDS_PER_DAY).unwrap(), 1);
+
+        let quiz = card.quiz(QuizDirection::DefinitionToWord);
+        assert_eq!(quiz.prompt, "(of things) to be brought to sb");
+        assert_eq!(quiz.hint, "verb A letter ____d for you this morning");
+        assert!(quiz.answer.starts_with("arrive /əˈraɪv/"));
+
+        store.unstar(id).unwrap();
+        assert!(store.get(id).unwrap().is_none());
+    }
+}
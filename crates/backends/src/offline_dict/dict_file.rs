@@ -0,0 +1,229 @@
+//! Data files of StarDict and DICT dictionaries, plain or compressed with dictzip.
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    sync::Mutex,
+};
+
+use anyhow::{Context, Error, Result, anyhow};
+use flate2::{Decompress, FlushDecompress, read::GzDecoder};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const FLAG_HCRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+
+/// Random-access reader of a dictionary data file.
+pub(super) enum DictFile {
+    Plain(Mutex<File>),
+    /// Gzip file with the dictzip `RA` extra field, whose chunks are compressed
+    /// independently and can be read without decompressing the whole file.
+    DictZip {
+        file: Mutex<File>,
+        chunk_length: usize,
+        /// Offset of each compressed chunk in the file, followed by the end of the last.
+        chunk_offsets: Vec<u64>,
+    },
+    /// Gzip file without random access, decompressed when opened.
+    InMemory(Vec<u8>),
+}
+impl DictFile {
+    pub(super) fn open(path: &Path) -> Result<Self, Error> {
+        let mut file =
+            File::open(path).with_context(|| anyhow!("Failed to open {}", path.display()))?;
+        let mut magic = [0; 2];
+        let is_gzip = file.read_exact(&mut magic).is_ok() && magic == GZIP_MAGIC;
+        file.seek(SeekFrom::Start(0))?;
+        if !is_gzip {
+            return Ok(Self::Plain(Mutex::new(file)));
+        }
+
+        match read_dictzip_header(&mut file)
+            .with_context(|| anyhow!("Failed to read gzip header of {}", path.display()))?
+        {
+            Some((chunk_length, chunk_offsets)) => Ok(Self::DictZip {
+                file: Mutex::new(file),
+                chunk_length,
+                chunk_offsets,
+            }),
+            None => {
+                file.seek(SeekFrom::Start(0))?;
+                let mut data = Vec::new();
+                GzDecoder::new(file)
+                    .read_to_end(&mut data)
+                    .with_context(|| anyhow!("Failed to decompress {}", path.display()))?;
+                Ok(Self::InMemory(data))
+            }
+        }
+    }
+
+    /// Read `size` bytes at `offset` of the uncompressed data.
+    pub(super) fn read(&self, offset: u64, size: usize) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Plain(file) => {
+                let mut file = file.lock().unwrap();
+                file.seek(SeekFrom::Start(offset))?;
+                let mut data = vec![0; size];
+                file.read_exact(&mut data)?;
+                Ok(data)
+            }
+            Self::InMemory(data) => (offset as usize)
+                .checked_add(size)
+                .and_then(|end| data.get(offset as usize..end))
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| anyhow!("Entry at {} is beyond the end of the dictionary", offset)),
+            Self::DictZip {
+                file,
+                chunk_length,
+                chunk_offsets,
+            } => {
+                let first_chunk = offset as usize / chunk_length;
+                let Some(last_chunk) = (offset as usize)
+                    .checked_add(size)
+                    .map(|end| end.div_ceil(*chunk_length))
+                    .filter(|&last_chunk| last_chunk < chunk_offsets.len())
+                else {
+                    return Err(anyhow!(
+                        "Entry at {} is beyond the end of the dictionary",
+                        offset
+                    ));
+                };
+
+                let mut file = file.lock().unwrap();
+                let mut data = Vec::with_capacity((last_chunk - first_chunk) * chunk_length);
+                for chunk in first_chunk..last_chunk {
+                    let start = chunk_offsets[chunk];
+                    let mut compressed = vec![0; (chunk_offsets[chunk + 1] - start) as usize];
+                    file.seek(SeekFrom::Start(start))?;
+                    file.read_exact(&mut compressed)?;
+
+                    let mut chunk_data = Vec::with_capacity(*chunk_length);
+                    Decompress::new(false)
+                        .decompress_vec(&compressed, &mut chunk_data, FlushDecompress::Sync)
+                        .map_err(|e| anyhow!("Failed to decompress chunk {}: {}", chunk, e))?;
+                    data.extend_from_slice(&chunk_data);
+                }
+
+                let start = offset as usize - first_chunk * chunk_length;
+                data.get(start..start + size)
+                    .map(<[u8]>::to_vec)
+                    .ok_or_else(|| anyhow!("Entry at {} is truncated", offset))
+            }
+        }
+    }
+}
+
+/// Parse the gzip header, returning the chunk length and chunk offsets if the file
+/// was written by dictzip.
+fn read_dictzip_header(file: &mut File) -> Result<Option<(usize, Vec<u64>)>, Error> {
+    let mut header = [0; 10];
+    file.read_exact(&mut header)?;
+    let flags = header[3];
+    if flags & FLAG_EXTRA == 0 {
+        return Ok(None);
+    }
+
+    let extra_length = read_u16_le(file)? as usize;
+    let mut extra = vec![0; extra_length];
+    file.read_exact(&mut extra)?;
+    let mut chunk_info = None;
+    let mut rest = extra.as_slice();
+    while rest.len() >= 4 {
+        let field_length = u16::from_le_bytes([rest[2], rest[3]]) as usize;
+        let field = rest
+            .get(4..4 + field_length)
+            .ok_or_else(|| anyhow!("Truncated gzip extra field"))?;
+        if &rest[..2] == b"RA" && field.len() >= 6 {
+            let chunk_length = u16::from_le_bytes([field[2], field[3]]) as usize;
+            if chunk_length == 0 {
+                return Err(anyhow!("Invalid dictzip chunk length of 0"));
+            }
+            let chunk_count = u16::from_le_bytes([field[4], field[5]]) as usize;
+            let chunk_sizes = field[6..]
+                .chunks_exact(2)
+                .take(chunk_count)
+                .map(|size| u64::from(u16::from_le_bytes([size[0], size[1]])))
+                .collect::<Vec<_>>();
+            chunk_info = Some((chunk_length, chunk_sizes));
+        }
+        rest = &rest[4 + field_length..];
+    }
+    let Some((chunk_length, chunk_sizes)) = chunk_info else {
+        return Ok(None);
+    };
+
+    if flags & FLAG_NAME != 0 {
+        skip_zero_terminated(file)?;
+    }
+    if flags & FLAG_COMMENT != 0 {
+        skip_zero_terminated(file)?;
+    }
+    if flags & FLAG_HCRC != 0 {
+        read_u16_le(file)?;
+    }
+
+    let mut offset = file.stream_position()?;
+    let mut chunk_offsets = vec![offset];
+    for size in chunk_sizes {
+        offset += size;
+        chunk_offsets.push(offset);
+    }
+    Ok(Some((chunk_length, chunk_offsets)))
+}
+
+fn read_u16_le(file: &mut File) -> Result<u16, Error> {
+    let mut bytes = [0; 2];
+    file.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn skip_zero_terminated(file: &mut File) -> Result<(), Error> {
+    let mut byte = [0; 1];
+    loop {
+        file.read_exact(&mut byte)?;
+        if byte[0] == 0 {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_dictzip_chunks_are_rejected() {
+        let mut extra = b"RA".to_vec();
+        extra.extend_from_slice(&8u16.to_le_bytes());
+        for number in [1u16, 0, 1, 0] {
+            extra.extend_from_slice(&number.to_le_bytes());
+        }
+        let mut dz = vec![0x1f, 0x8b, 8, FLAG_EXTRA, 0, 0, 0, 0, 2, 3];
+        dz.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        dz.extend_from_slice(&extra);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.dict.dz");
+        std::fs::write(&path, dz).unwrap();
+
+        let error = DictFile::open(&path).err().unwrap();
+        assert_eq!(
+            format!("{:#}", error.root_cause()),
+            "Invalid dictzip chunk length of 0"
+        );
+    }
+
+    #[test]
+    fn entries_past_the_end_are_rejected() {
+        let dict = DictFile::InMemory(b"entry".to_vec());
+        assert_eq!(dict.read(1, 3).unwrap(), b"ntr");
+
+        let error = dict.read(u64::MAX, 2).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!("Entry at {} is beyond the end of the dictionary", u64::MAX)
+        );
+    }
+}
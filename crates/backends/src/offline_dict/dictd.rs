@@ -0,0 +1,122 @@
+//! DICT protocol dictionaries as served by dictd: `.index` and `.dict(.dz)`.
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Error, Result, anyhow};
+
+use super::{Dictionary, dict_file::DictFile};
+use crate::dict_interface::RawDefinitionFormat;
+
+/// Headwords of the entries holding information about the dictionary itself.
+const SHORT_NAME_ENTRIES: [&str; 2] = ["00databaseshort", "00-database-short"];
+
+pub(super) struct DictdDictionary {
+    name: String,
+    /// Lowercased headword to the offsets and sizes of its entries in the data file.
+    index: HashMap<String, Vec<(u64, usize)>>,
+    data: DictFile,
+}
+impl DictdDictionary {
+    pub(super) fn open(index_path: &Path) -> Result<Self, Error> {
+        let index_text = std::fs::read_to_string(index_path)
+            .with_context(|| anyhow!("Failed to read {}", index_path.display()))?;
+        let mut index: HashMap<String, Vec<(u64, usize)>> = HashMap::new();
+        for (line_number, line) in index_text.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let mut columns = line.split('\t');
+            let (Some(word), Some(offset), Some(size)) =
+                (columns.next(), columns.next(), columns.next())
+            else {
+                return Err(anyhow!(
+                    "Malformed line {} of {}",
+                    line_number + 1,
+                    index_path.display()
+                ));
+            };
+            let offset = decode_base64_number(offset)
+                .with_context(|| anyhow!("Malformed offset of {}", word))?;
+            let size = decode_base64_number(size)
+                .with_context(|| anyhow!("Malformed size of {}", word))?;
+            index
+                .entry(word.to_lowercase())
+                .or_default()
+                .push((offset, size as usize));
+        }
+
+        let data_path = [
+            index_path.with_extension("dict.dz"),
+            index_path.with_extension("dict"),
+        ]
+        .into_iter()
+        .find(|path| path.exists())
+        .ok_or_else(|| anyhow!("No .dict or .dict.dz next to {}", index_path.display()))?;
+
+        let mut dictionary = Self {
+            name: index_path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into(),
+            index,
+            data: DictFile::open(&data_path)?,
+        };
+        if let Some(name) = SHORT_NAME_ENTRIES
+            .iter()
+            .find_map(|entry| dictionary.read_entries(entry).ok().flatten())
+        {
+            // The entry starts with its own headword line, followed by the name.
+            if let Some(name) = name.lines().map(str::trim).rfind(|line| !line.is_empty()) {
+                dictionary.name = name.to_string();
+            }
+        }
+        Ok(dictionary)
+    }
+
+    fn read_entries(&self, word: &str) -> Result<Option<String>, Error> {
+        let Some(locations) = self.index.get(&word.to_lowercase()) else {
+            return Ok(None);
+        };
+        let mut entries = Vec::new();
+        for (offset, size) in locations {
+            let entry = self.data.read(*offset, *size)?;
+            entries.push(String::from_utf8_lossy(&entry).trim_end().to_string());
+        }
+        Ok(Some(entries.join("\n\n")))
+    }
+}
+
+impl Dictionary for DictdDictionary {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn contains(&self, word: &str) -> bool {
+        self.index.contains_key(&word.to_lowercase())
+    }
+
+    fn lookup(&self, word: &str) -> Result<Option<(RawDefinitionFormat, String)>, Error> {
+        Ok(self
+            .read_entries(word)?
+            .map(|content| (RawDefinitionFormat::Text, content)))
+    }
+}
+
+/// Decode a number written in the base64 alphabet, most significant digit first.
+fn decode_base64_number(digits: &str) -> Result<u64, Error> {
+    digits.bytes().try_fold(0u64, |number, digit| {
+        let value = match digit {
+            b'A'..=b'Z' => digit - b'A',
+            b'a'..=b'z' => digit - b'a' + 26,
+            b'0'..=b'9' => digit - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(anyhow!("Invalid digit {:?}", digit as char)),
+        };
+        number
+            .checked_mul(64)
+            .map(|number| number + u64::from(value))
+            .ok_or_else(|| anyhow!("Number {} is too large", digits))
+    })
+}
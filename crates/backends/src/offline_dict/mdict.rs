@@ -0,0 +1,381 @@
+//! MDict dictionaries (`.mdx`), versions 1.2 and 2.0.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    sync::Mutex,
+};
+
+use anyhow::{Context, Error, Result, anyhow};
+use encoding_rs::{Encoding, UTF_8, UTF_16LE};
+use flate2::read::ZlibDecoder;
+use ripemd::{Digest, Ripemd128};
+
+use super::Dictionary;
+use crate::dict_interface::RawDefinitionFormat;
+
+/// Prefix of a record redirecting to another headword.
+const LINK_PREFIX: &str = "@@@LINK=";
+
+pub(super) struct MDict {
+    name: String,
+    encoding: &'static Encoding,
+    /// Lowercased headword to the offsets of its records in the decompressed record data.
+    index: HashMap<String, Vec<u64>>,
+    /// Offsets of every record in order, to find where each record ends.
+    record_offsets: Vec<u64>,
+    record_blocks: Vec<RecordBlock>,
+    file: Mutex<File>,
+}
+
+struct RecordBlock {
+    /// Offset of the compressed block in the file.
+    file_offset: u64,
+    compressed_size: u64,
+    /// Offset of the block in the decompressed record data.
+    offset: u64,
+    size: u64,
+}
+
+/// Widths of the numbers and key texts, which differ between versions.
+struct Layout {
+    version_2: bool,
+    /// Whether key sizes count UTF-16 code units rather than bytes.
+    utf16: bool,
+}
+impl Layout {
+    fn number(&self, data: &mut &[u8]) -> Result<u64, Error> {
+        if self.version_2 {
+            take(data, 8).map(|bytes| u64::from_be_bytes(bytes.try_into().unwrap()))
+        } else {
+            take(data, 4).map(|bytes| u64::from(u32::from_be_bytes(bytes.try_into().unwrap())))
+        }
+    }
+
+    /// Skip a key in the key block info, prefixed by its size.
+    fn skip_key(&self, data: &mut &[u8]) -> Result<(), Error> {
+        let size = if self.version_2 {
+            u16::from_be_bytes(take(data, 2)?.try_into().unwrap()) as usize
+        } else {
+            take(data, 1)?[0] as usize
+        };
+        // Version 2 keys are followed by a terminator not counted in their size.
+        let terminated_size = size + usize::from(self.version_2);
+        take(data, terminated_size * if self.utf16 { 2 } else { 1 })?;
+        Ok(())
+    }
+
+    /// Take a key in a key block, terminated by NUL.
+    fn terminated_key<'a>(&self, data: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+        let end = if self.utf16 {
+            data.chunks_exact(2)
+                .position(|unit| unit == [0, 0])
+                .map(|units| units * 2)
+        } else {
+            data.iter().position(|b| *b == 0)
+        }
+        .ok_or_else(|| anyhow!("Unterminated key"))?;
+        let key = &data[..end];
+        *data = &data[end + if self.utf16 { 2 } else { 1 }..];
+        Ok(key)
+    }
+}
+
+impl MDict {
+    pub(super) fn open(path: &Path) -> Result<Self, Error> {
+        let file =
+            File::open(path).with_context(|| anyhow!("Failed to open {}", path.display()))?;
+        Self::read(file, path)
+            .with_context(|| anyhow!("Failed to read MDict dictionary {}", path.display()))
+    }
+
+    fn read(mut file: File, path: &Path) -> Result<Self, Error> {
+        let header_size = u32::from_be_bytes(read_exact(&mut file, 4)?.try_into().unwrap());
+        let header = read_exact(&mut file, header_size as usize)?;
+        let header = UTF_16LE.decode_without_bom_handling(&header).0;
+        // Checksum of the header.
+        read_exact(&mut file, 4)?;
+        let attributes = header_attributes(&header);
+
+        let version = attributes
+            .get("GeneratedByEngineVersion")
+            .and_then(|version| version.parse::<f32>().ok())
+            .unwrap_or(2.0);
+        let encrypted = attributes
+            .get("Encrypted")
+            .and_then(|flags| match flags.as_str() {
+                "Yes" => Some(1),
+                "No" => Some(0),
+                flags => flags.parse::<u32>().ok(),
+            })
+            .unwrap_or(0);
+        if encrypted & 1 != 0 {
+            return Err(anyhow!(
+                "Dictionaries requiring a registration code are not supported"
+            ));
+        }
+        let encoding = match attributes.get("Encoding").map(String::as_str) {
+            None | Some("") => UTF_8,
+            Some(label) if label.eq_ignore_ascii_case("UTF-16") => UTF_16LE,
+            // GB2312 dictionaries are usually GBK in practice, which is a superset.
+            Some(label) if label.eq_ignore_ascii_case("GB2312") => encoding_rs::GBK,
+            Some(label) => Encoding::for_label(label.as_bytes())
+                .ok_or_else(|| anyhow!("Unsupported encoding {}", label))?,
+        };
+        let layout = Layout {
+            version_2: version >= 2.0,
+            utf16: encoding == UTF_16LE,
+        };
+
+        // Key section.
+        let key_header = read_exact(&mut file, if layout.version_2 { 40 } else { 16 })?;
+        let mut key_header = key_header.as_slice();
+        let key_block_count = layout.number(&mut key_header)?;
+        let _entry_count = layout.number(&mut key_header)?;
+        if layout.version_2 {
+            let _key_block_info_decompressed_size = layout.number(&mut key_header)?;
+            // Checksum of the key header.
+            read_exact(&mut file, 4)?;
+        }
+        let key_block_info_size = layout.number(&mut key_header)?;
+        let _key_blocks_size = layout.number(&mut key_header)?;
+
+        let key_block_info = read_exact(&mut file, key_block_info_size as usize)?;
+        let key_block_info = if layout.version_2 {
+            let key_block_info = if encrypted & 2 != 0 {
+                decrypt_key_block_info(&key_block_info)?
+            } else {
+                key_block_info
+            };
+            decompress_block(&key_block_info)?
+        } else {
+            key_block_info
+        };
+        let mut key_block_sizes = Vec::new();
+        let mut info = key_block_info.as_slice();
+        for _ in 0..key_block_count {
+            let _entry_count = layout.number(&mut info)?;
+            layout.skip_key(&mut info)?;
+            layout.skip_key(&mut info)?;
+            let compressed_size = layout.number(&mut info)?;
+            let _decompressed_size = layout.number(&mut info)?;
+            key_block_sizes.push(compressed_size);
+        }
+
+        let mut index: HashMap<String, Vec<u64>> = HashMap::new();
+        let mut record_offsets = Vec::new();
+        for compressed_size in key_block_sizes {
+            let key_block = decompress_block(&read_exact(&mut file, compressed_size as usize)?)?;
+            let mut keys = key_block.as_slice();
+            while !keys.is_empty() {
+                let offset = layout.number(&mut keys)?;
+                let key = layout.terminated_key(&mut keys)?;
+                let key = encoding.decode_without_bom_handling(key).0;
+                index.entry(key.to_lowercase()).or_default().push(offset);
+                record_offsets.push(offset);
+            }
+        }
+        record_offsets.sort_unstable();
+        record_offsets.dedup();
+
+        // Record section.
+        let record_header = read_exact(&mut file, if layout.version_2 { 32 } else { 16 })?;
+        let mut record_header = record_header.as_slice();
+        let record_block_count = layout.number(&mut record_header)?;
+        let _entry_count = layout.number(&mut record_header)?;
+        let record_block_info_size = layout.number(&mut record_header)?;
+        let _record_blocks_size = layout.number(&mut record_header)?;
+        let record_block_info = read_exact(&mut file, record_block_info_size as usize)?;
+        let mut info = record_block_info.as_slice();
+        let mut file_offset = file.stream_position()?;
+        let mut offset = 0;
+        let mut record_blocks = Vec::new();
+        for _ in 0..record_block_count {
+            let compressed_size = layout.number(&mut info)?;
+            let size = layout.number(&mut info)?;
+            record_blocks.push(RecordBlock {
+                file_offset,
+                compressed_size,
+                offset,
+                size,
+            });
+            file_offset += compressed_size;
+            offset += size;
+        }
+
+        let name = attributes
+            .get("Title")
+            .filter(|title| !title.is_empty())
+            .cloned()
+            .unwrap_or_else(|| {
+                path.file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into()
+            });
+        Ok(Self {
+            name,
+            encoding,
+            index,
+            record_offsets,
+            record_blocks,
+            file: Mutex::new(file),
+        })
+    }
+
+    fn read_record(&self, offset: u64) -> Result<String, Error> {
+        let end = match self.record_offsets.binary_search(&offset) {
+            Ok(i) => self.record_offsets.get(i + 1).copied(),
+            Err(i) => self.record_offsets.get(i).copied(),
+        }
+        .unwrap_or_else(|| {
+            self.record_blocks
+                .last()
+                .map(|block| block.offset + block.size)
+                .unwrap_or_default()
+        });
+
+        let first_block = self
+            .record_blocks
+            .partition_point(|block| block.offset + block.size <= offset);
+        let mut data = Vec::new();
+        let mut data_offset = None;
+        let mut file = self.file.lock().unwrap();
+        for block in &self.record_blocks[first_block..] {
+            if block.offset >= end {
+                break;
+            }
+            data_offset.get_or_insert(block.offset);
+            file.seek(SeekFrom::Start(block.file_offset))?;
+            data.extend(decompress_block(&read_exact(
+                &mut file,
+                block.compressed_size as usize,
+            )?)?);
+        }
+        let data_offset =
+            data_offset.ok_or_else(|| anyhow!("Record at {} is beyond the end", offset))?;
+        let record = data
+            .get((offset - data_offset) as usize..(end - data_offset) as usize)
+            .ok_or_else(|| anyhow!("Record at {} is truncated", offset))?;
+        let record = self.encoding.decode_without_bom_handling(record).0;
+        Ok(record.trim_end_matches('\0').to_string())
+    }
+}
+
+impl Dictionary for MDict {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn contains(&self, word: &str) -> bool {
+        self.index.contains_key(&word.to_lowercase())
+    }
+
+    fn lookup(&self, word: &str) -> Result<Option<(RawDefinitionFormat, String)>, Error> {
+        let Some(offsets) = self.index.get(&word.to_lowercase()) else {
+            return Ok(None);
+        };
+        let mut records = Vec::new();
+        for offset in offsets {
+            let record = self.read_record(*offset)?;
+            // Follow a redirect once, so that a loop of links cannot hang the lookup.
+            match record.strip_prefix(LINK_PREFIX) {
+                Some(target) => {
+                    let target = target.trim().to_lowercase();
+                    for offset in self.index.get(&target).into_iter().flatten() {
+                        let record = self.read_record(*offset)?;
+                        if !record.starts_with(LINK_PREFIX) {
+                            records.push(record);
+                        }
+                    }
+                }
+                None => records.push(record),
+            }
+        }
+        if records.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some((RawDefinitionFormat::Html, records.join("<hr>"))))
+    }
+}
+
+/// Attributes of the `Dictionary` element in the header.
+fn header_attributes(header: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = header;
+    while let Some(equals) = rest.find("=\"") {
+        let name = rest[..equals]
+            .rsplit(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or_default();
+        let value_start = equals + 2;
+        let Some(value_length) = rest[value_start..].find('"') else {
+            break;
+        };
+        let value = &rest[value_start..value_start + value_length];
+        attributes.insert(name.to_string(), unescape_xml(value));
+        rest = &rest[value_start + value_length + 1..];
+    }
+    attributes
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Decompress a key or record block, prefixed by its compression type and checksum.
+fn decompress_block(block: &[u8]) -> Result<Vec<u8>, Error> {
+    let compression = block.get(..4).ok_or_else(|| anyhow!("Truncated block"))?;
+    let data = block.get(8..).unwrap_or_default();
+    match compression {
+        [0, 0, 0, 0] => Ok(data.to_vec()),
+        [2, 0, 0, 0] => {
+            let mut decompressed = Vec::new();
+            ZlibDecoder::new(data)
+                .read_to_end(&mut decompressed)
+                .context("Failed to decompress block")?;
+            Ok(decompressed)
+        }
+        [1, 0, 0, 0] => Err(anyhow!("LZO compressed dictionaries are not supported")),
+        _ => Err(anyhow!("Unknown block compression {:?}", compression)),
+    }
+}
+
+/// Decrypt the key block info, keyed by its checksum.
+fn decrypt_key_block_info(block: &[u8]) -> Result<Vec<u8>, Error> {
+    let checksum = block
+        .get(4..8)
+        .ok_or_else(|| anyhow!("Truncated key block info"))?;
+    let key = Ripemd128::new()
+        .chain_update(checksum)
+        .chain_update(0x3695u32.to_le_bytes())
+        .finalize();
+    let mut decrypted = block[..8].to_vec();
+    let mut previous = 0x36;
+    for (i, byte) in block[8..].iter().enumerate() {
+        decrypted.push(byte.rotate_left(4) ^ previous ^ (i as u8) ^ key[i % key.len()]);
+        previous = *byte;
+    }
+    Ok(decrypted)
+}
+
+fn take<'a>(data: &mut &'a [u8], size: usize) -> Result<&'a [u8], Error> {
+    if data.len() < size {
+        return Err(anyhow!("Unexpected end of data"));
+    }
+    let (taken, rest) = data.split_at(size);
+    *data = rest;
+    Ok(taken)
+}
+
+fn read_exact(file: &mut File, size: usize) -> Result<Vec<u8>, Error> {
+    let mut data = vec![0; size];
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
@@ -0,0 +1,448 @@
+//! Word lookup in local StarDict, MDict and DICT (dictd) dictionaries.
+
+mod dict_file;
+mod dictd;
+mod mdict;
+mod stardict;
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Error, Result, anyhow};
+
+use crate::{
+    Language, Translator, WordTranslator,
+    dict_interface::{
+        Explanation, PartOfSpeech, PartOfSpeechType, RawDefinition, RawDefinitionFormat,
+        WordExplanation,
+    },
+    morphology,
+};
+
+/// Markers starting the senses of a part of speech in plain-text entries.
+const PART_OF_SPEECH_MARKERS: [(&str, PartOfSpeechType); 14] = [
+    ("n.", PartOfSpeechType::Noun),
+    ("v.", PartOfSpeechType::Verb),
+    ("vt.", PartOfSpeechType::Verb),
+    ("vi.", PartOfSpeechType::Verb),
+    ("adj.", PartOfSpeechType::Adjective),
+    ("a.", PartOfSpeechType::Adjective),
+    ("adv.", PartOfSpeechType::Adverb),
+    ("ad.", PartOfSpeechType::Adverb),
+    ("pron.", PartOfSpeechType::Pronoun),
+    ("prep.", PartOfSpeechType::Preposition),
+    ("conj.", PartOfSpeechType::Conjunction),
+    ("interj.", PartOfSpeechType::Interjection),
+    ("int.", PartOfSpeechType::Interjection),
+    ("abbr.", PartOfSpeechType::Other),
+];
+
+/// A dictionary file opened and indexed for lookup.
+trait Dictionary: Send + Sync {
+    fn name(&self) -> &str;
+    /// Whether there is an entry of `word`, matched case-insensitively.
+    fn contains(&self, word: &str) -> bool;
+    /// The entry of `word`, matched case-insensitively.
+    fn lookup(&self, word: &str) -> Result<Option<(RawDefinitionFormat, String)>, Error>;
+}
+
+/// Open a dictionary by the extension of its main file: `.ifo` for StarDict, `.mdx`
+/// for MDict and `.index` for DICT.
+fn open(path: &Path) -> Result<Box<dyn Dictionary>, Error> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("ifo") => Ok(Box::new(stardict::StarDict::open(path)?)),
+        Some("mdx") => Ok(Box::new(mdict::MDict::open(path)?)),
+        Some("index") => Ok(Box::new(dictd::DictdDictionary::open(path)?)),
+        _ => Err(anyhow!(
+            "{} is not a StarDict .ifo, MDict .mdx or DICT .index file",
+            path.display()
+        )),
+    }
+}
+
+/// Looks words up in the configured dictionaries in order, returning the first entry
+/// found. Dictionaries are indexed once when the translator is opened.
+pub struct OfflineDictWordTranslator {
+    paths: Vec<PathBuf>,
+    dictionaries: Vec<Box<dyn Dictionary>>,
+}
+impl OfflineDictWordTranslator {
+    /// Open every dictionary, skipping those which fail to open as long as one opens.
+    pub fn open(paths: &[PathBuf]) -> Result<Self, Error> {
+        if paths.is_empty() {
+            return Err(anyhow!("No offline dictionary is configured"));
+        }
+        let mut dictionaries = Vec::new();
+        let mut last_error = None;
+        for path in paths {
+            match open(path) {
+                Ok(dictionary) => dictionaries.push(dictionary),
+                Err(e) => {
+                    log::warn!("Failed to open offline dictionary: {:#}", e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        match (dictionaries.is_empty(), last_error) {
+            (true, Some(e)) => Err(e),
+            _ => Ok(Self {
+                paths: paths.to_vec(),
+                dictionaries,
+            }),
+        }
+    }
+
+    /// Paths the translator was opened with.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+}
+
+impl Translator for OfflineDictWordTranslator {
+    fn get_api_key(&self) -> String {
+        String::new()
+    }
+
+    fn save_api_key(&self) -> Result<(), anyhow::Error> {
+        Err(anyhow!("Offline dictionaries do not use an API key"))
+    }
+
+    fn get_url(&self) -> String {
+        String::new()
+    }
+}
+
+impl WordTranslator for OfflineDictWordTranslator {
+    fn translate_word(
+        &self,
+        word: &str,
+        _source_language: Language,
+        _target_language: Language,
+    ) -> Result<WordExplanation, Error> {
+        let word = word.trim();
+        for dictionary in &self.dictionaries {
+            if let Some((format, content)) = dictionary.lookup(word)? {
+                return Ok(word_explanation(
+                    word,
+                    RawDefinition {
+                        dictionary: dictionary.name().to_string(),
+                        format,
+                        content,
+                    },
+                ));
+            }
+        }
+        Err(anyhow!("\"{}\" is not found in offline dictionaries", word))
+    }
+
+    /// The first lemma candidate which is in a dictionary, so that only real words
+    /// are taken as lemmas.
+    fn lemma(&self, word: &str, language: Language) -> String {
+        let candidates = morphology::lemma_candidates(word, language);
+        candidates
+            .iter()
+            .find(|candidate| {
+                self.dictionaries
+                    .iter()
+                    .any(|dictionary| dictionary.contains(candidate))
+            })
+            .unwrap_or_else(|| candidates.last().unwrap())
+            .clone()
+    }
+}
+
+/// Map an entry into a word explanation as far as its plain text allows, keeping the
+/// entry itself as the raw definition.
+fn word_explanation(word: &str, raw_definition: RawDefinition) -> WordExplanation {
+    let text = raw_definition.to_plain_text();
+    let mut lines = text.lines().collect::<Vec<_>>();
+    // Entries often repeat the headword on their first line.
+    if lines
+        .first()
+        .is_some_and(|line| line.trim().eq_ignore_ascii_case(word))
+    {
+        lines.remove(0);
+    }
+
+    let phonetics = lines
+        .first()
+        .map(|line| phonetics(line))
+        .unwrap_or_default();
+    if !phonetics.is_empty() && lines.first().is_some_and(|line| is_only_phonetics(line)) {
+        lines.remove(0);
+    }
+
+    let mut part_of_speeches: Vec<PartOfSpeech> = Vec::new();
+    for line in lines {
+        let line = line.trim();
+        let marker = PART_OF_SPEECH_MARKERS.iter().find(|(marker, _)| {
+            line.strip_prefix(marker)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+        });
+        let line = match marker {
+            Some((marker, part_of_speech)) => {
+                part_of_speeches.push(PartOfSpeech {
+                    part_of_speech: part_of_speech.clone(),
+                    explanations: Vec::new(),
+                    idioms: None,
+                    phrasal_verbs: None,
+                });
+                line[marker.len()..].trim()
+            }
+            None => line,
+        };
+        let Some(part_of_speech) = part_of_speeches.last_mut() else {
+            continue;
+        };
+        if !line.is_empty() {
+            part_of_speech.explanations.push(Explanation {
+                phonetics: None,
+                abbreviation: None,
+                register: None,
+                explanation: line.to_string(),
+                definition: String::new(),
+                patterns: None,
+                examples: None,
+            });
+        }
+    }
+    part_of_speeches.retain(|part_of_speech| !part_of_speech.explanations.is_empty());
+
+    WordExplanation {
+        word: word.to_string(),
+        phonetics: (!phonetics.is_empty()).then_some(phonetics),
+        part_of_speeches: (!part_of_speeches.is_empty()).then_some(part_of_speeches),
+        synonyms: None,
+        antonyms: None,
+        word_family: None,
+        etymology: None,
+        cefr_level: None,
+        frequency_band: None,
+        inflections: None,
+        inflected_form: None,
+        corrected_from: None,
+        raw_definition: Some(raw_definition),
+    }
+}
+
+/// Transcriptions between slashes or square brackets.
+fn phonetics(line: &str) -> Vec<String> {
+    let mut phonetics = Vec::new();
+    let mut rest = line;
+    while let Some(start) = rest.find(['/', '[']) {
+        let close = if rest[start..].starts_with('/') {
+            '/'
+        } else {
+            ']'
+        };
+        let Some(length) = rest[start + 1..].find(close) else {
+            break;
+        };
+        let transcription = &rest[start..start + length + 2];
+        if length > 0 && !transcription.contains(char::is_whitespace) {
+            phonetics.push(transcription.to_string());
+        }
+        rest = &rest[start + length + 2..];
+    }
+    phonetics
+}
+
+fn is_only_phonetics(line: &str) -> bool {
+    phonetics(line)
+        .iter()
+        .fold(line.to_string(), |line, transcription| {
+            line.replace(transcription.as_str(), "")
+        })
+        .trim()
+        .is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{Compression, write::ZlibEncoder};
+
+    use super::*;
+
+    fn stardict_index(entries: &[(&str, u32, u32)]) -> Vec<u8> {
+        let mut idx = Vec::new();
+        for (word, offset, size) in entries {
+            idx.extend_from_slice(word.as_bytes());
+            idx.push(0);
+            idx.extend_from_slice(&offset.to_be_bytes());
+            idx.extend_from_slice(&size.to_be_bytes());
+        }
+        idx
+    }
+
+    #[test]
+    fn looks_up_stardict() {
+        let dir = tempfile::tempdir().unwrap();
+        let ifo = dir.path().join("test.ifo");
+        std::fs::write(
+            &ifo,
+            "StarDict's dict ifo file\nversion=2.4.2\nbookname=Test Dictionary\nwordcount=2\nsametypesequence=m\n",
+        )
+        .unwrap();
+        let arrive = "/əˈraɪv/\nv. 到达，抵达\n（东西）送达";
+        let leave = "v. 离开";
+        std::fs::write(dir.path().join("test.dict"), format!("{}{}", arrive, leave)).unwrap();
+        std::fs::write(
+            dir.path().join("test.idx"),
+            stardict_index(&[
+                ("arrive", 0, arrive.len() as u32),
+                ("leave", arrive.len() as u32, leave.len() as u32),
+            ]),
+        )
+        .unwrap();
+
+        let translator = OfflineDictWordTranslator::open(&[ifo]).unwrap();
+        assert_eq!(translator.lemma("arrived", Language::English), "arrive");
+        assert_eq!(translator.lemma("stays", Language::English), "stays");
+        let word = translator
+            .translate_word("Arrive", Language::English, Language::Chinese)
+            .unwrap();
+        assert_eq!(word.phonetics, Some(vec!["/əˈraɪv/".to_string()]));
+        let part_of_speeches = word.part_of_speeches.unwrap();
+        assert_eq!(part_of_speeches.len(), 1);
+        assert_eq!(part_of_speeches[0].explanations.len(), 2);
+        assert_eq!(
+            part_of_speeches[0].explanations[1].explanation,
+            "（东西）送达"
+        );
+        let raw_definition = word.raw_definition.unwrap();
+        assert_eq!(raw_definition.dictionary, "Test Dictionary");
+        assert_eq!(raw_definition.content, arrive);
+        assert!(
+            translator
+                .translate_word("stay", Language::English, Language::Chinese)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn looks_up_dictd_with_dictzip() {
+        let dir = tempfile::tempdir().unwrap();
+        let short_name = "00-database-short\n     Test DICT\n";
+        let entry = "arrive\n   to get to a place\n";
+        let data = format!("{}{}", short_name, entry);
+        // A dictzip file with chunks of 16 bytes, each compressed on its own.
+        let mut chunks = Vec::new();
+        for chunk in data.as_bytes().chunks(16) {
+            let mut compress = flate2::Compress::new(Compression::default(), false);
+            let mut compressed = Vec::with_capacity(64);
+            compress
+                .compress_vec(chunk, &mut compressed, flate2::FlushCompress::Full)
+                .unwrap();
+            chunks.push(compressed);
+        }
+        let mut extra = b"RA".to_vec();
+        extra.extend_from_slice(&(6 + 2 * chunks.len() as u16).to_le_bytes());
+        extra.extend_from_slice(&1u16.to_le_bytes());
+        extra.extend_from_slice(&16u16.to_le_bytes());
+        extra.extend_from_slice(&(chunks.len() as u16).to_le_bytes());
+        for chunk in &chunks {
+            extra.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+        }
+        let mut dz = vec![0x1f, 0x8b, 8, 0x04, 0, 0, 0, 0, 2, 3];
+        dz.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        dz.extend_from_slice(&extra);
+        for chunk in &chunks {
+            dz.extend_from_slice(chunk);
+        }
+        std::fs::write(dir.path().join("test.dict.dz"), dz).unwrap();
+        let index = dir.path().join("test.index");
+        std::fs::write(
+            &index,
+            format!(
+                "00-database-short\tA\t{}\narrive\t{}\t{}\n",
+                base64_digit(short_name.len()),
+                base64_digit(short_name.len()),
+                base64_digit(entry.len())
+            ),
+        )
+        .unwrap();
+
+        let translator = OfflineDictWordTranslator::open(&[index]).unwrap();
+        let word = translator
+            .translate_word("arrive", Language::English, Language::Chinese)
+            .unwrap();
+        let raw_definition = word.raw_definition.unwrap();
+        assert_eq!(raw_definition.dictionary, "Test DICT");
+        assert_eq!(raw_definition.content, entry.trim_end());
+    }
+
+    fn base64_digit(n: usize) -> char {
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/"[n] as char
+    }
+
+    fn zlib_block(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        let mut block = vec![2, 0, 0, 0, 0, 0, 0, 0];
+        block.extend(encoder.finish().unwrap());
+        block
+    }
+
+    #[test]
+    fn looks_up_mdict_with_links() {
+        let records = ["<b>arrive</b><br>到达\0", "@@@LINK=arrive\0"];
+        let keys = ["arrive", "arriving"];
+
+        let mut key_block = Vec::new();
+        let mut offset = 0u64;
+        for (key, record) in keys.iter().zip(records) {
+            key_block.extend_from_slice(&offset.to_be_bytes());
+            key_block.extend_from_slice(key.as_bytes());
+            key_block.push(0);
+            offset += record.len() as u64;
+        }
+        let key_block = zlib_block(&key_block);
+        let mut key_block_info = Vec::new();
+        key_block_info.extend_from_slice(&(keys.len() as u64).to_be_bytes());
+        for key in [keys[0], keys[1]] {
+            key_block_info.extend_from_slice(&(key.len() as u16).to_be_bytes());
+            key_block_info.extend_from_slice(key.as_bytes());
+            key_block_info.push(0);
+        }
+        key_block_info.extend_from_slice(&(key_block.len() as u64).to_be_bytes());
+        key_block_info.extend_from_slice(&0u64.to_be_bytes());
+        let key_block_info = zlib_block(&key_block_info);
+        let record_block = zlib_block(records.concat().as_bytes());
+
+        let header = "<Dictionary GeneratedByEngineVersion=\"2.0\" Encrypted=\"0\" Encoding=\"UTF-8\" Title=\"Test MDict\"/>\r\n\0"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+        let mut mdx = (header.len() as u32).to_be_bytes().to_vec();
+        mdx.extend(header);
+        mdx.extend_from_slice(&[0; 4]);
+        for number in [1, keys.len(), 0, key_block_info.len(), key_block.len()] {
+            mdx.extend_from_slice(&(number as u64).to_be_bytes());
+        }
+        mdx.extend_from_slice(&[0; 4]);
+        mdx.extend(key_block_info);
+        mdx.extend(key_block);
+        for number in [1, keys.len(), 16, record_block.len()] {
+            mdx.extend_from_slice(&(number as u64).to_be_bytes());
+        }
+        mdx.extend_from_slice(&(record_block.len() as u64).to_be_bytes());
+        mdx.extend_from_slice(&(records.concat().len() as u64).to_be_bytes());
+        mdx.extend(record_block);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.mdx");
+        std::fs::write(&path, mdx).unwrap();
+        let translator = OfflineDictWordTranslator::open(&[path]).unwrap();
+        let word = translator
+            .translate_word("arriving", Language::English, Language::Chinese)
+            .unwrap();
+        let raw_definition = word.raw_definition.unwrap();
+        assert_eq!(raw_definition.dictionary, "Test MDict");
+        assert_eq!(raw_definition.format, RawDefinitionFormat::Html);
+        assert_eq!(raw_definition.to_plain_text(), "arrive\n到达");
+    }
+}
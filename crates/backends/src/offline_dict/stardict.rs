@@ -0,0 +1,210 @@
+//! StarDict dictionaries: `.ifo` description, `.idx` index and `.dict(.dz)` data.
+
+use std::{collections::HashMap, io::Read, path::Path};
+
+use anyhow::{Context, Error, Result, anyhow};
+use flate2::read::GzDecoder;
+
+use super::{Dictionary, dict_file::DictFile};
+use crate::dict_interface::RawDefinitionFormat;
+
+pub(super) struct StarDict {
+    name: String,
+    /// Type of every field when the entries carry no type markers.
+    same_type_sequence: Option<String>,
+    /// Lowercased headword to the offsets and sizes of its entries in the data file.
+    index: HashMap<String, Vec<(u64, usize)>>,
+    data: DictFile,
+}
+impl StarDict {
+    pub(super) fn open(ifo_path: &Path) -> Result<Self, Error> {
+        let ifo = std::fs::read_to_string(ifo_path)
+            .with_context(|| anyhow!("Failed to read {}", ifo_path.display()))?;
+        let mut lines = ifo.lines();
+        if lines.next().map(str::trim) != Some("StarDict's dict ifo file") {
+            return Err(anyhow!(
+                "{} is not a StarDict .ifo file",
+                ifo_path.display()
+            ));
+        }
+        let options = lines
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .collect::<HashMap<_, _>>();
+        let name = options
+            .get("bookname")
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| {
+                ifo_path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into()
+            });
+        let offset_bits = match options.get("idxoffsetbits").copied() {
+            None | Some("32") => 32,
+            Some("64") => 64,
+            Some(bits) => return Err(anyhow!("Unsupported idxoffsetbits {}", bits)),
+        };
+
+        let idx_path = ifo_path.with_extension("idx");
+        let idx = if idx_path.exists() {
+            std::fs::read(&idx_path)
+                .with_context(|| anyhow!("Failed to read {}", idx_path.display()))?
+        } else {
+            let idx_gz_path = ifo_path.with_extension("idx.gz");
+            let mut idx = Vec::new();
+            std::fs::File::open(&idx_gz_path)
+                .map(GzDecoder::new)
+                .and_then(|mut decoder| decoder.read_to_end(&mut idx))
+                .with_context(|| anyhow!("Failed to read {}", idx_gz_path.display()))?;
+            idx
+        };
+        let index = parse_index(&idx, offset_bits)
+            .with_context(|| anyhow!("Malformed index {}", idx_path.display()))?;
+
+        let dict_path = [
+            ifo_path.with_extension("dict.dz"),
+            ifo_path.with_extension("dict"),
+        ]
+        .into_iter()
+        .find(|path| path.exists())
+        .ok_or_else(|| anyhow!("No .dict or .dict.dz next to {}", ifo_path.display()))?;
+
+        Ok(Self {
+            name,
+            same_type_sequence: options.get("sametypesequence").map(|s| s.to_string()),
+            index,
+            data: DictFile::open(&dict_path)?,
+        })
+    }
+
+    /// Split an entry into its typed fields.
+    fn fields<'a>(&self, mut entry: &'a [u8]) -> Vec<(u8, &'a [u8])> {
+        let mut fields = Vec::new();
+        let types = self.same_type_sequence.as_ref().map(|s| s.as_bytes());
+        let mut field_index = 0;
+        while !entry.is_empty() {
+            let field_type = match types {
+                Some(types) => match types.get(field_index) {
+                    Some(field_type) => *field_type,
+                    None => break,
+                },
+                None => {
+                    let field_type = entry[0];
+                    entry = &entry[1..];
+                    field_type
+                }
+            };
+            // With `sametypesequence` the last field has neither terminator nor size.
+            let is_last = types.is_some_and(|types| field_index + 1 == types.len());
+            let data;
+            if is_last {
+                data = entry;
+                entry = &[];
+            } else if field_type.is_ascii_lowercase() {
+                let end = entry.iter().position(|b| *b == 0).unwrap_or(entry.len());
+                data = &entry[..end];
+                entry = entry.get(end + 1..).unwrap_or_default();
+            } else {
+                let Some(size) = entry.get(..4) else { break };
+                let size = u32::from_be_bytes(size.try_into().unwrap()) as usize;
+                data = entry.get(4..4 + size).unwrap_or(&entry[4..]);
+                entry = entry.get(4 + size..).unwrap_or_default();
+            }
+            fields.push((field_type, data));
+            field_index += 1;
+        }
+        fields
+    }
+}
+
+impl Dictionary for StarDict {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn contains(&self, word: &str) -> bool {
+        self.index.contains_key(&word.to_lowercase())
+    }
+
+    fn lookup(&self, word: &str) -> Result<Option<(RawDefinitionFormat, String)>, Error> {
+        let Some(locations) = self.index.get(&word.to_lowercase()) else {
+            return Ok(None);
+        };
+        let mut format = RawDefinitionFormat::Text;
+        let mut texts = Vec::new();
+        for (offset, size) in locations {
+            let entry = self.data.read(*offset, *size)?;
+            for (field_type, data) in self.fields(&entry) {
+                let text = String::from_utf8_lossy(data);
+                match field_type {
+                    b'h' | b'g' | b'x' => {
+                        format = RawDefinitionFormat::Html;
+                        texts.push((true, text.into_owned()));
+                    }
+                    b'm' | b't' | b'y' | b'l' | b'k' | b'w' | b'n' => {
+                        texts.push((false, text.into_owned()));
+                    }
+                    // Pictures, sounds and other binary resources cannot be shown.
+                    _ => {}
+                }
+            }
+        }
+        if texts.is_empty() {
+            return Ok(None);
+        }
+        let content = match format {
+            RawDefinitionFormat::Text => texts
+                .into_iter()
+                .map(|(_, text)| text)
+                .collect::<Vec<_>>()
+                .join("\n"),
+            // Keep plain-text fields on their own lines in an HTML entry.
+            RawDefinitionFormat::Html => texts
+                .into_iter()
+                .map(|(is_html, text)| {
+                    if is_html {
+                        text
+                    } else {
+                        html_escape(&text).replace('\n', "<br>")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("<br>"),
+        };
+        Ok(Some((format, content)))
+    }
+}
+
+fn parse_index(
+    mut idx: &[u8],
+    offset_bits: u32,
+) -> Result<HashMap<String, Vec<(u64, usize)>>, Error> {
+    let offset_size = (offset_bits / 8) as usize;
+    let mut index: HashMap<String, Vec<(u64, usize)>> = HashMap::new();
+    while !idx.is_empty() {
+        let end = idx
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| anyhow!("Unterminated headword"))?;
+        let word = String::from_utf8_lossy(&idx[..end]).to_lowercase();
+        let location = idx
+            .get(end + 1..end + 1 + offset_size + 4)
+            .ok_or_else(|| anyhow!("Truncated entry of {}", word))?;
+        let offset = match offset_size {
+            8 => u64::from_be_bytes(location[..8].try_into().unwrap()),
+            _ => u64::from(u32::from_be_bytes(location[..4].try_into().unwrap())),
+        };
+        let size = u32::from_be_bytes(location[offset_size..].try_into().unwrap()) as usize;
+        index.entry(word).or_default().push((offset, size));
+        idx = &idx[end + 1 + offset_size + 4..];
+    }
+    Ok(index)
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
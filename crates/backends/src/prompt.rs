@@ -0,0 +1,253 @@
+//! Prompt templates of the AI providers, with named presets for words and sentences.
+//!
+//! Templates may contain the placeholders `{source_language}`, `{target_language}`,
+//! `{text}` and `{schema_example}`. Other braces are kept as they are.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use crate::Language;
+
+pub const DEFAULT_WORD_PRESET: &str = "standard";
+pub const DEFAULT_SENTENCE_PRESET: &str = "fluent";
+
+const SENTENCE_OUTPUT_ONLY: &str = "你只需要输出翻译结果，不要输出任何与翻译无关的内容。";
+const SENTENCE_LANGUAGES: &str = "请从{source_language}翻译为{target_language}。";
+
+const WORD_INSTRUCTIONS: &str = r#"请你翻译以下{source_language}单词或词组到{target_language}，给出音标、解释、搭配和例句。以json格式输出。
+若单词并不存在，你应回复一个最为接近的词语，并给出相应的解释；若没有相似的词语，按照我给定的json格式，只回复 {word: $word} 即可
+警告：你输出的内容应只包括json，诸如“```json```”等非json格式的内容会影响到结果解析。
+注：
+    1. 可选的词性有：[`noun`, `verb`, `adj.`, `adv.`, `pron.`, `prep.`, `conj.`, `interj.`, `other`]
+    2. `definition` 字段应由单词相应的语言给出定义
+    3. `synonyms`、`antonyms`、`word_family`、`etymology`、`cefr_level`（A1 至 C2）、`frequency_band`（1 为最常用，5 为罕用）、`inflections` 均为可选字段，不确定时省略
+    4. 带有语体色彩的义项可加入 `register` 字段，如 ["formal"]、["slang"]"#;
+const WORD_EXAMPLE: &str = r#"
+例：
+    User:
+        arrive
+    Assistant:
+        {schema_example}"#;
+
+/// A prompt of one preset, stored in the config.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PromptTemplate {
+    pub system: String,
+    /// The user message, usually just the text.
+    #[serde(default = "default_user_template")]
+    pub user: String,
+}
+
+fn default_user_template() -> String {
+    "{text}".to_string()
+}
+
+/// Values of the placeholders.
+#[derive(Debug, Clone, Copy)]
+pub struct PromptVariables<'a> {
+    pub source_language: Language,
+    pub target_language: Language,
+    pub text: &'a str,
+    /// Example output of the expected JSON, empty for sentences.
+    pub schema_example: &'a str,
+}
+
+/// The template of the preset chosen for a mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prompt {
+    pub preset: String,
+    pub template: PromptTemplate,
+}
+impl Prompt {
+    pub fn default_word() -> Self {
+        Self::from_presets(default_word_presets(), DEFAULT_WORD_PRESET)
+    }
+
+    pub fn default_sentence() -> Self {
+        Self::from_presets(default_sentence_presets(), DEFAULT_SENTENCE_PRESET)
+    }
+
+    fn from_presets(mut presets: BTreeMap<String, PromptTemplate>, preset: &str) -> Self {
+        Self {
+            preset: preset.to_string(),
+            template: presets.remove(preset).unwrap(),
+        }
+    }
+
+    /// Part of the cache key, e.g. `standard@0123abcd`, with a hash of the templates so
+    /// that explanations cached with a changed template are not reused.
+    pub fn cache_key(&self) -> String {
+        let digest =
+            Sha1::digest(format!("{}\x1f{}", self.template.system, self.template.user).as_bytes());
+        let hash = digest[..4]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        format!("{}@{}", self.preset, hash)
+    }
+
+    /// The system and the user message.
+    pub fn render(&self, variables: &PromptVariables) -> (String, String) {
+        (
+            render(&self.template.system, variables),
+            render(&self.template.user, variables),
+        )
+    }
+}
+
+/// Replace the placeholders in `template`. Values are inserted as they are, so that
+/// braces in the text are not taken for placeholders.
+pub fn render(template: &str, variables: &PromptVariables) -> String {
+    let mut rendered = String::with_capacity(template.len() + variables.text.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| {
+            let value = match &rest[1..end] {
+                "source_language" => variables.source_language.to_string(),
+                "target_language" => variables.target_language.to_string(),
+                "text" => variables.text.to_string(),
+                "schema_example" => variables.schema_example.to_string(),
+                _ => return None,
+            };
+            Some((value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                rendered.push_str(&value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                rendered.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Built-in presets for explaining words.
+pub fn default_word_presets() -> BTreeMap<String, PromptTemplate> {
+    BTreeMap::from([
+        (
+            "standard".to_string(),
+            PromptTemplate {
+                system: format!("{}{}", WORD_INSTRUCTIONS, WORD_EXAMPLE),
+                user: default_user_template(),
+            },
+        ),
+        (
+            "concise".to_string(),
+            PromptTemplate {
+                system: format!(
+                    "{}\n    5. 只给出最常用的两三个义项，每个义项一个例句，省略可选字段{}",
+                    WORD_INSTRUCTIONS, WORD_EXAMPLE
+                ),
+                user: default_user_template(),
+            },
+        ),
+    ])
+}
+
+/// Built-in presets for translating sentences.
+pub fn default_sentence_presets() -> BTreeMap<String, PromptTemplate> {
+    [
+        (
+            "literal",
+            "请直译以下句子，尽量保留原句的词序和结构，不要意译。",
+        ),
+        (
+            "fluent",
+            "请翻译以下句子。应注意用词应尽可能准确，不应改变原句的内容，同时恰到好处地还原原句的情感和写作风格。",
+        ),
+        (
+            "academic",
+            "请以学术论文的正式文体翻译以下句子，术语应准确、规范，句式应严谨。",
+        ),
+        (
+            "casual",
+            "请以日常口语的轻松语气翻译以下句子，用词应自然、地道。",
+        ),
+    ]
+    .into_iter()
+    .map(|(preset, instructions)| {
+        (
+            preset.to_string(),
+            PromptTemplate {
+                system: format!(
+                    "{}{}{}",
+                    instructions, SENTENCE_OUTPUT_ONLY, SENTENCE_LANGUAGES
+                ),
+                user: default_user_template(),
+            },
+        )
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Prompts;
+
+    const VARIABLES: PromptVariables = PromptVariables {
+        source_language: Language::English,
+        target_language: Language::Chinese,
+        text: "Set {target_language} to {x}",
+        schema_example: "",
+    };
+
+    #[test]
+    fn placeholders_are_replaced_once() {
+        assert_eq!(
+            render(
+                "{source_language} → {target_language}: {text} {word: $word}",
+                &VARIABLES
+            ),
+            "English → Chinese: Set {target_language} to {x} {word: $word}"
+        );
+        assert_eq!(render("{text", &VARIABLES), "{text");
+    }
+
+    #[test]
+    fn every_preset_names_the_languages() {
+        for (preset, template) in default_word_presets()
+            .into_iter()
+            .chain(default_sentence_presets())
+        {
+            let (system, user) = Prompt { preset, template }.render(&VARIABLES);
+            assert!(system.contains("English") && system.contains("Chinese"));
+            assert_eq!(user, VARIABLES.text);
+        }
+    }
+
+    #[test]
+    fn unknown_presets_fall_back_to_the_default() {
+        let mut prompts = Prompts {
+            sentence_preset: "academic".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(prompts.sentence_prompt().preset, "academic");
+
+        prompts.sentence_preset = "removed".to_string();
+        assert_eq!(prompts.sentence_prompt(), Prompt::default_sentence());
+        assert!(prompts.word_prompt().cache_key().starts_with("standard@"));
+    }
+
+    #[test]
+    fn changed_templates_change_the_cache_key() {
+        let prompt = Prompt::default_word();
+        let mut changed = prompt.clone();
+        changed.template.system += "\n    5. 例句应简短";
+        assert_ne!(prompt.cache_key(), changed.cache_key());
+        assert_eq!(prompt.cache_key(), Prompt::default_word().cache_key());
+
+        changed = prompt.clone();
+        changed.template.user = "Word: {text}".to_string();
+        assert_ne!(prompt.cache_key(), changed.cache_key());
+    }
+}
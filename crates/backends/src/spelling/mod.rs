@@ -0,0 +1,200 @@
+//! "Did you mean" suggestions for misspelt words, by edit distance over a bundled list
+//! of common words and the words the user has looked up before.
+
+use std::{
+    collections::HashMap,
+    sync::{OnceLock, RwLock},
+};
+
+use crate::{Language, morphology};
+
+/// Words by lowercase spelling, each with its rank; lower ranks are suggested first.
+type WordList = HashMap<String, usize>;
+
+/// Rank of words the user has looked up, ahead of every bundled word.
+const USER_WORD_RANK: usize = 0;
+
+/// Languages with a bundled word list.
+const BUNDLED_LANGUAGES: [Language; 4] = [
+    Language::English,
+    Language::German,
+    Language::French,
+    Language::Russian,
+];
+
+/// Checks words against the known words of their language and suggests the closest
+/// ones. Languages without a bundled list are never checked.
+pub struct SpellingSuggester {
+    words: RwLock<HashMap<Language, WordList>>,
+}
+
+impl Default for SpellingSuggester {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpellingSuggester {
+    pub fn new() -> Self {
+        let words = BUNDLED_LANGUAGES
+            .into_iter()
+            .filter_map(|language| Some((language, bundled_words(language)?.clone())))
+            .collect();
+        Self {
+            words: RwLock::new(words),
+        }
+    }
+
+    /// Remember a word the user has looked up, so that it is known from now on and
+    /// preferred among suggestions.
+    pub fn add_word(&self, word: &str, language: Language) {
+        let word = word.trim().to_lowercase();
+        if !is_single_word(&word) {
+            return;
+        }
+        let mut words = self.words.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(list) = words.get_mut(&language) {
+            list.insert(word, USER_WORD_RANK);
+        }
+    }
+
+    /// Whether `word` or one of its possible lemmas is known. Words of languages
+    /// without a list are taken as known.
+    pub fn is_known(&self, word: &str, language: Language) -> bool {
+        let words = self.words.read().unwrap_or_else(|e| e.into_inner());
+        let Some(list) = words.get(&language) else {
+            return true;
+        };
+        morphology::lemma_candidates(word, language)
+            .iter()
+            .any(|candidate| list.contains_key(&candidate.to_lowercase()))
+    }
+
+    /// Up to `limit` known words close to `word`, nearest first, or nothing if `word`
+    /// is known, capitalised like a name, or not a single word.
+    pub fn suggest(&self, word: &str, language: Language, limit: usize) -> Vec<String> {
+        let word = word.trim();
+        if !is_single_word(word)
+            || word.starts_with(char::is_uppercase)
+            || word.chars().count() < 3
+            || self.is_known(word, language)
+        {
+            return Vec::new();
+        }
+        let word = word.to_lowercase();
+        let length = word.chars().count();
+        let max_distance = if length <= 4 { 1 } else { 2 };
+        let words = self.words.read().unwrap_or_else(|e| e.into_inner());
+        let Some(list) = words.get(&language) else {
+            return Vec::new();
+        };
+        let mut suggestions = list
+            .iter()
+            .filter(|(candidate, _)| candidate.chars().count().abs_diff(length) <= max_distance)
+            .filter_map(|(candidate, rank)| {
+                let distance = strsim::damerau_levenshtein(&word, candidate);
+                (distance <= max_distance).then_some((distance, *rank, candidate))
+            })
+            .collect::<Vec<_>>();
+        suggestions.sort();
+        suggestions
+            .into_iter()
+            .take(limit)
+            .map(|(_, _, candidate)| candidate.clone())
+            .collect()
+    }
+}
+
+fn is_single_word(word: &str) -> bool {
+    !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_alphabetic() || c == '\'' || c == '-')
+}
+
+/// Whether `word` is in the bundled word list of its language.
+pub(crate) fn is_listed(word: &str, language: Language) -> bool {
+    bundled_words(language).is_some_and(|words| words.contains_key(&word.to_lowercase()))
+}
+
+fn bundled_words(language: Language) -> Option<&'static WordList> {
+    static ENGLISH: OnceLock<WordList> = OnceLock::new();
+    static GERMAN: OnceLock<WordList> = OnceLock::new();
+    static FRENCH: OnceLock<WordList> = OnceLock::new();
+    static RUSSIAN: OnceLock<WordList> = OnceLock::new();
+    let (words, list) = match language {
+        Language::English => (&ENGLISH, include_str!("english.txt")),
+        Language::German => (&GERMAN, include_str!("german.txt")),
+        Language::French => (&FRENCH, include_str!("french.txt")),
+        Language::Russian => (&RUSSIAN, include_str!("russian.txt")),
+        _ => return None,
+    };
+    Some(words.get_or_init(|| parse_word_list(list)))
+}
+
+/// Parse words in rough order of frequency, ranked from 1 by their first appearance.
+fn parse_word_list(list: &str) -> WordList {
+    let mut words = WordList::new();
+    for word in list
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .flat_map(str::split_whitespace)
+    {
+        let rank = words.len() + 1;
+        words.entry(word.to_lowercase()).or_insert(rank);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggests_common_words() {
+        let suggester = SpellingSuggester::new();
+        for (word, language, suggestion) in [
+            ("recieve", Language::English, "receive"),
+            ("beleive", Language::English, "believe"),
+            ("definately", Language::English, "definitely"),
+            ("teh", Language::English, "the"),
+            ("wrod", Language::English, "word"),
+            ("gestren", Language::German, "gestern"),
+            ("bonjuor", Language::French, "bonjour"),
+            ("спосибо", Language::Russian, "спасибо"),
+        ] {
+            let suggestions = suggester.suggest(word, language, 5);
+            assert!(
+                suggestions.iter().any(|s| s == suggestion),
+                "{}: {:?}",
+                word,
+                suggestions
+            );
+        }
+    }
+
+    #[test]
+    fn known_words_are_left_alone() {
+        let suggester = SpellingSuggester::new();
+        for word in ["arrive", "running", "children", "went", "Paris", "give up"] {
+            assert!(
+                suggester.suggest(word, Language::English, 5).is_empty(),
+                "{}",
+                word
+            );
+        }
+        assert!(suggester.is_known("gemacht", Language::German));
+        assert!(suggester.is_known("chevaux", Language::French));
+        assert!(suggester.is_known("нового", Language::Russian));
+        assert!(suggester.suggest("Hauss", Language::German, 5).is_empty());
+    }
+
+    #[test]
+    fn user_words_are_known_and_preferred() {
+        let suggester = SpellingSuggester::new();
+        assert!(!suggester.is_known("quokka", Language::English));
+        suggester.add_word("quokka", Language::English);
+        assert!(suggester.is_known("quokka", Language::English));
+        assert_eq!(suggester.suggest("quoka", Language::English, 1), ["quokka"]);
+    }
+}
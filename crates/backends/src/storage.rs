@@ -1,5 +1,14 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    AiProvider, Language,
+    failover::FailureKind,
+    glossary::GlossaryEntry,
+    prompt::{self, Prompt, PromptTemplate},
+};
+
 // pub struct BackendManager {
 //     api_key: String,
 //     url: String,
@@ -20,6 +29,17 @@ pub struct Settings {
     pub ai_accounts: Option<AiAccounts>,
     pub appearance: Option<Appearance>,
     pub behaviour: Option<Behaviour>,
+    pub quick_lookup: Option<QuickLookup>,
+    pub history: Option<History>,
+    pub offline_dictionaries: Option<OfflineDictionaries>,
+    pub usage: Option<Usage>,
+    pub prompts: Option<Prompts>,
+    pub engine_parameters: Option<EngineParameters>,
+    pub glossary: Option<Glossary>,
+    pub translation_memory: Option<TranslationMemory>,
+    pub documents: Option<Documents>,
+    pub local_models: Option<LocalModels>,
+    pub fallback: Option<Fallback>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -38,6 +58,64 @@ pub struct Qwen {
     pub api_key: String,
 }
 
+/// Model and sampling parameters of the AI engines.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EngineParameters {
+    pub deepseek: ModelParameters,
+    pub qwen: ModelParameters,
+}
+impl Default for EngineParameters {
+    fn default() -> Self {
+        Self {
+            deepseek: ModelParameters::deepseek_default(),
+            qwen: ModelParameters::qwen_default(),
+        }
+    }
+}
+
+/// Parameters sent with every request to an engine. Unset ones are left to the provider.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModelParameters {
+    /// e.g. `deepseek-reasoner`, `qwen-plus` or `qwen-max`.
+    pub model: String,
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    pub max_tokens: u32,
+    /// Requests with the same seed and parameters give the same output, as far as the
+    /// provider allows, so that outputs can be compared.
+    pub seed: Option<u32>,
+    /// Let Qwen 3 models think before translating sentences.
+    #[serde(default)]
+    pub enable_thinking: bool,
+    /// Most tokens spent on thinking.
+    pub thinking_budget: Option<u32>,
+}
+impl ModelParameters {
+    pub fn deepseek_default() -> Self {
+        Self {
+            model: "deepseek-chat".to_string(),
+            temperature: Some(1.3),
+            top_p: None,
+            max_tokens: 8_192,
+            seed: None,
+            enable_thinking: false,
+            thinking_budget: None,
+        }
+    }
+
+    pub fn qwen_default() -> Self {
+        Self {
+            model: "qwen3-235b-a22b".to_string(),
+            temperature: Some(1.0),
+            top_p: None,
+            max_tokens: 8_192,
+            seed: None,
+            enable_thinking: false,
+            thinking_budget: None,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Appearance {
     pub colour_theme: ColourTheme,
@@ -53,6 +131,301 @@ pub enum ColourTheme {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Behaviour {
     pub auto_scroll: bool,
+    pub clipboard_watch: Option<ClipboardWatch>,
+    pub back_translation: Option<BackTranslation>,
+}
+
+/// Opt-in monitor which translates copied or selected text automatically.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClipboardWatch {
+    pub enabled: bool,
+    /// Also watch the X11/Wayland PRIMARY selection (selected text), not only CLIPBOARD.
+    pub primary_selection: bool,
+    /// How long the copied text must stay unchanged before it is translated.
+    pub debounce_ms: u64,
+    /// Types which mark the copied data as coming from a password manager: MIME types
+    /// on X11 and Wayland, pasteboard types on macOS and registered clipboard formats on
+    /// Windows. Data offered with any of these types is never read.
+    pub ignore_list: Vec<String>,
+}
+impl Default for ClipboardWatch {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            primary_selection: false,
+            debounce_ms: 500,
+            ignore_list: vec![
+                "x-kde-passwordManagerHint".to_string(),
+                "org.nspasteboard.ConcealedType".to_string(),
+                "org.nspasteboard.TransientType".to_string(),
+                "ExcludeClipboardContentFromMonitorProcessing".to_string(),
+                "CanIncludeInClipboardHistory".to_string(),
+            ],
+        }
+    }
+}
+
+/// Opt-in check of translated sentences, which are translated back into their source
+/// language and scored against it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BackTranslation {
+    pub enabled: bool,
+    /// Engine translating back, the engine of the translation when unset.
+    pub engine: Option<AiProvider>,
+}
+
+/// Borderless popup which looks up the selected text on a global shortcut. Off by
+/// default, as the shortcut is taken from every other application.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QuickLookup {
+    pub enabled: bool,
+    /// Modifiers and key joined by `+`, e.g. `Ctrl+Alt+D`.
+    pub shortcut: String,
+    pub engine: AiProvider,
+    pub source_language: Language,
+    pub target_language: Language,
+}
+impl Default for QuickLookup {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            shortcut: "Ctrl+Alt+D".to_string(),
+            engine: AiProvider::Qwen,
+            source_language: Language::English,
+            target_language: Language::Chinese,
+        }
+    }
+}
+
+/// Translation history kept in the local database.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct History {
+    pub enabled: bool,
+    /// Only the latest entries are kept once there are more than this.
+    pub max_entries: Option<usize>,
+    /// Entries older than this are removed on start-up.
+    pub max_age_days: Option<u32>,
+}
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_entries: Some(10_000),
+            max_age_days: None,
+        }
+    }
+}
+
+/// Local dictionaries used by the offline word engine.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OfflineDictionaries {
+    /// StarDict `.ifo`, MDict `.mdx` or DICT `.index` files, searched in order.
+    pub dictionaries: Vec<PathBuf>,
+}
+
+/// Required translations of terms, given to the AI engines when translating sentences.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Glossary {
+    pub entries: Vec<GlossaryEntry>,
+}
+
+/// Translations reused from the translation memory in the local database.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TranslationMemory {
+    pub enabled: bool,
+    /// Percentage of similarity from which translations of similar sentences are given
+    /// to the engines as references.
+    pub fuzzy_threshold: f64,
+    pub max_references: usize,
+    /// Database file of the memory, the local database if unset.
+    pub path: Option<PathBuf>,
+}
+impl Default for TranslationMemory {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            fuzzy_threshold: 75.0,
+            max_references: 3,
+            path: None,
+        }
+    }
+}
+
+/// Texts too long for one request, translated in chunks.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Documents {
+    /// Estimated tokens of source text in one request, kept well under the most tokens
+    /// of an answer.
+    pub chunk_tokens: usize,
+    /// Estimated tokens of the text before a chunk, given as context.
+    pub context_tokens: usize,
+    /// Chunks translated at the same time. With one, they are translated in order.
+    pub parallel_requests: usize,
+}
+impl Default for Documents {
+    fn default() -> Self {
+        Self {
+            chunk_tokens: 1_500,
+            context_tokens: 200,
+            parallel_requests: 1,
+        }
+    }
+}
+
+/// Models of the `Local` engine, which translates on the CPU without network or API
+/// key.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LocalModels {
+    /// Models in other places than the `models` directory of the config dir, whose
+    /// models are found without being listed.
+    pub models: Vec<LocalModel>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LocalModel {
+    pub source_language: Language,
+    pub target_language: Language,
+    /// Directory of a Marian (OPUS-MT) model with `config.json`, `model.safetensors`
+    /// and its tokenizers.
+    pub path: PathBuf,
+}
+
+/// Engines tried in turn when the chosen one fails for reasons of its provider, e.g. a
+/// server error or a used-up quota. Empty chains leave the chosen engine alone.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Fallback {
+    /// Engines tried after the one chosen in the Sentences tab, e.g. Qwen, DeepSeek,
+    /// then Local. Texts translated in parts are only sent to the chosen engine.
+    pub sentence: Vec<AiProvider>,
+    /// Engine names tried after the one chosen in the Word tab, which may be `Offline`.
+    pub word: Vec<String>,
+    /// Failures which move on to the next engine; other errors are reported at once.
+    pub fail_over_on: Vec<FailureKind>,
+    /// Seconds for which a failed engine is tried after the others, also after a
+    /// restart.
+    pub cooldown_secs: u64,
+}
+impl Default for Fallback {
+    fn default() -> Self {
+        Self {
+            sentence: Vec::new(),
+            word: Vec::new(),
+            fail_over_on: vec![
+                FailureKind::Network,
+                FailureKind::Server,
+                FailureKind::Quota,
+            ],
+            cooldown_secs: 300,
+        }
+    }
+}
+
+/// Prices of the AI providers and the budgets which cap their costs.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Usage {
+    /// Currency of the prices and budgets, only shown to the user.
+    pub currency: String,
+    /// Prices by provider name, e.g. `DeepSeek`.
+    pub prices: BTreeMap<String, Price>,
+    pub budgets: Vec<Budget>,
+    /// Share of a budget after which the UI warns, between 0 and 1.
+    pub warning_threshold: f64,
+    /// Usage database shared by a team, e.g. on a network drive, so that the budgets
+    /// cover everybody's requests. The local database is used if unset.
+    pub ledger_path: Option<PathBuf>,
+}
+impl Default for Usage {
+    fn default() -> Self {
+        Self {
+            currency: "CNY".to_string(),
+            prices: BTreeMap::from([
+                (
+                    AiProvider::DeepSeek.to_string(),
+                    Price {
+                        input: 2.0,
+                        cached_input: 0.5,
+                        output: 8.0,
+                    },
+                ),
+                (
+                    AiProvider::Qwen.to_string(),
+                    Price {
+                        input: 2.0,
+                        cached_input: 0.8,
+                        output: 8.0,
+                    },
+                ),
+            ]),
+            budgets: Vec::new(),
+            warning_threshold: 0.8,
+            ledger_path: None,
+        }
+    }
+}
+
+/// Price per million tokens. Reasoning tokens are billed as output.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Price {
+    pub input: f64,
+    pub cached_input: f64,
+    pub output: f64,
+}
+
+/// Cap on the costs within a calendar day or month, after which requests are refused.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Budget {
+    /// Provider name, or every provider together if unset.
+    pub provider: Option<String>,
+    pub period: BudgetPeriod,
+    pub limit: f64,
+}
+
+#[derive(strum::Display, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetPeriod {
+    Day,
+    Month,
+}
+
+/// Prompt templates by preset name, and the presets in use.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Prompts {
+    pub word_preset: String,
+    pub sentence_preset: String,
+    pub word: BTreeMap<String, PromptTemplate>,
+    pub sentence: BTreeMap<String, PromptTemplate>,
+}
+impl Default for Prompts {
+    fn default() -> Self {
+        Self {
+            word_preset: prompt::DEFAULT_WORD_PRESET.to_string(),
+            sentence_preset: prompt::DEFAULT_SENTENCE_PRESET.to_string(),
+            word: prompt::default_word_presets(),
+            sentence: prompt::default_sentence_presets(),
+        }
+    }
+}
+impl Prompts {
+    /// The chosen word preset, or the built-in default if it is not defined.
+    pub fn word_prompt(&self) -> Prompt {
+        match self.word.get(&self.word_preset) {
+            Some(template) => Prompt {
+                preset: self.word_preset.clone(),
+                template: template.clone(),
+            },
+            None => Prompt::default_word(),
+        }
+    }
+
+    /// The chosen sentence preset, or the built-in default if it is not defined.
+    pub fn sentence_prompt(&self) -> Prompt {
+        match self.sentence.get(&self.sentence_preset) {
+            Some(template) => Prompt {
+                preset: self.sentence_preset.clone(),
+                template: template.clone(),
+            },
+            None => Prompt::default_sentence(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -0,0 +1,597 @@
+//! Translation memory of sentence pairs. Sentences translated before are reused
+//! as they are, similar ones are given to the AI engines as references.
+
+use std::{collections::BTreeSet, fs, path::Path, sync::Arc};
+
+use anyhow::{Context, Error, Result, anyhow};
+use rusqlite::{Connection, OptionalExtension, params, params_from_iter};
+
+use crate::{
+    Language,
+    database::{self, Schema, Store},
+    utils::xml,
+};
+
+const SCHEMA: Schema = Schema {
+    name: "translation memory tables",
+    sql: r#"
+PRAGMA foreign_keys = ON;
+CREATE TABLE IF NOT EXISTS translation_memory (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    source_language TEXT NOT NULL,
+    target_language TEXT NOT NULL,
+    -- The source with whitespace collapsed, which exact matches compare.
+    source_key TEXT NOT NULL,
+    source TEXT NOT NULL,
+    target TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    UNIQUE (source_language, target_language, source_key)
+);
+CREATE TABLE IF NOT EXISTS translation_memory_ngrams (
+    gram TEXT NOT NULL,
+    segment_id INTEGER NOT NULL REFERENCES translation_memory (id) ON DELETE CASCADE,
+    PRIMARY KEY (gram, segment_id)
+) WITHOUT ROWID;
+CREATE INDEX IF NOT EXISTS translation_memory_ngrams_segment
+    ON translation_memory_ngrams (segment_id);
+"#,
+};
+
+/// Length of the character n-grams indexed for fuzzy matching.
+const GRAM_LENGTH: usize = 3;
+/// Segments sharing the most n-grams with a sentence whose similarity is computed.
+const CANDIDATES: usize = 50;
+
+/// A sentence and its translation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranslationUnit {
+    pub source_language: Language,
+    pub target_language: Language,
+    pub source: String,
+    pub target: String,
+}
+
+/// A segment of the memory similar to a sentence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TmMatch {
+    pub source: String,
+    pub target: String,
+    /// Percentage from 0 to 100, which only exact matches reach.
+    pub similarity: f64,
+}
+
+/// Segment pairs shared between threads, indexed by character n-grams of the source.
+pub struct TranslationMemory {
+    store: Store,
+}
+impl TranslationMemory {
+    /// Open the memory in the local database under the config dir.
+    pub fn open_default() -> Result<Self, Error> {
+        Ok(Self {
+            store: Store::open_default(&SCHEMA)?,
+        })
+    }
+
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        Ok(Self {
+            store: Store::open(path, &SCHEMA)?,
+        })
+    }
+
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Ok(Self {
+            store: Store::open_in_memory(&SCHEMA)?,
+        })
+    }
+
+    /// Add a unit, replacing the translation of the same source sentence.
+    pub fn add(&self, unit: &TranslationUnit) -> Result<(), Error> {
+        let mut connection = self.store.connection();
+        let transaction = connection.transaction()?;
+        insert(&transaction, unit)?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Add units in one transaction. Returns the number of units added.
+    pub fn add_all(&self, units: &[TranslationUnit]) -> Result<usize, Error> {
+        let mut connection = self.store.connection();
+        let transaction = connection.transaction()?;
+        let mut added = 0;
+        for unit in units {
+            if insert(&transaction, unit)? {
+                added += 1;
+            }
+        }
+        transaction.commit()?;
+        Ok(added)
+    }
+
+    /// Every unit, oldest first.
+    pub fn units(&self) -> Result<Vec<TranslationUnit>, Error> {
+        let connection = self.store.connection();
+        let mut statement = connection.prepare(
+            "SELECT source_language, target_language, source, target FROM translation_memory \
+             ORDER BY id",
+        )?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| anyhow!("Failed to read translation memory"))?;
+        rows.into_iter()
+            .map(|(source_language, target_language, source, target)| {
+                Ok(TranslationUnit {
+                    source_language: source_language.parse()?,
+                    target_language: target_language.parse()?,
+                    source,
+                    target,
+                })
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> Result<usize, Error> {
+        let count = self.store.connection().query_row(
+            "SELECT COUNT(*) FROM translation_memory",
+            [],
+            |row| row.get::<_, i64>(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    pub fn is_empty(&self) -> Result<bool, Error> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Segments of the language pair at least `min_similarity` percent similar to
+    /// `sentence`, the most similar first.
+    pub fn lookup(
+        &self,
+        sentence: &str,
+        source_language: Language,
+        target_language: Language,
+        min_similarity: f64,
+        limit: usize,
+    ) -> Result<Vec<TmMatch>, Error> {
+        let key = source_key(sentence);
+        if key.is_empty() || limit == 0 {
+            return Ok(Vec::new());
+        }
+        let connection = self.store.connection();
+        let exact = connection
+            .query_row(
+                "SELECT source, target FROM translation_memory WHERE source_language = ?1 \
+                 AND target_language = ?2 AND source_key = ?3",
+                params![
+                    source_language.to_string(),
+                    target_language.to_string(),
+                    key
+                ],
+                |row| {
+                    Ok(TmMatch {
+                        source: row.get(0)?,
+                        target: row.get(1)?,
+                        similarity: 100.0,
+                    })
+                },
+            )
+            .optional()?;
+        if let Some(exact) = exact {
+            return Ok(vec![exact]);
+        }
+
+        let grams = grams(&key);
+        let placeholders = vec!["?"; grams.len()].join(", ");
+        let mut statement = connection.prepare(&format!(
+            "SELECT m.source_key, m.source, m.target FROM translation_memory_ngrams g \
+             JOIN translation_memory m ON m.id = g.segment_id \
+             WHERE m.source_language = ? AND m.target_language = ? AND g.gram IN ({}) \
+             GROUP BY m.id ORDER BY COUNT(*) DESC LIMIT {}",
+            placeholders, CANDIDATES
+        ))?;
+        let languages = [source_language.to_string(), target_language.to_string()];
+        let mut matches = statement
+            .query_map(
+                params_from_iter(languages.iter().chain(grams.iter())),
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                    ))
+                },
+            )?
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| anyhow!("Failed to search translation memory"))?
+            .into_iter()
+            .map(|(candidate_key, source, target)| TmMatch {
+                source,
+                target,
+                similarity: similarity(&key, &candidate_key),
+            })
+            .filter(|tm_match| tm_match.similarity >= min_similarity)
+            .collect::<Vec<_>>();
+        matches.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+        matches.truncate(limit);
+        Ok(matches)
+    }
+
+    pub fn clear(&self) -> Result<(), Error> {
+        self.store
+            .connection()
+            .execute_batch("DELETE FROM translation_memory_ngrams; DELETE FROM translation_memory;")
+            .with_context(|| anyhow!("Failed to clear translation memory"))?;
+        Ok(())
+    }
+}
+
+/// Insert or replace a unit and index its n-grams. Returns whether anything changed.
+fn insert(connection: &Connection, unit: &TranslationUnit) -> Result<bool, Error> {
+    let key = source_key(&unit.source);
+    let target = unit.target.trim();
+    if key.is_empty() || target.is_empty() {
+        return Ok(false);
+    }
+    let segment_id = connection
+        .query_row(
+            "INSERT INTO translation_memory (source_language, target_language, source_key, \
+             source, target, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6) \
+             ON CONFLICT (source_language, target_language, source_key) DO UPDATE \
+             SET source = excluded.source, target = excluded.target, \
+             created_at = excluded.created_at WHERE target != excluded.target \
+             RETURNING id",
+            params![
+                unit.source_language.to_string(),
+                unit.target_language.to_string(),
+                key,
+                unit.source.trim(),
+                target,
+                database::now(),
+            ],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()
+        .with_context(|| anyhow!("Failed to add {} to translation memory", unit.source))?;
+    let Some(segment_id) = segment_id else {
+        // The same translation is already there.
+        return Ok(false);
+    };
+    let mut statement = connection.prepare_cached(
+        "INSERT OR IGNORE INTO translation_memory_ngrams (gram, segment_id) VALUES (?1, ?2)",
+    )?;
+    for gram in grams(&key) {
+        statement.execute(params![gram, segment_id])?;
+    }
+    Ok(true)
+}
+
+/// Consults a translation memory before an engine is asked.
+#[derive(Clone)]
+pub struct MemoryLookup {
+    pub memory: Arc<TranslationMemory>,
+    /// Matches less similar than this percentage are ignored.
+    pub min_similarity: f64,
+    pub max_references: usize,
+}
+
+pub enum MemoryHit {
+    /// The translation of the same sentence, which needs no engine.
+    Exact(String),
+    /// Translations of similar sentences, possibly none.
+    Fuzzy(Vec<TmMatch>),
+}
+
+impl MemoryLookup {
+    pub fn consult(
+        &self,
+        sentence: &str,
+        source_language: Language,
+        target_language: Language,
+    ) -> Result<MemoryHit, Error> {
+        let mut matches = self.memory.lookup(
+            sentence,
+            source_language,
+            target_language,
+            self.min_similarity,
+            self.max_references.max(1),
+        )?;
+        Ok(match matches.first() {
+            Some(first) if first.similarity >= 100.0 => MemoryHit::Exact(matches.remove(0).target),
+            _ => {
+                matches.truncate(self.max_references);
+                MemoryHit::Fuzzy(matches)
+            }
+        })
+    }
+}
+
+/// Reference translations appended to the system prompt, empty without matches.
+pub fn prompt_supplement(references: &[TmMatch]) -> String {
+    if references.is_empty() {
+        return String::new();
+    }
+    let mut supplement = "\n以下是相似句子的已有译文，可供参考，请保持用词和风格一致：".to_string();
+    for reference in references {
+        supplement += &format!(
+            "\n原文（相似度 {:.0}%）：{}\n译文：{}",
+            reference.similarity, reference.source, reference.target
+        );
+    }
+    supplement
+}
+
+/// Similarity of two sentences as a percentage, from the edit distance between them.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (source_key(a), source_key(b));
+    let length = a.chars().count().max(b.chars().count());
+    if length == 0 {
+        return 100.0;
+    }
+    let distance = strsim::levenshtein(&a, &b);
+    100.0 * (1.0 - distance as f64 / length as f64)
+}
+
+/// The sentence with whitespace collapsed.
+fn source_key(sentence: &str) -> String {
+    sentence.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Lower case character n-grams of a key, or the whole key if it is shorter.
+fn grams(key: &str) -> BTreeSet<String> {
+    let chars = key.to_lowercase().chars().collect::<Vec<_>>();
+    if chars.len() <= GRAM_LENGTH {
+        return BTreeSet::from([chars.into_iter().collect()]);
+    }
+    chars
+        .windows(GRAM_LENGTH)
+        .map(|gram| gram.iter().collect())
+        .collect()
+}
+
+/// Read a `.tmx` file.
+pub fn import(path: &Path) -> Result<Vec<TranslationUnit>, Error> {
+    let text = fs::read_to_string(path)
+        .with_context(|| anyhow!("Failed to read translation memory {}", path.display()))?;
+    read_tmx(&text)
+        .with_context(|| anyhow!("Failed to import translation memory {}", path.display()))
+}
+
+/// Write a `.tmx` file.
+pub fn export(units: &[TranslationUnit], path: &Path) -> Result<(), Error> {
+    fs::write(path, write_tmx(units))
+        .with_context(|| anyhow!("Failed to write translation memory {}", path.display()))
+}
+
+/// Read the translation units of a TMX file. The variant in the source language of a
+/// unit, given by `srclang` of the unit or the header, is paired with every other
+/// variant. Without a source language every pair of variants becomes a unit.
+/// Variants in unsupported languages are skipped, and so is inline markup.
+pub fn read_tmx(text: &str) -> Result<Vec<TranslationUnit>, Error> {
+    let document = roxmltree::Document::parse(text)?;
+    let source_language = |node: roxmltree::Node| {
+        node.attribute("srclang")
+            .and_then(|code| Language::from_name_or_code(code).ok())
+    };
+    let header_language = document
+        .descendants()
+        .find(|node| node.has_tag_name("header"))
+        .and_then(source_language);
+    let mut units = Vec::new();
+    for unit in document
+        .descendants()
+        .filter(|node| node.has_tag_name("tu"))
+    {
+        let unit_language = source_language(unit).or(header_language);
+        let variants = unit
+            .children()
+            .filter(|node| node.has_tag_name("tuv"))
+            .filter_map(|variant| {
+                let code = variant
+                    .attribute((xml::NAMESPACE, "lang"))
+                    .or_else(|| variant.attribute("lang"))?;
+                let language = Language::from_name_or_code(code).ok()?;
+                let segment = variant.children().find(|node| node.has_tag_name("seg"))?;
+                let text = segment_text(segment);
+                (!text.is_empty()).then_some((language, text))
+            })
+            .collect::<Vec<_>>();
+        for (source_language, source) in &variants {
+            if unit_language.is_some_and(|language| language != *source_language) {
+                continue;
+            }
+            for (target_language, target) in &variants {
+                if source_language != target_language {
+                    units.push(TranslationUnit {
+                        source_language: *source_language,
+                        target_language: *target_language,
+                        source: source.clone(),
+                        target: target.clone(),
+                    });
+                }
+            }
+        }
+    }
+    Ok(units)
+}
+
+/// Text of a `seg`, leaving out the native codes of inline tags such as `bpt` and `ph`.
+fn segment_text(segment: roxmltree::Node) -> String {
+    segment
+        .descendants()
+        .filter(|node| node.is_text())
+        .filter(|node| {
+            !node.ancestors().any(|ancestor| {
+                matches!(
+                    ancestor.tag_name().name(),
+                    "bpt" | "ept" | "it" | "ph" | "ut"
+                )
+            })
+        })
+        .filter_map(|node| node.text())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Write a TMX 1.4 file with one translation unit per unit.
+pub fn write_tmx(units: &[TranslationUnit]) -> String {
+    let mut tmx = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <tmx version=\"1.4\">\n\
+         \x20 <header creationtool=\"Neat Translator\" creationtoolversion=\"1\" \
+         segtype=\"sentence\" o-tmf=\"neat-translator\" adminlang=\"en\" srclang=\"*all*\" \
+         datatype=\"plaintext\"/>\n\
+         \x20 <body>\n",
+    );
+    for unit in units {
+        tmx += &format!("    <tu srclang=\"{}\">\n", unit.source_language.code());
+        for (language, text) in [
+            (unit.source_language, &unit.source),
+            (unit.target_language, &unit.target),
+        ] {
+            tmx += &format!(
+                "      <tuv xml:lang=\"{}\"><seg>{}</seg></tuv>\n",
+                language.code(),
+                xml::escape(text)
+            );
+        }
+        tmx += "    </tu>\n";
+    }
+    tmx += "  </body>\n</tmx>\n";
+    tmx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(source: &str, target: &str) -> TranslationUnit {
+        TranslationUnit {
+            source_language: Language::English,
+            target_language: Language::Chinese,
+            source: source.to_string(),
+            target: target.to_string(),
+        }
+    }
+
+    #[test]
+    fn exact_matches_win_and_fuzzy_ones_are_ranked() {
+        let memory = Arc::new(TranslationMemory::open_in_memory().unwrap());
+        let added = memory
+            .add_all(&[
+                unit("The file could not be opened.", "无法打开该文件。"),
+                unit("The file could not be saved.", "无法保存该文件。"),
+                unit("The folder could not be opened.", "无法打开该文件夹。"),
+                unit("Press any key to continue.", "按任意键继续。"),
+                unit("The file could not be opened.", "无法打开该文件。"),
+            ])
+            .unwrap();
+        assert_eq!(added, 4);
+
+        let lookup = MemoryLookup {
+            memory,
+            min_similarity: 70.0,
+            max_references: 2,
+        };
+        let MemoryHit::Exact(target) = lookup
+            .consult(
+                "  The file could   not be opened.",
+                Language::English,
+                Language::Chinese,
+            )
+            .unwrap()
+        else {
+            panic!("expected an exact match");
+        };
+        assert_eq!(target, "无法打开该文件。");
+
+        let MemoryHit::Fuzzy(references) = lookup
+            .consult(
+                "The file could not be open.",
+                Language::English,
+                Language::Chinese,
+            )
+            .unwrap()
+        else {
+            panic!("expected fuzzy matches");
+        };
+        assert_eq!(references.len(), 2);
+        assert_eq!(references[0].target, "无法打开该文件。");
+        assert!(references[0].similarity > 80.0 && references[0].similarity < 100.0);
+        assert!(references[0].similarity >= references[1].similarity);
+
+        // Other language pairs and unrelated sentences do not match.
+        assert!(matches!(
+            lookup.consult("The file could not be opened.", Language::English, Language::French),
+            Ok(MemoryHit::Fuzzy(references)) if references.is_empty()
+        ));
+        assert!(matches!(
+            lookup.consult("Hello there!", Language::English, Language::Chinese),
+            Ok(MemoryHit::Fuzzy(references)) if references.is_empty()
+        ));
+        assert!(prompt_supplement(&[]).is_empty());
+    }
+
+    #[test]
+    fn newer_translations_replace_older_ones() {
+        let memory = TranslationMemory::open_in_memory().unwrap();
+        memory.add(&unit("Save", "存储")).unwrap();
+        memory.add(&unit("Save ", "保存")).unwrap();
+        assert_eq!(memory.units().unwrap(), [unit("Save", "保存")]);
+        let matches = memory
+            .lookup("save", Language::English, Language::Chinese, 50.0, 5)
+            .unwrap();
+        assert_eq!(matches.len(), 1);
+        assert!(matches[0].similarity < 100.0);
+        memory.clear().unwrap();
+        assert!(memory.is_empty().unwrap());
+    }
+
+    #[test]
+    fn similarity_is_a_percentage_of_the_edit_distance() {
+        assert_eq!(similarity("abcd", "abcd"), 100.0);
+        assert_eq!(similarity("abcd", "abce"), 75.0);
+        assert_eq!(similarity("a  b", "a b"), 100.0);
+        assert_eq!(similarity("", "abc"), 0.0);
+    }
+
+    #[test]
+    fn tmx_round_trips() {
+        let units = [unit("R&D <draft>", "研发“草稿”")];
+        assert_eq!(read_tmx(&write_tmx(&units)).unwrap(), units);
+
+        let tmx = r#"<?xml version="1.0"?>
+            <tmx version="1.4"><header srclang="en-US" adminlang="en" segtype="sentence"
+                datatype="plaintext" o-tmf="x" creationtool="x" creationtoolversion="1"/>
+            <body>
+                <tu>
+                    <tuv xml:lang="en-US"><seg>Click <bpt i="1">&lt;b&gt;</bpt>OK<ept i="1">&lt;/b&gt;</ept>.</seg></tuv>
+                    <tuv xml:lang="zh-CN"><seg>单击<ph>&lt;br/&gt;</ph>确定。</seg></tuv>
+                    <tuv xml:lang="tlh"><seg>Qapla'</seg></tuv>
+                </tu>
+                <tu srclang="zh-CN">
+                    <tuv lang="en"><seg>Cancel</seg></tuv>
+                    <tuv lang="zh"><seg>取消</seg></tuv>
+                </tu>
+            </body></tmx>"#;
+        assert_eq!(
+            read_tmx(tmx).unwrap(),
+            [
+                unit("Click OK.", "单击确定。"),
+                TranslationUnit {
+                    source_language: Language::Chinese,
+                    target_language: Language::English,
+                    source: "取消".to_string(),
+                    target: "Cancel".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,326 @@
+//! Token usage and costs of the AI providers, kept in a ledger with one row per request.
+
+use std::path::Path;
+
+use anyhow::{Context, Error, Result, anyhow};
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone};
+use rusqlite::params;
+
+use crate::{
+    TokenUsage,
+    database::{self, Schema, Store},
+    storage::{self, BudgetPeriod, Price},
+};
+
+const SCHEMA: Schema = Schema {
+    name: "usage ledger table",
+    sql: r#"
+CREATE TABLE IF NOT EXISTS usage_ledger (
+    id INTEGER PRIMARY KEY,
+    provider TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    input_tokens INTEGER NOT NULL,
+    cached_input_tokens INTEGER NOT NULL,
+    output_tokens INTEGER NOT NULL,
+    reasoning_tokens INTEGER NOT NULL,
+    cost REAL NOT NULL
+);
+CREATE INDEX IF NOT EXISTS usage_ledger_created_at ON usage_ledger (created_at);
+"#,
+};
+
+/// Sums of the requests of a period.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub tokens: TokenUsage,
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BudgetStatus {
+    Within,
+    /// Past the warning threshold of the budget.
+    Nearing {
+        budget: storage::Budget,
+        spent: f64,
+    },
+    Exceeded {
+        budget: storage::Budget,
+        spent: f64,
+    },
+}
+
+/// Ledger of the requests to AI providers. The ledger can be shared between threads,
+/// and between users if the database is on a shared drive.
+pub struct UsageLedger {
+    store: Store,
+}
+impl UsageLedger {
+    /// Open the ledger at the configured path, or in the local database under the
+    /// config dir.
+    pub fn open_default(config: &storage::Usage) -> Result<Self, Error> {
+        match &config.ledger_path {
+            Some(path) => Self::open(path),
+            None => Ok(Self {
+                store: Store::open_default(&SCHEMA)?,
+            }),
+        }
+    }
+
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        Ok(Self {
+            store: Store::open(path, &SCHEMA)?,
+        })
+    }
+
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Ok(Self {
+            store: Store::open_in_memory(&SCHEMA)?,
+        })
+    }
+
+    /// Record a finished request and return its cost. Providers without a price
+    /// cost nothing.
+    pub fn record(
+        &self,
+        provider: &str,
+        usage: TokenUsage,
+        config: &storage::Usage,
+    ) -> Result<f64, Error> {
+        let cost = config
+            .prices
+            .get(provider)
+            .map_or(0.0, |price| cost(usage, price));
+        self.store
+            .connection()
+            .execute(
+                "INSERT INTO usage_ledger (provider, created_at, input_tokens, \
+                 cached_input_tokens, output_tokens, reasoning_tokens, cost) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    provider,
+                    database::now(),
+                    usage.input_tokens,
+                    usage.cached_input_tokens,
+                    usage.output_tokens,
+                    usage.reasoning_tokens,
+                    cost,
+                ],
+            )
+            .with_context(|| anyhow!("Failed to record usage of {}", provider))?;
+        Ok(cost)
+    }
+
+    /// Totals of the requests since `since`, in seconds since the Unix epoch, of
+    /// `provider` or of every provider.
+    pub fn totals(&self, provider: Option<&str>, since: i64) -> Result<UsageTotals, Error> {
+        self.store
+            .connection()
+            .query_row(
+                "SELECT COUNT(*), TOTAL(input_tokens), TOTAL(cached_input_tokens), \
+                 TOTAL(output_tokens), TOTAL(reasoning_tokens), TOTAL(cost) FROM usage_ledger \
+                 WHERE created_at >= ?1 AND (?2 IS NULL OR provider = ?2)",
+                params![since, provider],
+                totals_from_row,
+            )
+            .with_context(|| anyhow!("Failed to read usage totals"))
+    }
+
+    /// Totals of the requests since `since` by provider, ordered by provider name.
+    pub fn totals_by_provider(&self, since: i64) -> Result<Vec<(String, UsageTotals)>, Error> {
+        let connection = self.store.connection();
+        let mut statement = connection.prepare(
+            "SELECT COUNT(*), TOTAL(input_tokens), TOTAL(cached_input_tokens), \
+             TOTAL(output_tokens), TOTAL(reasoning_tokens), TOTAL(cost), provider \
+             FROM usage_ledger WHERE created_at >= ?1 GROUP BY provider ORDER BY provider",
+        )?;
+        statement
+            .query_map([since], |row| Ok((row.get(6)?, totals_from_row(row)?)))?
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| anyhow!("Failed to read usage totals"))
+    }
+
+    /// The most pressing of the budgets covering `provider`: an exceeded one before one
+    /// past the warning threshold.
+    pub fn budget_status(
+        &self,
+        provider: &str,
+        config: &storage::Usage,
+        now: DateTime<Local>,
+    ) -> Result<BudgetStatus, Error> {
+        let mut status = BudgetStatus::Within;
+        for budget in &config.budgets {
+            if budget
+                .provider
+                .as_deref()
+                .is_some_and(|budget_provider| budget_provider != provider)
+            {
+                continue;
+            }
+            let spent = self
+                .totals(budget.provider.as_deref(), period_start(budget.period, now))?
+                .cost;
+            if spent >= budget.limit {
+                return Ok(BudgetStatus::Exceeded {
+                    budget: budget.clone(),
+                    spent,
+                });
+            }
+            if spent >= budget.limit * config.warning_threshold && status == BudgetStatus::Within {
+                status = BudgetStatus::Nearing {
+                    budget: budget.clone(),
+                    spent,
+                };
+            }
+        }
+        Ok(status)
+    }
+}
+
+/// Cost of a request. Cached input tokens are billed at their own price.
+pub fn cost(usage: TokenUsage, price: &Price) -> f64 {
+    let cached_input_tokens = usage.cached_input_tokens.min(usage.input_tokens);
+    let uncached_input_tokens = usage.input_tokens - cached_input_tokens;
+    (uncached_input_tokens as f64 * price.input
+        + cached_input_tokens as f64 * price.cached_input
+        + usage.output_tokens as f64 * price.output)
+        / 1_000_000.0
+}
+
+/// Start of the local day or month containing `now`, in seconds since the Unix epoch.
+pub fn period_start(period: BudgetPeriod, now: DateTime<Local>) -> i64 {
+    let date = now.date_naive();
+    let first_day = match period {
+        BudgetPeriod::Day => date,
+        BudgetPeriod::Month => NaiveDate::from_ymd_opt(date.year(), date.month(), 1).unwrap(),
+    };
+    let midnight = first_day.and_hms_opt(0, 0, 0).unwrap();
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        // Midnight may be skipped by a daylight saving change.
+        .map_or_else(|| midnight.and_utc().timestamp(), |start| start.timestamp())
+}
+
+fn totals_from_row(row: &rusqlite::Row) -> rusqlite::Result<UsageTotals> {
+    Ok(UsageTotals {
+        requests: row.get::<_, i64>(0)? as u64,
+        tokens: TokenUsage {
+            input_tokens: row.get::<_, f64>(1)? as u32,
+            cached_input_tokens: row.get::<_, f64>(2)? as u32,
+            output_tokens: row.get::<_, f64>(3)? as u32,
+            reasoning_tokens: row.get::<_, f64>(4)? as u32,
+        },
+        cost: row.get(5)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Budget;
+
+    const USAGE: TokenUsage = TokenUsage {
+        input_tokens: 1_000_000,
+        output_tokens: 500_000,
+        cached_input_tokens: 400_000,
+        reasoning_tokens: 100_000,
+    };
+
+    #[test]
+    fn cached_input_is_billed_at_its_price() {
+        let price = Price {
+            input: 2.0,
+            cached_input: 0.5,
+            output: 8.0,
+        };
+        assert_eq!(cost(USAGE, &price), 0.6 * 2.0 + 0.4 * 0.5 + 0.5 * 8.0);
+    }
+
+    #[test]
+    fn totals_are_kept_per_provider() {
+        let ledger = UsageLedger::open_in_memory().unwrap();
+        let config = storage::Usage::default();
+        ledger.record("DeepSeek", USAGE, &config).unwrap();
+        ledger.record("DeepSeek", USAGE, &config).unwrap();
+        ledger.record("Qwen", USAGE, &config).unwrap();
+        ledger.record("Unpriced", USAGE, &config).unwrap();
+
+        let deepseek = ledger.totals(Some("DeepSeek"), 0).unwrap();
+        assert_eq!(deepseek.requests, 2);
+        assert_eq!(deepseek.tokens, USAGE + USAGE);
+        assert_eq!(ledger.totals(None, 0).unwrap().requests, 4);
+        assert_eq!(
+            ledger.totals(None, database::now() + 1).unwrap().requests,
+            0
+        );
+
+        let by_provider = ledger.totals_by_provider(0).unwrap();
+        let providers = by_provider
+            .iter()
+            .map(|(provider, _)| provider.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(providers, ["DeepSeek", "Qwen", "Unpriced"]);
+        assert_eq!(by_provider[2].1.cost, 0.0);
+    }
+
+    #[test]
+    fn budgets_warn_and_then_block() {
+        let ledger = UsageLedger::open_in_memory().unwrap();
+        let mut config = storage::Usage::default();
+        let cost = ledger.record("DeepSeek", USAGE, &config).unwrap();
+        config.budgets = vec![
+            // Shared by every provider.
+            Budget {
+                provider: None,
+                period: BudgetPeriod::Month,
+                limit: cost * 1.1,
+            },
+            Budget {
+                provider: Some("Qwen".to_string()),
+                period: BudgetPeriod::Day,
+                limit: cost / 2.0,
+            },
+        ];
+        let now = Local::now();
+        assert!(matches!(
+            ledger.budget_status("DeepSeek", &config, now).unwrap(),
+            BudgetStatus::Nearing { .. }
+        ));
+        // The Qwen budget only counts Qwen requests.
+        assert!(matches!(
+            ledger.budget_status("Qwen", &config, now).unwrap(),
+            BudgetStatus::Nearing { .. }
+        ));
+
+        ledger.record("Qwen", USAGE, &config).unwrap();
+        assert!(matches!(
+            ledger.budget_status("DeepSeek", &config, now).unwrap(),
+            BudgetStatus::Exceeded { budget, .. } if budget.provider.is_none()
+        ));
+    }
+
+    #[test]
+    fn periods_start_at_local_midnight() {
+        let now = Local.with_ymd_and_hms(2025, 3, 14, 15, 9, 26).unwrap();
+        let day = period_start(BudgetPeriod::Day, now);
+        let month = period_start(BudgetPeriod::Month, now);
+        assert_eq!(
+            Local
+                .timestamp_opt(day, 0)
+                .unwrap()
+                .naive_local()
+                .to_string(),
+            "2025-03-14 00:00:00"
+        );
+        assert_eq!(
+            Local
+                .timestamp_opt(month, 0)
+                .unwrap()
+                .naive_local()
+                .to_string(),
+            "2025-03-01 00:00:00"
+        );
+    }
+}
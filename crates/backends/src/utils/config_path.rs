@@ -66,3 +66,11 @@ pub fn get_config_dir() -> Result<PathBuf, Error> {
     dirs::config_dir().ok_or(anyhow::anyhow!("Cannot locate config_dir!"))
 }
 // 在其他平台使用默认实现
+
+/// Directory of the programme under the config dir, which holds the config file
+/// and the local database.
+pub fn get_prog_config_dir() -> Result<PathBuf, Error> {
+    Ok(get_config_dir()
+        .map_err(|e| anyhow!("Cannot locate config_dir: {}", e))?
+        .join("neat-translator.org"))
+}
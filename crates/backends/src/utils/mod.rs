@@ -1 +1,2 @@
 pub mod config_path;
+pub(crate) mod xml;
@@ -0,0 +1,17 @@
+/// Namespace of the `xml:lang` attribute.
+pub(crate) const NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Escape text for element content and attribute values.
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Escape text for element content, where quotes are left as they are.
+pub(crate) fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
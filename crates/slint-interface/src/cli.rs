@@ -0,0 +1,166 @@
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use anyhow::{Context, Error, Result, anyhow};
+use backends::{
+    AiProvider, Language, file_translation, storage,
+    translation_memory::{MemoryLookup, TranslationMemory},
+    usage::{BudgetStatus, UsageLedger},
+};
+use chrono::Local;
+
+const USAGE: &str = "Usage: neat-translator-slint translate-file <FILE> [--from <LANGUAGE>] \
+                     [--to <LANGUAGE>] [--engine <ENGINE>] [--output <FILE>]";
+
+/// Run the command given on the command line, if any. Returns `None` when there is no
+/// command and the window should open.
+pub fn run(args: &[String]) -> Option<Result<(), Error>> {
+    match args.first().map(String::as_str) {
+        Some("translate-file") => Some(translate_file(&args[1..])),
+        Some("--help" | "-h" | "help") => {
+            println!("{}", USAGE);
+            Some(Ok(()))
+        }
+        _ => None,
+    }
+}
+
+struct FileArguments {
+    path: PathBuf,
+    output: Option<PathBuf>,
+    source_language: Language,
+    target_language: Language,
+    engine: AiProvider,
+}
+
+fn parse_file_arguments(args: &[String]) -> Result<FileArguments, Error> {
+    let mut path = None;
+    let mut output = None;
+    let mut source_language = Language::English;
+    let mut target_language = Language::Chinese;
+    let mut engine = AiProvider::Qwen;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow!("{} needs a value\n{}", arg, USAGE))
+        };
+        match arg.as_str() {
+            "--from" => source_language = Language::from_name_or_code(value()?)?,
+            "--to" => target_language = Language::from_name_or_code(value()?)?,
+            "--engine" => {
+                let name = value()?;
+                engine = name
+                    .parse()
+                    .map_err(|_| anyhow!("Unknown engine: {}", name))?;
+            }
+            "--output" | "-o" => output = Some(PathBuf::from(value()?)),
+            _ if arg.starts_with('-') => return Err(anyhow!("Unknown option {}\n{}", arg, USAGE)),
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => return Err(anyhow!("Only one file can be translated\n{}", USAGE)),
+        }
+    }
+    Ok(FileArguments {
+        path: path.ok_or_else(|| anyhow!("No file to translate\n{}", USAGE))?,
+        output,
+        source_language,
+        target_language,
+        engine,
+    })
+}
+
+/// Translate a file with the engine, glossary and translation memory of the config
+/// file, showing the progress on stderr. Requests are recorded in the usage ledger,
+/// and none is sent once a budget is used up.
+fn translate_file(args: &[String]) -> Result<(), Error> {
+    let arguments = parse_file_arguments(args)?;
+    let settings = backends::initialise().context("Failed to load the config file")?;
+    let memory = settings.translation_memory.clone().unwrap_or_default();
+    let lookup = match memory.enabled {
+        true => match &memory.path {
+            Some(path) => TranslationMemory::open(path),
+            None => TranslationMemory::open_default(),
+        }
+        .inspect_err(|e| log::error!("Failed to open translation memory: {:#}", e))
+        .ok()
+        .map(|translation_memory| MemoryLookup {
+            memory: Arc::new(translation_memory),
+            min_similarity: memory.fuzzy_threshold,
+            max_references: memory.max_references,
+        }),
+        false => None,
+    };
+    let translator = backends::configured_sentence_translator(arguments.engine, &settings, lookup)?;
+    let usage = settings.usage.clone().unwrap_or_default();
+    let ledger = UsageLedger::open_default(&usage)
+        .inspect_err(|e| log::error!("Failed to open usage ledger: {:#}", e))
+        .ok();
+    let engine = arguments.engine.to_string();
+
+    let (output, summary) = file_translation::translate_file(
+        &arguments.path,
+        arguments.output.as_deref(),
+        arguments.source_language,
+        arguments.target_language,
+        |segment| {
+            if let Some(ledger) = &ledger {
+                check_budget(ledger, &engine, &usage)?;
+            }
+            let translation = file_translation::translate_segment(
+                translator.as_ref(),
+                segment,
+                arguments.source_language,
+                arguments.target_language,
+            );
+            if let (Some(ledger), Some(token_usage)) = (&ledger, translator.last_token_usage())
+                && let Err(e) = ledger.record(&engine, token_usage, &usage)
+            {
+                log::error!("Failed to record usage of {}: {:#}", engine, e);
+            }
+            translation
+        },
+        |done, total| {
+            eprint!("\rTranslated {} of {} segments", done, total);
+            let _ = io::stderr().flush();
+        },
+    )?;
+    eprintln!();
+    if summary.untranslated > 0 {
+        eprintln!(
+            "{} segments lost their code or markup in translation and were left untranslated",
+            summary.untranslated
+        );
+    }
+    if !summary.failures.is_empty() {
+        eprintln!(
+            "{} segments failed and were left untranslated:",
+            summary.failures.len()
+        );
+        for failure in &summary.failures {
+            eprintln!("  {}", failure);
+        }
+    }
+    println!("{}", output.display());
+    Ok(())
+}
+
+/// Fail if a budget covering `engine` is used up, so that no request is sent.
+fn check_budget(ledger: &UsageLedger, engine: &str, usage: &storage::Usage) -> Result<(), Error> {
+    match ledger.budget_status(engine, usage, Local::now()) {
+        Ok(BudgetStatus::Exceeded { budget, spent }) => Err(anyhow!(
+            "The budget of {} is used up ({:.2} of {:.2} {} spent), no request is sent",
+            budget.provider.as_deref().unwrap_or("all providers"),
+            spent,
+            budget.limit,
+            usage.currency
+        )),
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::warn!("Failed to check budgets of {}: {:#}", engine, e);
+            Ok(())
+        }
+    }
+}
@@ -1,12 +1,11 @@
 #![windows_subsystem = "windows"]
 use log;
+mod cli;
 mod slint_interface;
 
 const DEFAULT_INTERFACE: &str = "TRANSLATOR_INTERFACE";
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("Hello, world!");
-
     unsafe {
         let rust_log = std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string());
         if vec!["trace", "debug", "info", "warn", "error"]
@@ -28,6 +27,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         std::env::var(DEFAULT_INTERFACE).unwrap_or_else(|_| "slint".to_string())
     );
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(result) = cli::run(&args) {
+        return Ok(result?);
+    }
+
     // if let Some(err) = backends::initialise().err() {
     //     match err.downcast_ref::<backends::error::Error>() {
     //         // TODO
@@ -1,17 +1,54 @@
 use std::{
+    collections::BTreeMap,
+    path::PathBuf,
     rc::Rc,
     sync::{Arc, Mutex, mpsc},
 };
 
 use anyhow::{Error, Result, anyhow};
 use backends::{
-    QwenWordSentenceTranslator, StreamSentenceTranslator, WordTranslator,
+    AiProvider, Language, QwenWordSentenceTranslator, StreamSentenceTranslator, WordTranslator,
+    cache::WordCache,
+    context::{self, WordInContext},
     dict_interface::WordExplanation,
+    history::TranslationOutput,
+    local_mt::{self, LocalSentenceTranslator},
+    offline_dict::OfflineDictWordTranslator,
+    prompt::PromptTemplate,
+    spelling::SpellingSuggester,
+    storage,
+    translation_memory::MemoryLookup,
 };
 use slint::{ModelRc, VecModel};
 
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use backends::clipboard::{ClipboardText, ClipboardWatcher};
+
 slint::include_modules!();
 
+mod aligned;
+mod back_translation;
+mod compare;
+mod document;
+mod export;
+mod fallback;
+mod file_translation;
+mod glossary;
+mod history;
+mod markup;
+mod notebook;
+mod translation_memory;
+mod usage;
+
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+mod quick_lookup;
+
+/// Name of the offline dictionary engine in the Word tab and in history.
+const OFFLINE_ENGINE: &str = "Offline";
+
+/// Number of spellings offered for a word which is not known.
+const SPELLING_SUGGESTIONS: usize = 5;
+
 #[allow(dead_code)]
 pub fn run() -> Result<(), slint::PlatformError> {
     log::info!("Using slint interface");
@@ -42,30 +79,202 @@ pub fn run() -> Result<(), slint::PlatformError> {
     let setting_window = SettingWindow::new()?;
     let setting_window_weak_arc = Arc::new(setting_window.as_weak());
 
-    // Update setting profile
-    let _ = setting_window_weak_arc
+    let clipboard_watch = profile
+        .behaviour
+        .as_ref()
+        .and_then(|behaviour| behaviour.clipboard_watch.clone())
+        .unwrap_or_default();
+    let back_translation_config = profile
+        .behaviour
+        .as_ref()
+        .and_then(|behaviour| behaviour.back_translation.clone())
+        .unwrap_or_default();
+
+    #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+    let quick_lookup = profile.quick_lookup.clone().unwrap_or_default();
+
+    // Prompt presets of the AI engines, chosen in the Word and Sentences tabs
+    let prompts_arc_mutex = Arc::new(Mutex::new(profile.prompts.clone().unwrap_or_default()));
+    show_prompt_presets(&main_window, &prompts_arc_mutex.lock().unwrap());
+
+    // Star senses of words and review them in the Review tab
+    let vocabulary_notebook = notebook::Notebook::open();
+    let _due_count_timer = vocabulary_notebook
         .clone()
-        .upgrade_in_event_loop(move |handle| {
+        .map(|vocabulary_notebook| notebook::connect(&main_window, vocabulary_notebook));
+
+    // Show past translations in the History tab
+    let translation_history =
+        history::TranslationHistory::open(&profile.history.clone().unwrap_or_default());
+    if let Some(translation_history) = &translation_history {
+        history::connect(
+            &main_window,
+            translation_history.clone(),
+            vocabulary_notebook.clone(),
+        );
+        translation_history.refresh(main_window.as_weak());
+    }
+    // Record token usage and costs, shown in the Usage tab
+    let usage_meter = usage::UsageMeter::open(&profile.usage.clone().unwrap_or_default());
+    if let Some(usage_meter) = &usage_meter {
+        usage_meter.refresh(main_window.as_weak());
+    }
+    export::connect(
+        &main_window,
+        translation_history.clone(),
+        vocabulary_notebook.clone(),
+    );
+    // Import and export glossaries in the setting window
+    glossary::connect(&setting_window);
+    // Reuse translations of the same and similar sentences
+    let translation_memory_config = profile.translation_memory.clone().unwrap_or_default();
+    let translation_memory = translation_memory::open(&translation_memory_config);
+    translation_memory::connect(&setting_window, translation_memory.clone());
+    // Long texts in the Sentences tab are translated in chunks
+    let documents = profile.documents.clone().unwrap_or_default();
+    // Compare engines side by side in the Sentences tab
+    compare::connect(
+        &main_window,
+        setting_window.as_weak(),
+        translation_history.clone(),
+        usage_meter.clone(),
+        prompts_arc_mutex.clone(),
+    );
+    // Translate files into copies next to them from the Sentences tab
+    file_translation::connect(
+        &main_window,
+        setting_window.as_weak(),
+        usage_meter.clone(),
+        prompts_arc_mutex.clone(),
+        translation_memory.clone(),
+    );
+
+    // Update setting profile
+    let _ = setting_window_weak_arc.clone().upgrade_in_event_loop({
+        let clipboard_watch = clipboard_watch.clone();
+        let deepseek_api_key = match profile.ai_accounts {
+            Some(ref accounts) => match &accounts.deepseek {
+                Some(deepseek) => &deepseek.api_key,
+                None => "",
+            },
+            None => "",
+        }
+        .to_string();
+        let qwen_api_key = match profile.ai_accounts {
+            Some(ref accounts) => match &accounts.qwen {
+                Some(qwen) => &qwen.api_key,
+                None => "",
+            },
+            None => "",
+        }
+        .to_string();
+        let offline_dictionaries = profile
+            .offline_dictionaries
+            .iter()
+            .flat_map(|offline_dictionaries| &offline_dictionaries.dictionaries)
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let local_models = local_mt::models_to_text(
+            &profile
+                .local_models
+                .as_ref()
+                .map(|local_models| local_models.models.clone())
+                .unwrap_or_default(),
+        );
+        let (fallback_sentence, fallback_word, fallback_on, fallback_cooldown) =
+            fallback::to_slint(&profile.fallback.clone().unwrap_or_default());
+        let engine_parameters = profile.engine_parameters.clone().unwrap_or_default();
+        let glossary = glossary::to_slint(
+            &profile
+                .glossary
+                .as_ref()
+                .map(|glossary| glossary.entries.clone())
+                .unwrap_or_default(),
+        );
+        move |handle| {
             handle.set_settings_from_slint(Settings {
-                deepseek_api_key: match profile.ai_accounts {
-                    Some(ref accounts) => match &accounts.deepseek {
-                        Some(deepseek) => &deepseek.api_key,
-                        None => "",
-                    },
-                    None => "",
-                }
-                .into(),
-                qwen_api_key: match profile.ai_accounts {
-                    Some(ref accounts) => match &accounts.qwen {
-                        Some(qwen) => &qwen.api_key,
-                        None => "",
-                    },
-                    None => "",
-                }
-                .into(),
+                deepseek_api_key: deepseek_api_key.into(),
+                qwen_api_key: qwen_api_key.into(),
+                clipboard_watch: clipboard_watch.enabled,
+                clipboard_primary_selection: clipboard_watch.primary_selection,
+                back_translation: back_translation_config.enabled,
+                back_translation_engine: back_translation_config
+                    .engine
+                    .map(|engine| engine.to_string())
+                    .unwrap_or_default()
+                    .into(),
+                offline_dictionaries: offline_dictionaries.into(),
+                local_models: local_models.into(),
+                fallback_sentence: fallback_sentence.into(),
+                fallback_word: fallback_word.into(),
+                fallback_cooldown: fallback_cooldown.into(),
+                fallback_on: fallback_on.into(),
+                deepseek_parameters: parameters_to_slint(&engine_parameters.deepseek),
+                qwen_parameters: parameters_to_slint(&engine_parameters.qwen),
+                glossary: glossary.into(),
+                translation_memory_enabled: translation_memory_config.enabled,
+                translation_memory_threshold: translation_memory_config
+                    .fuzzy_threshold
+                    .to_string()
+                    .into(),
+                translation_memory_references: translation_memory_config
+                    .max_references
+                    .to_string()
+                    .into(),
             });
             handle.invoke_sync_settings_from_property();
-        });
+        }
+    });
+    // The loaded profile is kept, so that settings which are not shown
+    // in the setting window survive saving.
+    let profile_arc_mutex = Arc::new(Mutex::new(profile));
+
+    // Keep the chosen prompt presets in the config
+    main_window.global::<Logic>().on_select_prompt_preset({
+        let prompts_arc_mutex = prompts_arc_mutex.clone();
+        let profile_arc_mutex = profile_arc_mutex.clone();
+        move |mode, preset| {
+            log::info!("Use {} prompt preset {}", mode, preset);
+            let mut prompts = prompts_arc_mutex.lock().unwrap();
+            match mode.as_str() {
+                "word" => prompts.word_preset = preset.to_string(),
+                "sentence" => prompts.sentence_preset = preset.to_string(),
+                _ => {
+                    log::error!("Unknown prompt mode: {}", mode);
+                    return;
+                }
+            }
+            let mut profile = profile_arc_mutex.lock().unwrap();
+            profile.prompts = Some(prompts.clone());
+            if let Err(e) = backends::save_config(&profile) {
+                log::error!("Failed to save prompt preset: {}", e);
+            }
+        }
+    });
+
+    // Translate copied text
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    let clipboard_watcher_arc_mutex = Arc::new(Mutex::new(watch_clipboard(
+        &clipboard_watch,
+        (*main_window_weak_arc).clone(),
+    )));
+
+    // Look up the selection on the global shortcut
+    #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+    let _quick_lookup_hotkey = if quick_lookup.enabled {
+        quick_lookup::register(
+            &quick_lookup,
+            setting_window.as_weak(),
+            usage_meter.clone(),
+            prompts_arc_mutex.clone(),
+            main_window.as_weak(),
+        )
+        .inspect_err(|e| log::error!("Failed to enable quick lookup: {:#}", e))
+        .ok()
+    } else {
+        None
+    };
 
     // Save settings from Slint
     // TODO
@@ -75,11 +284,12 @@ pub fn run() -> Result<(), slint::PlatformError> {
     // Create a global Arc<Mutex<Receiver<WordExplanation>>> pointer.
     // The Receiver<WordExplanation>> will be replaced by a new one every time
     // the user sends a new callback to translate.
-    let (_, rx) = mpsc::channel::<Result<WordExplanation, Error>>();
+    let (_, rx) = mpsc::channel::<Result<(WordInContext, (Language, Language)), Error>>();
     let wd_rx_arc_mutex = Arc::new(Mutex::new(rx));
     std::thread::spawn({
         let main_window_weak_arc = main_window_weak_arc.clone();
         let wd_rx_arc_mutex = wd_rx_arc_mutex.clone();
+        let vocabulary_notebook = vocabulary_notebook.clone();
         move || {
             loop {
                 if let Some(received_result) = match wd_rx_arc_mutex.try_lock() {
@@ -93,164 +303,27 @@ pub fn run() -> Result<(), slint::PlatformError> {
                     }
                 } {
                     match received_result {
-                        Ok(received_we) => {
+                        Ok((word_in_context, languages)) => {
+                            let received_we = word_in_context.word_explanation;
+                            let starred_senses = vocabulary_notebook
+                                .as_ref()
+                                .map(|notebook| notebook.show_word(&received_we, languages))
+                                .unwrap_or_default();
+                            // Counted like the starred senses.
+                            let context_sense = word_in_context
+                                .sense
+                                .and_then(|sense| {
+                                    context::senses(&received_we)
+                                        .iter()
+                                        .position(|(other, _, _)| *other == sense)
+                                })
+                                .map_or(-1, |index| index as i32);
                             let _ = main_window_weak_arc.upgrade_in_event_loop(move |handle| {
-                                let mut results: Vec<WordTransResult> = Vec::new();
-                                results.push(WordTransResult {
-                                    index: "".into(),
-                                    text: "WORD".into(),
-                                    type_: WordTransType::Header,
-                                });
-                                results.push(WordTransResult {
-                                    index: "".into(),
-                                    text: received_we.word.into(),
-                                    type_: WordTransType::Word,
-                                });
-                                if let Some(phonetics) = received_we.phonetics {
-                                    results.push(WordTransResult {
-                                        index: "".into(),
-                                        text: phonetics.join(", ").into(),
-                                        type_: WordTransType::Phonetic,
-                                    });
-                                }
-                                results.push(WordTransResult {
-                                    index: "".into(),
-                                    text: "EXPLANATION".into(),
-                                    type_: WordTransType::Header,
-                                });
-                                let mut index = 0;
-
-                                for part_of_speech in
-                                    received_we.part_of_speeches.unwrap_or_default()
-                                {
-                                    results.push(WordTransResult {
-                                        index: "".into(),
-                                        text: part_of_speech.part_of_speech.to_string().into(),
-                                        type_: WordTransType::PartOfSpeech,
-                                    });
-                                    for explanation in part_of_speech.explanations {
-                                        index += 1;
-                                        let mut text = String::new();
-                                        if let Some(phonetics) = explanation.phonetics {
-                                            text.push_str(&format!("{} ", phonetics.join(", ")));
-                                        }
-                                        if let Some(abbr) = explanation.abbreviation {
-                                            text.push_str(&format!("(abbr. {}) ", abbr));
-                                        }
-                                        if let Some(patterns) = explanation.patterns {
-                                            text.push_str(&format!("({})", patterns.join(", ")));
-                                        }
-                                        text.push_str(&format!("{} ", explanation.definition));
-                                        results.push(WordTransResult {
-                                            index: index.to_string().into(),
-                                            text: text.into(),
-                                            type_: WordTransType::Explanation,
-                                        });
-                                        results.push(WordTransResult {
-                                            index: "".into(),
-                                            text: explanation.explanation.into(),
-                                            type_: WordTransType::Explanation,
-                                        });
-                                        if let Some(examples) = explanation.examples {
-                                            for example in examples {
-                                                results.push(WordTransResult {
-                                                    index: "".into(),
-                                                    text: example.example.into(),
-                                                    type_: WordTransType::Example,
-                                                });
-                                                results.push(WordTransResult {
-                                                    index: "".into(),
-                                                    text: example.translation.into(),
-                                                    type_: WordTransType::ExampleTranslation,
-                                                });
-                                            }
-                                        }
-                                    }
-
-                                    if let Some(idioms) = part_of_speech.idioms {
-                                        results.push(WordTransResult {
-                                            index: "".into(),
-                                            text: "IDIOMS".into(),
-                                            type_: WordTransType::Header,
-                                        });
-
-                                        let mut index = 0;
-
-                                        for idiom in idioms {
-                                            index += 1;
-                                            results.push(WordTransResult {
-                                                index: index.to_string().into(),
-                                                text: idiom.idiom.into(),
-                                                type_: WordTransType::IdiomAndPhrase,
-                                            });
-                                            results.push(WordTransResult {
-                                                index: "".into(),
-                                                text: idiom.explanation.into(),
-                                                type_: WordTransType::Explanation,
-                                            });
-                                            results.push(WordTransResult {
-                                                index: "".into(),
-                                                text: idiom.definition.into(),
-                                                type_: WordTransType::Definition,
-                                            });
-                                            for example in idiom.example.unwrap_or_default() {
-                                                results.push(WordTransResult {
-                                                    index: "".into(),
-                                                    text: example.example.into(),
-                                                    type_: WordTransType::Example,
-                                                });
-                                                results.push(WordTransResult {
-                                                    index: "".into(),
-                                                    text: example.translation.into(),
-                                                    type_: WordTransType::ExampleTranslation,
-                                                });
-                                            }
-                                        }
-                                    }
-
-                                    if let Some(phrasal_verbs) = part_of_speech.phrasal_verbs {
-                                        results.push(WordTransResult {
-                                            index: "".into(),
-                                            text: "PHRASAL VERBS".into(),
-                                            type_: WordTransType::Header,
-                                        });
-
-                                        let mut index = 0;
-
-                                        for phrasal_verb in phrasal_verbs {
-                                            index += 1;
-                                            results.push(WordTransResult {
-                                                index: index.to_string().into(),
-                                                text: phrasal_verb.phrasal_verb.into(),
-                                                type_: WordTransType::IdiomAndPhrase,
-                                            });
-                                            results.push(WordTransResult {
-                                                index: "".into(),
-                                                text: phrasal_verb.explanation.into(),
-                                                type_: WordTransType::Explanation,
-                                            });
-                                            results.push(WordTransResult {
-                                                index: "".into(),
-                                                text: phrasal_verb.definition.into(),
-                                                type_: WordTransType::Definition,
-                                            });
-                                            for example in phrasal_verb.example.unwrap_or_default()
-                                            {
-                                                results.push(WordTransResult {
-                                                    index: "".into(),
-                                                    text: example.example.into(),
-                                                    type_: WordTransType::Example,
-                                                });
-                                                results.push(WordTransResult {
-                                                    index: "".into(),
-                                                    text: example.translation.into(),
-                                                    type_: WordTransType::ExampleTranslation,
-                                                });
-                                            }
-                                        }
-                                    }
-                                }
-
+                                handle.set_starred_senses(ModelRc::from(Rc::new(VecModel::from(
+                                    starred_senses,
+                                ))));
+                                handle.set_context_sense(context_sense);
+                                let results = word_trans_results(received_we);
                                 let vec_model_results =
                                     ModelRc::from(Rc::new(VecModel::from(results)));
                                 handle.set_word_trans_results(ModelRc::from(vec_model_results));
@@ -276,8 +349,76 @@ pub fn run() -> Result<(), slint::PlatformError> {
         }
     });
     // `Logic.translate_word` callback
+    // Offline dictionaries are indexed on the first lookup and kept open until
+    // the list of dictionaries changes.
+    let offline_translator_arc_mutex = Arc::new(Mutex::new(None));
+    // Explanations of lemmas, shared by all their inflected forms
+    let word_cache = match WordCache::open_default() {
+        Ok(word_cache) => Some(Arc::new(word_cache)),
+        Err(e) => {
+            log::error!("Failed to open word cache: {:#}", e);
+            None
+        }
+    };
+    // Failures of the engines of the fallback chains, kept across restarts
+    let engine_health = fallback::open_health();
+    // Words looked up before are known to the spelling check besides the bundled ones.
+    let spelling_suggester = Arc::new(SpellingSuggester::new());
+    let looked_up_words = [
+        translation_history
+            .as_ref()
+            .map(|translation_history| translation_history.looked_up_words()),
+        vocabulary_notebook
+            .as_ref()
+            .map(|vocabulary_notebook| vocabulary_notebook.words()),
+    ];
+    for words in looked_up_words.into_iter().flatten() {
+        match words {
+            Ok(words) => {
+                for (word, language) in words {
+                    spelling_suggester.add_word(&word, language);
+                }
+            }
+            Err(e) => log::warn!("Failed to load looked up words for spelling: {:#}", e),
+        }
+    }
+    // The word last answered with suggestions, looked up as it is if submitted again.
+    let misspelt_word_arc_mutex = Arc::new(Mutex::new(None::<String>));
+    // The sentence of the next word to look up, if it was selected in the Sentences tab.
+    let context_sentence_arc_mutex = Arc::new(Mutex::new(None::<String>));
+    main_window.global::<Logic>().on_look_up_in_context({
+        let main_window_weak_arc = main_window_weak_arc.clone();
+        let context_sentence_arc_mutex = context_sentence_arc_mutex.clone();
+        move |sentence| {
+            let Some(main_window) = main_window_weak_arc.upgrade() else {
+                return;
+            };
+            match selected_text().filter(|selected| sentence.contains(selected.as_str())) {
+                Some(word) => {
+                    *context_sentence_arc_mutex.lock().unwrap() = Some(sentence.to_string());
+                    main_window.invoke_translate_word_in_sentence(word.into());
+                }
+                None => {
+                    main_window.invoke_show_word_entry(slint::SharedString::new());
+                    main_window.set_word_trans_results(ModelRc::from(Rc::new(VecModel::from(
+                        vec![WordTransResult {
+                            index: "".into(),
+                            text: "Select a word of the original text to look it up in context"
+                                .into(),
+                            type_: WordTransType::Error,
+                        }],
+                    ))));
+                }
+            }
+        }
+    });
     main_window.global::<Logic>().on_translate_word({
         let wd_rx_arc_mutex = wd_rx_arc_mutex.clone();
+        let main_window_weak_arc = main_window_weak_arc.clone();
+        let translation_history = translation_history.clone();
+        let usage_meter = usage_meter.clone();
+        let prompts_arc_mutex = prompts_arc_mutex.clone();
+        let engine_health = engine_health.clone();
 
         let setting_window_weak_arc = setting_window_weak_arc.clone();
 
@@ -344,43 +485,147 @@ pub fn run() -> Result<(), slint::PlatformError> {
                 }
             };
 
-            let translator: Box<dyn WordTranslator + Send + Sync> = match model.as_str() {
-                "deepseek" => {
-                    let api_key = settings_from_slint.deepseek_api_key.to_string();
-                    log::info!("Got api_key from settings_from_slint: {}", api_key);
-                    let (tx, rx) = mpsc::channel();
-                    *wd_rx_arc_mutex.lock().unwrap() = rx;
-                    tx.send(Err(anyhow!("DeepSeek api is not supported yet!!")))
-                        .unwrap();
-                    return;
-                }
-                "youdao" => {
-                    let (tx, rx) = mpsc::channel();
-                    *wd_rx_arc_mutex.lock().unwrap() = rx;
-                    tx.send(Err(anyhow!("Youdao api is not supported yet!!")))
-                        .unwrap();
-                    return;
-                }
-                "qwen" => {
-                    let api_key = settings_from_slint.qwen_api_key.to_string();
-                    log::info!("Got api_key from settings_from_slint: {}", api_key);
-                    Box::new(QwenWordSentenceTranslator::new(api_key))
-                }
-                _ => {
-                    let (tx, rx) = mpsc::channel();
-                    *wd_rx_arc_mutex.lock().unwrap() = rx;
-                    tx.send(Err(anyhow!("Unknown AI api"))).unwrap();
-                    return;
-                }
-            };
-
             let (tx, rx) = mpsc::channel();
             *wd_rx_arc_mutex.lock().unwrap() = rx;
 
+            // Offer known spellings before sending an unknown word to the engine.
+            let text = text.trim().to_string();
+            let resubmitted = misspelt_word_arc_mutex.lock().unwrap().take() == Some(text.clone());
+            let sentence = context_sentence_arc_mutex.lock().unwrap().take();
+            // Words selected in a sentence are spelt as they were written.
+            let suggestions = if resubmitted || sentence.is_some() {
+                Vec::new()
+            } else {
+                spelling_suggester.suggest(&text, from_language, SPELLING_SUGGESTIONS)
+            };
+            if let Some(main_window) = main_window_weak_arc.upgrade() {
+                main_window.set_word_answered_by(slint::SharedString::new());
+                main_window.set_misspelt_word(text.clone().into());
+                main_window.set_spelling_suggestions(ModelRc::from(Rc::new(VecModel::from(
+                    suggestions
+                        .iter()
+                        .map(|suggestion| suggestion.into())
+                        .collect::<Vec<slint::SharedString>>(),
+                ))));
+                if !suggestions.is_empty() {
+                    main_window.set_word_trans_results(ModelRc::from(Rc::new(VecModel::from(
+                        vec![
+                            WordTransResult {
+                                index: "".into(),
+                                text: "WORD".into(),
+                                type_: WordTransType::Header,
+                            },
+                            WordTransResult {
+                                index: "".into(),
+                                text: format!("“{}” is not in the word list", text).into(),
+                                type_: WordTransType::Definition,
+                            },
+                        ],
+                    ))));
+                }
+            }
+            if !suggestions.is_empty() {
+                *misspelt_word_arc_mutex.lock().unwrap() = Some(text);
+                return;
+            }
+
+            let main_window_weak = (*main_window_weak_arc).clone();
+            let translation_history = translation_history.clone();
+            let offline_translator_arc_mutex = offline_translator_arc_mutex.clone();
+            let word_cache = word_cache.clone();
+            let spelling_suggester = spelling_suggester.clone();
+            let usage_meter = usage_meter.clone();
+            let prompts = prompts_arc_mutex.lock().unwrap().clone();
+            let engine_health = engine_health.clone();
             std::thread::spawn(move || {
                 let result =
-                    translator.translate_word(&text.to_string(), from_language, to_language);
-                if let Err(e) = tx.send(result) {
+                    fallback::word_translators(
+                        &model,
+                        &settings_from_slint,
+                        &prompts,
+                        &offline_translator_arc_mutex,
+                        usage_meter.as_deref(),
+                        engine_health.as_ref(),
+                    )
+                    .and_then(|engines| {
+                        let chosen = &engines[0].0;
+                        let look_up =
+                            |engine: &str, translator: &Arc<dyn WordTranslator + Send + Sync>| {
+                                // Offline dictionaries are fast enough and may be changed at any time.
+                                let cache = word_cache
+                                    .as_deref()
+                                    .filter(|_| engine != OFFLINE_ENGINE)
+                                    .map(|word_cache| (word_cache, engine));
+                                match &sentence {
+                                    Some(sentence) => backends::look_up_word_in_context(
+                                        translator.as_ref(),
+                                        cache,
+                                        &text,
+                                        sentence,
+                                        from_language,
+                                        to_language,
+                                    ),
+                                    None => backends::look_up_word(
+                                        translator.as_ref(),
+                                        cache,
+                                        &text,
+                                        from_language,
+                                        to_language,
+                                    )
+                                    .map(|word_explanation| WordInContext {
+                                        word_explanation,
+                                        sense: None,
+                                    }),
+                                }
+                            };
+                        // The engines of the chain are only asked when the chosen one fails.
+                        let (answered, result) =
+                            match engine_health.as_ref().filter(|_| engines.len() > 1) {
+                                Some(engine_health) => {
+                                    match fallback::failover(engine_health, &settings_from_slint)?
+                                        .run(&engines, look_up)
+                                    {
+                                        Ok((answered, word_in_context)) => {
+                                            (answered, Ok(word_in_context))
+                                        }
+                                        Err(e) => (0, Err(e)),
+                                    }
+                                }
+                                None => (0, look_up(chosen, &engines[0].1)),
+                            };
+                        let (engine, translator) = &engines[answered];
+                        if answered > 0 {
+                            let engine = engine.clone();
+                            let _ = main_window_weak.upgrade_in_event_loop(move |handle| {
+                                handle.set_word_answered_by(engine.into());
+                            });
+                        }
+                        if let Some(usage_meter) = &usage_meter {
+                            usage_meter.record(
+                                &engine,
+                                translator.last_token_usage(),
+                                main_window_weak.clone(),
+                            );
+                        }
+                        if let Ok(word_in_context) = &result {
+                            spelling_suggester
+                                .add_word(&word_in_context.word_explanation.word, from_language);
+                        }
+                        if let (Ok(word_in_context), Some(translation_history)) =
+                            (&result, &translation_history)
+                        {
+                            translation_history.record(
+                                &text,
+                                &TranslationOutput::Word(word_in_context.word_explanation.clone()),
+                                &engine,
+                                (from_language, to_language),
+                                translator.last_token_usage(),
+                                main_window_weak,
+                            );
+                        }
+                        result
+                    });
+                if let Err(e) = tx.send(result.map(|we| (we, (from_language, to_language)))) {
                     log::info!(
                         "Error sending message, maybe because Receiver is dropped: {}",
                         e
@@ -409,6 +654,7 @@ pub fn run() -> Result<(), slint::PlatformError> {
     // Save settings
     setting_window.global::<Logic>().on_save_settings({
         let setting_window_weak_arc = setting_window_weak_arc.clone();
+        let main_window_weak_arc = main_window_weak_arc.clone();
         move || {
             log::trace!("Save Settings");
             let settings_from_slint = setting_window_weak_arc
@@ -417,38 +663,103 @@ pub fn run() -> Result<(), slint::PlatformError> {
                 .unwrap()
                 .get_settings_from_slint();
 
-            let setting: backends::storage::Settings = backends::storage::Settings {
-                ai_accounts: {
-                    let deepseek_api_key = settings_from_slint.deepseek_api_key.to_string();
-                    let qwen_api_key = settings_from_slint.qwen_api_key.to_string();
-                    if deepseek_api_key.is_empty() && qwen_api_key.is_empty() {
-                        None
-                    } else {
-                        Some(backends::storage::AiAccounts {
-                            deepseek: {
-                                if !deepseek_api_key.is_empty() {
-                                    Some(backends::storage::DeepSeek {
-                                        api_key: deepseek_api_key,
-                                    })
-                                } else {
-                                    None
-                                }
-                            },
-                            qwen: {
-                                if !qwen_api_key.is_empty() {
-                                    Some(backends::storage::Qwen {
-                                        api_key: qwen_api_key,
-                                    })
-                                } else {
-                                    None
-                                }
-                            },
-                        })
-                    }
-                },
-                behaviour: None,
-                appearance: None,
+            let mut setting = profile_arc_mutex.lock().unwrap();
+            // Invalid numbers are reported when translating, the saved ones are kept.
+            match engine_parameters(&settings_from_slint) {
+                Ok(engine_parameters) => setting.engine_parameters = Some(engine_parameters),
+                Err(e) => log::error!("Failed to save engine parameters: {:#}", e),
+            }
+            match glossary::from_slint(&settings_from_slint) {
+                Ok(glossary) => {
+                    setting.glossary = (!glossary.entries.is_empty()).then_some(glossary)
+                }
+                Err(e) => log::error!("Failed to save glossary: {:#}", e),
+            }
+            let translation_memory_path = setting
+                .translation_memory
+                .as_ref()
+                .and_then(|translation_memory| translation_memory.path.clone());
+            match translation_memory::from_slint(
+                &settings_from_slint,
+                translation_memory_path.as_deref(),
+            ) {
+                Ok(translation_memory) => setting.translation_memory = Some(translation_memory),
+                Err(e) => log::error!("Failed to save translation memory settings: {:#}", e),
+            }
+            setting.ai_accounts = {
+                let deepseek_api_key = settings_from_slint.deepseek_api_key.to_string();
+                let qwen_api_key = settings_from_slint.qwen_api_key.to_string();
+                if deepseek_api_key.is_empty() && qwen_api_key.is_empty() {
+                    None
+                } else {
+                    Some(backends::storage::AiAccounts {
+                        deepseek: {
+                            if !deepseek_api_key.is_empty() {
+                                Some(backends::storage::DeepSeek {
+                                    api_key: deepseek_api_key,
+                                })
+                            } else {
+                                None
+                            }
+                        },
+                        qwen: {
+                            if !qwen_api_key.is_empty() {
+                                Some(backends::storage::Qwen {
+                                    api_key: qwen_api_key,
+                                })
+                            } else {
+                                None
+                            }
+                        },
+                    })
+                }
             };
+            let offline_dictionaries =
+                offline_dictionary_paths(&settings_from_slint.offline_dictionaries);
+            setting.offline_dictionaries = (!offline_dictionaries.is_empty()).then_some(
+                backends::storage::OfflineDictionaries {
+                    dictionaries: offline_dictionaries,
+                },
+            );
+            match local_mt::models_from_text(&settings_from_slint.local_models) {
+                Ok(models) => {
+                    setting.local_models =
+                        (!models.is_empty()).then_some(backends::storage::LocalModels { models })
+                }
+                Err(e) => log::error!("Failed to save local models: {:#}", e),
+            }
+            match fallback::from_slint(&settings_from_slint) {
+                Ok(fallback) => {
+                    setting.fallback = (!fallback.sentence.is_empty() || !fallback.word.is_empty())
+                        .then_some(fallback)
+                }
+                Err(e) => log::error!("Failed to save fallback chains: {:#}", e),
+            }
+            let behaviour = setting
+                .behaviour
+                .get_or_insert(backends::storage::Behaviour {
+                    auto_scroll: false,
+                    clipboard_watch: None,
+                    back_translation: None,
+                });
+            match back_translation::from_slint(&settings_from_slint) {
+                Ok(back_translation) => behaviour.back_translation = Some(back_translation),
+                Err(e) => log::error!("Failed to save back-translation settings: {:#}", e),
+            }
+            let clipboard_watch = behaviour
+                .clipboard_watch
+                .get_or_insert_with(Default::default);
+            clipboard_watch.enabled = settings_from_slint.clipboard_watch;
+            clipboard_watch.primary_selection = settings_from_slint.clipboard_primary_selection;
+
+            #[cfg(not(any(target_os = "android", target_os = "ios")))]
+            {
+                // Drop the running watcher before a new one is started.
+                let mut clipboard_watcher = clipboard_watcher_arc_mutex.lock().unwrap();
+                *clipboard_watcher = None;
+                *clipboard_watcher =
+                    watch_clipboard(clipboard_watch, (*main_window_weak_arc).clone());
+            }
 
             // write to disk
             if let Err(e) = backends::save_config(&setting) {
@@ -476,12 +787,23 @@ pub fn run() -> Result<(), slint::PlatformError> {
     // the user sends a new callback.
     let (_, rx) = mpsc::channel::<String>();
     let st_rx_arc_mutex = Arc::new(Mutex::new(rx));
+    // The thinking of reasoning models, replaced in the same way.
+    let (_, rx) = mpsc::channel::<String>();
+    let reasoning_rx_arc_mutex = Arc::new(Mutex::new(rx));
     std::thread::spawn({
         let main_window_weak_arc = main_window_weak_arc.clone();
         let st_rx_arc_mutex = st_rx_arc_mutex.clone();
+        let reasoning_rx_arc_mutex = reasoning_rx_arc_mutex.clone();
         move || {
             let mut received_flag: bool;
             loop {
+                if let Ok(rx) = reasoning_rx_arc_mutex.try_lock()
+                    && let Some(reasoning) = rx.try_iter().last()
+                {
+                    let _ = main_window_weak_arc.upgrade_in_event_loop(move |handle| {
+                        handle.set_sentence_reasoning(reasoning.into());
+                    });
+                }
                 if let Some(received_string) = match st_rx_arc_mutex.try_lock() {
                     Ok(rx) => {
                         log::trace!("Successfully acquired lock");
@@ -507,11 +829,35 @@ pub fn run() -> Result<(), slint::PlatformError> {
             }
         }
     });
+    // Translate pasted HTML and XLIFF keeping the tags
+    markup::connect(
+        &main_window,
+        setting_window.as_weak(),
+        st_rx_arc_mutex.clone(),
+        usage_meter.clone(),
+        prompts_arc_mutex.clone(),
+        documents.clone(),
+    );
+    // Sentence pairs of the translation, shown under the text and exported
+    let aligned_text = Arc::new(aligned::AlignedText::default());
+    aligned::connect(&main_window, aligned_text.clone());
     // Logic implementation
     main_window.global::<Logic>().on_translate_sentence({
         let rx_arc_mutex = st_rx_arc_mutex.clone();
+        let main_window_weak_arc = main_window_weak_arc.clone();
 
         move |text, from_language, to_language, model| {
+            // Drop the thinking of the previous sentence.
+            *reasoning_rx_arc_mutex.lock().unwrap() = mpsc::channel().1;
+            if let Some(main_window) = main_window_weak_arc.upgrade() {
+                main_window.set_sentence_reasoning(slint::SharedString::new());
+                main_window.set_sentence_reasoning_tokens(0);
+                main_window.set_glossary_violations(ModelRc::default());
+                main_window.set_sentence_progress(slint::SharedString::new());
+                aligned_text.clear(&main_window);
+                main_window.set_back_translation(slint::SharedString::new());
+                main_window.set_sentence_answered_by(slint::SharedString::new());
+            }
             // let api_key = main_window.get_api_key().to_string();
             let setting_window = setting_window_weak_arc.clone().upgrade().unwrap();
             let settings_from_slint = setting_window.get_settings_from_slint();
@@ -566,33 +912,32 @@ pub fn run() -> Result<(), slint::PlatformError> {
                 }
             };
 
-            let translator: Box<dyn StreamSentenceTranslator + Send + Sync> = match model.as_str() {
-                "deepseek" => {
-                    let api_key = settings_from_slint.deepseek_api_key.to_string();
-                    log::info!("Got api_key from settings_from_slint: {}", api_key);
-                    Box::new(backends::DeepSeekSentenceTranslator::new(api_key))
-                }
-                "youdao" => {
-                    let (tx, rx) = mpsc::channel();
-                    *rx_arc_mutex.lock().unwrap() = rx;
-                    tx.send("Youdao api is not supported yet!!".into()).unwrap();
-                    return;
-                }
-                "qwen" => {
-                    let api_key = settings_from_slint.qwen_api_key.to_string();
-                    log::info!("Got api_key from settings_from_slint: {}", api_key);
-                    let (tx, rx) = mpsc::channel();
-                    *rx_arc_mutex.lock().unwrap() = rx;
-                    tx.send("Qwen api is not supported yet!!".into()).unwrap();
-                    return;
-                }
-                _ => {
-                    let (tx, rx) = mpsc::channel();
-                    *rx_arc_mutex.lock().unwrap() = rx;
-                    tx.send("Unknown AI api".into()).unwrap();
-                    return;
-                }
-            };
+            let memory_lookup =
+                match translation_memory::lookup(&settings_from_slint, translation_memory.as_ref())
+                {
+                    Ok(memory_lookup) => memory_lookup,
+                    Err(e) => {
+                        let (tx, rx) = mpsc::channel();
+                        *rx_arc_mutex.lock().unwrap() = rx;
+                        tx.send(e.to_string()).unwrap();
+                        return;
+                    }
+                };
+            // Left to the fallback chain if the chosen engine cannot be used.
+            let translator = model
+                .parse::<AiProvider>()
+                .map_err(|_| anyhow!("Unknown AI api"))
+                .and_then(|engine| {
+                    if let Some(usage_meter) = &usage_meter {
+                        usage_meter.check(&engine.to_string())?;
+                    }
+                    sentence_translator(
+                        engine,
+                        &settings_from_slint,
+                        &prompts_arc_mutex.lock().unwrap(),
+                        memory_lookup.clone(),
+                    )
+                });
 
             if text == String::new() {
                 log::debug!("Detect empty string, skip translating.");
@@ -602,15 +947,236 @@ pub fn run() -> Result<(), slint::PlatformError> {
                 return;
             }
 
+            // Texts too long for one answer are translated in parts, by several
+            // translators at once if configured.
+            let chunks = match model.parse::<AiProvider>() {
+                Ok(engine) => document::chunks(
+                    &text,
+                    from_language,
+                    &documents,
+                    engine,
+                    &settings_from_slint,
+                ),
+                Err(_) => Vec::new(),
+            };
+
+            // A text translated in one part fails over to the engines of the chain.
+            let translator = match model.parse::<AiProvider>() {
+                Ok(engine) if chunks.len() <= 1 => fallback::fallback_sentence_translator(
+                    engine,
+                    translator,
+                    &settings_from_slint,
+                    &prompts_arc_mutex.lock().unwrap(),
+                    memory_lookup.clone(),
+                    usage_meter.as_deref(),
+                    engine_health.as_ref(),
+                ),
+                _ => translator.map(|translator| (translator, None)),
+            };
+            let (translator, fallback_translator) = match translator {
+                Ok(translator) => translator,
+                Err(e) => {
+                    let (tx, rx) = mpsc::channel();
+                    *rx_arc_mutex.lock().unwrap() = rx;
+                    tx.send(e.to_string()).unwrap();
+                    return;
+                }
+            };
+
+            let mut translators = vec![translator.clone()];
+            if chunks.len() > 1 {
+                log::info!("Translate {} parts", chunks.len());
+                for _ in 1..documents.parallel_requests {
+                    match model
+                        .parse::<AiProvider>()
+                        .map_err(Error::from)
+                        .and_then(|engine| {
+                            sentence_translator(
+                                engine,
+                                &settings_from_slint,
+                                &prompts_arc_mutex.lock().unwrap(),
+                                memory_lookup.clone(),
+                            )
+                        }) {
+                        Ok(translator) => translators.push(translator),
+                        Err(e) => {
+                            log::error!("Failed to create another translator: {:#}", e);
+                            break;
+                        }
+                    }
+                }
+            }
+
+            // A text translated in one part is checked by translating it back.
+            let back_translator = match model.parse::<AiProvider>() {
+                Ok(engine) if chunks.len() <= 1 => back_translation::back_translator(
+                    &settings_from_slint,
+                    engine,
+                    &prompts_arc_mutex.lock().unwrap(),
+                )
+                .unwrap_or_else(|e| {
+                    if let Some(main_window) = main_window_weak_arc.upgrade() {
+                        main_window.set_back_translation(
+                            format!("Failed to translate back: {}", e).into(),
+                        );
+                    }
+                    None
+                }),
+                _ => None,
+            };
+
             // update translation result with a spawned thread to avoid blocking the UI.
             std::thread::spawn({
                 let rx_arc_mutex = rx_arc_mutex.clone();
+                let reasoning_rx_arc_mutex = reasoning_rx_arc_mutex.clone();
+                let main_window_weak = (*main_window_weak_arc).clone();
+                // Checked in the translation, the glossary is known to be valid here.
+                let glossary = glossary::from_slint(&settings_from_slint).unwrap_or_default();
+                let translation_history = translation_history.clone();
+                let usage_meter = usage_meter.clone();
+                let aligned_text = aligned_text.clone();
+                let mut engine = model
+                    .parse::<AiProvider>()
+                    .map_or_else(|_| model.clone(), |engine| engine.to_string());
                 move || {
-                    let translate_result_rx = translator
-                        .stream_translate_sentence(&text, from_language, to_language)
-                        .unwrap();
+                    // Forward the partial results, keeping the last one for the history.
+                    let (tx, rx) = mpsc::channel();
+                    *rx_arc_mutex.lock().unwrap() = rx;
+                    let (translated, token_usage, complete) = if chunks.len() > 1 {
+                        match document::translate(
+                            translators,
+                            chunks,
+                            from_language,
+                            to_language,
+                            &tx,
+                            &engine,
+                            usage_meter.as_deref(),
+                            main_window_weak.clone(),
+                        ) {
+                            Some(translated) => translated,
+                            None => return,
+                        }
+                    } else {
+                        let (reasoning_tx, reasoning_rx) = mpsc::channel();
+                        *reasoning_rx_arc_mutex.lock().unwrap() = reasoning_rx;
+                        let stream = match translator.stream_translate_sentence_with_reasoning(
+                            &text,
+                            from_language,
+                            to_language,
+                        ) {
+                            Ok(stream) => stream,
+                            Err(e) => {
+                                log::error!("Failed to translate sentence: {:#}", e);
+                                let _ = tx.send(format!("Error: {}", e));
+                                return;
+                            }
+                        };
+                        if let Some(answered_by) = fallback_translator
+                            .as_ref()
+                            .and_then(|fallback_translator| fallback_translator.answered_by())
+                            .filter(|answered_by| *answered_by != engine)
+                        {
+                            engine.clone_from(&answered_by);
+                            let _ = main_window_weak.upgrade_in_event_loop(move |handle| {
+                                handle.set_sentence_answered_by(answered_by.into());
+                            });
+                        }
+                        std::thread::spawn(move || {
+                            for reasoning in stream.reasoning {
+                                if reasoning_tx.send(reasoning).is_err() {
+                                    break;
+                                }
+                            }
+                        });
+                        let mut translated = String::new();
+                        let mut replaced = false;
+                        for partial in stream.answer {
+                            translated.clone_from(&partial);
+                            if !replaced && tx.send(partial).is_err() {
+                                log::debug!("Another sentence is being translated, skip history.");
+                                // The rest of the output is still billed.
+                                replaced = true;
+                            }
+                        }
+                        let complete = matches!(stream.outcome.recv(), Ok(Ok(())));
+                        if let Some(usage_meter) = &usage_meter {
+                            usage_meter.record(
+                                &engine,
+                                translator.last_token_usage(),
+                                main_window_weak.clone(),
+                            );
+                        }
+                        if replaced {
+                            return;
+                        }
+                        if let Some(token_usage) = translator.last_token_usage() {
+                            let _ = main_window_weak.upgrade_in_event_loop(move |handle| {
+                                handle.set_sentence_reasoning_tokens(
+                                    token_usage.reasoning_tokens as i32,
+                                );
+                            });
+                        }
+                        (translated, translator.last_token_usage(), complete)
+                    };
+                    aligned_text.show(
+                        &text,
+                        &translated,
+                        from_language,
+                        to_language,
+                        main_window_weak.clone(),
+                    );
+                    if let Some(back_translator) = back_translator {
+                        let (text, translated) = (text.clone(), translated.clone());
+                        let usage_meter = usage_meter.clone();
+                        let main_window_weak = main_window_weak.clone();
+                        std::thread::spawn(move || {
+                            back_translation::show(
+                                back_translator,
+                                &text,
+                                &translated,
+                                from_language,
+                                to_language,
+                                usage_meter.as_deref(),
+                                main_window_weak,
+                            );
+                        });
+                    }
+                    let violations = glossary::violations(
+                        &glossary,
+                        &text,
+                        &translated,
+                        from_language,
+                        to_language,
+                    );
+                    if !violations.is_empty() {
+                        let _ = main_window_weak.upgrade_in_event_loop(move |handle| {
+                            handle.set_glossary_violations(ModelRc::from(Rc::new(VecModel::from(
+                                violations,
+                            ))));
+                        });
+                    }
 
-                    *rx_arc_mutex.lock().unwrap() = translate_result_rx;
+                    // Remembered translations report no usage. Answers cut off, ended
+                    // with an error or with parts left untranslated are not remembered.
+                    if token_usage.is_some() && complete {
+                        translation_memory::record(
+                            memory_lookup.as_ref(),
+                            &text,
+                            &translated,
+                            from_language,
+                            to_language,
+                        );
+                    }
+                    if let Some(translation_history) = translation_history {
+                        translation_history.record(
+                            &text,
+                            &TranslationOutput::Sentence(translated),
+                            &engine,
+                            (from_language, to_language),
+                            token_usage,
+                            main_window_weak,
+                        );
+                    }
                 }
             });
         }
@@ -618,3 +1184,522 @@ pub fn run() -> Result<(), slint::PlatformError> {
 
     main_window.run()
 }
+
+/// Text selected in any window, the clipboard where there is no selection.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+fn selected_text() -> Option<String> {
+    backends::clipboard::read_selection()
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+fn selected_text() -> Option<String> {
+    None
+}
+
+/// Start watching the clipboard if enabled, sending copied text to the matching tab.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+fn watch_clipboard(
+    config: &backends::storage::ClipboardWatch,
+    main_window_weak: slint::Weak<MainWindow>,
+) -> Option<ClipboardWatcher> {
+    if !config.enabled {
+        return None;
+    }
+    let (watcher, rx) = match ClipboardWatcher::spawn(config) {
+        Ok(spawned) => spawned,
+        Err(e) => {
+            log::error!("Failed to watch clipboard: {:#}", e);
+            return None;
+        }
+    };
+    log::info!("Watching clipboard");
+
+    std::thread::spawn(move || {
+        // Ends when the watcher is dropped.
+        for copied in rx {
+            let _ = main_window_weak.upgrade_in_event_loop(move |handle| match copied {
+                ClipboardText::Word(word) => handle.invoke_translate_word_text(word.into()),
+                ClipboardText::Sentence(sentence) => {
+                    handle.invoke_translate_sentence_text(sentence.into())
+                }
+            });
+        }
+    });
+
+    Some(watcher)
+}
+
+/// Create the word translator of `engine` with the API keys from the setting window.
+fn word_translator(
+    engine: AiProvider,
+    settings_from_slint: &Settings,
+    prompts: &storage::Prompts,
+) -> Result<Box<dyn WordTranslator + Send + Sync>, Error> {
+    match engine {
+        AiProvider::DeepSeek => Err(anyhow!("DeepSeek api is not supported yet!!")),
+        AiProvider::Youdao => Err(anyhow!("Youdao api is not supported yet!!")),
+        AiProvider::Local => Err(anyhow!("Local models only translate sentences")),
+        AiProvider::Qwen => {
+            let api_key = settings_from_slint.qwen_api_key.to_string();
+            log::info!("Got api_key from settings_from_slint: {}", api_key);
+            Ok(Box::new(
+                QwenWordSentenceTranslator::new(api_key)
+                    .with_prompts(prompts)
+                    .with_parameters(&engine_parameters(settings_from_slint)?.qwen),
+            ))
+        }
+    }
+}
+
+fn sentence_translator(
+    engine: AiProvider,
+    settings_from_slint: &Settings,
+    prompts: &storage::Prompts,
+    memory: Option<MemoryLookup>,
+) -> Result<Arc<dyn StreamSentenceTranslator + Send + Sync>, Error> {
+    match engine {
+        AiProvider::DeepSeek => {
+            let api_key = settings_from_slint.deepseek_api_key.to_string();
+            log::info!("Got api_key from settings_from_slint: {}", api_key);
+            Ok(Arc::new(
+                backends::DeepSeekSentenceTranslator::new(api_key)
+                    .with_prompts(prompts)
+                    .with_glossary(&glossary::from_slint(settings_from_slint)?)
+                    .with_translation_memory(memory)
+                    .with_parameters(&engine_parameters(settings_from_slint)?.deepseek),
+            ))
+        }
+        AiProvider::Youdao => Err(anyhow!("Youdao api is not supported yet!!")),
+        AiProvider::Qwen => {
+            let api_key = settings_from_slint.qwen_api_key.to_string();
+            log::info!("Got api_key from settings_from_slint: {}", api_key);
+            Ok(Arc::new(
+                QwenWordSentenceTranslator::new(api_key)
+                    .with_prompts(prompts)
+                    .with_glossary(&glossary::from_slint(settings_from_slint)?)
+                    .with_translation_memory(memory)
+                    .with_parameters(&engine_parameters(settings_from_slint)?.qwen),
+            ))
+        }
+        // Local models have no prompt, so the glossary and the memory are not used.
+        AiProvider::Local => Ok(Arc::new(LocalSentenceTranslator::new(
+            &storage::LocalModels {
+                models: local_mt::models_from_text(&settings_from_slint.local_models)?,
+            },
+        )?)),
+    }
+}
+
+/// Translator of the engine chosen in the Word tab, with the engine name recorded in
+/// history.
+fn word_tab_translator(
+    model: &str,
+    settings_from_slint: &Settings,
+    prompts: &storage::Prompts,
+    offline_translator: &Mutex<Option<Arc<OfflineDictWordTranslator>>>,
+) -> Result<(String, Arc<dyn WordTranslator + Send + Sync>), Error> {
+    if model.eq_ignore_ascii_case(OFFLINE_ENGINE) {
+        let translator = offline_word_translator(offline_translator, settings_from_slint)?;
+        return Ok((OFFLINE_ENGINE.to_string(), translator));
+    }
+    let engine = model
+        .parse::<AiProvider>()
+        .map_err(|_| anyhow!("Unknown AI api"))?;
+    let translator = word_translator(engine, settings_from_slint, prompts)?;
+    Ok((engine.to_string(), Arc::from(translator)))
+}
+
+/// Parameters of the AI engines entered in the setting window.
+fn engine_parameters(settings_from_slint: &Settings) -> Result<storage::EngineParameters, Error> {
+    Ok(storage::EngineParameters {
+        deepseek: parameters_from_slint(
+            "DeepSeek",
+            &settings_from_slint.deepseek_parameters,
+            storage::ModelParameters::deepseek_default(),
+        )?,
+        qwen: parameters_from_slint(
+            "Qwen",
+            &settings_from_slint.qwen_parameters,
+            storage::ModelParameters::qwen_default(),
+        )?,
+    })
+}
+
+/// Empty fields are unset, or the default for the model and the most tokens.
+fn parameters_from_slint(
+    engine: &str,
+    parameters: &EngineParameters,
+    default: storage::ModelParameters,
+) -> Result<storage::ModelParameters, Error> {
+    fn parse<T: std::str::FromStr>(engine: &str, name: &str, text: &str) -> Result<Option<T>> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(None);
+        }
+        text.parse()
+            .map(Some)
+            .map_err(|_| anyhow!("Invalid {} of {}: {}", name, engine, text))
+    }
+    let model = parameters.model.trim();
+    Ok(storage::ModelParameters {
+        model: match model.is_empty() {
+            true => default.model,
+            false => model.to_string(),
+        },
+        temperature: parse(engine, "temperature", &parameters.temperature)?,
+        top_p: parse(engine, "top p", &parameters.top_p)?,
+        max_tokens: parse(engine, "max tokens", &parameters.max_tokens)?
+            .unwrap_or(default.max_tokens),
+        seed: parse(engine, "seed", &parameters.seed)?,
+        enable_thinking: parameters.enable_thinking,
+        thinking_budget: parse(engine, "thinking budget", &parameters.thinking_budget)?,
+    })
+}
+
+fn parameters_to_slint(parameters: &storage::ModelParameters) -> EngineParameters {
+    let text = |value: Option<String>| value.unwrap_or_default().into();
+    EngineParameters {
+        model: parameters.model.as_str().into(),
+        temperature: text(parameters.temperature.map(|t| t.to_string())),
+        top_p: text(parameters.top_p.map(|p| p.to_string())),
+        max_tokens: parameters.max_tokens.to_string().into(),
+        seed: text(parameters.seed.map(|seed| seed.to_string())),
+        enable_thinking: parameters.enable_thinking,
+        thinking_budget: text(parameters.thinking_budget.map(|budget| budget.to_string())),
+    }
+}
+
+/// List the prompt presets in the Word and Sentences tabs, selecting the ones in use.
+fn show_prompt_presets(main_window: &MainWindow, prompts: &storage::Prompts) {
+    let (names, index) = preset_names(&prompts.word, &prompts.word_preset);
+    main_window.set_word_presets(names);
+    main_window.set_word_preset_index(index);
+    let (names, index) = preset_names(&prompts.sentence, &prompts.sentence_preset);
+    main_window.set_sentence_presets(names);
+    main_window.set_sentence_preset_index(index);
+}
+
+fn preset_names(
+    templates: &BTreeMap<String, PromptTemplate>,
+    chosen: &str,
+) -> (ModelRc<slint::SharedString>, i32) {
+    let names = templates
+        .keys()
+        .map(|name| name.into())
+        .collect::<Vec<slint::SharedString>>();
+    let index = templates
+        .keys()
+        .position(|name| name == chosen)
+        .unwrap_or(0);
+    (ModelRc::from(Rc::new(VecModel::from(names))), index as i32)
+}
+
+/// The offline dictionaries listed in the settings, reopened when the list changes.
+fn offline_word_translator(
+    offline_translator: &Mutex<Option<Arc<OfflineDictWordTranslator>>>,
+    settings_from_slint: &Settings,
+) -> Result<Arc<OfflineDictWordTranslator>, Error> {
+    let paths = offline_dictionary_paths(&settings_from_slint.offline_dictionaries);
+    let mut offline_translator = offline_translator.lock().unwrap();
+    if let Some(translator) = offline_translator.as_ref()
+        && translator.paths() == paths
+    {
+        return Ok(translator.clone());
+    }
+    let translator = Arc::new(OfflineDictWordTranslator::open(&paths)?);
+    *offline_translator = Some(translator.clone());
+    Ok(translator)
+}
+
+/// Paths entered in the setting window, one per line.
+fn offline_dictionary_paths(text: &str) -> Vec<PathBuf> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Flatten a `WordExplanation` into the rows rendered by `WordTransResultView`.
+fn word_trans_results(received_we: WordExplanation) -> Vec<WordTransResult> {
+    let mut results: Vec<WordTransResult> = Vec::new();
+    results.push(WordTransResult {
+        index: "".into(),
+        text: "WORD".into(),
+        type_: WordTransType::Header,
+    });
+    results.push(WordTransResult {
+        index: "".into(),
+        text: received_we.word.into(),
+        type_: WordTransType::Word,
+    });
+    if let Some(inflected_form) = received_we.inflected_form {
+        results.push(WordTransResult {
+            index: "".into(),
+            text: format!("Looked up from “{}”", inflected_form).into(),
+            type_: WordTransType::Definition,
+        });
+    }
+    if let Some(corrected_from) = received_we.corrected_from {
+        results.push(WordTransResult {
+            index: "".into(),
+            text: format!("Corrected from “{}”", corrected_from).into(),
+            type_: WordTransType::Definition,
+        });
+    }
+    if let Some(phonetics) = received_we.phonetics {
+        results.push(WordTransResult {
+            index: "".into(),
+            text: phonetics.join(", ").into(),
+            type_: WordTransType::Phonetic,
+        });
+    }
+    let level = [
+        received_we
+            .cefr_level
+            .map(|level| format!("CEFR {}", level)),
+        received_we
+            .frequency_band
+            .map(|band| format!("Frequency band {} of 5", band)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+    if !level.is_empty() {
+        results.push(WordTransResult {
+            index: "".into(),
+            text: level.join(" · ").into(),
+            type_: WordTransType::Level,
+        });
+    }
+    results.push(WordTransResult {
+        index: "".into(),
+        text: "EXPLANATION".into(),
+        type_: WordTransType::Header,
+    });
+    let mut index = 0;
+
+    // Show the dictionary entry as it is when it could not be split into senses.
+    if let Some(raw_definition) = received_we
+        .raw_definition
+        .filter(|_| received_we.part_of_speeches.is_none())
+    {
+        for line in raw_definition.to_plain_text().lines() {
+            results.push(WordTransResult {
+                index: "".into(),
+                text: line.into(),
+                type_: WordTransType::Explanation,
+            });
+        }
+        results.push(WordTransResult {
+            index: "".into(),
+            text: format!("From {}", raw_definition.dictionary).into(),
+            type_: WordTransType::Definition,
+        });
+    }
+
+    for part_of_speech in received_we.part_of_speeches.unwrap_or_default() {
+        results.push(WordTransResult {
+            index: "".into(),
+            text: part_of_speech.part_of_speech.to_string().into(),
+            type_: WordTransType::PartOfSpeech,
+        });
+        for explanation in part_of_speech.explanations {
+            index += 1;
+            let mut text = String::new();
+            if let Some(phonetics) = explanation.phonetics {
+                text.push_str(&format!("{} ", phonetics.join(", ")));
+            }
+            if let Some(abbr) = explanation.abbreviation {
+                text.push_str(&format!("(abbr. {}) ", abbr));
+            }
+            if let Some(register) = explanation.register {
+                text.push_str(&format!("[{}] ", register.join(", ")));
+            }
+            if let Some(patterns) = explanation.patterns {
+                text.push_str(&format!("({})", patterns.join(", ")));
+            }
+            // Senses from offline dictionaries have no separate definition.
+            if explanation.definition.is_empty() {
+                text.push_str(&explanation.explanation);
+                results.push(WordTransResult {
+                    index: index.to_string().into(),
+                    text: text.into(),
+                    type_: WordTransType::Explanation,
+                });
+            } else {
+                text.push_str(&format!("{} ", explanation.definition));
+                results.push(WordTransResult {
+                    index: index.to_string().into(),
+                    text: text.into(),
+                    type_: WordTransType::Explanation,
+                });
+                results.push(WordTransResult {
+                    index: "".into(),
+                    text: explanation.explanation.into(),
+                    type_: WordTransType::Explanation,
+                });
+            }
+            if let Some(examples) = explanation.examples {
+                for example in examples {
+                    results.push(WordTransResult {
+                        index: "".into(),
+                        text: example.example.into(),
+                        type_: WordTransType::Example,
+                    });
+                    results.push(WordTransResult {
+                        index: "".into(),
+                        text: example.translation.into(),
+                        type_: WordTransType::ExampleTranslation,
+                    });
+                }
+            }
+        }
+
+        if let Some(idioms) = part_of_speech.idioms {
+            results.push(WordTransResult {
+                index: "".into(),
+                text: "IDIOMS".into(),
+                type_: WordTransType::Header,
+            });
+
+            let mut index = 0;
+
+            for idiom in idioms {
+                index += 1;
+                results.push(WordTransResult {
+                    index: index.to_string().into(),
+                    text: idiom.idiom.into(),
+                    type_: WordTransType::IdiomAndPhrase,
+                });
+                results.push(WordTransResult {
+                    index: "".into(),
+                    text: idiom.explanation.into(),
+                    type_: WordTransType::Explanation,
+                });
+                results.push(WordTransResult {
+                    index: "".into(),
+                    text: idiom.definition.into(),
+                    type_: WordTransType::Definition,
+                });
+                for example in idiom.example.unwrap_or_default() {
+                    results.push(WordTransResult {
+                        index: "".into(),
+                        text: example.example.into(),
+                        type_: WordTransType::Example,
+                    });
+                    results.push(WordTransResult {
+                        index: "".into(),
+                        text: example.translation.into(),
+                        type_: WordTransType::ExampleTranslation,
+                    });
+                }
+            }
+        }
+
+        if let Some(phrasal_verbs) = part_of_speech.phrasal_verbs {
+            results.push(WordTransResult {
+                index: "".into(),
+                text: "PHRASAL VERBS".into(),
+                type_: WordTransType::Header,
+            });
+
+            let mut index = 0;
+
+            for phrasal_verb in phrasal_verbs {
+                index += 1;
+                results.push(WordTransResult {
+                    index: index.to_string().into(),
+                    text: phrasal_verb.phrasal_verb.into(),
+                    type_: WordTransType::IdiomAndPhrase,
+                });
+                results.push(WordTransResult {
+                    index: "".into(),
+                    text: phrasal_verb.explanation.into(),
+                    type_: WordTransType::Explanation,
+                });
+                results.push(WordTransResult {
+                    index: "".into(),
+                    text: phrasal_verb.definition.into(),
+                    type_: WordTransType::Definition,
+                });
+                for example in phrasal_verb.example.unwrap_or_default() {
+                    results.push(WordTransResult {
+                        index: "".into(),
+                        text: example.example.into(),
+                        type_: WordTransType::Example,
+                    });
+                    results.push(WordTransResult {
+                        index: "".into(),
+                        text: example.translation.into(),
+                        type_: WordTransType::ExampleTranslation,
+                    });
+                }
+            }
+        }
+    }
+
+    for (header, words) in [
+        ("SYNONYMS", received_we.synonyms),
+        ("ANTONYMS", received_we.antonyms),
+    ] {
+        if let Some(words) = words.filter(|words| !words.is_empty()) {
+            results.push(WordTransResult {
+                index: "".into(),
+                text: header.into(),
+                type_: WordTransType::Header,
+            });
+            results.push(WordTransResult {
+                index: "".into(),
+                text: words.join(", ").into(),
+                type_: WordTransType::RelatedWord,
+            });
+        }
+    }
+    if let Some(word_family) = received_we.word_family.filter(|words| !words.is_empty()) {
+        results.push(WordTransResult {
+            index: "".into(),
+            text: "WORD FAMILY".into(),
+            type_: WordTransType::Header,
+        });
+        for related_word in word_family {
+            results.push(WordTransResult {
+                index: "".into(),
+                text: format!(
+                    "{} ({}) {}",
+                    related_word.word, related_word.part_of_speech, related_word.explanation
+                )
+                .into(),
+                type_: WordTransType::RelatedWord,
+            });
+        }
+    }
+    if let Some(inflections) = received_we.inflections.filter(|forms| !forms.is_empty()) {
+        results.push(WordTransResult {
+            index: "".into(),
+            text: "FORMS".into(),
+            type_: WordTransType::Header,
+        });
+        for inflection in inflections {
+            results.push(WordTransResult {
+                index: "".into(),
+                text: format!("{}: {}", inflection.label, inflection.form).into(),
+                type_: WordTransType::RelatedWord,
+            });
+        }
+    }
+    if let Some(etymology) = received_we.etymology {
+        results.push(WordTransResult {
+            index: "".into(),
+            text: "ETYMOLOGY".into(),
+            type_: WordTransType::Header,
+        });
+        results.push(WordTransResult {
+            index: "".into(),
+            text: etymology.into(),
+            type_: WordTransType::Definition,
+        });
+    }
+
+    results
+}
@@ -0,0 +1,125 @@
+use std::{
+    path::PathBuf,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Error, Result, anyhow};
+use backends::{
+    Language,
+    alignment::{self, SegmentPair},
+    export,
+};
+use slint::{ComponentHandle, ModelRc, SharedString, VecModel};
+
+use super::{AlignedSegment, Logic, MainWindow};
+
+/// The sentence pairs of the last text translated in the Sentences tab, kept for
+/// exporting them.
+#[derive(Default)]
+pub(super) struct AlignedText {
+    shown: Mutex<Option<(Vec<SegmentPair>, Language, Language)>>,
+}
+impl AlignedText {
+    /// Pair the sentences of `text` and `translated`, and show them.
+    pub(super) fn show(
+        &self,
+        text: &str,
+        translated: &str,
+        source_language: Language,
+        target_language: Language,
+        main_window_weak: slint::Weak<MainWindow>,
+    ) {
+        let pairs = alignment::align(text, translated, source_language, target_language);
+        let segments = pairs
+            .iter()
+            .map(|pair| AlignedSegment {
+                source: pair.source.as_str().into(),
+                target: pair.target.as_str().into(),
+            })
+            .collect::<Vec<_>>();
+        *self.shown.lock().unwrap() = Some((pairs, source_language, target_language));
+        let _ = main_window_weak.upgrade_in_event_loop(move |handle| {
+            handle.set_aligned_segments(ModelRc::from(Rc::new(VecModel::from(segments))));
+            handle.set_aligned_status(SharedString::new());
+        });
+    }
+
+    /// Hide the pairs of an earlier translation.
+    pub(super) fn clear(&self, main_window: &MainWindow) {
+        *self.shown.lock().unwrap() = None;
+        main_window.set_aligned_segments(ModelRc::default());
+    }
+
+    fn export(&self, format: &str) -> Result<PathBuf, Error> {
+        let shown = self.shown.lock().unwrap();
+        let Some((pairs, source_language, target_language)) = shown.as_ref() else {
+            return Err(anyhow!("There is no translation to export"));
+        };
+        let (text, extension) = match format {
+            "Markdown" => (
+                alignment::to_markdown(pairs, *source_language, *target_language),
+                "md",
+            ),
+            "HTML" => (
+                alignment::to_html(pairs, *source_language, *target_language),
+                "html",
+            ),
+            _ => return Err(anyhow!("Unknown export format: {}", format)),
+        };
+        let file_stem = format!(
+            "neat-translator-aligned-{}",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        );
+        let path = export::default_export_path(&file_stem, extension);
+        std::fs::write(&path, text)
+            .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))?;
+        Ok(path)
+    }
+}
+
+/// Implement `Logic.copy_text` and `Logic.export_aligned` for the aligned view of the
+/// Sentences tab.
+pub(super) fn connect(main_window: &MainWindow, aligned_text: Arc<AlignedText>) {
+    main_window.global::<Logic>().on_copy_text({
+        let main_window_weak = main_window.as_weak();
+        move |text| {
+            let status = match copy(&text) {
+                Ok(()) => format!("Copied: {}", text),
+                Err(e) => {
+                    log::error!("Failed to copy the segment: {:#}", e);
+                    e.to_string()
+                }
+            };
+            if let Some(main_window) = main_window_weak.upgrade() {
+                main_window.set_aligned_status(status.into());
+            }
+        }
+    });
+
+    main_window.global::<Logic>().on_export_aligned({
+        let main_window_weak = main_window.as_weak();
+        move |format| {
+            let status = match aligned_text.export(&format) {
+                Ok(path) => format!("Exported to {}", path.display()),
+                Err(e) => {
+                    log::error!("Failed to export the aligned text: {:#}", e);
+                    format!("Export failed: {}", e)
+                }
+            };
+            if let Some(main_window) = main_window_weak.upgrade() {
+                main_window.set_aligned_status(status.into());
+            }
+        }
+    });
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+fn copy(text: &str) -> Result<(), Error> {
+    backends::clipboard::write_text(text)
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+fn copy(_text: &str) -> Result<(), Error> {
+    Err(anyhow!("Copying is not supported on this platform"))
+}
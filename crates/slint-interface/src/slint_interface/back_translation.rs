@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use anyhow::{Error, Result, anyhow};
+use backends::{
+    AiProvider, Language, StreamSentenceTranslator,
+    back_translation::{self, Streamed},
+    storage::{self, Prompts},
+};
+use slint::SharedString;
+
+use super::{MainWindow, Settings, sentence_translator, usage::UsageMeter};
+
+/// The back-translation settings shown in the setting window.
+pub(super) fn from_slint(
+    settings_from_slint: &Settings,
+) -> Result<storage::BackTranslation, Error> {
+    let engine = settings_from_slint.back_translation_engine.trim();
+    Ok(storage::BackTranslation {
+        enabled: settings_from_slint.back_translation,
+        engine: match engine.is_empty() {
+            true => None,
+            false => Some(
+                engine
+                    .parse::<AiProvider>()
+                    .map_err(|_| anyhow!("Unknown back-translation engine: {}", engine))?,
+            ),
+        },
+    })
+}
+
+/// The translator and engine translating back the sentences translated by `engine`,
+/// `None` if back-translation is disabled.
+pub(super) fn back_translator(
+    settings_from_slint: &Settings,
+    engine: AiProvider,
+    prompts: &Prompts,
+) -> Result<Option<(Arc<dyn StreamSentenceTranslator + Send + Sync>, AiProvider)>, Error> {
+    let config = from_slint(settings_from_slint)?;
+    if !config.enabled {
+        return Ok(None);
+    }
+    let engine = config.engine.unwrap_or(engine);
+    // The translation memory is left out: it holds translations from the source
+    // language, not back into it.
+    let translator = sentence_translator(engine, settings_from_slint, prompts, None)?;
+    Ok(Some((translator, engine)))
+}
+
+/// Translate `translated` back into the language of `text` and show it with its score,
+/// unless another translation is shown by then. Nothing is sent once the budget of
+/// `back_engine` is used up.
+pub(super) fn show(
+    (back_translator, back_engine): (Arc<dyn StreamSentenceTranslator + Send + Sync>, AiProvider),
+    text: &str,
+    translated: &str,
+    source_language: Language,
+    target_language: Language,
+    usage_meter: Option<&UsageMeter>,
+    main_window_weak: slint::Weak<MainWindow>,
+) {
+    let shown = SharedString::from(translated);
+    let _ = main_window_weak.upgrade_in_event_loop({
+        let shown = shown.clone();
+        move |handle| {
+            if handle.get_sentence_translate_result() == shown {
+                handle.set_back_translation("Translating back…".into());
+                handle.set_back_translation_score(-1);
+            }
+        }
+    });
+
+    let check = usage_meter
+        .map_or(Ok(()), |usage_meter| {
+            usage_meter.check(&back_engine.to_string())
+        })
+        .and_then(|()| {
+            back_translation::check(
+                &Streamed(back_translator.clone()),
+                text,
+                translated,
+                source_language,
+                target_language,
+            )
+        });
+    if let Some(usage_meter) = usage_meter {
+        usage_meter.record(
+            &back_engine.to_string(),
+            back_translator.last_token_usage(),
+            main_window_weak.clone(),
+        );
+    }
+    let (back_translation, score) = match check {
+        Ok(check) => (check.back_translation, check.score.round() as i32),
+        Err(e) => {
+            log::error!("Failed to translate back: {:#}", e);
+            (format!("Failed to translate back: {}", e), -1)
+        }
+    };
+    let _ = main_window_weak.upgrade_in_event_loop(move |handle| {
+        if handle.get_sentence_translate_result() == shown {
+            handle.set_back_translation(back_translation.into());
+            handle.set_back_translation_score(score);
+        }
+    });
+}
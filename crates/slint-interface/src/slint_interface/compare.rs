@@ -0,0 +1,257 @@
+use std::{
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use backends::{
+    AiProvider, Language, TokenUsage,
+    compare::{ComparisonEvent, compare_sentence},
+    history::TranslationOutput,
+    storage::Prompts,
+};
+use slint::{ComponentHandle, Model, ModelRc, VecModel};
+
+use super::{
+    ComparisonResult, Logic, MainWindow, SettingWindow, history::TranslationHistory,
+    sentence_translator, usage::UsageMeter,
+};
+
+/// Engines which stream sentences, compared when their API key is set.
+const COMPARED_ENGINES: [AiProvider; 2] = [AiProvider::DeepSeek, AiProvider::Qwen];
+
+/// The comparison shown in the Sentences tab.
+#[derive(Default)]
+struct Comparison {
+    /// Counts comparisons, so that events of an older one are ignored.
+    generation: u64,
+    sentence: String,
+    languages: Option<(Language, Language)>,
+    engines: Vec<AiProvider>,
+    /// Finished outputs by engine.
+    outputs: Vec<Option<(String, Option<TokenUsage>)>>,
+}
+
+/// Implement the `Logic` callbacks comparing engines in the Sentences tab.
+pub(super) fn connect(
+    main_window: &MainWindow,
+    setting_window_weak: slint::Weak<SettingWindow>,
+    translation_history: Option<Arc<TranslationHistory>>,
+    usage_meter: Option<Arc<UsageMeter>>,
+    prompts_arc_mutex: Arc<Mutex<Prompts>>,
+) {
+    let comparison = Arc::new(Mutex::new(Comparison::default()));
+
+    main_window.global::<Logic>().on_compare_sentence({
+        let main_window_weak = main_window.as_weak();
+        let comparison = comparison.clone();
+        move |text, from_language, to_language| {
+            let Some(main_window) = main_window_weak.upgrade() else {
+                return;
+            };
+            let (Ok(source_language), Ok(target_language)) = (
+                from_language.parse::<Language>(),
+                to_language.parse::<Language>(),
+            ) else {
+                log::error!(
+                    "Unsupported languages: {} to {}",
+                    from_language,
+                    to_language
+                );
+                return;
+            };
+            let settings_from_slint = match setting_window_weak.upgrade() {
+                Some(setting_window) => setting_window.get_settings_from_slint(),
+                None => return,
+            };
+            let engines = COMPARED_ENGINES
+                .into_iter()
+                .filter(|engine| match engine {
+                    AiProvider::DeepSeek => !settings_from_slint.deepseek_api_key.is_empty(),
+                    AiProvider::Qwen => !settings_from_slint.qwen_api_key.is_empty(),
+                    AiProvider::Youdao | AiProvider::Local => false,
+                })
+                .collect::<Vec<_>>();
+            let mut translators = Vec::new();
+            // Row of the engine of each translator, as engines which cannot be asked keep
+            // their row with the reason.
+            let mut translator_rows = Vec::new();
+            let mut rows = Vec::new();
+            for engine in &engines {
+                let translator = match &usage_meter {
+                    Some(usage_meter) => usage_meter.check(&engine.to_string()),
+                    None => Ok(()),
+                }
+                .and_then(|_| {
+                    sentence_translator(
+                        *engine,
+                        &settings_from_slint,
+                        &prompts_arc_mutex.lock().unwrap(),
+                        // Every engine is asked, even for remembered sentences.
+                        None,
+                    )
+                });
+                let status = match translator {
+                    Ok(translator) => {
+                        translators.push(translator);
+                        translator_rows.push(rows.len());
+                        "Translating…".to_string()
+                    }
+                    Err(e) => e.to_string(),
+                };
+                rows.push(ComparisonResult {
+                    engine: engine.to_string().into(),
+                    text: "".into(),
+                    status: status.into(),
+                    finished: false,
+                    picked: false,
+                });
+            }
+            if rows.is_empty() {
+                rows.push(ComparisonResult {
+                    engine: "No engine".into(),
+                    text: "".into(),
+                    status: "Set an API key of DeepSeek or Qwen to compare engines".into(),
+                    finished: false,
+                    picked: false,
+                });
+            }
+            main_window.set_comparison_results(ModelRc::from(Rc::new(VecModel::from(rows))));
+            if translators.is_empty() || text.is_empty() {
+                return;
+            }
+
+            let compared_engines = engines.clone();
+            let generation = {
+                let mut comparison = comparison.lock().unwrap();
+                *comparison = Comparison {
+                    generation: comparison.generation + 1,
+                    sentence: text.to_string(),
+                    languages: Some((source_language, target_language)),
+                    outputs: vec![None; engines.len()],
+                    engines,
+                };
+                comparison.generation
+            };
+            let events = compare_sentence(translators, &text, source_language, target_language);
+            std::thread::spawn({
+                let main_window_weak = main_window_weak.clone();
+                let comparison = comparison.clone();
+                let usage_meter = usage_meter.clone();
+                move || {
+                    for event in events {
+                        let row = translator_rows[event.engine()];
+                        if let (
+                            Some(usage_meter),
+                            ComparisonEvent::Finished { token_usage, .. }
+                            | ComparisonEvent::Failed { token_usage, .. },
+                        ) = (&usage_meter, &event)
+                        {
+                            // Billed even if a newer comparison has been started.
+                            usage_meter.record(
+                                &compared_engines[row].to_string(),
+                                *token_usage,
+                                main_window_weak.clone(),
+                            );
+                        }
+                        {
+                            let mut comparison = comparison.lock().unwrap();
+                            if comparison.generation != generation {
+                                continue;
+                            }
+                            if let ComparisonEvent::Finished {
+                                text, token_usage, ..
+                            } = &event
+                            {
+                                comparison.outputs[row] = Some((text.clone(), *token_usage));
+                            }
+                        }
+                        let _ = main_window_weak.upgrade_in_event_loop(move |main_window| {
+                            let results = main_window.get_comparison_results();
+                            if let Some(mut row_data) = results.row_data(row) {
+                                update_row(&mut row_data, event);
+                                results.set_row_data(row, row_data);
+                            }
+                        });
+                    }
+                }
+            });
+        }
+    });
+
+    main_window.global::<Logic>().on_pick_comparison({
+        let main_window_weak = main_window.as_weak();
+        move |index| {
+            let Some(main_window) = main_window_weak.upgrade() else {
+                return;
+            };
+            let index = index as usize;
+            let comparison = comparison.lock().unwrap();
+            let (Some(Some((text, token_usage))), Some(engine), Some(languages)) = (
+                comparison.outputs.get(index),
+                comparison.engines.get(index),
+                comparison.languages,
+            ) else {
+                return;
+            };
+            if let Some(translation_history) = &translation_history {
+                translation_history.record(
+                    &comparison.sentence,
+                    &TranslationOutput::Sentence(text.clone()),
+                    &engine.to_string(),
+                    languages,
+                    *token_usage,
+                    main_window_weak.clone(),
+                );
+            }
+            main_window.set_sentence_translate_result(text.into());
+            main_window.set_aligned_segments(Default::default());
+            main_window.set_back_translation(slint::SharedString::new());
+            let results = main_window.get_comparison_results();
+            for row_index in 0..results.row_count() {
+                if let Some(mut row) = results.row_data(row_index) {
+                    row.picked = row_index == index;
+                    results.set_row_data(row_index, row);
+                }
+            }
+        }
+    });
+}
+
+fn update_row(row: &mut ComparisonResult, event: ComparisonEvent) {
+    match event {
+        ComparisonEvent::Partial { text, .. } => row.text = text.into(),
+        ComparisonEvent::Finished {
+            text,
+            latency,
+            token_usage,
+            ..
+        } => {
+            row.text = text.into();
+            row.status = match token_usage {
+                Some(usage) => format!(
+                    "{} · {} in / {} out tokens",
+                    seconds(latency),
+                    usage.input_tokens,
+                    usage.output_tokens
+                ),
+                None => seconds(latency),
+            }
+            .into();
+            row.finished = true;
+        }
+        ComparisonEvent::Failed {
+            text,
+            error,
+            latency,
+            ..
+        } => {
+            row.text = text.into();
+            row.status = format!("Failed after {}: {}", seconds(latency), error).into();
+        }
+    }
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{:.1} s", duration.as_secs_f64())
+}
@@ -0,0 +1,107 @@
+use std::sync::{Arc, mpsc::Sender};
+
+use backends::{
+    AiProvider, Language, StreamSentenceTranslator, TokenUsage,
+    document::{self, Chunk, DocumentEvent},
+    storage,
+};
+
+use super::{MainWindow, Settings, engine_parameters, usage::UsageMeter};
+
+/// Chunks of a text in the Sentences tab.
+pub(super) fn chunks(
+    text: &str,
+    language: Language,
+    config: &storage::Documents,
+    engine: AiProvider,
+    settings_from_slint: &Settings,
+) -> Vec<Chunk> {
+    let config = storage::Documents {
+        chunk_tokens: chunk_tokens(config, engine, settings_from_slint),
+        ..config.clone()
+    };
+    document::chunks(text, language, &config)
+}
+
+/// Most tokens of source in one request. The source is kept under a third of the
+/// most tokens of an answer, which leaves room for longer translations.
+pub(super) fn chunk_tokens(
+    config: &storage::Documents,
+    engine: AiProvider,
+    settings_from_slint: &Settings,
+) -> usize {
+    let max_tokens = engine_parameters(settings_from_slint)
+        .map(|parameters| match engine {
+            AiProvider::DeepSeek => parameters.deepseek.max_tokens,
+            // Local models translate a sentence at a time.
+            AiProvider::Local => u32::MAX,
+            _ => parameters.qwen.max_tokens,
+        })
+        .unwrap_or(u32::MAX);
+    config.chunk_tokens.min(max_tokens as usize / 3)
+}
+
+/// Translate `chunks`, sending the translation so far to `tx` and showing the progress
+/// in the Sentences tab. Returns the translation, the usage of all chunks and whether
+/// every chunk was translated, or `None` once another text is translated.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn translate(
+    translators: Vec<Arc<dyn StreamSentenceTranslator + Send + Sync>>,
+    chunks: Vec<Chunk>,
+    source_language: Language,
+    target_language: Language,
+    tx: &Sender<String>,
+    engine: &str,
+    usage_meter: Option<&UsageMeter>,
+    main_window_weak: slint::Weak<MainWindow>,
+) -> Option<(String, Option<TokenUsage>, bool)> {
+    let mut translated = String::new();
+    let mut token_usage: Option<TokenUsage> = None;
+    let mut failures = Vec::new();
+    let events =
+        document::translate_document(translators, chunks, source_language, target_language);
+    for event in events {
+        match event {
+            DocumentEvent::Progress {
+                text,
+                finished_chunks,
+                total_chunks,
+            } => {
+                if tx.send(text.clone()).is_err() {
+                    log::debug!("Another text is being translated, stop translating parts.");
+                    return None;
+                }
+                translated = text;
+                let mut progress =
+                    format!("Translated {} of {} parts", finished_chunks, total_chunks);
+                for failure in &failures {
+                    progress += &format!("\n{}", failure);
+                }
+                let _ = main_window_weak.upgrade_in_event_loop(move |handle| {
+                    handle.set_sentence_progress(progress.into());
+                });
+            }
+            DocumentEvent::ChunkFinished {
+                chunk,
+                token_usage: chunk_usage,
+                error,
+            } => {
+                if let Some(usage_meter) = usage_meter {
+                    usage_meter.record(engine, chunk_usage, main_window_weak.clone());
+                }
+                if let Some(chunk_usage) = chunk_usage {
+                    token_usage = Some(token_usage.unwrap_or_default() + chunk_usage);
+                }
+                if let Some(error) = error {
+                    log::error!("Failed to translate part {}: {}", chunk + 1, error);
+                    failures.push(format!(
+                        "Part {} is left untranslated: {}",
+                        chunk + 1,
+                        error
+                    ));
+                }
+            }
+        }
+    }
+    Some((translated, token_usage, failures.is_empty()))
+}
@@ -0,0 +1,76 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::{Error, Result, anyhow};
+use backends::export::{self, Flashcard, TableFormat};
+use slint::ComponentHandle;
+
+use super::{Logic, MainWindow, history::TranslationHistory, notebook::Notebook};
+
+/// Implement `Logic.export_words`, which exports the notebook or the words in the
+/// history to the documents directory.
+pub(super) fn connect(
+    main_window: &MainWindow,
+    translation_history: Option<Arc<TranslationHistory>>,
+    vocabulary_notebook: Option<Arc<Notebook>>,
+) {
+    main_window.global::<Logic>().on_export_words({
+        let main_window_weak = main_window.as_weak();
+        move |source, format| {
+            let flashcards = match source.as_str() {
+                "notebook" => vocabulary_notebook
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("The vocabulary notebook is not available"))
+                    .and_then(|notebook| notebook.flashcards()),
+                "history" => translation_history
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("The translation history is disabled"))
+                    .and_then(|history| history.word_flashcards()),
+                _ => Err(anyhow!("Unknown export source: {}", source)),
+            };
+            let status = match flashcards
+                .and_then(|flashcards| export_flashcards(&flashcards, &source, &format))
+            {
+                Ok((count, path)) => {
+                    format!("Exported {} cards to {}", count, path.display())
+                }
+                Err(e) => {
+                    log::error!("Failed to export words: {:#}", e);
+                    format!("Export failed: {}", e)
+                }
+            };
+            if let Some(main_window) = main_window_weak.upgrade() {
+                main_window.set_export_status(status.into());
+            }
+        }
+    });
+}
+
+fn export_flashcards(
+    flashcards: &[Flashcard],
+    source: &str,
+    format: &str,
+) -> Result<(usize, PathBuf), Error> {
+    if flashcards.is_empty() {
+        return Err(anyhow!("There are no words to export"));
+    }
+    let file_stem = format!(
+        "neat-translator-{}-{}",
+        source,
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    );
+    let path = if format == "Anki" {
+        let path = export::default_export_path(&file_stem, "apkg");
+        export::write_apkg(flashcards, "Neat Translator", &path)?;
+        path
+    } else {
+        let format = format
+            .parse::<TableFormat>()
+            .map_err(|_| anyhow!("Unknown export format: {}", format))?;
+        let path = export::default_export_path(&file_stem, format.extension());
+        let file = std::fs::File::create(&path)
+            .map_err(|e| anyhow!("Failed to create {}: {}", path.display(), e))?;
+        export::write_table(flashcards, format, file)?;
+        path
+    };
+    Ok((flashcards.len(), path))
+}
@@ -0,0 +1,195 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Error, Result, anyhow};
+use backends::{
+    AiProvider, StreamSentenceTranslator, WordTranslator,
+    failover::{self, EngineHealth, Failover, FailureKind, FallbackSentenceTranslator},
+    offline_dict::OfflineDictWordTranslator,
+    storage::{self, Prompts},
+    translation_memory::MemoryLookup,
+};
+
+use super::{
+    OFFLINE_ENGINE, Settings, sentence_translator, usage::UsageMeter, word_tab_translator,
+};
+
+/// Open the health of the engines kept across restarts, `None` if it is unavailable,
+/// which leaves the chains unused.
+pub(super) fn open_health() -> Option<Arc<EngineHealth>> {
+    match EngineHealth::open_default() {
+        Ok(engine_health) => Some(Arc::new(engine_health)),
+        Err(e) => {
+            log::error!("Failed to open engine health: {:#}", e);
+            None
+        }
+    }
+}
+
+/// The fallback settings shown in the setting window.
+pub(super) fn from_slint(settings_from_slint: &Settings) -> Result<storage::Fallback, Error> {
+    let default = storage::Fallback::default();
+    let cooldown = settings_from_slint.fallback_cooldown.trim();
+    let fail_over_on = names(&settings_from_slint.fallback_on)
+        .map(|failure| {
+            failure
+                .parse::<FailureKind>()
+                .map_err(|_| anyhow!("Unknown failure to fail over on: {}", failure))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(storage::Fallback {
+        sentence: names(&settings_from_slint.fallback_sentence)
+            .map(|engine| {
+                engine
+                    .parse::<AiProvider>()
+                    .map_err(|_| anyhow!("Unknown sentence fallback engine: {}", engine))
+            })
+            .collect::<Result<_>>()?,
+        word: names(&settings_from_slint.fallback_word)
+            .map(|engine| match engine.eq_ignore_ascii_case(OFFLINE_ENGINE) {
+                true => Ok(OFFLINE_ENGINE.to_string()),
+                false => engine
+                    .parse::<AiProvider>()
+                    .map(|engine| engine.to_string())
+                    .map_err(|_| anyhow!("Unknown word fallback engine: {}", engine)),
+            })
+            .collect::<Result<_>>()?,
+        fail_over_on: match fail_over_on.is_empty() {
+            true => default.fail_over_on,
+            false => fail_over_on,
+        },
+        cooldown_secs: match cooldown.is_empty() {
+            true => default.cooldown_secs,
+            false => cooldown
+                .parse()
+                .map_err(|_| anyhow!("Invalid cooldown of failed engines: {}", cooldown))?,
+        },
+    })
+}
+
+/// The settings as shown in the setting window: the chains, the failures and the
+/// cooldown.
+pub(super) fn to_slint(config: &storage::Fallback) -> (String, String, String, String) {
+    let join = |names: Vec<String>| names.join(", ");
+    (
+        join(
+            config
+                .sentence
+                .iter()
+                .map(|engine| engine.to_string())
+                .collect(),
+        ),
+        join(config.word.clone()),
+        join(
+            config
+                .fail_over_on
+                .iter()
+                .map(|failure| failure.to_string())
+                .collect(),
+        ),
+        config.cooldown_secs.to_string(),
+    )
+}
+
+/// Names separated by commas.
+fn names(text: &str) -> impl Iterator<Item = &str> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+}
+
+/// How the chains of the setting window fail over.
+pub(super) fn failover(
+    engine_health: &Arc<EngineHealth>,
+    settings_from_slint: &Settings,
+) -> Result<Failover, Error> {
+    Ok(Failover::new(
+        engine_health.clone(),
+        &from_slint(settings_from_slint)?,
+    ))
+}
+
+/// The `translator` of `engine` followed by the sentence fallback chain, and the chain
+/// to tell which engine answered. Without a chain, the translator is used alone. Engines
+/// which cannot be set up or whose budget is used up, the chosen one included, are left
+/// out.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn fallback_sentence_translator(
+    engine: AiProvider,
+    translator: Result<Arc<dyn StreamSentenceTranslator + Send + Sync>, Error>,
+    settings_from_slint: &Settings,
+    prompts: &Prompts,
+    memory: Option<MemoryLookup>,
+    usage_meter: Option<&UsageMeter>,
+    engine_health: Option<&Arc<EngineHealth>>,
+) -> Result<
+    (
+        Arc<dyn StreamSentenceTranslator + Send + Sync>,
+        Option<Arc<FallbackSentenceTranslator>>,
+    ),
+    Error,
+> {
+    let config = from_slint(settings_from_slint)?;
+    let Some(engine_health) = engine_health.filter(|_| !config.sentence.is_empty()) else {
+        return Ok((translator?, None));
+    };
+    let mut engines = vec![(engine.to_string(), translator)];
+    for fallback in &config.sentence {
+        let name = fallback.to_string();
+        if engines.iter().any(|(engine, _)| *engine == name) {
+            continue;
+        }
+        let translator = usage_meter
+            .map_or(Ok(()), |usage_meter| usage_meter.check(&name))
+            .and_then(|()| {
+                sentence_translator(*fallback, settings_from_slint, prompts, memory.clone())
+            });
+        engines.push((name, translator));
+    }
+    let fallback_translator = Arc::new(FallbackSentenceTranslator::new(
+        failover::available(engines)?,
+        Failover::new(engine_health.clone(), &config),
+    ));
+    Ok((fallback_translator.clone(), Some(fallback_translator)))
+}
+
+/// The translator of the engine chosen in the Word tab followed by those of the word
+/// fallback chain. Without a chain, the chosen one is used alone. Engines which cannot
+/// be set up or whose budget is used up, the chosen one included, are left out.
+pub(super) fn word_translators(
+    model: &str,
+    settings_from_slint: &Settings,
+    prompts: &Prompts,
+    offline_translator: &Mutex<Option<Arc<OfflineDictWordTranslator>>>,
+    usage_meter: Option<&UsageMeter>,
+    engine_health: Option<&Arc<EngineHealth>>,
+) -> Result<Vec<(String, Arc<dyn WordTranslator + Send + Sync>)>, Error> {
+    let set_up = |model: &str| {
+        let (engine, translator) =
+            word_tab_translator(model, settings_from_slint, prompts, offline_translator)?;
+        if let Some(usage_meter) = usage_meter.filter(|_| engine != OFFLINE_ENGINE) {
+            usage_meter.check(&engine)?;
+        }
+        Ok((engine, translator))
+    };
+    let chain = from_slint(settings_from_slint)?.word;
+    if engine_health.is_none() || chain.is_empty() {
+        return Ok(vec![set_up(model)?]);
+    }
+    let mut names = vec![model.to_string()];
+    for fallback in chain {
+        if !names
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(&fallback))
+        {
+            names.push(fallback);
+        }
+    }
+    let engines = names.into_iter().map(|name| {
+        let engine = set_up(&name);
+        (name, engine)
+    });
+    Ok(failover::available(engines)?
+        .into_iter()
+        .map(|(_, engine)| engine)
+        .collect())
+}
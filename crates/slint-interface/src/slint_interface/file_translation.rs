@@ -0,0 +1,194 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Error, Result, anyhow};
+use backends::{
+    AiProvider, Language, StreamSentenceTranslator, file_translation,
+    storage::Prompts,
+    translation_memory::{MemoryLookup, TranslationMemory},
+};
+use slint::ComponentHandle;
+
+use super::{
+    Logic, MainWindow, SettingWindow, Settings, sentence_translator, translation_memory,
+    usage::UsageMeter,
+};
+
+/// Implement `Logic.pick_file` and `Logic.translate_file` of the Sentences tab, which
+/// translate a file into a copy next to it.
+pub(super) fn connect(
+    main_window: &MainWindow,
+    setting_window_weak: slint::Weak<SettingWindow>,
+    usage_meter: Option<Arc<UsageMeter>>,
+    prompts_arc_mutex: Arc<Mutex<Prompts>>,
+    memory: Option<Arc<TranslationMemory>>,
+) {
+    main_window.global::<Logic>().on_pick_file(|current| {
+        pick_file()
+            .map(|path| path.display().to_string().into())
+            .unwrap_or(current)
+    });
+
+    main_window.global::<Logic>().on_translate_file({
+        let main_window_weak = main_window.as_weak();
+        move |path, from_language, to_language, model| {
+            let path = PathBuf::from(path.trim());
+            let engine = model.to_string();
+            let translator = setting_window_weak
+                .upgrade()
+                .ok_or_else(|| anyhow!("The setting window is closed"))
+                .and_then(|setting_window| {
+                    file_translation::FileFormat::from_path(&path)?;
+                    let settings_from_slint = setting_window.get_settings_from_slint();
+                    tab_translator(
+                        &from_language,
+                        &to_language,
+                        &engine,
+                        &settings_from_slint,
+                        usage_meter.as_deref(),
+                        &prompts_arc_mutex.lock().unwrap(),
+                        translation_memory::lookup(&settings_from_slint, memory.as_ref())?,
+                    )
+                });
+            let (translator, engine, source_language, target_language) = match translator {
+                Ok(translator) => translator,
+                Err(e) => {
+                    show_status(&main_window_weak, format!("Error: {:#}", e));
+                    return;
+                }
+            };
+
+            show_status(&main_window_weak, format!("Translating {}", path.display()));
+            std::thread::spawn({
+                let main_window_weak = main_window_weak.clone();
+                let usage_meter = usage_meter.clone();
+                move || {
+                    let result = file_translation::translate_file(
+                        &path,
+                        None,
+                        source_language,
+                        target_language,
+                        |segment| {
+                            if let Some(usage_meter) = &usage_meter {
+                                usage_meter.check(&engine.to_string())?;
+                            }
+                            let translation = file_translation::translate_segment(
+                                translator.as_ref(),
+                                segment,
+                                source_language,
+                                target_language,
+                            );
+                            if let Some(usage_meter) = &usage_meter {
+                                usage_meter.record(
+                                    &engine.to_string(),
+                                    translator.last_token_usage(),
+                                    main_window_weak.clone(),
+                                );
+                            }
+                            translation
+                        },
+                        |done, total| {
+                            show_status(
+                                &main_window_weak,
+                                format!("Translated {} of {} segments", done, total),
+                            );
+                        },
+                    );
+                    show_status(&main_window_weak, status(&path, result));
+                }
+            });
+        }
+    });
+}
+
+/// The translator of `engine`, the engine and the languages of a translation started
+/// in the Sentences tab.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn tab_translator(
+    from_language: &str,
+    to_language: &str,
+    engine: &str,
+    settings_from_slint: &Settings,
+    usage_meter: Option<&UsageMeter>,
+    prompts: &Prompts,
+    memory: Option<MemoryLookup>,
+) -> Result<
+    (
+        Arc<dyn StreamSentenceTranslator + Send + Sync>,
+        AiProvider,
+        Language,
+        Language,
+    ),
+    Error,
+> {
+    let (Ok(source_language), Ok(target_language)) = (
+        from_language.parse::<Language>(),
+        to_language.parse::<Language>(),
+    ) else {
+        return Err(anyhow!(
+            "Unsupported languages: {} to {}",
+            from_language,
+            to_language
+        ));
+    };
+    let engine = engine
+        .parse::<AiProvider>()
+        .map_err(|_| anyhow!("Unknown AI api"))?;
+    if let Some(usage_meter) = usage_meter {
+        usage_meter.check(&engine.to_string())?;
+    }
+    let translator = sentence_translator(engine, settings_from_slint, prompts, memory)?;
+    Ok((translator, engine, source_language, target_language))
+}
+
+fn status(path: &Path, result: Result<(PathBuf, file_translation::Summary), Error>) -> String {
+    match result {
+        Ok((output, summary)) => {
+            let mut status = format!("Saved to {}", output.display());
+            if summary.untranslated > 0 {
+                status += &format!(
+                    ", {} segments kept in the source language as their code or markup was lost",
+                    summary.untranslated
+                );
+            }
+            if let Some(failure) = summary.failures.first() {
+                status += &format!(
+                    ", {} segments kept in the source language as they failed: {}",
+                    summary.failures.len(),
+                    failure
+                );
+            }
+            status
+        }
+        Err(e) => {
+            log::error!("Failed to translate {}: {:#}", path.display(), e);
+            format!("Error: {:#}", e)
+        }
+    }
+}
+
+fn show_status(main_window_weak: &slint::Weak<MainWindow>, status: String) {
+    let _ = main_window_weak.upgrade_in_event_loop(move |handle| {
+        handle.set_file_translation_status(status.into());
+    });
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+fn pick_file() -> Option<PathBuf> {
+    rfd::FileDialog::new()
+        .add_filter(
+            "Text, Markdown, subtitle, PO, HTML and XLIFF files",
+            &[
+                "txt", "md", "markdown", "srt", "vtt", "po", "pot", "html", "htm", "xlf", "xliff",
+            ],
+        )
+        .pick_file()
+}
+
+/// There is no file dialog on mobile, the path is entered by hand.
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+fn pick_file() -> Option<PathBuf> {
+    None
+}
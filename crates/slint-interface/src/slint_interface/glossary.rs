@@ -0,0 +1,111 @@
+use std::path::Path;
+
+use anyhow::{Context, Error, Result, anyhow};
+use backends::{Language, glossary, storage};
+use slint::{ComponentHandle, SharedString};
+
+use super::{Logic, SettingWindow, Settings};
+
+/// Implement `Logic.import_glossary` and `Logic.export_glossary` of the setting window,
+/// which read and write `.tbx` or `.csv` files.
+pub(super) fn connect(setting_window: &SettingWindow) {
+    setting_window.global::<Logic>().on_import_glossary({
+        let setting_window_weak = setting_window.as_weak();
+        move |path| {
+            let Some(setting_window) = setting_window_weak.upgrade() else {
+                return;
+            };
+            let status = match import(&setting_window.get_glossary(), Path::new(path.trim())) {
+                Ok((text, count)) => {
+                    setting_window.set_glossary(text.into());
+                    format!("Imported {} entries, save to use them", count)
+                }
+                Err(e) => {
+                    log::error!("Failed to import glossary: {:#}", e);
+                    format!("Import failed: {:#}", e)
+                }
+            };
+            setting_window.set_glossary_status(status.into());
+        }
+    });
+
+    setting_window.global::<Logic>().on_export_glossary({
+        let setting_window_weak = setting_window.as_weak();
+        move |path| {
+            let Some(setting_window) = setting_window_weak.upgrade() else {
+                return;
+            };
+            let status = match export(&setting_window.get_glossary(), Path::new(path.trim())) {
+                Ok(count) => format!("Exported {} entries to {}", count, path.trim()),
+                Err(e) => {
+                    log::error!("Failed to export glossary: {:#}", e);
+                    format!("Export failed: {:#}", e)
+                }
+            };
+            setting_window.set_glossary_status(status.into());
+        }
+    });
+}
+
+/// Glossary entered in the setting window, one CSV row per entry.
+pub(super) fn from_slint(settings_from_slint: &Settings) -> Result<storage::Glossary, Error> {
+    Ok(storage::Glossary {
+        entries: glossary::read_csv(settings_from_slint.glossary.as_bytes())
+            .context("Invalid glossary")?,
+    })
+}
+
+pub(super) fn to_slint(entries: &[glossary::GlossaryEntry]) -> String {
+    let mut csv = Vec::new();
+    if let Err(e) = glossary::write_csv(entries, false, &mut csv) {
+        log::error!("Failed to show glossary: {:#}", e);
+    }
+    String::from_utf8(csv).unwrap_or_default()
+}
+
+/// One line per glossary term of `sentence` whose translation is missing from
+/// `translation`.
+pub(super) fn violations(
+    glossary: &storage::Glossary,
+    sentence: &str,
+    translation: &str,
+    source_language: Language,
+    target_language: Language,
+) -> Vec<SharedString> {
+    let relevant = glossary::relevant_entries(
+        &glossary.entries,
+        sentence,
+        source_language,
+        target_language,
+    );
+    glossary::violations(&relevant, translation)
+        .into_iter()
+        .map(|entry| {
+            format!(
+                "“{}” should be translated as “{}”",
+                entry.source_term, entry.target_term
+            )
+            .into()
+        })
+        .collect()
+}
+
+/// Merge the entries of the file at `path` into the entries of `text`.
+fn import(text: &str, path: &Path) -> Result<(String, usize), Error> {
+    let mut entries = glossary::read_csv(text.as_bytes()).context("Invalid glossary")?;
+    let imported = glossary::import(path)?;
+    if imported.is_empty() {
+        return Err(anyhow!("There are no entries in {}", path.display()));
+    }
+    let count = glossary::merge(&mut entries, imported);
+    Ok((to_slint(&entries), count))
+}
+
+fn export(text: &str, path: &Path) -> Result<usize, Error> {
+    let entries = glossary::read_csv(text.as_bytes()).context("Invalid glossary")?;
+    if entries.is_empty() {
+        return Err(anyhow!("The glossary is empty"));
+    }
+    glossary::export(&entries, path)?;
+    Ok(entries.len())
+}
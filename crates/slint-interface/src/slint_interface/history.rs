@@ -0,0 +1,265 @@
+use std::{
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Error, Result};
+use backends::{
+    Language, TokenUsage,
+    export::{self, Flashcard},
+    history::{HistoryEntry, HistoryQuery, HistoryStore, TranslationMode, TranslationOutput},
+};
+use chrono::{Days, Local, TimeZone};
+use slint::{ComponentHandle, ModelRc, VecModel};
+
+use super::{HistoryItem, Logic, MainWindow, notebook::Notebook, word_trans_results};
+
+/// Length of the output shown in the list before it is cut off.
+const SUMMARY_CHARS: usize = 80;
+
+/// Translation history shown in the History tab.
+pub(super) struct TranslationHistory {
+    store: HistoryStore,
+    /// Filters last entered in the History tab, used to refresh the list.
+    query: Mutex<HistoryQuery>,
+}
+impl TranslationHistory {
+    /// Open the history if it is enabled, removing entries beyond the retention policy.
+    pub(super) fn open(config: &backends::storage::History) -> Option<Arc<Self>> {
+        if !config.enabled {
+            return None;
+        }
+        let store = match HistoryStore::open_default() {
+            Ok(store) => store,
+            Err(e) => {
+                log::error!("Failed to open translation history: {:#}", e);
+                return None;
+            }
+        };
+        match store.apply_retention(config) {
+            Ok(removed) => log::info!("Removed {} history entries by retention policy", removed),
+            Err(e) => log::warn!("Failed to apply history retention policy: {:#}", e),
+        }
+        Some(Arc::new(Self {
+            store,
+            query: Mutex::new(HistoryQuery::default()),
+        }))
+    }
+
+    /// Record a finished translation and refresh the History tab.
+    pub(super) fn record(
+        &self,
+        input: &str,
+        output: &TranslationOutput,
+        engine: &str,
+        languages: (Language, Language),
+        token_usage: Option<TokenUsage>,
+        main_window_weak: slint::Weak<MainWindow>,
+    ) {
+        if let Err(e) = self
+            .store
+            .record(input, output, engine, languages, token_usage)
+        {
+            log::error!("Failed to record translation history: {:#}", e);
+            return;
+        }
+        self.refresh(main_window_weak);
+    }
+
+    /// Search with the last entered filters and show the result in the History tab.
+    pub(super) fn refresh(&self, main_window_weak: slint::Weak<MainWindow>) {
+        let query = self.query.lock().unwrap().clone();
+        let items = match self.store.search(&query) {
+            Ok(entries) => entries.iter().map(history_item).collect::<Vec<_>>(),
+            Err(e) => {
+                log::error!("Failed to search translation history: {:#}", e);
+                return;
+            }
+        };
+        let _ = main_window_weak.upgrade_in_event_loop(move |handle| {
+            handle.set_history_items(ModelRc::from(Rc::new(VecModel::from(items))));
+        });
+    }
+}
+
+impl TranslationHistory {
+    /// Flashcards of the words matching the last entered filters, a sense looked up
+    /// several times from its latest explanation.
+    pub(super) fn word_flashcards(&self) -> Result<Vec<Flashcard>, Error> {
+        let query = HistoryQuery {
+            mode: Some(TranslationMode::Word),
+            limit: usize::MAX,
+            ..self.query.lock().unwrap().clone()
+        };
+        let mut flashcards = Vec::new();
+        for entry in self.store.search(&query)? {
+            if let TranslationOutput::Word(word_explanation) = &entry.output {
+                flashcards.extend(Flashcard::from_word_explanation(word_explanation));
+            }
+        }
+        // The history is searched newest first.
+        Ok(export::unique_notes(flashcards))
+    }
+
+    /// Every word looked up in the Word tab, with its source language.
+    pub(super) fn looked_up_words(&self) -> Result<Vec<(String, Language)>, Error> {
+        let query = HistoryQuery {
+            mode: Some(TranslationMode::Word),
+            limit: usize::MAX,
+            ..Default::default()
+        };
+        Ok(self
+            .store
+            .search(&query)?
+            .into_iter()
+            .filter_map(|entry| match entry.output {
+                TranslationOutput::Word(word_explanation) => {
+                    Some((word_explanation.word, entry.source_language))
+                }
+                TranslationOutput::Sentence(_) => None,
+            })
+            .collect())
+    }
+}
+
+/// Implement the `Logic` callbacks of the History tab.
+pub(super) fn connect(
+    main_window: &MainWindow,
+    history: Arc<TranslationHistory>,
+    vocabulary_notebook: Option<Arc<Notebook>>,
+) {
+    main_window.global::<Logic>().on_search_history({
+        let history = history.clone();
+        let main_window_weak = main_window.as_weak();
+        move |text, mode, source_language, target_language, period| {
+            *history.query.lock().unwrap() = HistoryQuery {
+                text: Some(text.to_string()),
+                mode: mode.parse::<TranslationMode>().ok(),
+                source_language: source_language.parse::<Language>().ok(),
+                target_language: target_language.parse::<Language>().ok(),
+                since: since(&period),
+                ..Default::default()
+            };
+            history.refresh(main_window_weak.clone());
+        }
+    });
+
+    main_window.global::<Logic>().on_open_history({
+        let history = history.clone();
+        let main_window_weak = main_window.as_weak();
+        move |id| {
+            let entry = match history.store.get(id.into()) {
+                Ok(Some(entry)) => entry,
+                Ok(None) => {
+                    log::warn!("History entry {} no longer exists", id);
+                    return;
+                }
+                Err(e) => {
+                    log::error!("Failed to open history entry {}: {:#}", id, e);
+                    return;
+                }
+            };
+            let Some(main_window) = main_window_weak.upgrade() else {
+                return;
+            };
+            match entry.output {
+                TranslationOutput::Word(word_explanation) => {
+                    let starred_senses = vocabulary_notebook
+                        .as_ref()
+                        .map(|notebook| {
+                            notebook.show_word(
+                                &word_explanation,
+                                (entry.source_language, entry.target_language),
+                            )
+                        })
+                        .unwrap_or_default();
+                    main_window
+                        .set_starred_senses(ModelRc::from(Rc::new(VecModel::from(starred_senses))));
+                    main_window.set_word_trans_results(ModelRc::from(Rc::new(VecModel::from(
+                        word_trans_results(word_explanation),
+                    ))));
+                    main_window.invoke_show_word_entry(entry.input.into());
+                }
+                TranslationOutput::Sentence(sentence) => {
+                    main_window.invoke_show_sentence_entry(entry.input.into(), sentence.into());
+                }
+            }
+        }
+    });
+
+    main_window.global::<Logic>().on_delete_history({
+        let history = history.clone();
+        let main_window_weak = main_window.as_weak();
+        move |id| {
+            if let Err(e) = history.store.delete(id.into()) {
+                log::error!("Failed to delete history entry {}: {:#}", id, e);
+            }
+            history.refresh(main_window_weak.clone());
+        }
+    });
+
+    main_window.global::<Logic>().on_clear_history({
+        let main_window_weak = main_window.as_weak();
+        move || {
+            if let Err(e) = history.store.clear() {
+                log::error!("Failed to clear translation history: {:#}", e);
+            }
+            history.refresh(main_window_weak.clone());
+        }
+    });
+}
+
+/// Start of the period selected in the History tab, in local time.
+fn since(period: &str) -> Option<i64> {
+    let today = Local::now().date_naive();
+    let first_day = match period {
+        "Today" => today,
+        "Past week" => today.checked_sub_days(Days::new(7))?,
+        "Past month" => today.checked_sub_days(Days::new(30))?,
+        _ => return None,
+    };
+    Local
+        .from_local_datetime(&first_day.and_hms_opt(0, 0, 0)?)
+        .earliest()
+        .map(|datetime| datetime.timestamp())
+}
+
+fn history_item(entry: &HistoryEntry) -> HistoryItem {
+    let output = entry.output.text();
+    // The first line of a word is the word itself, which is already the title.
+    let skipped_lines = match entry.output.mode() {
+        TranslationMode::Word => 1,
+        TranslationMode::Sentence => 0,
+    };
+    let mut summary = output
+        .lines()
+        .skip(skipped_lines)
+        .collect::<Vec<_>>()
+        .join(" ");
+    if summary.chars().count() > SUMMARY_CHARS {
+        summary = summary.chars().take(SUMMARY_CHARS).collect::<String>() + "…";
+    }
+    let created_at = Local
+        .timestamp_opt(entry.created_at, 0)
+        .single()
+        .map(|datetime| datetime.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default();
+    let mut detail = format!(
+        "{} · {} → {} · {}",
+        entry.engine, entry.source_language, entry.target_language, created_at
+    );
+    if let Some(usage) = entry.token_usage {
+        detail.push_str(&format!(
+            " · {} tokens",
+            usage.input_tokens + usage.output_tokens
+        ));
+    }
+    HistoryItem {
+        // Ids are assigned in order and stay far below `i32::MAX` in practice.
+        id: entry.id as i32,
+        mode: entry.output.mode().to_string().into(),
+        title: entry.input.as_str().into(),
+        summary: summary.into(),
+        detail: detail.into(),
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::{
+    Arc, Mutex,
+    mpsc::{self, Receiver},
+};
+
+use anyhow::{Error, Result};
+use backends::{
+    file_translation::{self, Summary, markup},
+    storage::{self, Prompts},
+};
+use slint::ComponentHandle;
+
+use super::{
+    Logic, MainWindow, SettingWindow, document, file_translation::tab_translator, usage::UsageMeter,
+};
+
+/// Implement `Logic.translate_markup` of the Sentences tab, which translates the text
+/// of pasted HTML or XLIFF in batches and shows the markup with the translations.
+pub(super) fn connect(
+    main_window: &MainWindow,
+    setting_window_weak: slint::Weak<SettingWindow>,
+    rx_arc_mutex: Arc<Mutex<Receiver<String>>>,
+    usage_meter: Option<Arc<UsageMeter>>,
+    prompts_arc_mutex: Arc<Mutex<Prompts>>,
+    documents: storage::Documents,
+) {
+    main_window.global::<Logic>().on_translate_markup({
+        let main_window_weak = main_window.as_weak();
+        move |text, from_language, to_language, model| {
+            // The translation replaces the one shown, even if it is still streaming.
+            let (tx, rx) = mpsc::channel();
+            *rx_arc_mutex.lock().unwrap() = rx;
+            if let Some(main_window) = main_window_weak.upgrade() {
+                main_window.set_sentence_reasoning(slint::SharedString::new());
+                main_window.set_glossary_violations(Default::default());
+                main_window.set_sentence_progress(slint::SharedString::new());
+                main_window.set_aligned_segments(Default::default());
+                main_window.set_back_translation(slint::SharedString::new());
+            }
+            let Some(settings_from_slint) = setting_window_weak
+                .upgrade()
+                .map(|setting_window| setting_window.get_settings_from_slint())
+            else {
+                return;
+            };
+            let prepared = tab_translator(
+                &from_language,
+                &to_language,
+                &model,
+                &settings_from_slint,
+                usage_meter.as_deref(),
+                &prompts_arc_mutex.lock().unwrap(),
+                None,
+            );
+            let (translator, engine, source_language, target_language) = match prepared {
+                Ok(prepared) => prepared,
+                Err(e) => {
+                    let _ = tx.send(format!("Error: {:#}", e));
+                    return;
+                }
+            };
+            let batch_tokens = document::chunk_tokens(&documents, engine, &settings_from_slint);
+
+            let text = text.to_string();
+            let main_window_weak = main_window_weak.clone();
+            let usage_meter = usage_meter.clone();
+            std::thread::spawn(move || {
+                let result = markup::translate(
+                    &text,
+                    target_language,
+                    batch_tokens,
+                    |request| {
+                        if let Some(usage_meter) = &usage_meter {
+                            usage_meter.check(&engine.to_string())?;
+                        }
+                        let translation = file_translation::translate_segment(
+                            translator.as_ref(),
+                            request,
+                            source_language,
+                            target_language,
+                        );
+                        if let Some(usage_meter) = &usage_meter {
+                            usage_meter.record(
+                                &engine.to_string(),
+                                translator.last_token_usage(),
+                                main_window_weak.clone(),
+                            );
+                        }
+                        translation
+                    },
+                    |done, total| {
+                        let progress = format!("Translated {} of {} segments", done, total);
+                        let _ = main_window_weak.upgrade_in_event_loop(move |handle| {
+                            handle.set_sentence_progress(progress.into());
+                        });
+                    },
+                );
+                let (translated, progress) = shown(result);
+                if tx.send(translated).is_err() {
+                    log::debug!("Another text is being translated, drop the markup.");
+                    return;
+                }
+                let _ = main_window_weak.upgrade_in_event_loop(move |handle| {
+                    handle.set_sentence_progress(progress.into());
+                });
+            });
+        }
+    });
+}
+
+/// The text shown for a translation of markup and the line below it.
+fn shown(result: Result<(String, Summary), Error>) -> (String, String) {
+    match result {
+        Ok((translated, summary)) => {
+            let mut kept = Vec::new();
+            if summary.untranslated > 0 {
+                kept.push(format!(
+                    "{} segments kept in the source language as their tags were lost",
+                    summary.untranslated
+                ));
+            }
+            if let Some(failure) = summary.failures.first() {
+                kept.push(format!(
+                    "{} segments kept in the source language as they failed: {}",
+                    summary.failures.len(),
+                    failure
+                ));
+            }
+            (translated, kept.join(", "))
+        }
+        Err(e) => {
+            log::error!("Failed to translate markup: {:#}", e);
+            (format!("Error: {:#}", e), String::new())
+        }
+    }
+}
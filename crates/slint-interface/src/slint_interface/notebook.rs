@@ -0,0 +1,200 @@
+use std::{
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Error, Result};
+use backends::{
+    Language,
+    dict_interface::WordExplanation,
+    export::Flashcard,
+    notebook::{NotebookStore, QuizDirection, ReviewGrade},
+};
+use slint::{ComponentHandle, ModelRc, VecModel};
+
+use super::{Logic, MainWindow, ReviewCard};
+
+/// How often the due-count badge is updated while the window is open.
+const DUE_COUNT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Vocabulary notebook, starred from the Word tab and studied in the Review tab.
+pub(super) struct Notebook {
+    store: NotebookStore,
+    /// The word shown in the Word tab, whose senses can be starred.
+    shown_word: Mutex<Option<(WordExplanation, (Language, Language))>>,
+}
+impl Notebook {
+    pub(super) fn open() -> Option<Arc<Self>> {
+        match NotebookStore::open_default() {
+            Ok(store) => Some(Arc::new(Self {
+                store,
+                shown_word: Mutex::new(None),
+            })),
+            Err(e) => {
+                log::error!("Failed to open vocabulary notebook: {:#}", e);
+                None
+            }
+        }
+    }
+
+    /// Remember the word shown in the Word tab and return whether each of its senses
+    /// is starred.
+    pub(super) fn show_word(
+        &self,
+        word_explanation: &WordExplanation,
+        languages: (Language, Language),
+    ) -> Vec<bool> {
+        *self.shown_word.lock().unwrap() = Some((word_explanation.clone(), languages));
+        self.starred_senses(word_explanation)
+    }
+
+    fn starred_senses(&self, word_explanation: &WordExplanation) -> Vec<bool> {
+        match self.store.starred_senses(word_explanation) {
+            Ok(ids) => ids.iter().map(Option::is_some).collect(),
+            Err(e) => {
+                log::error!("Failed to read starred senses: {:#}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn toggle_star(&self, sense: usize, starred: bool) -> Vec<bool> {
+        let shown_word = self.shown_word.lock().unwrap();
+        let Some((word_explanation, languages)) = shown_word.as_ref() else {
+            return Vec::new();
+        };
+        let result = if starred {
+            self.store
+                .star(word_explanation, sense, *languages)
+                .map(|_| ())
+        } else {
+            self.store.starred_senses(word_explanation).and_then(|ids| {
+                match ids.get(sense).copied().flatten() {
+                    Some(id) => self.store.unstar(id),
+                    None => Ok(()),
+                }
+            })
+        };
+        if let Err(e) = result {
+            log::error!("Failed to update notebook: {:#}", e);
+        }
+        self.starred_senses(word_explanation)
+    }
+
+    /// Flashcards of every starred sense.
+    pub(super) fn flashcards(&self) -> Result<Vec<Flashcard>, Error> {
+        Ok(self.store.cards()?.iter().map(Flashcard::from).collect())
+    }
+
+    /// Words with a starred sense, with their source language.
+    pub(super) fn words(&self) -> Result<Vec<(String, Language)>, Error> {
+        Ok(self
+            .store
+            .cards()?
+            .into_iter()
+            .map(|card| (card.word, card.source_language))
+            .collect())
+    }
+
+    fn update_due_count(&self, main_window: &MainWindow) {
+        match self.store.due_count(now()) {
+            Ok(due_count) => main_window.set_due_count(due_count as i32),
+            Err(e) => log::error!("Failed to count due cards: {:#}", e),
+        }
+    }
+
+    /// Show the most overdue card in the Review tab, or clear it if none is due.
+    fn show_next_card(&self, main_window: &MainWindow, direction: i32) {
+        let card = match self.store.due_cards(now(), 1) {
+            Ok(cards) => cards.into_iter().next(),
+            Err(e) => {
+                log::error!("Failed to read due cards: {:#}", e);
+                None
+            }
+        };
+        let Some(card) = card else {
+            main_window.set_has_review_card(false);
+            return;
+        };
+        let direction = match direction {
+            1 => QuizDirection::DefinitionToWord,
+            2 => QuizDirection::WordToTranslation,
+            // Mixed, alternating on every successful review of the card.
+            _ if (card.id + i64::from(card.schedule.repetitions)) % 2 == 0 => {
+                QuizDirection::DefinitionToWord
+            }
+            _ => QuizDirection::WordToTranslation,
+        };
+        let quiz = card.quiz(direction);
+        main_window.set_review_card(ReviewCard {
+            id: card.id as i32,
+            prompt: quiz.prompt.into(),
+            hint: quiz.hint.into(),
+            answer: quiz.answer.into(),
+        });
+        main_window.set_has_review_card(true);
+    }
+}
+
+/// Implement the `Logic` callbacks of starring and reviewing, and keep the due-count
+/// badge up to date while the returned timer is alive.
+pub(super) fn connect(main_window: &MainWindow, notebook: Arc<Notebook>) -> slint::Timer {
+    main_window.global::<Logic>().on_toggle_star({
+        let notebook = notebook.clone();
+        let main_window_weak = main_window.as_weak();
+        move |sense, starred| {
+            let Some(main_window) = main_window_weak.upgrade() else {
+                return;
+            };
+            let starred_senses = notebook.toggle_star(sense as usize, starred);
+            main_window.set_starred_senses(ModelRc::from(Rc::new(VecModel::from(starred_senses))));
+            notebook.update_due_count(&main_window);
+        }
+    });
+
+    main_window.global::<Logic>().on_next_review({
+        let notebook = notebook.clone();
+        let main_window_weak = main_window.as_weak();
+        move |direction| {
+            if let Some(main_window) = main_window_weak.upgrade() {
+                notebook.show_next_card(&main_window, direction);
+            }
+        }
+    });
+
+    main_window.global::<Logic>().on_grade_review({
+        let notebook = notebook.clone();
+        let main_window_weak = main_window.as_weak();
+        move |id, grade, direction| {
+            let Some(main_window) = main_window_weak.upgrade() else {
+                return;
+            };
+            match grade.parse::<ReviewGrade>() {
+                Ok(grade) => {
+                    if let Err(e) = notebook.store.review(id.into(), grade, now()) {
+                        log::error!("Failed to save review: {:#}", e);
+                    }
+                }
+                Err(_) => log::error!("Unknown review grade: {}", grade),
+            }
+            notebook.update_due_count(&main_window);
+            notebook.show_next_card(&main_window, direction);
+        }
+    });
+
+    notebook.update_due_count(main_window);
+    let timer = slint::Timer::default();
+    timer.start(slint::TimerMode::Repeated, DUE_COUNT_INTERVAL, {
+        let main_window_weak = main_window.as_weak();
+        move || {
+            if let Some(main_window) = main_window_weak.upgrade() {
+                notebook.update_due_count(&main_window);
+            }
+        }
+    });
+    timer
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
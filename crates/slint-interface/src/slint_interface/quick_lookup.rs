@@ -0,0 +1,198 @@
+use std::{
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Error, Result, anyhow};
+use backends::storage::{Prompts, QuickLookup};
+use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState, hotkey::HotKey};
+use slint::{ComponentHandle, ModelRc, VecModel};
+
+use super::{
+    MainWindow, QuickLookupWindow, SettingWindow, WordTransResult, WordTransType,
+    usage::UsageMeter, word_trans_results, word_translator,
+};
+
+/// Distance between the cursor and the top-left corner of the popup.
+const CURSOR_OFFSET: i32 = 12;
+
+/// Keeps the global shortcut registered while alive.
+pub(super) struct QuickLookupHotKey {
+    _manager: GlobalHotKeyManager,
+    _timer: slint::Timer,
+}
+
+/// Register the quick lookup shortcut, which shows the popup with the translation
+/// of the current selection.
+pub(super) fn register(
+    config: &QuickLookup,
+    setting_window_weak: slint::Weak<SettingWindow>,
+    usage_meter: Option<Arc<UsageMeter>>,
+    prompts_arc_mutex: Arc<Mutex<Prompts>>,
+    main_window_weak: slint::Weak<MainWindow>,
+) -> Result<QuickLookupHotKey, Error> {
+    let hotkey: HotKey = config
+        .shortcut
+        .parse()
+        .map_err(|e| anyhow!("Invalid quick lookup shortcut {}: {}", config.shortcut, e))?;
+    let manager = GlobalHotKeyManager::new()
+        .map_err(|e| anyhow!("Failed to create global hotkey manager: {}", e))?;
+    manager
+        .register(hotkey)
+        .map_err(|e| anyhow!("Failed to register shortcut {}: {}", config.shortcut, e))?;
+
+    let popup = Rc::new(QuickLookupWindow::new()?);
+    popup.on_close_requested({
+        let popup_weak = popup.as_weak();
+        move || {
+            if let Some(popup) = popup_weak.upgrade() {
+                let _ = popup.hide();
+            }
+        }
+    });
+
+    // Hotkey events are delivered on a channel, which is polled from the event loop
+    // so that the popup can be shown directly.
+    let hotkey_id = hotkey.id();
+    let timer = slint::Timer::default();
+    timer.start(
+        slint::TimerMode::Repeated,
+        std::time::Duration::from_millis(100),
+        {
+            let config = config.clone();
+            move || {
+                while let Ok(event) = GlobalHotKeyEvent::receiver().try_recv() {
+                    if event.id() == hotkey_id && event.state() == HotKeyState::Pressed {
+                        look_up_selection(
+                            &config,
+                            &popup,
+                            &setting_window_weak,
+                            &prompts_arc_mutex.lock().unwrap(),
+                            usage_meter.clone().zip(Some(main_window_weak.clone())),
+                        );
+                    }
+                }
+            }
+        },
+    );
+
+    log::info!("Registered quick lookup shortcut {}", config.shortcut);
+    Ok(QuickLookupHotKey {
+        _manager: manager,
+        _timer: timer,
+    })
+}
+
+fn look_up_selection(
+    config: &QuickLookup,
+    popup: &QuickLookupWindow,
+    setting_window_weak: &slint::Weak<SettingWindow>,
+    prompts: &Prompts,
+    usage_meter: Option<(Arc<UsageMeter>, slint::Weak<MainWindow>)>,
+) {
+    let Some(text) = backends::clipboard::read_selection() else {
+        log::info!("Nothing is selected, skip quick lookup.");
+        return;
+    };
+    log::info!("Quick lookup: {}", text);
+
+    popup.set_query(text.as_str().into());
+    popup.set_word_trans_results(rows(vec![WordTransResult {
+        index: "".into(),
+        text: "Translating...".into(),
+        type_: WordTransType::Header,
+    }]));
+    if let Some((x, y)) = cursor_position() {
+        popup.window().set_position(slint::PhysicalPosition::new(
+            x + CURSOR_OFFSET,
+            y + CURSOR_OFFSET,
+        ));
+    }
+    if let Err(e) = popup.show() {
+        log::error!("Failed to show quick lookup window: {}", e);
+        return;
+    }
+
+    let translator = match setting_window_weak
+        .upgrade()
+        .ok_or_else(|| anyhow!("Setting window is closed"))
+        .and_then(|setting_window| {
+            if let Some((usage_meter, _)) = &usage_meter {
+                usage_meter.check(&config.engine.to_string())?;
+            }
+            word_translator(
+                config.engine,
+                &setting_window.get_settings_from_slint(),
+                prompts,
+            )
+        }) {
+        Ok(translator) => translator,
+        Err(e) => {
+            popup.set_word_trans_results(error_rows(e));
+            return;
+        }
+    };
+
+    let popup_weak = popup.as_weak();
+    let (source_language, target_language) = (config.source_language, config.target_language);
+    let engine = config.engine;
+    std::thread::spawn(move || {
+        let result = backends::look_up_word(
+            translator.as_ref(),
+            None,
+            &text,
+            source_language,
+            target_language,
+        );
+        if let Some((usage_meter, main_window_weak)) = usage_meter {
+            usage_meter.record(
+                &engine.to_string(),
+                translator.last_token_usage(),
+                main_window_weak,
+            );
+        }
+        let _ = popup_weak.upgrade_in_event_loop(move |popup| {
+            // Another lookup may have been started in the meantime.
+            if popup.get_query() != text.as_str() {
+                return;
+            }
+            match result {
+                Ok(word_explanation) => {
+                    popup.set_word_trans_results(rows(word_trans_results(word_explanation)))
+                }
+                Err(e) => {
+                    log::warn!("Error looking up word: {:#}", e);
+                    popup.set_word_trans_results(error_rows(e));
+                }
+            }
+        });
+    });
+}
+
+fn rows(results: Vec<WordTransResult>) -> ModelRc<WordTransResult> {
+    ModelRc::from(Rc::new(VecModel::from(results)))
+}
+
+fn error_rows(e: Error) -> ModelRc<WordTransResult> {
+    rows(vec![WordTransResult {
+        index: "".into(),
+        text: e.to_string().into(),
+        type_: WordTransType::Error,
+    }])
+}
+
+/// Global position of the mouse cursor, if it can be queried on this platform.
+#[cfg(target_os = "linux")]
+fn cursor_position() -> Option<(i32, i32)> {
+    use x11rb::{connection::Connection, protocol::xproto::ConnectionExt};
+
+    let (connection, screen) = x11rb::connect(None).ok()?;
+    let root = connection.setup().roots.get(screen)?.root;
+    let pointer = connection.query_pointer(root).ok()?.reply().ok()?;
+    Some((pointer.root_x.into(), pointer.root_y.into()))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cursor_position() -> Option<(i32, i32)> {
+    None
+}
@@ -0,0 +1,160 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::{Context, Error, Result, anyhow};
+use backends::{
+    Language, storage,
+    translation_memory::{self, MemoryLookup, TranslationMemory, TranslationUnit},
+};
+use slint::ComponentHandle;
+
+use super::{Logic, SettingWindow, Settings};
+
+/// Open the memory at the configured path, or in the local database. Changing the
+/// path takes effect on the next start.
+pub(super) fn open(config: &storage::TranslationMemory) -> Option<Arc<TranslationMemory>> {
+    match &config.path {
+        Some(path) => TranslationMemory::open(path),
+        None => TranslationMemory::open_default(),
+    }
+    .map(Arc::new)
+    .inspect_err(|e| log::error!("Failed to open translation memory: {:#}", e))
+    .ok()
+}
+
+/// Implement `Logic.import_translation_memory` and `Logic.export_translation_memory`
+/// of the setting window, which read and write `.tmx` files.
+pub(super) fn connect(setting_window: &SettingWindow, memory: Option<Arc<TranslationMemory>>) {
+    setting_window
+        .global::<Logic>()
+        .on_import_translation_memory({
+            let setting_window_weak = setting_window.as_weak();
+            let memory = memory.clone();
+            move |path| {
+                let Some(setting_window) = setting_window_weak.upgrade() else {
+                    return;
+                };
+                let status = match import(memory.as_deref(), Path::new(path.trim())) {
+                    Ok((added, count)) => {
+                        format!("Imported {} units, {} in the memory", added, count)
+                    }
+                    Err(e) => {
+                        log::error!("Failed to import translation memory: {:#}", e);
+                        format!("Import failed: {:#}", e)
+                    }
+                };
+                setting_window.set_translation_memory_status(status.into());
+            }
+        });
+
+    setting_window
+        .global::<Logic>()
+        .on_export_translation_memory({
+            let setting_window_weak = setting_window.as_weak();
+            move |path| {
+                let Some(setting_window) = setting_window_weak.upgrade() else {
+                    return;
+                };
+                let status = match export(memory.as_deref(), Path::new(path.trim())) {
+                    Ok(count) => format!("Exported {} units to {}", count, path.trim()),
+                    Err(e) => {
+                        log::error!("Failed to export translation memory: {:#}", e);
+                        format!("Export failed: {:#}", e)
+                    }
+                };
+                setting_window.set_translation_memory_status(status.into());
+            }
+        });
+}
+
+/// Settings of the memory entered in the setting window. The path is only set in
+/// the config file.
+pub(super) fn from_slint(
+    settings_from_slint: &Settings,
+    path: Option<&Path>,
+) -> Result<storage::TranslationMemory, Error> {
+    let default = storage::TranslationMemory::default();
+    let threshold = settings_from_slint.translation_memory_threshold.trim();
+    let fuzzy_threshold = match threshold.is_empty() {
+        true => default.fuzzy_threshold,
+        false => threshold
+            .parse::<f64>()
+            .ok()
+            .filter(|threshold| (0.0..=100.0).contains(threshold))
+            .ok_or_else(|| anyhow!("Invalid fuzzy match threshold: {}", threshold))?,
+    };
+    let references = settings_from_slint.translation_memory_references.trim();
+    let max_references = match references.is_empty() {
+        true => default.max_references,
+        false => references
+            .parse()
+            .map_err(|_| anyhow!("Invalid number of references: {}", references))?,
+    };
+    Ok(storage::TranslationMemory {
+        enabled: settings_from_slint.translation_memory_enabled,
+        fuzzy_threshold,
+        max_references,
+        path: path.map(Path::to_path_buf),
+    })
+}
+
+/// How translators consult `memory`, none if it is disabled.
+pub(super) fn lookup(
+    settings_from_slint: &Settings,
+    memory: Option<&Arc<TranslationMemory>>,
+) -> Result<Option<MemoryLookup>, Error> {
+    let config = from_slint(settings_from_slint, None)?;
+    Ok(memory
+        .filter(|_| config.enabled)
+        .map(|memory| MemoryLookup {
+            memory: memory.clone(),
+            min_similarity: config.fuzzy_threshold,
+            max_references: config.max_references,
+        }))
+}
+
+/// Remember a finished translation for the sentences to come.
+pub(super) fn record(
+    lookup: Option<&MemoryLookup>,
+    sentence: &str,
+    translation: &str,
+    source_language: Language,
+    target_language: Language,
+) {
+    let Some(lookup) = lookup else {
+        return;
+    };
+    let unit = TranslationUnit {
+        source_language,
+        target_language,
+        source: sentence.to_string(),
+        target: translation.to_string(),
+    };
+    if let Err(e) = lookup.memory.add(&unit) {
+        log::error!("Failed to add translation to memory: {:#}", e);
+    }
+}
+
+/// Add the units of the file at `path`. Returns the number of units added and the
+/// number in the memory.
+fn import(memory: Option<&TranslationMemory>, path: &Path) -> Result<(usize, usize), Error> {
+    let memory = memory.context("The translation memory could not be opened")?;
+    let units = translation_memory::import(path)?;
+    if units.is_empty() {
+        return Err(anyhow!(
+            "There are no translation units in {}",
+            path.display()
+        ));
+    }
+    let added = memory.add_all(&units)?;
+    Ok((added, memory.len()?))
+}
+
+fn export(memory: Option<&TranslationMemory>, path: &Path) -> Result<usize, Error> {
+    let memory = memory.context("The translation memory could not be opened")?;
+    let units = memory.units()?;
+    if units.is_empty() {
+        return Err(anyhow!("The translation memory is empty"));
+    }
+    translation_memory::export(&units, path)?;
+    Ok(units.len())
+}
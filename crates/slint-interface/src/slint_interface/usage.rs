@@ -0,0 +1,170 @@
+use std::{rc::Rc, sync::Arc};
+
+use anyhow::{Error, Result, anyhow};
+use backends::{
+    TokenUsage,
+    storage::{self, BudgetPeriod},
+    usage::{BudgetStatus, UsageLedger, UsageTotals, period_start},
+};
+use chrono::Local;
+use slint::{ModelRc, SharedString, VecModel};
+
+use super::{MainWindow, UsageRow};
+
+/// Name of the row summing every provider.
+const ALL_PROVIDERS: &str = "All";
+
+/// Token usage and costs of the AI providers, shown in the Usage tab.
+pub(super) struct UsageMeter {
+    ledger: UsageLedger,
+    config: storage::Usage,
+}
+impl UsageMeter {
+    pub(super) fn open(config: &storage::Usage) -> Option<Arc<Self>> {
+        match UsageLedger::open_default(config) {
+            Ok(ledger) => Some(Arc::new(Self {
+                ledger,
+                config: config.clone(),
+            })),
+            Err(e) => {
+                log::error!("Failed to open usage ledger: {:#}", e);
+                None
+            }
+        }
+    }
+
+    /// Fail if a budget covering `engine` is used up, so that no request is sent.
+    pub(super) fn check(&self, engine: &str) -> Result<(), Error> {
+        match self
+            .ledger
+            .budget_status(engine, &self.config, Local::now())
+        {
+            Ok(BudgetStatus::Exceeded { budget, spent }) => Err(anyhow!(
+                "{} is used up ({}), no request is sent",
+                budget_name(&budget),
+                self.spending(spent, budget.limit)
+            )),
+            Ok(_) => Ok(()),
+            Err(e) => {
+                // The ledger may be on a shared drive which is unavailable for a moment.
+                log::warn!("Failed to check budgets of {}: {:#}", engine, e);
+                Ok(())
+            }
+        }
+    }
+
+    /// Record a finished request and refresh the Usage tab. Requests without reported
+    /// usage, e.g. of the offline dictionaries, are not recorded.
+    pub(super) fn record(
+        &self,
+        engine: &str,
+        token_usage: Option<TokenUsage>,
+        main_window_weak: slint::Weak<MainWindow>,
+    ) {
+        let Some(token_usage) = token_usage else {
+            return;
+        };
+        match self.ledger.record(engine, token_usage, &self.config) {
+            Ok(cost) => log::debug!(
+                "Request to {} cost {:.4} {}",
+                engine,
+                cost,
+                self.config.currency
+            ),
+            Err(e) => {
+                log::error!("Failed to record usage of {}: {:#}", engine, e);
+                return;
+            }
+        }
+        self.refresh(main_window_weak);
+    }
+
+    /// Show the totals of today and this month, the budgets and the budget warning.
+    pub(super) fn refresh(&self, main_window_weak: slint::Weak<MainWindow>) {
+        let (rows, budgets, warning) = match self.summary() {
+            Ok(summary) => summary,
+            Err(e) => {
+                log::error!("Failed to read usage totals: {:#}", e);
+                return;
+            }
+        };
+        let _ = main_window_weak.upgrade_in_event_loop(move |handle| {
+            handle.set_usage_rows(ModelRc::from(Rc::new(VecModel::from(rows))));
+            handle.set_budgets(ModelRc::from(Rc::new(VecModel::from(budgets))));
+            handle.set_budget_warning(warning.into());
+        });
+    }
+
+    fn summary(&self) -> Result<(Vec<UsageRow>, Vec<SharedString>, String), Error> {
+        let now = Local::now();
+        let mut rows = Vec::new();
+        for (period, name) in [
+            (BudgetPeriod::Day, "Today"),
+            (BudgetPeriod::Month, "This month"),
+        ] {
+            let since = period_start(period, now);
+            for (provider, totals) in self.ledger.totals_by_provider(since)? {
+                rows.push(self.usage_row(&provider, name, totals));
+            }
+            rows.push(self.usage_row(ALL_PROVIDERS, name, self.ledger.totals(None, since)?));
+        }
+
+        let mut budgets = Vec::new();
+        let mut warnings = Vec::new();
+        for budget in &self.config.budgets {
+            let spent = self
+                .ledger
+                .totals(budget.provider.as_deref(), period_start(budget.period, now))?
+                .cost;
+            let line = format!(
+                "{}: {}",
+                budget_name(budget),
+                self.spending(spent, budget.limit)
+            );
+            if spent >= budget.limit {
+                warnings.push(format!("{} is used up, requests are blocked", line));
+            } else if spent >= budget.limit * self.config.warning_threshold {
+                warnings.push(format!("{} is nearly used up", line));
+            }
+            budgets.push(line.into());
+        }
+        Ok((rows, budgets, warnings.join("\n")))
+    }
+
+    fn usage_row(&self, provider: &str, period: &str, totals: UsageTotals) -> UsageRow {
+        let tokens = totals.tokens;
+        UsageRow {
+            provider: provider.into(),
+            period: period.into(),
+            requests: totals.requests as i32,
+            tokens: format!(
+                "{} in ({} cached) / {} out ({} reasoning) tokens",
+                tokens.input_tokens,
+                tokens.cached_input_tokens,
+                tokens.output_tokens,
+                tokens.reasoning_tokens
+            )
+            .into(),
+            cost: format!("{:.4} {}", totals.cost, self.config.currency).into(),
+        }
+    }
+
+    fn spending(&self, spent: f64, limit: f64) -> String {
+        format!(
+            "{:.2} of {:.2} {} spent",
+            spent, limit, self.config.currency
+        )
+    }
+}
+
+/// e.g. "Daily budget of DeepSeek" or "Monthly budget of all providers".
+fn budget_name(budget: &storage::Budget) -> String {
+    format!(
+        "{} budget of {}",
+        match budget.period {
+            BudgetPeriod::Day => "Daily",
+            BudgetPeriod::Month => "Monthly",
+        },
+        budget.provider.as_deref().unwrap_or("all providers")
+    )
+}